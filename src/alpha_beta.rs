@@ -1,13 +1,18 @@
 use arrayvec::ArrayVec;
 
 use crate::{
-    evaluation::MATE_SCORE,
+    correction_history::CORRECTION_HISTORY_TABLE,
+    evaluation::{MATE_SCORE, MATE_THRESHOLD},
     move_picker::MovePicker,
     move_scoring::{HISTORY_TABLE, history_bonus, history_maluse},
     prelude::*,
     quiescence_search,
-    settings::{self, MAX_AB_DEPTH, RFP_MARGIN},
-    transposition_table::{Bound, TT},
+    retrograde::Outcome,
+    settings::{
+        self, MAX_AB_DEPTH, NMP_VERIFICATION_DEPTH, NMP_VERIFICATION_MAX_PIECES, RFP_MARGIN,
+    },
+    syzygy::{TB_WIN, Wdl},
+    transposition_table::Bound,
 };
 
 use std::{cmp::min, f64, sync::atomic::Ordering};
@@ -21,8 +26,7 @@ pub const fn rfp_margin(depth: usize) -> usize {
 }
 
 /// <https://www.chessprogramming.org/Alpha-Beta>
-/// Returns the pv, and the associated evaluation
-/// Note that the pv is reversed, i.e. the best move at this depth is at the end of the list
+/// Returns the evaluation, the pv is collected in `sd.pv` along the way
 ///
 /// Shared data is extracted to a struct, to keep the number of arguments <= 6. This should, in theory,
 /// optimize the function as all arguments can be passed through registers
@@ -30,13 +34,15 @@ pub const fn rfp_margin(depth: usize) -> usize {
 pub fn alpha_beta<const PV_NODE: bool>(
     depth: usize,
     mut alpha: i32,
-    beta: i32,
+    mut beta: i32,
     sd: &mut SharedSearchData,
     ply: usize,
     null_move_allowed: bool,
 ) -> i32 {
     *sd.local_seldepth = (*sd.local_seldepth).max(ply);
     sd.total_alpha_beta_nodes.fetch_add(1, Ordering::Relaxed);
+    // before anything can return, a stale row would get copied into the PV of the parent
+    sd.pv.clear(ply);
 
     assert!(depth <= MAX_AB_DEPTH);
 
@@ -48,6 +54,19 @@ pub fn alpha_beta<const PV_NODE: bool>(
         return 0;
     }
 
+    // Mate Distance Pruning
+    // Even if we mate right on the next move we can't score higher than a mate at ply + 1, and even if we get mated here
+    // we can't score lower than a mate at the current ply. When a shorter mate was already found the window collapses
+    // <https://www.chessprogramming.org/Mate_Distance_Pruning>
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    if ply > 0 {
+        alpha = alpha.max(-MATE_SCORE + ply as i32);
+        beta = beta.min(MATE_SCORE - ply as i32 - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+    }
+
     if depth == 0 {
         if settings::QS && ply < MAX_AB_DEPTH - 1 {
             sd.ab_ply = ply;
@@ -60,21 +79,21 @@ pub fn alpha_beta<const PV_NODE: bool>(
     }
 
     let original_alpha = alpha;
-    let eval = sd.board.evaluate();
-
     let mut tt_move: Option<EncodedMove> = None;
+    let mut tt_static_eval = None;
     let tt_score;
 
     if settings::TT_AB {
         // TODO: legal detection to prevent collisions
         #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-        if let Some(tt_hit) = TT.probe(sd.board.hash(), ply as i32) {
+        if let Some(tt_hit) = sd.tt.probe(sd.board.hash(), ply as i32) {
             sd.total_tt_hits.fetch_add(1, Ordering::Relaxed);
 
             let bound = tt_hit.bound();
 
             tt_move = tt_hit.best_move();
             tt_score = tt_hit.score();
+            tt_static_eval = tt_hit.static_eval();
 
             let depth_req = depth as i32 + i32::from(tt_score >= beta);
 
@@ -93,13 +112,73 @@ pub fn alpha_beta<const PV_NODE: bool>(
         }
     }
 
+    // The tables know the outcome, a cursed win / blessed loss is scored as a slightly better / worse draw.
+    // Wins and losses are only bounds as the search might still find a mate.
+    // Like every early return these leave the PV row of this ply empty (it's cleared on entry), so the line ends here
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    if settings::SYZYGY
+        && ply > 0
+        && let Some(tablebases) = sd.tablebases
+        && tablebases.can_probe(sd.board)
+        && let Some(wdl) = tablebases.probe_wdl(sd.board)
+    {
+        sd.total_tb_hits.fetch_add(1, Ordering::Relaxed);
+
+        let (score, bound) = match wdl {
+            Wdl::Win => (TB_WIN - ply as i32, Bound::Lower),
+            Wdl::Loss => (-TB_WIN + ply as i32, Bound::Upper),
+            wdl => (2 * wdl as i32, Bound::Exact),
+        };
+        if match bound {
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+            _ => true,
+        } {
+            sd.tt.store(
+                sd.board.hash(),
+                None,
+                score,
+                None,
+                (depth + 6).min(MAX_AB_DEPTH) as i8,
+                ply as i32,
+                bound,
+                PV_NODE,
+            );
+            return score;
+        }
+    }
+
+    // The generated tables know the exact distance to mate, nothing left to search
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    if settings::RETROGRADE
+        && ply > 0
+        && let Some(tables) = sd.retrograde
+        && let Some(outcome) = tables.probe(sd.board)
+    {
+        sd.total_tb_hits.fetch_add(1, Ordering::Relaxed);
+        return match outcome {
+            Outcome::Win(plies) => MATE_SCORE - ply as i32 - i32::from(plies),
+            Outcome::Loss(plies) => -MATE_SCORE + ply as i32 + i32::from(plies),
+            Outcome::Draw => 0,
+        };
+    }
+
     // cancels search if time is over
     if sd.stop.load(Ordering::Relaxed) {
         sd.timeout_occurred.store(true, Ordering::Relaxed);
         return 0;
     }
 
-    if !PV_NODE || !settings::PVS {
+    let raw_eval = tt_static_eval.unwrap_or_else(|| sd.board.evaluate());
+    // the eval used for pruning decisions, corrected by what previous searches found for similar positions
+    let eval = if settings::CORRECTION_HISTORY {
+        CORRECTION_HISTORY_TABLE.correct(sd.board, raw_eval)
+    } else {
+        raw_eval
+    };
+
+    // a mate bound can't be proven by a static eval, that only hides shorter mates behind the one already found
+    if (!PV_NODE || !settings::PVS) && beta.abs() < MATE_THRESHOLD {
         if settings::RFP {
             // apparently RFP should only be done in the later parts of the tree. CPW explicitly mentions
             // pre-frontier nodes, i.e. those nodes where depth == 1. However viridithias, smol.cs and akimbo
@@ -126,7 +205,19 @@ pub fn alpha_beta<const PV_NODE: bool>(
             let eval =
                 -alpha_beta::<false>(depth - reduction, -alpha - 1, -alpha, sd, ply + 1, false);
             sd.board.unmake_null_move();
-            if eval >= beta {
+            // In zugzwang passing is the best move there is, so a fail high only counts if a real move holds as well.
+            // Otherwise quiet moves like 1. Ra6 in kbK5/pp6/1P6/8/8/8/8/R7 w get refuted by black passing.
+            // Zugzwang needs few pieces to move, so the verification only runs then or where a wrong cutoff prunes a lot
+            // <https://www.chessprogramming.org/Null_Move_Pruning#Verified_Null_Move_Pruning>
+            let verify = depth > reduction
+                && (depth >= NMP_VERIFICATION_DEPTH
+                    || sd.board.non_pawn_piece_count(sd.board.current_color())
+                        <= NMP_VERIFICATION_MAX_PIECES);
+            if eval >= beta
+                && (!verify
+                    || alpha_beta::<false>(depth - reduction, beta - 1, beta, sd, ply, false)
+                        >= beta)
+            {
                 return beta;
             }
         }
@@ -146,6 +237,13 @@ pub fn alpha_beta<const PV_NODE: bool>(
     let mut moves_visited = 0;
 
     while let Some(mv) = movepicker.next(sd.board) {
+        if ply == 0
+            && sd
+                .root_moves
+                .is_some_and(|root_moves| !root_moves.contains(&mv))
+        {
+            continue;
+        }
         moves_visited += 1;
         // cancels search if time is over
         if sd.stop.load(Ordering::Relaxed) {
@@ -207,6 +305,7 @@ pub fn alpha_beta<const PV_NODE: bool>(
             if eval > alpha {
                 best_move = Some(mv);
                 alpha = eval;
+                sd.pv.update(ply, mv);
             }
 
             if settings::AB && alpha >= beta {
@@ -266,11 +365,26 @@ pub fn alpha_beta<const PV_NODE: bool>(
         Bound::Upper
     };
 
+    // Only learn from results that say something about the static eval: a noisy best move isn't covered by it,
+    // and failing high below / failing low above the eval doesn't tell us how far off it is
+    if settings::CORRECTION_HISTORY
+        && !sd.board.is_in_check()
+        && best_move.is_none_or(|mv| {
+            !mv.decode().mv_type.is_capture() && !mv.decode().mv_type.is_promotion()
+        })
+        && best_eval.abs() < MATE_THRESHOLD
+        && !(bound == Bound::Lower && best_eval <= eval)
+        && !(bound == Bound::Upper && best_eval >= eval)
+    {
+        CORRECTION_HISTORY_TABLE.update(sd.board, depth, best_eval, raw_eval);
+    }
+
     #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-    TT.store(
+    sd.tt.store(
         sd.board.hash(),
         best_move,
         best_eval,
+        Some(raw_eval),
         depth as i8,
         ply as i32,
         bound,
@@ -318,6 +432,52 @@ const fn int_ln(n: usize) -> u32 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        retrograde::Tables,
+        transposition_table::TranspositionTable,
+        types::{pv_table::PvTable, search_data::SharedSearchData},
+    };
+    use std::sync::{Arc, atomic::AtomicBool};
+
+    /// A child answered by the tables ends the PV, no stale line of an earlier search or a sibling may stick to it
+    #[test]
+    #[allow(clippy::significant_drop_tightening)]
+    fn test_pv_ends_at_table_hit() {
+        let mut tables = Tables::default();
+        tables.add("KQK", None).unwrap();
+        let tt = TranspositionTable::new(1);
+        let tt = tt.view();
+
+        // every move is a table hit, and only taking the rook is one next to moves searched deeper
+        for fen in [
+            "8/8/8/4k3/8/8/8/KQ6 w - - 0 1",
+            "4k3/8/8/8/8/8/1r6/KQ6 w - - 0 1",
+        ] {
+            let mut board = Board::new(fen);
+            let stop = Arc::new(AtomicBool::new(false));
+            let mut seldepth = 0;
+            let mut killers = [EncodedMove(0); MAX_AB_DEPTH + 1];
+            let mut pv = PvTable::new();
+            // lines left over from an earlier search or a sibling
+            let stale = DecodedMove::from_coords("a1a2", &board).encode();
+            for ply in (1..4).rev() {
+                pv.update(ply, stale);
+            }
+            let mut sd =
+                SharedSearchData::new(&mut board, &stop, &mut seldepth, &mut killers, &mut pv, &tt);
+            sd.retrograde = Some(&tables);
+            let score = alpha_beta::<true>(4, -MATE_SCORE, MATE_SCORE, &mut sd, 0, true);
+
+            let line = pv.line();
+            assert_eq!(line.len(), 1, "{fen}");
+            let mut board = Board::new(fen);
+            board.make_move(line[0]);
+            let Some(Outcome::Loss(plies)) = tables.probe(&board) else {
+                panic!("{fen}: the pv doesn't lead into a won table position");
+            };
+            assert_eq!(score, MATE_SCORE - 1 - i32::from(plies), "{fen}");
+        }
+    }
 
     #[test]
     fn print_lmp_board() {
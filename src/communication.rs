@@ -1,44 +1,65 @@
 #![allow(dead_code)]
 
 use crate::{
+    correction_history::CORRECTION_HISTORY_TABLE,
     debug::custom_commands::handle_custom_commands,
+    eval_params,
+    experience::EXPERIENCE,
     iterative_deepening::{self, iterative_deepening},
+    material_hash_table::{MATERIAL_HASH_TABLE, MaterialHashTable},
     move_scoring::HISTORY_TABLE,
+    nnue,
+    pawn_hash_table::{PAWN_HASH_TABLE, PawnHashTable},
     prelude::*,
-    time_management::calc_search_time,
+    retrograde, settings, syzygy,
+    time_management::{calc_search_time, parse_mate},
     transposition_table::{DEFAULT_TT_SIZE, MAX_TT_SIZE, TT},
     types::board::START_POS,
 };
 use std::{
     env,
     io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
     process::exit,
+    sync::PoisonError,
     time::Duration,
 };
 
+/// State set via `setoption` which isn't owned by any engine component
+#[derive(Default)]
+struct UciOptions {
+    /// Where `SaveTT` and `LoadTT` dump and load the TT
+    tt_file: Option<PathBuf>,
+}
+
 pub fn handle_communication() {
     // trigger lazy initialization before we do anything to avoid
     // paying that cost during a game
     TT.clear();
     HISTORY_TABLE.clear();
+    CORRECTION_HISTORY_TABLE.clear();
     let mut board = Board::new(START_POS);
+    let mut options = UciOptions::default();
 
     let stdin = io::stdin();
     let reader = BufReader::with_capacity(65536, stdin.lock());
     let mut stdout = io::stdout();
 
     let args: Vec<String> = env::args().collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--eval-params") {
+        load_eval_params(args.get(pos + 1).cloned());
+    }
     if args.iter().any(|arg| arg.contains("flamegraph")) {
-        let best_move = iterative_deepening::iterative_deepening(
+        let result = iterative_deepening::iterative_deepening(
             &mut board,
             100,
             Duration::from_millis(10000),
+            None,
             false,
             false,
         );
-        if let Some(mv) = best_move {
-            println!("info pv {}", mv.decode().to_coords());
-            println!("bestmove {}", mv.decode().to_coords());
+        if !result.pv.is_empty() {
+            print_bestmove(&result.pv);
         }
         exit(0);
     }
@@ -51,7 +72,7 @@ pub fn handle_communication() {
             let args: Vec<&str> = parts.collect();
 
             // Try to handle UCI commands first
-            if !handle_uci_commands(&mut board, command, &args) {
+            if !handle_uci_commands(&mut board, &mut options, command, &args) {
                 // If no uci command matched, then try matching a custom command
                 handle_custom_commands(&mut board, command, &args);
             }
@@ -62,7 +83,12 @@ pub fn handle_communication() {
 }
 
 // UCI Commands: needed to comply with UCI protocoll
-fn handle_uci_commands(board: &mut Board, command: &str, args: &[&str]) -> bool {
+fn handle_uci_commands(
+    board: &mut Board,
+    options: &mut UciOptions,
+    command: &str,
+    args: &[&str],
+) -> bool {
     #[allow(clippy::match_same_arms)]
     match command {
         "uci" => {
@@ -71,6 +97,15 @@ fn handle_uci_commands(board: &mut Board, command: &str, args: &[&str]) -> bool
             println!(
                 "option name Hash type spin default {DEFAULT_TT_SIZE} min 1 max {MAX_TT_SIZE}"
             );
+            println!("option name TTFile type string default <empty>");
+            println!("option name SaveTT type button");
+            println!("option name LoadTT type button");
+            println!("option name ExperienceFile type string default <empty>");
+            println!("option name EvalFile type string default <empty>");
+            println!("option name EvalParams type string default <empty>");
+            println!("option name SyzygyPath type string default <empty>");
+            println!("option name RetrogradePath type string default <empty>");
+            println!("option name RetrogradeTables type string default <empty>");
 
             println!("uciok");
         }
@@ -81,7 +116,7 @@ fn handle_uci_commands(board: &mut Board, command: &str, args: &[&str]) -> bool
             // We are always ready
             println!("readyok");
         }
-        "setoption" => handle_setoption(args),
+        "setoption" => handle_setoption(args, options),
         "register" => {
             // This is probably a legacy UCI feature
             println!("info register not implemented");
@@ -90,6 +125,9 @@ fn handle_uci_commands(board: &mut Board, command: &str, args: &[&str]) -> bool
             *board = Board::new(START_POS);
             TT.clear();
             HISTORY_TABLE.clear();
+            CORRECTION_HISTORY_TABLE.clear();
+            PAWN_HASH_TABLE.with_borrow_mut(PawnHashTable::clear);
+            MATERIAL_HASH_TABLE.with_borrow_mut(MaterialHashTable::clear);
         }
         "position" => {
             set_position(board, args);
@@ -184,11 +222,34 @@ fn set_position(board: &mut Board, args: &[&str]) {
 
 pub fn handle_go(board: &mut Board, args: &[&str], debug: bool, help: bool) {
     let (max_depth, time_limit) = calc_search_time(args, board);
-    let best_move = iterative_deepening(board, max_depth, time_limit, debug, help);
+    let mate = parse_mate(args);
+    board.set_network(nnue::network());
+    board.set_eval_params(eval_params::loaded_params());
+
+    let experience = EXPERIENCE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(entry) = experience.consult(board) {
+        println!(
+            "info string experience: {} at depth {} score {}",
+            entry.best_move.decode().to_coords(),
+            entry.depth,
+            entry.score
+        );
+    }
+    drop(experience);
+
+    let result = iterative_deepening(board, max_depth, time_limit, mate, debug, help);
+
+    let learned = EXPERIENCE
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .learn(board, &result);
+    if let Err(err) = learned {
+        println!("info string experience could not be saved: {err}");
+    }
 
-    if let Some(mv) = best_move {
-        println!("info pv {}", mv.decode().to_coords());
-        println!("bestmove {}", mv.decode().to_coords());
+    let pv = result.pv;
+    if !pv.is_empty() {
+        print_bestmove(&pv);
     } else if board.is_in_check() {
         println!("info Game over: Checkmate!");
         println!("bestmove ");
@@ -200,30 +261,134 @@ pub fn handle_go(board: &mut Board, args: &[&str], debug: bool, help: bool) {
     TT.increase_age();
 }
 
-fn handle_setoption(args: &[&str]) {
-    let mut iter = args.iter().peekable();
-    iter.next(); // Skips the "name" section of setoption
-    if let Some(&&token) = iter.peek() {
-        match token {
-            "Hash" => {
-                iter.next();
-                if let Some(&next) = iter.next() {
-                    let value_str = if next == "value" {
-                        iter.next().copied()
-                    } else {
-                        Some(next)
-                    };
-
-                    if let Some(val) = value_str
-                        && let Ok(mib_size) = val.parse::<usize>()
-                    {
-                        TT.resize(mib_size);
+/// Prints the final PV and the best move, the second move of the PV is the move we expect the opponent to play
+fn print_bestmove(pv: &[EncodedMove]) {
+    let pv_string = pv
+        .iter()
+        .map(|mv| mv.decode().to_coords())
+        .collect::<Vec<_>>()
+        .join(" ");
+    println!("info pv {pv_string}");
+
+    match pv {
+        [best, ponder, ..] => println!(
+            "bestmove {} ponder {}",
+            best.decode().to_coords(),
+            ponder.decode().to_coords()
+        ),
+        [best] => println!("bestmove {}", best.decode().to_coords()),
+        [] => {}
+    }
+}
+
+fn handle_setoption(args: &[&str], options: &mut UciOptions) {
+    // setoption name <id> [value <x>], the value may contain spaces (e.g. paths)
+    let Some(&name) = args.get(1) else { return };
+    let value_start = if args.get(2) == Some(&"value") { 3 } else { 2 };
+    let value = args
+        .get(value_start..)
+        .map(|value| value.join(" "))
+        .filter(|value| !value.is_empty() && value != "<empty>");
+
+    match name {
+        "Hash" => {
+            if let Some(mib_size) = value.and_then(|value| value.parse::<usize>().ok()) {
+                // blocks until a running search dropped its view of the TT
+                TT.resize(mib_size);
+            }
+        }
+        "TTFile" => options.tt_file = value.map(PathBuf::from),
+        "SaveTT" | "LoadTT" => {
+            let Some(path) = &options.tt_file else {
+                println!("info string {name} needs the TTFile option to be set");
+                return;
+            };
+            let result = if name == "SaveTT" {
+                TT.save(path)
+            } else {
+                TT.load(path)
+            };
+            if let Err(err) = result {
+                println!("info string {name} {}: {err}", path.display());
+            }
+        }
+        "ExperienceFile" => {
+            let mut experience = EXPERIENCE.lock().unwrap_or_else(PoisonError::into_inner);
+            match value {
+                Some(path) => match experience.open(PathBuf::from(&path)) {
+                    Ok(positions) => {
+                        println!("info string experience: {positions} positions in {path}");
                     }
+                    Err(err) => println!("info string ExperienceFile {path}: {err}"),
+                },
+                None => experience.close(),
+            }
+        }
+        "EvalFile" => {
+            // every search attaches the current network to its board, nothing else needs to know
+            if let Err(err) = nnue::load_network(value.as_deref().map(Path::new)) {
+                println!("info string EvalFile {}: {err}", value.unwrap_or_default());
+            } else if !settings::NNUE {
+                println!("info string EvalFile has no effect without the nnue feature");
+            }
+        }
+        "EvalParams" => load_eval_params(value),
+        "SyzygyPath" => match syzygy::load_tablebases(value.as_deref()) {
+            Ok(tables) if value.is_some() => {
+                println!("info string SyzygyPath: found {tables} tables");
+                if !settings::SYZYGY {
+                    println!("info string SyzygyPath has no effect without the syzygy feature");
                 }
             }
-            _ => {
-                println!("This option is not supported, currently supported options: Hash");
+            Ok(_) => {}
+            Err(err) => println!(
+                "info string SyzygyPath {}: {err}",
+                value.unwrap_or_default()
+            ),
+        },
+        "RetrogradePath" => {
+            if let Err(err) = retrograde::set_cache_dir(value.as_deref().map(Path::new)) {
+                println!(
+                    "info string RetrogradePath {}: {err}",
+                    value.unwrap_or_default()
+                );
             }
         }
+        // generating the tables can take a while, they are cached in the RetrogradePath if there is one
+        "RetrogradeTables" => match retrograde::load_tables(value.as_deref()) {
+            Ok(tables) if value.is_some() => {
+                println!("info string RetrogradeTables: {tables} tables");
+                if !settings::RETROGRADE {
+                    println!(
+                        "info string RetrogradeTables has no effect without the retrograde feature"
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(err) => println!(
+                "info string RetrogradeTables {}: {err}",
+                value.unwrap_or_default()
+            ),
+        },
+        _ => {
+            println!(
+                "This option is not supported, currently supported options: Hash, TTFile, SaveTT, LoadTT, ExperienceFile, EvalFile, EvalParams, SyzygyPath, RetrogradePath, RetrogradeTables"
+            );
+        }
+    }
+}
+
+/// Weights of the handcrafted evaluation from a JSON file of the tuner
+fn load_eval_params(path: Option<String>) {
+    match eval_params::load_params(path.as_deref().map(Path::new)) {
+        Ok(missing) if !missing.is_empty() => println!(
+            "info string EvalParams: using the compiled in weights for {}",
+            missing.join(", ")
+        ),
+        Ok(_) => {}
+        Err(err) => println!(
+            "info string EvalParams {}: {err}, using the compiled in weights",
+            path.unwrap_or_default()
+        ),
     }
 }
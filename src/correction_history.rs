@@ -0,0 +1,135 @@
+use crate::{evaluation::MATE_THRESHOLD, prelude::*};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+/// Entries per color and table, indexed by the lower bits of the pawn hash / material key
+const CORRECTION_HISTORY_SIZE: usize = 16384;
+
+/// Corrections are stored with this fixed point factor so small differences don't get rounded away
+const CORRECTION_GRAIN: i32 = 256;
+
+/// Weights of the moving average are out of this
+const CORRECTION_WEIGHT_SCALE: i32 = 256;
+
+/// How fast the tables follow the search, the weight of a new sample is `min(depth + 1, CORRECTION_LEARNING_RATE)`
+/// out of `CORRECTION_WEIGHT_SCALE`, so deeper (more trustworthy) results move the entry further.
+/// THIS IS TUNABLE.
+pub const CORRECTION_LEARNING_RATE: i32 = 16;
+
+/// Maximum correction in centipawns
+/// THIS IS TUNABLE.
+pub const CORRECTION_CLAMP: i32 = 128;
+
+/// Correction history shared between all search threads
+pub static CORRECTION_HISTORY_TABLE: std::sync::LazyLock<CorrectionHistory> =
+    std::sync::LazyLock::new(CorrectionHistory::new);
+
+/// Learns how far the static evaluation is off from what the search found,
+/// keyed once by the pawn structure and once by the material on the board.
+/// <https://www.chessprogramming.org/Static_Evaluation_Correction_History>
+pub struct CorrectionHistory {
+    pawn: [Box<[AtomicI32]>; 2],
+    material: [Box<[AtomicI32]>; 2],
+}
+
+impl CorrectionHistory {
+    pub fn new() -> Self {
+        let table = || {
+            (0..CORRECTION_HISTORY_SIZE)
+                .map(|_| AtomicI32::new(0))
+                .collect::<Box<[AtomicI32]>>()
+        };
+        Self {
+            pawn: [table(), table()],
+            material: [table(), table()],
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn index(key: u64) -> usize {
+        key as usize % CORRECTION_HISTORY_SIZE
+    }
+
+    /// The static evaluation of `board` (from the side to move) with both learned corrections applied
+    pub fn correct(&self, board: &Board, static_eval: i32) -> i32 {
+        let color = board.current_color() as usize;
+        let pawn = self.pawn[color][Self::index(board.pawn_hash())].load(Ordering::Relaxed);
+        let material =
+            self.material[color][Self::index(board.material_key())].load(Ordering::Relaxed);
+
+        // both tables learn the full difference, so their average is applied
+        // and a correction never turns a normal evaluation into something that looks like a mate
+        (static_eval + (pawn + material) / (2 * CORRECTION_GRAIN))
+            .clamp(-MATE_THRESHOLD + 1, MATE_THRESHOLD - 1)
+    }
+
+    /// Moves the corrections of `board` towards the difference between the search result and the static evaluation
+    pub fn update(&self, board: &Board, depth: usize, search_score: i32, static_eval: i32) {
+        let color = board.current_color() as usize;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let weight = (depth as i32 + 1).min(CORRECTION_LEARNING_RATE);
+        let diff = (search_score - static_eval) * CORRECTION_GRAIN;
+
+        for entry in [
+            &self.pawn[color][Self::index(board.pawn_hash())],
+            &self.material[color][Self::index(board.material_key())],
+        ] {
+            let old = entry.load(Ordering::Relaxed);
+            let new = (old * (CORRECTION_WEIGHT_SCALE - weight) + diff * weight)
+                / CORRECTION_WEIGHT_SCALE;
+            entry.store(
+                new.clamp(
+                    -CORRECTION_CLAMP * CORRECTION_GRAIN,
+                    CORRECTION_CLAMP * CORRECTION_GRAIN,
+                ),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    pub fn clear(&self) {
+        for table in self.pawn.iter().chain(&self.material) {
+            for entry in table {
+                entry.store(0, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correction_follows_search() {
+        // own table, the global one is shared with every other test searching
+        let history = CorrectionHistory::new();
+        let mut board = Board::new("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+        assert_eq!(history.correct(&board, 20), 20);
+
+        for _ in 0..200 {
+            history.update(&board, 10, 60, 20);
+        }
+        let corrected = history.correct(&board, 20);
+        // the +40 difference got learned, slightly less due to rounding
+        assert!((55..=60).contains(&corrected), "corrected to {corrected}");
+
+        // the other side has its own entries
+        board.make_null_move();
+        assert_eq!(history.correct(&board, 20), 20);
+    }
+
+    #[test]
+    fn test_correction_is_clamped() {
+        let history = CorrectionHistory::new();
+        let board = Board::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+        for _ in 0..500 {
+            history.update(&board, 20, 5000, 0);
+        }
+        assert_eq!(history.correct(&board, 0), CORRECTION_CLAMP);
+
+        history.clear();
+        assert_eq!(history.correct(&board, 0), 0);
+    }
+}
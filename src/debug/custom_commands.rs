@@ -1,14 +1,15 @@
 use crate::{
     communication::handle_go,
     debug::{perft, visualize},
-    evaluation::GAMEPHASE_INC,
-    evaluation_constants::MOBILITY_COEFFICIENTS,
+    endgame::{Endgame, SCALE_NORMAL},
+    eval_params,
     move_generator::{
         masks::{self, king_safety_mask},
         pinmask,
     },
     move_picker::MoveList,
     move_scoring::{mvv_lva, score_quiets},
+    nnue,
     prelude::*,
     settings,
     transposition_table::TT,
@@ -46,6 +47,8 @@ pub fn handle_custom_commands(board: &mut Board, command: &str, args: &[&str]) {
             println!("Captures: {captures:?}");
         }
         "eval" => {
+            board.set_network(nnue::network());
+            board.set_eval_params(eval_params::loaded_params());
             let color_multiplier = match board.current_color() {
                 White => 1,
                 Black => -1,
@@ -54,8 +57,7 @@ pub fn handle_custom_commands(board: &mut Board, command: &str, args: &[&str]) {
                 "Depth 0 Board Evaluation: {}\n",
                 board.evaluate() * color_multiplier
             );
-            // #[cfg(debug_assertions)]
-            print_debug_eval_info(board);
+            print_eval_trace(board);
         }
         "do" => {
             let mv_str: &str = args[0];
@@ -202,106 +204,50 @@ fn perft(board: &mut Board, args: &[&str]) {
     }
 }
 
-/// Yes this is ugly af
-/// I'm inlining a lot of stuff in the eval for caching optimizations and better readability there
-/// so we just accept that this is a lot of duplicated code and kinda ugly, this is only part of the debug utils anyway after all'
-/// WARNING: THIS IS EXCLUSIVELY VIBE-CODED SLOP, FOR YOUR OWN SANITY DO NOT ATTEMPT TO WORK ON THIS WITHOUT AI
-#[allow(
-    clippy::needless_range_loop,
-    clippy::cast_possible_truncation,
-    clippy::cast_possible_wrap,
-    clippy::similar_names
-)]
-fn print_debug_eval_info(board: &Board) {
-    if settings::DOUBLED_PAWNS {
-        // Doubled pawns
-        let doubled_pawns = board.doubled_pawn_penalties();
-        println!(
-            "Doubled Pawn offset: {} - {} = {}",
-            doubled_pawns[0],
-            doubled_pawns[1],
-            doubled_pawns[0] - doubled_pawns[1]
-        );
-    }
-
-    // Pawn structure
-    let (mg_pawn, eg_pawn) = board.pawn_structure();
-
-    // Calculate game phase
-    let mut phase = 32;
-
-    for i in 0..=11 {
-        let mut bb = board.figure_bb_by_index(i);
-        let count = bb.iter_mut().count() as i32;
-        phase -= count * GAMEPHASE_INC[i];
-    }
-    let gamephase = (phase * 256 + 16) / 32;
+/// Prints every term of the handcrafted evaluation, the trace is filled by the evaluation itself so this can't drift from it
+fn print_eval_trace(board: &Board) {
+    let trace = board.trace();
+    let gamephase = trace.gamephase;
 
-    let white_blended =
-        (i32::from(mg_pawn[0]) * (256 - gamephase) + i32::from(eg_pawn[0]) * gamephase) >> 8;
-    let black_blended =
-        (i32::from(mg_pawn[1]) * (256 - gamephase) + i32::from(eg_pawn[1]) * gamephase) >> 8;
-
-    if settings::PASSED_PAWNS || settings::ISOLATED_PAWNS {
+    println!(
+        "{:<16} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "Term", "White MG", "White EG", "Black MG", "Black EG", "Tapered"
+    );
+    for (name, [white, black]) in trace.terms() {
         println!(
-            "Pawn Structure (passed, isolated):\n  White: MG {}, EG {}\n  Black: MG {}, EG {}",
-            mg_pawn[0], eg_pawn[0], mg_pawn[1], eg_pawn[1]
+            "{name:<16} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            white.mg,
+            white.eg,
+            black.mg,
+            black.eg,
+            (white - black).taper(gamephase)
         );
-        println!("Blended Passed Pawn eval:\n  White: {white_blended}\n  Black: {black_blended}",);
-    }
-
-    // Mobility evaluation
-    let mut mg_mobility = [0i32; 2];
-    let mut eg_mobility = [0i32; 2];
-    let mut figure_movements = [Bitboard::EMPTY; 12];
-    for i in 0..=11 {
-        let mobility = board.calculate_piece_mobility(i, &mut figure_movements);
-        // println!("mobility {:?}, {mobility}", Figure::from_idx(i));
-        mg_mobility[i & 1] += MOBILITY_COEFFICIENTS[0][i >> 1] * mobility;
-        eg_mobility[i & 1] += MOBILITY_COEFFICIENTS[1][i >> 1] * mobility;
     }
 
-    if settings::MOBILITY {
-        println!(
-            "Mobility scores:\n  White: MG {}, EG {}\n  Black: MG {}, EG {}",
-            mg_mobility[0], eg_mobility[0], mg_mobility[1], eg_mobility[1]
-        );
-
-        let mg_mobility_diff = mg_mobility[0] - mg_mobility[1];
-        let eg_mobility_diff = eg_mobility[0] - eg_mobility[1];
-        let blended_mobility =
-            (mg_mobility_diff * (256 - gamephase) + eg_mobility_diff * gamephase) >> 8;
-
-        println!(
-            "Mobility blended (White - Black): MG {mg_mobility_diff}, EG {eg_mobility_diff} -> Blended: {blended_mobility}",
-        );
-    }
-
-    if settings::KING_SAFETY {
-        // King safety evaluation
-        for i in 0..=11 {
-            if figure_movements[i].is_empty() {
-                // Ensure movement masks are populated if they were not filled by mobility evaluation.
-                figure_movements[i] =
-                    masks::calculate_attackmask_by_figure(board, board.occupied(), i, None);
-            }
-            figure_movements[i] |= board.figure_bb_by_index(i);
-        }
-
-        let (mg_king_safety, eg_king_safety) = board.king_safety(&figure_movements);
-        println!(
-            "King Safety table scores:\n  White: MG {}, EG {}\n  Black: MG {}, EG {}",
-            mg_king_safety[0], eg_king_safety[0], mg_king_safety[1], eg_king_safety[1]
-        );
-
-        let mg_king_safety_diff = mg_king_safety[0] - mg_king_safety[1];
-        let eg_king_safety_diff = eg_king_safety[0] - eg_king_safety[1];
-        let blended_king_safety = (i32::from(mg_king_safety_diff) * (256 - gamephase)
-            + i32::from(eg_king_safety_diff) * gamephase)
-            >> 8;
-
-        println!(
-            "King Safety blended (White - Black): MG {mg_king_safety_diff}, EG {eg_king_safety_diff} -> Blended: {blended_king_safety}",
-        );
+    let total = trace.total();
+    println!(
+        "\nKing danger buckets: White MG {}, EG {} | Black MG {}, EG {}",
+        trace.king_danger[0].mg,
+        trace.king_danger[0].eg,
+        trace.king_danger[1].mg,
+        trace.king_danger[1].eg
+    );
+    println!(
+        "Total (White - Black): MG {}, EG {} at game phase {gamephase}/256 -> {}",
+        total.mg,
+        total.eg,
+        total.taper(gamephase)
+    );
+    match trace.endgame {
+        Some(Endgame::Score(score)) => println!("Endgame evaluator (White - Black): {score}"),
+        Some(Endgame::Scale([white, black])) => println!(
+            "Endgame scale factors: White {white}/{SCALE_NORMAL}, Black {black}/{SCALE_NORMAL}"
+        ),
+        None => {}
     }
+    println!(
+        "Handcrafted evaluation for the side to move ({:?}): {}",
+        trace.side_to_move,
+        trace.score()
+    );
 }
@@ -212,6 +212,8 @@ pub fn hash_test_perft(board: &mut Board, depth: usize) -> usize {
             b2.make_move(mv.mv);
             // Compare hash from scratch with the incremental hash to test if the hashing works
             assert_eq!(b2.hash(), b2.generate_hash());
+            assert_eq!(b2.pawn_hash(), b2.generate_pawn_hash());
+            assert_eq!(b2.psqt(), crate::evaluation::Psqt::compute(&b2));
             r_perft(&mut b2, depth - 1)
         })
         .sum::<usize>()
@@ -0,0 +1,567 @@
+// Knowledge about endgames the general evaluation gets wrong.
+// Material signatures with a specialised evaluator are looked up by `Board::material_key`,
+// drawish material which is otherwise evaluated normally gets scaled down.
+// Based on the endgame code of Stockfish <https://www.chessprogramming.org/Stockfish>
+
+use crate::{
+    evaluation_constants::EG_PIECE_VALUES,
+    move_generator::normal_targets::{KING_TARGETS, PAWN_ATTACK_TARGETS},
+    prelude::*,
+    retrograde::{self, Outcome},
+    settings,
+};
+use std::{collections::HashMap, sync::LazyLock};
+
+/// Won endgames score above this so the search prefers them over anything the normal evaluation
+/// comes up with, while staying far below the mate scores
+pub const KNOWN_WIN: i32 = 10_000;
+
+/// Scale factors are out of this, i.e. this leaves the evaluation as it is
+pub const SCALE_NORMAL: i32 = 64;
+
+/// What the endgame knowledge has to say about a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endgame {
+    /// Replaces the evaluation, white - black
+    Score(i32),
+    /// Scale factor of the evaluation depending on who is ahead, `[white, black]`
+    Scale([i32; 2]),
+}
+
+impl Endgame {
+    /// Applies this to `score` (white - black)
+    pub const fn apply(self, score: i32) -> i32 {
+        match self {
+            Self::Score(score) => score,
+            Self::Scale(factors) => {
+                let factor = if score > 0 { factors[0] } else { factors[1] };
+                score * factor / SCALE_NORMAL
+            }
+        }
+    }
+}
+
+/// Score of the strong side
+type Evaluator = fn(&Board, Color) -> i32;
+
+/// Specialised evaluators by material key, along with the strong side
+static ENDGAMES: LazyLock<HashMap<u64, (Evaluator, Color)>> = LazyLock::new(|| {
+    let evaluators: [(&str, Evaluator); 4] =
+        [("KPK", kpk), ("KBNK", kbnk), ("KRKP", krkp), ("KQKP", kqkp)];
+
+    let mut endgames = HashMap::new();
+    for (code, evaluator) in evaluators {
+        for strong in [White, Black] {
+            endgames.insert(material_key(code, strong), (evaluator, strong));
+        }
+    }
+    endgames
+});
+
+/// Material key of a signature like `KBNK`, the pieces before the second king belong to `strong`
+fn material_key(code: &str, strong: Color) -> u64 {
+    let (strong_pieces, weak_pieces) = code[1..]
+        .split_once('K')
+        .expect("signatures contain both kings");
+    let (white, black) = match strong {
+        White => (strong_pieces, weak_pieces),
+        Black => (weak_pieces, strong_pieces),
+    };
+
+    // the squares don't matter for the material key, just the count of each figure
+    let rank = |king: &str, pieces: &str| {
+        let empty = 7 - pieces.len();
+        format!(
+            "{king}{pieces}{}",
+            if empty > 0 {
+                empty.to_string()
+            } else {
+                String::new()
+            }
+        )
+    };
+    let fen = format!(
+        "8/{}/8/8/8/8/{}/8 w - - 0 1",
+        rank("k", &black.to_lowercase()),
+        rank("K", white)
+    );
+    Board::new(&fen).material_key()
+}
+
+/// The endgame knowledge about `board`, `None` if the normal evaluation is fine as it is
+pub fn probe(board: &Board) -> Option<Endgame> {
+    if settings::RETROGRADE
+        && board.occupied().get_count() as usize <= retrograde::MAX_PIECES
+        && let Some(outcome) = retrograde::tables().and_then(|tables| tables.probe(board))
+    {
+        let score = retrograde_score(outcome);
+        return Some(Endgame::Score(match board.current_color() {
+            White => score,
+            Black => -score,
+        }));
+    }
+
+    if board.occupied().get_count() <= 4
+        && let Some(&(evaluator, strong)) = ENDGAMES.get(&board.material_key())
+    {
+        let score = evaluator(board, strong);
+        return Some(Endgame::Score(match strong {
+            White => score,
+            Black => -score,
+        }));
+    }
+
+    let factors = [scale_factor(board, White), scale_factor(board, Black)];
+    (factors != [SCALE_NORMAL; 2]).then_some(Endgame::Scale(factors))
+}
+
+/// Score of the side to move, quicker mates are better and slower ones less bad
+fn retrograde_score(outcome: Outcome) -> i32 {
+    match outcome {
+        Outcome::Win(plies) => KNOWN_WIN + 256 - i32::from(plies),
+        Outcome::Loss(plies) => -KNOWN_WIN - 256 + i32::from(plies),
+        Outcome::Draw => 0,
+    }
+}
+
+/// How much of its advantage `strong` can actually convert
+fn scale_factor(board: &Board, strong: Color) -> i32 {
+    let weak = !strong;
+    let strong_pawns = board.figure_bb(strong, Pawn);
+    let strong_pieces = board.color_bbs_without_king(strong) & !strong_pawns;
+    let weak_king = board.king(weak);
+
+    if (board.color_bbs_without_king(weak)).is_empty() && !strong_pawns.is_empty() {
+        let on_file_a = (strong_pawns & !Bitboard::file(0)).is_empty();
+        let on_file_h = (strong_pawns & !Bitboard::file(7)).is_empty();
+
+        if on_file_a || on_file_h {
+            let file = if on_file_a { 0 } else { 7 };
+            let queening = relative(Square::from_xy(file, 7).i(), strong);
+
+            // rook pawns with the defending king in front of them can't be promoted
+            if strong_pieces.is_empty()
+                && (strong_pawns & !Bitboard::passed_pawn_mask(weak_king, weak)).is_empty()
+            {
+                return 0;
+            }
+
+            // the bishop can't drive the king out of the corner if it doesn't control the queening square
+            let bishops = board.figure_bb(strong, Bishop);
+            if strong_pieces == bishops
+                && bishops.get_count() == 1
+                && !same_color(Bit(bishops.0).to_square().i(), queening)
+                && distance(weak_king.to_square().i(), queening) <= 1
+            {
+                return 0;
+            }
+        }
+    }
+
+    let white_bishops = board.figure_bb(White, Bishop);
+    let black_bishops = board.figure_bb(Black, Bishop);
+    if white_bishops.get_count() == 1
+        && black_bishops.get_count() == 1
+        && !same_color(
+            Bit(white_bishops.0).to_square().i(),
+            Bit(black_bishops.0).to_square().i(),
+        )
+    {
+        let only_bishops = board.color_bbs_without_king(White) & !board.figure_bb(White, Pawn)
+            == white_bishops
+            && board.color_bbs_without_king(Black) & !board.figure_bb(Black, Pawn) == black_bishops;
+
+        // opposite colored bishops are drawish, only passed pawns give some winning chances
+        #[allow(clippy::cast_possible_wrap)]
+        let factor = if only_bishops {
+            18 + 4 * board.passed_pawns(strong).get_count() as i32
+        } else {
+            22 + 3 * board.color_bbs(strong).get_count() as i32
+        };
+        return factor.min(SCALE_NORMAL);
+    }
+
+    SCALE_NORMAL
+}
+
+/// Square from the point of view of `color`, i.e. flipped for black
+const fn relative(square: usize, color: Color) -> usize {
+    match color {
+        White => square,
+        Black => square ^ 0x38,
+    }
+}
+
+/// Number of king moves between both squares
+pub const fn distance(a: usize, b: usize) -> i32 {
+    let dx = (a % 8).abs_diff(b % 8);
+    let dy = (a / 8).abs_diff(b / 8);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let distance = (if dx > dy { dx } else { dy }) as i32;
+    distance
+}
+
+/// Whether both squares have the same color
+const fn same_color(a: usize, b: usize) -> bool {
+    (a % 8 + a / 8) % 2 == (b % 8 + b / 8) % 2
+}
+
+/// Rewards the strong king for approaching the weak one
+const fn push_close(a: usize, b: usize) -> i32 {
+    140 - 20 * distance(a, b)
+}
+
+/// Squares of the pieces of an endgame relative to `strong`, i.e. as if the strong side were white
+struct Relative {
+    strong_king: usize,
+    weak_king: usize,
+}
+
+impl Relative {
+    fn new(board: &Board, strong: Color) -> Self {
+        Self {
+            strong_king: relative(board.king(strong).to_square().i(), strong),
+            weak_king: relative(board.king(!strong).to_square().i(), strong),
+        }
+    }
+}
+
+/// The only square of `piece` of `color`
+const fn square_of(board: &Board, color: Color, piece: Piece, strong: Color) -> usize {
+    relative(Bit(board.figure_bb(color, piece).0).to_square().i(), strong)
+}
+
+/// Mate with bishop and knight is only possible in a corner of the bishop's color
+fn kbnk(board: &Board, strong: Color) -> i32 {
+    let Relative {
+        strong_king,
+        weak_king,
+    } = Relative::new(board, strong);
+    let bishop = square_of(board, strong, Bishop, strong);
+
+    // a1 and h8 are dark, flip the board for a light squared bishop
+    let weak_king = if same_color(bishop, 0) {
+        weak_king
+    } else {
+        weak_king ^ 7
+    };
+    #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+    let corner = (7 - (weak_king / 8) as i32 - (weak_king % 8) as i32).abs();
+
+    KNOWN_WIN + push_close(strong_king, weak_king) + 420 * corner
+}
+
+/// Queen against pawn wins unless a rook or bishop pawn on the 7th rank is supported by its king
+fn kqkp(board: &Board, strong: Color) -> i32 {
+    let Relative {
+        strong_king,
+        weak_king,
+    } = Relative::new(board, strong);
+    let pawn = square_of(board, !strong, Pawn, strong);
+
+    let mut score = push_close(strong_king, weak_king);
+    // the pawn moves down from the strong side's point of view
+    if pawn / 8 != 1 || distance(weak_king, pawn) != 1 || !matches!(pawn % 8, 0 | 2 | 5 | 7) {
+        score += EG_PIECE_VALUES[Queen as usize] - EG_PIECE_VALUES[Pawn as usize];
+    }
+    score
+}
+
+/// Rook against pawn depends on whether the kings get there in time
+fn krkp(board: &Board, strong: Color) -> i32 {
+    let Relative {
+        strong_king,
+        weak_king,
+    } = Relative::new(board, strong);
+    let rook = square_of(board, strong, Rook, strong);
+    let pawn = square_of(board, !strong, Pawn, strong);
+    let queening = pawn % 8;
+    let weak_to_move = i32::from(board.current_color() != strong);
+    let rook_value = EG_PIECE_VALUES[Rook as usize];
+
+    if strong_king % 8 == pawn % 8 && strong_king < pawn {
+        // the strong king blocks the pawn
+        rook_value - distance(strong_king, pawn)
+    } else if distance(weak_king, pawn) >= 3 + weak_to_move && distance(weak_king, rook) >= 3 {
+        // the weak king is too far away to support the pawn
+        rook_value - distance(strong_king, pawn)
+    } else if weak_king / 8 <= 2
+        && distance(weak_king, pawn) == 1
+        && strong_king / 8 >= 3
+        && distance(strong_king, pawn) > 3 - weak_to_move
+    {
+        // a far advanced pawn supported by its king is drawish
+        80 - 8 * distance(strong_king, pawn)
+    } else {
+        200 - 8
+            * (distance(strong_king, pawn - 8)
+                - distance(weak_king, pawn - 8)
+                - distance(pawn, queening))
+    }
+}
+
+/// King and pawn against king is decided by the bitbase
+fn kpk(board: &Board, strong: Color) -> i32 {
+    let Relative {
+        mut strong_king,
+        mut weak_king,
+    } = Relative::new(board, strong);
+    let mut pawn = square_of(board, strong, Pawn, strong);
+
+    // the bitbase only covers pawns on the a to d file
+    if pawn % 8 >= 4 {
+        strong_king ^= 7;
+        weak_king ^= 7;
+        pawn ^= 7;
+    }
+
+    let stm = usize::from(board.current_color() != strong);
+    if KPK_BITBASE.is_win(stm, strong_king, weak_king, pawn) {
+        #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+        let rank = (pawn / 8) as i32;
+        KNOWN_WIN + EG_PIECE_VALUES[Pawn as usize] + rank
+    } else {
+        0
+    }
+}
+
+static KPK_BITBASE: LazyLock<KpkBitbase> = LazyLock::new(KpkBitbase::generate);
+
+/// Side to move (strong or weak) * pawn on a2 to d7 * both kings
+const KPK_SIZE: usize = 2 * 24 * 64 * 64;
+
+// Results are flags so the results of all moves can be combined with `|`
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+/// Whether king and pawn win against king, for every position with a white pawn on the a to d file.
+/// Generated by retrograde analysis the first time it is needed, one bit per position
+/// <https://www.chessprogramming.org/KPK>
+struct KpkBitbase(Vec<u64>);
+
+impl KpkBitbase {
+    /// `stm` is 0 if the strong side (white) is to move
+    const fn index(stm: usize, strong_king: usize, weak_king: usize, pawn: usize) -> usize {
+        strong_king | (weak_king << 6) | (stm << 12) | ((pawn % 8) << 13) | ((6 - pawn / 8) << 15)
+    }
+
+    fn is_win(&self, stm: usize, strong_king: usize, weak_king: usize, pawn: usize) -> bool {
+        let index = Self::index(stm, strong_king, weak_king, pawn);
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn generate() -> Self {
+        let decode = |index: usize| {
+            let stm = (index >> 12) & 1;
+            let pawn = ((index >> 13) & 3) + 8 * (6 - (index >> 15));
+            (stm, index & 63, (index >> 6) & 63, pawn)
+        };
+
+        let mut results: Vec<u8> = (0..KPK_SIZE)
+            .map(|index| {
+                let (stm, strong_king, weak_king, pawn) = decode(index);
+                Self::initial_result(stm, strong_king, weak_king, pawn)
+            })
+            .collect();
+
+        // resolve positions from the ones they lead to until nothing changes anymore,
+        // whatever is still unknown after that is a draw
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..KPK_SIZE {
+                if results[index] == UNKNOWN {
+                    let (stm, strong_king, weak_king, pawn) = decode(index);
+                    let result = Self::classify(&results, stm, strong_king, weak_king, pawn);
+                    if result != UNKNOWN {
+                        results[index] = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut bits = vec![0u64; KPK_SIZE / 64];
+        for (index, &result) in results.iter().enumerate() {
+            if result == WIN {
+                bits[index / 64] |= 1 << (index % 64);
+            }
+        }
+        Self(bits)
+    }
+
+    /// Results which are known without looking at any moves
+    fn initial_result(stm: usize, strong_king: usize, weak_king: usize, pawn: usize) -> u8 {
+        let strong_king_targets = KING_TARGETS[strong_king];
+        let weak_king_targets = KING_TARGETS[weak_king];
+        let pawn_attacks = PAWN_ATTACK_TARGETS[White as usize][pawn];
+        let weak_king_bb = Bitboard(1 << weak_king);
+        let pawn_bb = Bitboard(1 << pawn);
+
+        if distance(strong_king, weak_king) <= 1
+            || strong_king == pawn
+            || weak_king == pawn
+            || (stm == 0 && !(pawn_attacks & weak_king_bb).is_empty())
+        {
+            INVALID
+        } else if stm == 0
+            && pawn / 8 == 6
+            && strong_king != pawn + 8
+            && (distance(weak_king, pawn + 8) > 1 || distance(strong_king, pawn + 8) == 1)
+        {
+            // promotes and the queen can't be taken
+            WIN
+        } else if stm == 1
+            && ((weak_king_targets & !(strong_king_targets | pawn_attacks)).is_empty()
+                || !(weak_king_targets & pawn_bb & !strong_king_targets).is_empty())
+        {
+            // stalemate or the pawn can be taken
+            DRAW
+        } else {
+            UNKNOWN
+        }
+    }
+
+    /// The strong side wins if any move wins, the weak side draws if any move draws
+    fn classify(
+        results: &[u8],
+        stm: usize,
+        strong_king: usize,
+        weak_king: usize,
+        pawn: usize,
+    ) -> u8 {
+        let mut result = INVALID;
+        if stm == 0 {
+            let mut targets = KING_TARGETS[strong_king];
+            for to in targets.iter_mut() {
+                result |= results[Self::index(1, to.to_square().i(), weak_king, pawn)];
+            }
+            if pawn / 8 < 6 {
+                let push = pawn + 8;
+                result |= results[Self::index(1, strong_king, weak_king, push)];
+                if pawn / 8 == 1 && push != strong_king && push != weak_king {
+                    result |= results[Self::index(1, strong_king, weak_king, push + 8)];
+                }
+            }
+        } else {
+            let mut targets = KING_TARGETS[weak_king];
+            for to in targets.iter_mut() {
+                result |= results[Self::index(0, strong_king, to.to_square().i(), pawn)];
+            }
+        }
+
+        let (good, bad) = if stm == 0 { (WIN, DRAW) } else { (DRAW, WIN) };
+        if result & good != 0 {
+            good
+        } else if result & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            bad
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(fen: &str) -> Option<i32> {
+        match probe(&Board::new(fen)) {
+            Some(Endgame::Score(score)) => Some(score),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_kpk() {
+        // king in front of the pawn on the 6th rank wins no matter who moves
+        assert!(score("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap() > KNOWN_WIN);
+        assert!(score("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap() > KNOWN_WIN);
+        assert!(score("3k4/8/3K4/3P4/8/8/8/8 b - - 0 1").unwrap() > KNOWN_WIN);
+        // the same for black
+        assert!(score("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1").unwrap() < -KNOWN_WIN);
+        // rook pawn with the defending king in the corner
+        assert_eq!(score("k7/8/K7/P7/8/8/8/8 w - - 0 1"), Some(0));
+        // two squares in front of the pawn wins, one square only with the opposition
+        assert!(score("4k3/8/4K3/8/4P3/8/8/8 w - - 0 1").unwrap() > KNOWN_WIN);
+        assert!(score("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1").unwrap() > KNOWN_WIN);
+        assert_eq!(score("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), Some(0));
+        // the pawn gets taken
+        assert_eq!(score("8/8/8/8/8/1k6/P7/7K b - - 0 1"), Some(0));
+    }
+
+    #[test]
+    fn test_kbnk_drives_to_the_right_corner() {
+        // dark squared bishop mates in a1 and h8
+        let right = score("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+        let wrong = score("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1").unwrap();
+        assert!(right > wrong && wrong > KNOWN_WIN);
+        // the same for black with a light squared bishop (mates in a8 and h1)
+        let right = score("2b1n3/8/8/8/8/8/5k2/7K b - - 0 1").unwrap();
+        let wrong = score("2b1n3/8/8/8/8/8/8/K1k5 b - - 0 1").unwrap();
+        assert!(right < wrong && wrong < -KNOWN_WIN);
+    }
+
+    #[test]
+    fn test_kqkp() {
+        // supported bishop pawn on the 7th rank is drawish
+        let drawish = score("8/8/8/8/8/8/2pk4/K6Q w - - 0 1").unwrap();
+        let winning = score("8/8/8/8/8/8/3pk3/K6Q w - - 0 1").unwrap();
+        assert!(drawish < 200);
+        assert!(winning > 500);
+        assert!(score("k6q/3PK3/8/8/8/8/8/8 b - - 0 1").unwrap() < -500);
+    }
+
+    #[test]
+    fn test_krkp() {
+        // the strong king blocks the pawn
+        let blocked = score("8/8/8/8/8/2k5/3p4/3K3R w - - 0 1").unwrap();
+        // the pawn is about to promote with support
+        let drawish = score("8/8/8/8/K7/8/2kp4/7R w - - 0 1").unwrap();
+        assert!(blocked > 400);
+        assert!(drawish < blocked);
+    }
+
+    #[test]
+    fn test_scale_factors() {
+        // wrong colored bishop with a rook pawn
+        let board = Board::new("k7/8/8/8/P7/8/8/2B3K1 w - - 0 1");
+        assert_eq!(probe(&board), Some(Endgame::Scale([0, SCALE_NORMAL])));
+        assert_eq!(board.evaluate(), 0);
+        // the right bishop wins
+        assert_eq!(probe(&Board::new("k7/8/8/8/P7/8/8/1B4K1 w - - 0 1")), None);
+        // doubled rook pawns with the king in front
+        assert_eq!(board_eval("8/8/8/8/7p/7p/8/4k2K b - - 0 1"), 0);
+        assert_eq!(board_eval("8/k7/8/P7/P7/8/8/6K1 w - - 0 1"), 0);
+        assert_ne!(board_eval("8/7k/8/8/P7/P7/8/K7 w - - 0 1"), 0);
+        // opposite colored bishops
+        let Some(Endgame::Scale(factors)) =
+            probe(&Board::new("8/4kb2/8/3p4/3P1P2/4K3/3B4/8 w - - 0 1"))
+        else {
+            panic!("opposite colored bishops are drawish");
+        };
+        assert!(factors.iter().all(|&factor| factor < SCALE_NORMAL / 2));
+        assert_eq!(
+            probe(&Board::new("8/4k3/4b3/3p4/3P1P2/4K3/2B5/8 w - - 0 1")),
+            None
+        );
+    }
+
+    fn board_eval(fen: &str) -> i32 {
+        Board::new(fen).evaluate()
+    }
+
+    #[test]
+    fn test_material_key() {
+        assert_eq!(
+            material_key("KBNK", White),
+            Board::new("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1").material_key()
+        );
+        assert_eq!(
+            material_key("KRKP", Black),
+            Board::new("8/8/8/8/8/2k5/3P4/3K3r w - - 0 1").material_key()
+        );
+    }
+}
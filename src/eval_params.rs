@@ -0,0 +1,822 @@
+#[allow(clippy::wildcard_imports)]
+use crate::{
+    eval_trace::Tapered,
+    evaluation::{GAMEPHASE_INC, Psqt},
+    evaluation_constants::*,
+    material_hash_table::{MATERIAL_HASH_TABLE, MaterialEntry},
+    pawn_hash_table::{PAWN_HASH_TABLE, PawnEntry},
+    prelude::*,
+    settings,
+    utils::{json::Json, persistence::invalid_data},
+};
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Weights loaded via the `EvalParams` option
+static LOADED_PARAMS: Mutex<Option<Arc<LoadedParams>>> = Mutex::new(None);
+
+/// The weights the search should evaluate with, `None` means the compiled in `ConstantParams`
+pub fn loaded_params() -> Option<Arc<LoadedParams>> {
+    LOADED_PARAMS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Replaces the loaded weights, `None` goes back to the compiled in constants and so does an invalid file.
+/// Returns the terms the file didn't contain, they keep their compiled in value
+pub fn load_params(path: Option<&Path>) -> io::Result<Vec<&'static str>> {
+    let loaded =
+        path.map(|path| fs::read_to_string(path).and_then(|json| LoadedParams::from_json(&json)));
+    let (params, result) = match loaded {
+        Some(Ok((params, missing))) => (Some(Arc::new(params)), Ok(missing)),
+        Some(Err(err)) => (None, Err(err)),
+        None => (None, Ok(Vec::new())),
+    };
+    *LOADED_PARAMS.lock().unwrap_or_else(PoisonError::into_inner) = params;
+    result
+}
+
+/// Where the handcrafted evaluation takes its weights from.
+/// The engine uses the compiled in `ConstantParams`, the tuner its `TunableParams`,
+/// both run through the very same `Board::evaluate_with` so they can't diverge.
+///
+/// Pairs are `[mg, eg]` like in `evaluation_constants.rs`.
+pub trait EvalParams {
+    /// Indexed by `Piece`
+    fn piece_values(&self) -> [&[i32; 6]; 2];
+    /// Indexed by `Piece` and square from black's point of view (i.e. `[0]` is a8)
+    fn base_position_tables(&self) -> [&[[i32; 64]; 6]; 2];
+    fn rook_open_file_bonus(&self) -> [i32; 2];
+    fn king_open_file_penalty(&self) -> [i32; 2];
+    fn doubled_pawn_penalty(&self) -> i32;
+    fn isolated_pawn_penalty(&self) -> [i16; 2];
+    fn bishop_pair_bonus(&self) -> [i16; 2];
+    fn mobility_coefficients(&self) -> &[[i32; 6]; 2];
+    fn piece_attack_values(&self) -> &[[i16; 6]; 2];
+    fn piece_defend_values(&self) -> &[[i16; 6]; 2];
+    fn pawn_shield_bonus(&self) -> [i16; 2];
+    /// Danger per square a piece can check from without being taken, indexed by `Piece`
+    fn safe_check_values(&self) -> &[[i16; 6]; 2];
+    /// Danger per square of the king zone attacked by the opponent and defended by the king or queen only
+    fn king_zone_weak_square_value(&self) -> [i16; 2];
+    /// Danger by the number of pieces attacking the king zone
+    fn attacker_count_values(&self) -> &[[i16; 8]; 2];
+    /// Danger by the relative rank of the friendly pawn in front of the king on each of the three king files, 0 = no pawn
+    fn pawn_shelter_values(&self) -> &[[i16; 8]; 2];
+    /// Danger by the relative rank of the closest opponent pawn on each of the three king files, 0 = no pawn
+    fn pawn_storm_values(&self) -> &[[i16; 8]; 2];
+    fn initiative(&self) -> i32;
+    /// Indexed by the kind of threat: by a pawn, by a minor piece, hanging and by a safe pawn push
+    fn threat_values(&self) -> &[[i16; 4]; 2];
+    /// Indexed by backward, supported, phalanx and candidate passed pawns and pawn majorities
+    fn pawn_formation_values(&self) -> &[[i16; 5]; 2];
+    /// Indexed by blocked passers, the distance of the friendly and the opponent king and unstoppable passers
+    fn passer_values(&self) -> &[[i16; 4]; 2];
+    /// Indexed by knight and bishop outposts, rooks on the seventh, connected rooks, rooks on semi-open files,
+    /// blocked pawns on the color of a bishop and minor pieces left undeveloped by an early queen
+    fn piece_position_values(&self) -> &[[i16; 7]; 2];
+    /// Quadratic imbalance of the own and the opponent pieces, lower triangle of `[piece][other piece]`
+    /// in the order bishop pair, pawn, knight, bishop, rook and queen
+    fn imbalance_tables(&self) -> [&[[i16; 6]; 6]; 2];
+    /// Scale factors of material hard to win with: no pawns and less than a rook, a rook against a minor piece,
+    /// no pawns and at most a minor piece ahead, one pawn and at most a minor piece ahead
+    fn material_scale_factors(&self) -> [i16; 4];
+    /// Indexed by square from black's point of view
+    fn passed_pawn_tables(&self) -> [&[i16; 64]; 2];
+    /// Indexed by the danger bucket of `Board::king_danger`
+    fn king_safety_tables(&self) -> [&[i16; 100]; 2];
+
+    /// Material + position of `figure` on `square`
+    fn piece_square(&self, figure: Figure, square: Square) -> Tapered {
+        let (piece, color) = figure.piece_and_color();
+        let piece = piece as usize;
+        // position tables are from black's point of view, so flip the board for white
+        let square = match color {
+            White => square.i() ^ 0x38,
+            Black => square.i(),
+        };
+        let [mg_values, eg_values] = self.piece_values();
+        let [mg_table, eg_table] = self.base_position_tables();
+        Tapered::new(
+            mg_values[piece] + mg_table[piece][square],
+            eg_values[piece] + eg_table[piece][square],
+        )
+    }
+
+    /// Material + PSQT and game phase of `board`
+    fn psqt(&self, board: &Board) -> Psqt {
+        let mut psqt = Psqt::EMPTY;
+        for (i, phase_inc) in GAMEPHASE_INC.iter().enumerate() {
+            let figure = Figure::from_idx(i);
+            for bit in board.figure_bb_by_index(i).iter_mut() {
+                let value = self.piece_square(figure, bit.to_square());
+                let sign = if i & 1 == 0 { 1 } else { -1 };
+                psqt.mg += sign * value.mg;
+                psqt.eg += sign * value.eg;
+                psqt.phase += phase_inc;
+            }
+        }
+        psqt
+    }
+
+    /// The pawn terms of `board`
+    fn pawn_entry(&self, board: &Board) -> PawnEntry {
+        PawnEntry::new(board, self)
+    }
+
+    /// The material terms of `board`
+    fn material_entry(&self, board: &Board) -> MaterialEntry {
+        MaterialEntry::new(board, self)
+    }
+}
+
+/// The weights compiled into the engine, `evaluation_constants.rs`
+pub struct ConstantParams;
+
+impl EvalParams for ConstantParams {
+    fn piece_values(&self) -> [&[i32; 6]; 2] {
+        [&MG_PIECE_VALUES, &EG_PIECE_VALUES]
+    }
+
+    fn base_position_tables(&self) -> [&[[i32; 64]; 6]; 2] {
+        [&MG_BASE_POSITION_TABLE, &EG_BASE_POSITION_TABLE]
+    }
+
+    fn rook_open_file_bonus(&self) -> [i32; 2] {
+        ROOK_OPEN_FILE_BONUS
+    }
+
+    fn king_open_file_penalty(&self) -> [i32; 2] {
+        KING_OPEN_FILE_PENALTY
+    }
+
+    fn doubled_pawn_penalty(&self) -> i32 {
+        DOUBLED_PAWN_PENALTY
+    }
+
+    fn isolated_pawn_penalty(&self) -> [i16; 2] {
+        ISOLATED_PAWN_PENALTY
+    }
+
+    fn bishop_pair_bonus(&self) -> [i16; 2] {
+        BISHOP_PAIR_BONUS
+    }
+
+    fn mobility_coefficients(&self) -> &[[i32; 6]; 2] {
+        &MOBILITY_COEFFICIENTS
+    }
+
+    fn piece_attack_values(&self) -> &[[i16; 6]; 2] {
+        &PIECE_ATTACK_VALUES
+    }
+
+    fn piece_defend_values(&self) -> &[[i16; 6]; 2] {
+        &PIECE_DEFEND_VALUES
+    }
+
+    fn pawn_shield_bonus(&self) -> [i16; 2] {
+        PAWN_SHIELD_BONUS
+    }
+
+    fn safe_check_values(&self) -> &[[i16; 6]; 2] {
+        &SAFE_CHECK_VALUES
+    }
+
+    fn king_zone_weak_square_value(&self) -> [i16; 2] {
+        KING_ZONE_WEAK_SQUARE_VALUE
+    }
+
+    fn attacker_count_values(&self) -> &[[i16; 8]; 2] {
+        &ATTACKER_COUNT_VALUES
+    }
+
+    fn pawn_shelter_values(&self) -> &[[i16; 8]; 2] {
+        &PAWN_SHELTER_VALUES
+    }
+
+    fn pawn_storm_values(&self) -> &[[i16; 8]; 2] {
+        &PAWN_STORM_VALUES
+    }
+
+    fn initiative(&self) -> i32 {
+        INITIATIVE
+    }
+
+    fn threat_values(&self) -> &[[i16; 4]; 2] {
+        &THREAT_VALUES
+    }
+
+    fn pawn_formation_values(&self) -> &[[i16; 5]; 2] {
+        &PAWN_FORMATION_VALUES
+    }
+
+    fn passer_values(&self) -> &[[i16; 4]; 2] {
+        &PASSER_VALUES
+    }
+
+    fn piece_position_values(&self) -> &[[i16; 7]; 2] {
+        &PIECE_POSITION_VALUES
+    }
+
+    fn imbalance_tables(&self) -> [&[[i16; 6]; 6]; 2] {
+        [&IMBALANCE_OURS, &IMBALANCE_THEIRS]
+    }
+
+    fn material_scale_factors(&self) -> [i16; 4] {
+        MATERIAL_SCALE_FACTORS
+    }
+
+    fn passed_pawn_tables(&self) -> [&[i16; 64]; 2] {
+        [&MG_PASSED_PAWN_TABLE, &EG_PASSED_PAWN_TABLE]
+    }
+
+    fn king_safety_tables(&self) -> [&[i16; 100]; 2] {
+        [&MG_KING_SAFETY_TABLE, &EG_KING_SAFETY_TABLE]
+    }
+
+    fn piece_square(&self, figure: Figure, square: Square) -> Tapered {
+        Psqt::table_value(figure, square)
+    }
+
+    /// Kept up to date by `Board::toggle`
+    fn psqt(&self, board: &Board) -> Psqt {
+        let psqt = board.psqt();
+        debug_assert_eq!(
+            psqt,
+            Psqt::compute(board),
+            "incremental PSQT diverged in {}",
+            board.fen()
+        );
+        psqt
+    }
+
+    /// Only the constants can be cached, the pawn hash table doesn't know which weights an entry was computed with
+    fn pawn_entry(&self, board: &Board) -> PawnEntry {
+        if settings::PAWN_HASH {
+            PAWN_HASH_TABLE.with_borrow_mut(|table| table.probe(board))
+        } else {
+            PawnEntry::new(board, self)
+        }
+    }
+
+    /// Same as for the pawn hash table, only the constants are cached
+    fn material_entry(&self, board: &Board) -> MaterialEntry {
+        if settings::MATERIAL_HASH {
+            MATERIAL_HASH_TABLE.with_borrow_mut(|table| table.probe(board))
+        } else {
+            MaterialEntry::new(board, self)
+        }
+    }
+}
+
+/// Declares `LoadedParams` with the compiled in constant every term defaults to.
+/// The struct, its defaults and the keys of the weight files all come from this one list,
+/// a term the tuner writes can't be forgotten on the reading side
+macro_rules! loaded_params {
+    ($(#[$meta:meta])* pub struct $params:ident { $($term:ident: $type:ty = $default:expr,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub struct $params {
+            $($term: $type,)*
+        }
+
+        impl Default for $params {
+            fn default() -> Self {
+                Self {
+                    $($term: $default,)*
+                }
+            }
+        }
+
+        impl $params {
+            /// Keys of all terms in the weight files
+            pub const TERMS: &[&str] = &[$(stringify!($term),)*];
+
+            fn read_term(&mut self, key: &str, json: &Json) -> io::Result<()> {
+                match key {
+                    $(stringify!($term) => read_weights(json, &mut self.$term),)*
+                    _ => Err(invalid_data(format!("unknown term {key}"))),
+                }
+            }
+
+            /// All weights of the term `key` in file order, `None` for unknown keys
+            pub fn term(&self, key: &str) -> Option<Vec<i64>> {
+                match key {
+                    $(stringify!($term) => Some(
+                        self.$term.flat().iter().map(|&weight| i64::from(weight)).collect(),
+                    ),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+loaded_params! {
+    /// Weights read at runtime from a JSON file of the tuner (`TunableParams::write_to_file`),
+    /// allows comparing weight sets without rebuilding.
+    /// Nothing is incremental or cached for these, so evaluating with them is a bit slower than with `ConstantParams`
+    pub struct LoadedParams {
+        mg_piece_values: [i32; 6] = MG_PIECE_VALUES,
+        eg_piece_values: [i32; 6] = EG_PIECE_VALUES,
+        rook_open_file_bonus: [i32; 2] = ROOK_OPEN_FILE_BONUS,
+        king_open_file_penalty: [i32; 2] = KING_OPEN_FILE_PENALTY,
+        doubled_pawn_penalty: i32 = DOUBLED_PAWN_PENALTY,
+        isolated_pawn_penalty: [i16; 2] = ISOLATED_PAWN_PENALTY,
+        bishop_pair_bonus: [i16; 2] = BISHOP_PAIR_BONUS,
+        mobility_coefficients: [[i32; 6]; 2] = MOBILITY_COEFFICIENTS,
+        piece_attack_values: [[i16; 6]; 2] = PIECE_ATTACK_VALUES,
+        piece_defend_values: [[i16; 6]; 2] = PIECE_DEFEND_VALUES,
+        pawn_shield_bonus: [i16; 2] = PAWN_SHIELD_BONUS,
+        safe_check_values: [[i16; 6]; 2] = SAFE_CHECK_VALUES,
+        king_zone_weak_square_value: [i16; 2] = KING_ZONE_WEAK_SQUARE_VALUE,
+        attacker_count_values: [[i16; 8]; 2] = ATTACKER_COUNT_VALUES,
+        pawn_shelter_values: [[i16; 8]; 2] = PAWN_SHELTER_VALUES,
+        pawn_storm_values: [[i16; 8]; 2] = PAWN_STORM_VALUES,
+        initiative: i32 = INITIATIVE,
+        threat_values: [[i16; 4]; 2] = THREAT_VALUES,
+        pawn_formation_values: [[i16; 5]; 2] = PAWN_FORMATION_VALUES,
+        passer_values: [[i16; 4]; 2] = PASSER_VALUES,
+        piece_position_values: [[i16; 7]; 2] = PIECE_POSITION_VALUES,
+        imbalance_ours: [[i16; 6]; 6] = IMBALANCE_OURS,
+        imbalance_theirs: [[i16; 6]; 6] = IMBALANCE_THEIRS,
+        material_scale_factors: [i16; 4] = MATERIAL_SCALE_FACTORS,
+        mg_passed_pawn_table: [i16; 64] = MG_PASSED_PAWN_TABLE,
+        eg_passed_pawn_table: [i16; 64] = EG_PASSED_PAWN_TABLE,
+        mg_king_safety_table: [i16; 100] = MG_KING_SAFETY_TABLE,
+        eg_king_safety_table: [i16; 100] = EG_KING_SAFETY_TABLE,
+        mg_base_position_table: [[i32; 64]; 6] = MG_BASE_POSITION_TABLE,
+        eg_base_position_table: [[i32; 64]; 6] = EG_BASE_POSITION_TABLE,
+    }
+}
+
+impl LoadedParams {
+    /// Terms missing in `json` keep their compiled in value and are returned, any term with the wrong
+    /// dimensions or values out of range rejects the whole file
+    pub fn from_json(json: &str) -> io::Result<(Self, Vec<&'static str>)> {
+        let json = Json::parse(json)?;
+        if !matches!(json, Json::Object(_)) {
+            return Err(invalid_data("expected an object of weights".to_string()));
+        }
+
+        let mut params = Self::default();
+        let mut missing = Vec::new();
+        for &key in Self::TERMS {
+            match json.get(key) {
+                Some(value) => params
+                    .read_term(key, value)
+                    .map_err(|err| invalid_data(format!("{key}: {err}")))?,
+                None => missing.push(key),
+            }
+        }
+
+        Ok((params, missing))
+    }
+}
+
+/// A term of `LoadedParams`: a single weight or (nested) arrays of them
+trait Weights {
+    type Weight: Copy + Into<i64> + TryFrom<i64>;
+
+    /// Dimensions of the nested arrays in the weight files, `[]` for a plain number
+    fn shape() -> Vec<usize>;
+    fn flat(&self) -> &[Self::Weight];
+    fn flat_mut(&mut self) -> &mut [Self::Weight];
+}
+
+macro_rules! impl_weights {
+    ($($weight:ty),*) => {$(
+        impl Weights for $weight {
+            type Weight = Self;
+
+            fn shape() -> Vec<usize> {
+                Vec::new()
+            }
+            fn flat(&self) -> &[Self] {
+                std::slice::from_ref(self)
+            }
+            fn flat_mut(&mut self) -> &mut [Self] {
+                std::slice::from_mut(self)
+            }
+        }
+
+        impl<const N: usize> Weights for [$weight; N] {
+            type Weight = $weight;
+
+            fn shape() -> Vec<usize> {
+                vec![N]
+            }
+            fn flat(&self) -> &[$weight] {
+                self
+            }
+            fn flat_mut(&mut self) -> &mut [$weight] {
+                self
+            }
+        }
+
+        impl<const N: usize, const M: usize> Weights for [[$weight; N]; M] {
+            type Weight = $weight;
+
+            fn shape() -> Vec<usize> {
+                vec![M, N]
+            }
+            fn flat(&self) -> &[$weight] {
+                self.as_flattened()
+            }
+            fn flat_mut(&mut self) -> &mut [$weight] {
+                self.as_flattened_mut()
+            }
+        }
+    )*};
+}
+
+impl_weights!(i16, i32);
+
+/// Fills `weights` from `json`, which has to be nested arrays of exactly their shape (a plain number for a single weight)
+/// holding integers which fit into the weight type
+fn read_weights<W: Weights>(json: &Json, weights: &mut W) -> io::Result<()> {
+    fn flatten(json: &Json, shape: &[usize], values: &mut Vec<f64>) -> io::Result<()> {
+        match (json, shape) {
+            (Json::Number(value), []) => {
+                values.push(*value);
+                Ok(())
+            }
+            (Json::Array(elements), [len, inner @ ..]) if elements.len() == *len => elements
+                .iter()
+                .try_for_each(|element| flatten(element, inner, values)),
+            _ => Err(invalid_data(format!("expected dimensions {shape:?}"))),
+        }
+    }
+
+    let weights = weights.flat_mut();
+    let mut values = Vec::with_capacity(weights.len());
+    flatten(json, &W::shape(), &mut values)?;
+    debug_assert_eq!(values.len(), weights.len());
+
+    for (weight, value) in weights.iter_mut().zip(values) {
+        #[allow(clippy::cast_possible_truncation)]
+        let integer = value as i64;
+        #[allow(clippy::cast_precision_loss, clippy::float_cmp)]
+        let is_integer = integer as f64 == value;
+        *weight = is_integer
+            .then_some(integer)
+            .and_then(|integer| W::Weight::try_from(integer).ok())
+            .ok_or_else(|| invalid_data(format!("{value} is not a valid weight")))?;
+    }
+    Ok(())
+}
+
+impl EvalParams for LoadedParams {
+    fn piece_values(&self) -> [&[i32; 6]; 2] {
+        [&self.mg_piece_values, &self.eg_piece_values]
+    }
+
+    fn base_position_tables(&self) -> [&[[i32; 64]; 6]; 2] {
+        [&self.mg_base_position_table, &self.eg_base_position_table]
+    }
+
+    fn rook_open_file_bonus(&self) -> [i32; 2] {
+        self.rook_open_file_bonus
+    }
+
+    fn king_open_file_penalty(&self) -> [i32; 2] {
+        self.king_open_file_penalty
+    }
+
+    fn doubled_pawn_penalty(&self) -> i32 {
+        self.doubled_pawn_penalty
+    }
+
+    fn isolated_pawn_penalty(&self) -> [i16; 2] {
+        self.isolated_pawn_penalty
+    }
+
+    fn bishop_pair_bonus(&self) -> [i16; 2] {
+        self.bishop_pair_bonus
+    }
+
+    fn mobility_coefficients(&self) -> &[[i32; 6]; 2] {
+        &self.mobility_coefficients
+    }
+
+    fn piece_attack_values(&self) -> &[[i16; 6]; 2] {
+        &self.piece_attack_values
+    }
+
+    fn piece_defend_values(&self) -> &[[i16; 6]; 2] {
+        &self.piece_defend_values
+    }
+
+    fn pawn_shield_bonus(&self) -> [i16; 2] {
+        self.pawn_shield_bonus
+    }
+
+    fn safe_check_values(&self) -> &[[i16; 6]; 2] {
+        &self.safe_check_values
+    }
+
+    fn king_zone_weak_square_value(&self) -> [i16; 2] {
+        self.king_zone_weak_square_value
+    }
+
+    fn attacker_count_values(&self) -> &[[i16; 8]; 2] {
+        &self.attacker_count_values
+    }
+
+    fn pawn_shelter_values(&self) -> &[[i16; 8]; 2] {
+        &self.pawn_shelter_values
+    }
+
+    fn pawn_storm_values(&self) -> &[[i16; 8]; 2] {
+        &self.pawn_storm_values
+    }
+
+    fn initiative(&self) -> i32 {
+        self.initiative
+    }
+
+    fn threat_values(&self) -> &[[i16; 4]; 2] {
+        &self.threat_values
+    }
+
+    fn pawn_formation_values(&self) -> &[[i16; 5]; 2] {
+        &self.pawn_formation_values
+    }
+
+    fn passer_values(&self) -> &[[i16; 4]; 2] {
+        &self.passer_values
+    }
+
+    fn piece_position_values(&self) -> &[[i16; 7]; 2] {
+        &self.piece_position_values
+    }
+
+    fn imbalance_tables(&self) -> [&[[i16; 6]; 6]; 2] {
+        [&self.imbalance_ours, &self.imbalance_theirs]
+    }
+
+    fn material_scale_factors(&self) -> [i16; 4] {
+        self.material_scale_factors
+    }
+
+    fn passed_pawn_tables(&self) -> [&[i16; 64]; 2] {
+        [&self.mg_passed_pawn_table, &self.eg_passed_pawn_table]
+    }
+
+    fn king_safety_tables(&self) -> [&[i16; 100]; 2] {
+        [&self.mg_king_safety_table, &self.eg_king_safety_table]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The constants without the incremental and cached shortcuts of `ConstantParams`
+    struct PlainParams;
+
+    impl EvalParams for PlainParams {
+        fn piece_values(&self) -> [&[i32; 6]; 2] {
+            ConstantParams.piece_values()
+        }
+        fn base_position_tables(&self) -> [&[[i32; 64]; 6]; 2] {
+            ConstantParams.base_position_tables()
+        }
+        fn rook_open_file_bonus(&self) -> [i32; 2] {
+            ConstantParams.rook_open_file_bonus()
+        }
+        fn king_open_file_penalty(&self) -> [i32; 2] {
+            ConstantParams.king_open_file_penalty()
+        }
+        fn doubled_pawn_penalty(&self) -> i32 {
+            ConstantParams.doubled_pawn_penalty()
+        }
+        fn isolated_pawn_penalty(&self) -> [i16; 2] {
+            ConstantParams.isolated_pawn_penalty()
+        }
+        fn bishop_pair_bonus(&self) -> [i16; 2] {
+            ConstantParams.bishop_pair_bonus()
+        }
+        fn mobility_coefficients(&self) -> &[[i32; 6]; 2] {
+            ConstantParams.mobility_coefficients()
+        }
+        fn piece_attack_values(&self) -> &[[i16; 6]; 2] {
+            ConstantParams.piece_attack_values()
+        }
+        fn piece_defend_values(&self) -> &[[i16; 6]; 2] {
+            ConstantParams.piece_defend_values()
+        }
+        fn pawn_shield_bonus(&self) -> [i16; 2] {
+            ConstantParams.pawn_shield_bonus()
+        }
+        fn safe_check_values(&self) -> &[[i16; 6]; 2] {
+            ConstantParams.safe_check_values()
+        }
+        fn king_zone_weak_square_value(&self) -> [i16; 2] {
+            ConstantParams.king_zone_weak_square_value()
+        }
+        fn attacker_count_values(&self) -> &[[i16; 8]; 2] {
+            ConstantParams.attacker_count_values()
+        }
+        fn pawn_shelter_values(&self) -> &[[i16; 8]; 2] {
+            ConstantParams.pawn_shelter_values()
+        }
+        fn pawn_storm_values(&self) -> &[[i16; 8]; 2] {
+            ConstantParams.pawn_storm_values()
+        }
+        fn initiative(&self) -> i32 {
+            ConstantParams.initiative()
+        }
+        fn threat_values(&self) -> &[[i16; 4]; 2] {
+            ConstantParams.threat_values()
+        }
+        fn pawn_formation_values(&self) -> &[[i16; 5]; 2] {
+            ConstantParams.pawn_formation_values()
+        }
+        fn passer_values(&self) -> &[[i16; 4]; 2] {
+            ConstantParams.passer_values()
+        }
+        fn piece_position_values(&self) -> &[[i16; 7]; 2] {
+            ConstantParams.piece_position_values()
+        }
+        fn imbalance_tables(&self) -> [&[[i16; 6]; 6]; 2] {
+            ConstantParams.imbalance_tables()
+        }
+        fn material_scale_factors(&self) -> [i16; 4] {
+            ConstantParams.material_scale_factors()
+        }
+        fn passed_pawn_tables(&self) -> [&[i16; 64]; 2] {
+            ConstantParams.passed_pawn_tables()
+        }
+        fn king_safety_tables(&self) -> [&[i16; 100]; 2] {
+            ConstantParams.king_safety_tables()
+        }
+    }
+
+    #[test]
+    fn test_shortcuts_match_plain_computation() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "2B5/kpp2rqr/pbbp4/8/8/B7/RP3PPP/QRNn2K1 w - - 0 1",
+            "r1b1k2r/1pqp1ppp/p2bpnn1/6Q1/2BNP3/2N1B3/PPP2PPP/2KR3R b kq - 8 12",
+        ] {
+            let board = Board::new(fen);
+            assert_eq!(PlainParams.psqt(&board), ConstantParams.psqt(&board));
+            assert_eq!(
+                PlainParams.pawn_entry(&board),
+                ConstantParams.pawn_entry(&board)
+            );
+            assert_eq!(
+                PlainParams.material_entry(&board),
+                ConstantParams.material_entry(&board)
+            );
+            assert_eq!(
+                board.evaluate_with(&PlainParams),
+                board.evaluate_with(&ConstantParams),
+                "{fen}"
+            );
+        }
+    }
+
+    /// The compiled in weights the way the tuner writes them
+    fn constants_json() -> String {
+        let terms = [
+            format!("\"mg_piece_values\":{MG_PIECE_VALUES:?}"),
+            format!("\"eg_piece_values\":{EG_PIECE_VALUES:?}"),
+            format!("\"rook_open_file_bonus\":{ROOK_OPEN_FILE_BONUS:?}"),
+            format!("\"king_open_file_penalty\":{KING_OPEN_FILE_PENALTY:?}"),
+            format!("\"doubled_pawn_penalty\":{DOUBLED_PAWN_PENALTY:?}"),
+            format!("\"isolated_pawn_penalty\":{ISOLATED_PAWN_PENALTY:?}"),
+            format!("\"bishop_pair_bonus\":{BISHOP_PAIR_BONUS:?}"),
+            format!("\"mobility_coefficients\":{MOBILITY_COEFFICIENTS:?}"),
+            format!("\"piece_attack_values\":{PIECE_ATTACK_VALUES:?}"),
+            format!("\"piece_defend_values\":{PIECE_DEFEND_VALUES:?}"),
+            format!("\"pawn_shield_bonus\":{PAWN_SHIELD_BONUS:?}"),
+            format!("\"safe_check_values\":{SAFE_CHECK_VALUES:?}"),
+            format!("\"king_zone_weak_square_value\":{KING_ZONE_WEAK_SQUARE_VALUE:?}"),
+            format!("\"attacker_count_values\":{ATTACKER_COUNT_VALUES:?}"),
+            format!("\"pawn_shelter_values\":{PAWN_SHELTER_VALUES:?}"),
+            format!("\"pawn_storm_values\":{PAWN_STORM_VALUES:?}"),
+            format!("\"initiative\":{INITIATIVE:?}"),
+            format!("\"threat_values\":{THREAT_VALUES:?}"),
+            format!("\"pawn_formation_values\":{PAWN_FORMATION_VALUES:?}"),
+            format!("\"passer_values\":{PASSER_VALUES:?}"),
+            format!("\"piece_position_values\":{PIECE_POSITION_VALUES:?}"),
+            format!("\"imbalance_ours\":{IMBALANCE_OURS:?}"),
+            format!("\"imbalance_theirs\":{IMBALANCE_THEIRS:?}"),
+            format!("\"material_scale_factors\":{MATERIAL_SCALE_FACTORS:?}"),
+            format!("\"mg_passed_pawn_table\":{MG_PASSED_PAWN_TABLE:?}"),
+            format!("\"eg_passed_pawn_table\":{EG_PASSED_PAWN_TABLE:?}"),
+            format!("\"mg_king_safety_table\":{MG_KING_SAFETY_TABLE:?}"),
+            format!("\"eg_king_safety_table\":{EG_KING_SAFETY_TABLE:?}"),
+            format!("\"mg_base_position_table\":{MG_BASE_POSITION_TABLE:?}"),
+            format!("\"eg_base_position_table\":{EG_BASE_POSITION_TABLE:?}"),
+        ];
+        format!("{{{}}}", terms.join(","))
+    }
+
+    #[test]
+    fn test_load_constants() {
+        let (params, missing) = LoadedParams::from_json(&constants_json()).unwrap();
+        assert!(missing.is_empty());
+        assert_eq!(params, LoadedParams::default());
+
+        let mut board =
+            Board::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let expected = board.evaluate();
+        board.set_eval_params(Some(Arc::new(params)));
+        assert_eq!(board.evaluate(), expected);
+        assert_eq!(board.trace().score(), expected);
+    }
+
+    #[test]
+    // danger coefficients are whole numbers, so their sums are exact
+    #[allow(clippy::float_cmp)]
+    fn test_trace_coefficients_match_terms() {
+        fn flatten(json: &Json, values: &mut Vec<f64>) {
+            match json {
+                Json::Number(value) => values.push(*value),
+                Json::Array(elements) => elements.iter().for_each(|e| flatten(e, values)),
+                _ => panic!("unexpected weight {json:?}"),
+            }
+        }
+        let Json::Object(weights) = Json::parse(&constants_json()).unwrap() else {
+            panic!("weights are no object");
+        };
+        let weight = |name: &str, index: usize| {
+            let (_, json) = weights.iter().find(|(key, _)| key == name).unwrap();
+            let mut values = Vec::new();
+            flatten(json, &mut values);
+            values[index]
+        };
+        let sum = |coefficients: &[crate::eval_trace::Coefficient]| {
+            coefficients.iter().fold((0.0, 0.0), |(mg, eg), c| {
+                let weight = weight(c.name, c.index);
+                (c.mg.mul_add(weight, mg), c.eg.mul_add(weight, eg))
+            })
+        };
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "2B5/kpp2rqr/pbbp4/8/8/B7/RP3PPP/QRNn2K1 w - - 0 1",
+            "r1b1k2r/1pqp1ppp/p2bpnn1/6Q1/2BNP3/2N1B3/PPP2PPP/2KR3R b kq - 8 12",
+        ] {
+            let trace = Board::new(fen).trace();
+            let (mg, eg) = sum(&trace.coefficients);
+            let expected = trace.total() - trace.king_safety[0] + trace.king_safety[1];
+            // the imbalance is rounded once per side
+            assert!((mg - f64::from(expected.mg)).abs() <= 2.0, "{fen}: {mg}");
+            assert!((eg - f64::from(expected.eg)).abs() <= 2.0, "{fen}: {eg}");
+
+            for side in 0..2 {
+                let (mg, eg) = sum(&trace.danger_coefficients[side]);
+                let danger = trace.king_danger[side];
+                assert_eq!(mg.clamp(0.0, 99.0), f64::from(danger.mg), "{fen}");
+                assert_eq!(eg.clamp(0.0, 99.0), f64::from(danger.eg), "{fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_load_partial() {
+        let (mut params, missing) = LoadedParams::from_json(
+            r#"{"mg_piece_values": [100, 300, 300, 500, 900, 0], "unknown": 1}"#,
+        )
+        .unwrap();
+        assert_eq!(params.mg_piece_values, [100, 300, 300, 500, 900, 0]);
+        assert_eq!(params.eg_piece_values, EG_PIECE_VALUES);
+        assert_eq!(missing.len(), 29);
+        assert!(!missing.contains(&"mg_piece_values"));
+
+        // white is a rook up, more valuable rooks have to show
+        let mut board = Board::new("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        board.set_eval_params(Some(Arc::new(params.clone())));
+        let before = board.evaluate();
+        params.mg_piece_values[Rook as usize] += 100;
+        params.eg_piece_values[Rook as usize] += 100;
+        board.set_eval_params(Some(Arc::new(params)));
+        assert_eq!(board.evaluate(), before + 100);
+    }
+
+    #[test]
+    fn test_reject_invalid_weights() {
+        for json in [
+            "[]",
+            r#"{"mg_piece_values": [1, 2, 3]}"#,
+            r#"{"mg_piece_values": [1, 2, 3, 4, 5, 6.5]}"#,
+            r#"{"initiative": [15]}"#,
+            r#"{"isolated_pawn_penalty": [40000, 0]}"#,
+            r#"{"mobility_coefficients": [0, 5, 3, 2, 1, 0, 0, 5, 3, 4, 1, 0]}"#,
+        ] {
+            assert!(LoadedParams::from_json(json).is_err(), "{json}");
+        }
+    }
+}
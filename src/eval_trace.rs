@@ -0,0 +1,389 @@
+use crate::{endgame::Endgame, prelude::*};
+use std::ops::{Add, AddAssign, Sub};
+
+/// Middlegame and endgame value of an evaluation term
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tapered {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Tapered {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Blends both values by `gamephase` (0 = opening, 256 = endgame) the same way `Board::evaluate` does
+    pub const fn taper(self, gamephase: i32) -> i32 {
+        (self.mg * (256 - gamephase) + self.eg * gamephase) >> 8
+    }
+}
+
+impl Add for Tapered {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl AddAssign for Tapered {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Tapered {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+/// How much a single weight of `EvalParams` adds to the score of white: `mg` and `eg` times the weight go into
+/// the middlegame and the endgame score. The weight is addressed like in the weights files of `LoadedParams`,
+/// `name` is the key and `index` the index into the flattened array.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Coefficient {
+    pub name: &'static str,
+    pub index: usize,
+    pub mg: f64,
+    pub eg: f64,
+}
+
+/// Every term of the handcrafted evaluation, filled by `Board::trace`.
+/// All terms are `[white, black]` from the perspective of the side they belong to, i.e. penalties are negative.
+/// `score` sums them up to exactly what `Board::evaluate` returns.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EvalTrace {
+    pub material: [Tapered; 2],
+    /// Quadratic in the piece counts and not tapered, so middlegame and endgame value are the same
+    pub imbalance: [Tapered; 2],
+    pub psqt: [Tapered; 2],
+    /// Indexed by `Piece`
+    pub mobility: [[Tapered; 6]; 2],
+    pub rook_open_file: [Tapered; 2],
+    pub king_open_file: [Tapered; 2],
+    /// Passed, isolated, backward, connected and candidate passed pawns as well as pawn majorities
+    pub pawn_structure: [Tapered; 2],
+    /// Blocked passed pawns, king distances to them and unstoppable ones
+    pub passers: [Tapered; 2],
+    /// Doubled pawns aren't tapered, so middlegame and endgame value are the same
+    pub doubled_pawns: [Tapered; 2],
+    pub bishop_pair: [Tapered; 2],
+    pub king_safety: [Tapered; 2],
+    /// Danger bucket (index into the king safety tables) of each king, not a term on its own
+    pub king_danger: [Tapered; 2],
+    pub initiative: [Tapered; 2],
+    /// Indexed like `EvalParams::piece_position_values`
+    pub piece_positions: [[Tapered; 7]; 2],
+    /// Pieces of the opponent attacked by pawns or minor pieces, hanging or attacked by a safe pawn push
+    pub threats: [Tapered; 2],
+    /// 0 = opening, 256 = endgame
+    pub gamephase: i32,
+    /// Replaces or scales the sum of all terms, includes the scaling of drawish material
+    pub endgame: Option<Endgame>,
+    /// Index into `EvalParams::material_scale_factors` of each side if its factor in `endgame` comes from there
+    pub material_scale: [Option<usize>; 2],
+    pub side_to_move: Color,
+    /// The weights of every term except king safety times how often they apply, summing up all of them
+    /// times their weights gives the unscaled score (up to rounding). This is what the tuner computes gradients from
+    pub coefficients: Vec<Coefficient>,
+    /// The same for the danger of each king before it is clamped to a bucket of the king safety tables,
+    /// `mg` adds to the middlegame and `eg` to the endgame danger
+    pub danger_coefficients: [Vec<Coefficient>; 2],
+}
+
+impl EvalTrace {
+    /// Records that `side` gets `mg` and `eg` times the weight `name[index]`
+    pub(crate) fn record(
+        &mut self,
+        name: &'static str,
+        index: usize,
+        side: usize,
+        mg: f64,
+        eg: f64,
+    ) {
+        let sign = if side == 0 { 1.0 } else { -1.0 };
+        self.coefficients.push(Coefficient {
+            name,
+            index,
+            mg: sign * mg,
+            eg: sign * eg,
+        });
+    }
+
+    /// Records `count` times a tapered weight of `name`, which is `[[mg; len], [eg; len]]` flattened
+    pub(crate) fn record_tapered(
+        &mut self,
+        name: &'static str,
+        len: usize,
+        index: usize,
+        side: usize,
+        count: impl Into<f64>,
+    ) {
+        let count = count.into();
+        self.record(name, index, side, count, 0.0);
+        self.record(name, len + index, side, 0.0, count);
+    }
+
+    /// Records that the king of `side` gets `count` times the tapered danger weight `index` of `name`
+    pub(crate) fn record_danger(
+        &mut self,
+        name: &'static str,
+        len: usize,
+        index: usize,
+        side: usize,
+        count: impl Into<f64>,
+    ) {
+        let count = count.into();
+        let danger = &mut self.danger_coefficients[side];
+        danger.push(Coefficient {
+            name,
+            index,
+            mg: count,
+            eg: 0.0,
+        });
+        danger.push(Coefficient {
+            name,
+            index: len + index,
+            mg: 0.0,
+            eg: count,
+        });
+    }
+
+    /// Name and value of every term which contributes to the score
+    pub fn terms(&self) -> Vec<(&'static str, [Tapered; 2])> {
+        let mobility = |piece: Piece| {
+            let idx = piece as usize;
+            [self.mobility[0][idx], self.mobility[1][idx]]
+        };
+        let piece_position =
+            |kind: usize| [self.piece_positions[0][kind], self.piece_positions[1][kind]];
+
+        vec![
+            ("Material", self.material),
+            ("Imbalance", self.imbalance),
+            ("PSQT", self.psqt),
+            ("Mobility Pawn", mobility(Pawn)),
+            ("Mobility Knight", mobility(Knight)),
+            ("Mobility Bishop", mobility(Bishop)),
+            ("Mobility Rook", mobility(Rook)),
+            ("Mobility Queen", mobility(Queen)),
+            ("Mobility King", mobility(King)),
+            ("Rook Open File", self.rook_open_file),
+            ("King Open File", self.king_open_file),
+            ("Pawn Structure", self.pawn_structure),
+            ("Doubled Pawns", self.doubled_pawns),
+            ("Passed Pawns", self.passers),
+            ("Bishop Pair", self.bishop_pair),
+            ("Knight Outpost", piece_position(0)),
+            ("Bishop Outpost", piece_position(1)),
+            ("Rook On Seventh", piece_position(2)),
+            ("Connected Rooks", piece_position(3)),
+            ("Rook Semi-Open File", piece_position(4)),
+            ("Bad Bishop", piece_position(5)),
+            ("Early Queen", piece_position(6)),
+            ("King Safety", self.king_safety),
+            ("Threats", self.threats),
+            ("Initiative", self.initiative),
+        ]
+    }
+
+    /// Sum of all terms, white - black
+    pub fn total(&self) -> Tapered {
+        self.terms()
+            .iter()
+            .fold(Tapered::default(), |total, (_, [white, black])| {
+                total + *white - *black
+            })
+    }
+
+    /// Relative to the side to move like `Board::evaluate`.
+    /// Doubled pawns are a multiple of 256 before the shift of the taper, so folding them in doesn't change the rounding
+    pub fn score(&self) -> i32 {
+        let score = self.total().taper(self.gamephase);
+        let score = self.endgame.map_or(score, |endgame| endgame.apply(score));
+        match self.side_to_move {
+            White => score,
+            Black => -score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endgame::SCALE_NORMAL;
+    use crate::evaluation_constants::{
+        EG_PASSED_PAWN_TABLE, ISOLATED_PAWN_PENALTY, MG_PASSED_PAWN_TABLE, PASSER_VALUES,
+        PAWN_FORMATION_VALUES, PIECE_POSITION_VALUES, THREAT_VALUES,
+    };
+
+    const POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "2B5/kpp2rqr/pbbp4/8/8/B7/RP3PPP/QRNn2K1 w - - 0 1",
+        "r1b1k2r/1pqp1ppp/p2bpnn1/6Q1/2BNP3/2N1B3/PPP2PPP/2KR3R b kq - 8 12",
+        "8/k7/3p4/p2P1p2/P2P1P2/8/8/K7 b - - 0 1",
+    ];
+
+    #[test]
+    fn test_trace_sums_to_evaluation() {
+        for fen in POSITIONS {
+            let board = Board::new(fen);
+            assert_eq!(board.trace().score(), board.evaluate(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_symmetric_start_position() {
+        let trace = Board::new(POSITIONS[0]).trace();
+        for (name, [white, black]) in trace.terms() {
+            if name != "Initiative" {
+                assert_eq!(white, black, "{name}");
+            }
+        }
+        assert_eq!(trace.gamephase, 0);
+        // 8 pawns, 2 knights, 2 bishops, 2 rooks and a queen
+        assert!(trace.material[0].mg > 3000);
+    }
+
+    #[test]
+    fn test_threats() {
+        let threat = |kind: usize| {
+            Tapered::new(
+                i32::from(THREAT_VALUES[0][kind]),
+                i32::from(THREAT_VALUES[1][kind]),
+            )
+        };
+
+        // the knight is attacked by the pawn and nothing defends it
+        let trace = Board::new("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1").trace();
+        assert_eq!(trace.threats, [threat(0) + threat(2), Tapered::default()]);
+
+        // pushing the pawn attacks the knight, which in turn attacks the undefended pawn
+        let trace = Board::new("4k3/8/8/3n4/8/4P3/8/4K3 w - - 0 1").trace();
+        assert_eq!(trace.threats, [threat(3), threat(2)]);
+    }
+
+    #[test]
+    fn test_pawn_formation() {
+        let formation = |kind: usize| {
+            Tapered::new(
+                i32::from(PAWN_FORMATION_VALUES[0][kind]),
+                i32::from(PAWN_FORMATION_VALUES[1][kind]),
+            )
+        };
+
+        // d3 is backward since e5 controls d4, c4 is a passer supported by d3.
+        // White has the majority on the queen side, black on the king side with its isolated pawn
+        let trace = Board::new("4k3/8/8/4p3/2P5/3P4/8/4K3 w - - 0 1").trace();
+        // c4 seen from black's point of view is c5
+        let passed = Tapered::new(
+            i32::from(MG_PASSED_PAWN_TABLE[34]),
+            i32::from(EG_PASSED_PAWN_TABLE[34]),
+        );
+        let isolated = Tapered::new(
+            i32::from(ISOLATED_PAWN_PENALTY[0]),
+            i32::from(ISOLATED_PAWN_PENALTY[1]),
+        );
+        assert_eq!(
+            trace.pawn_structure,
+            [
+                passed + formation(0) + formation(1) + formation(4),
+                isolated + formation(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_passers() {
+        let passer = |counts: [i32; 4]| {
+            let mut value = Tapered::default();
+            for (kind, count) in counts.into_iter().enumerate() {
+                value += Tapered::new(
+                    count * i32::from(PASSER_VALUES[0][kind]),
+                    count * i32::from(PASSER_VALUES[1][kind]),
+                );
+            }
+            value
+        };
+
+        // the black king is outside the square of the pawn
+        let trace = Board::new("7k/8/8/P7/8/8/8/4K3 w - - 0 1").trace();
+        assert_eq!(trace.passers, [passer([0, 5, 7, 1]), Tapered::default()]);
+
+        // a blockade by the knight, which also means the pawn can't simply run anymore
+        let trace = Board::new("7k/8/n7/P7/8/8/8/4K3 w - - 0 1").trace();
+        assert_eq!(trace.passers, [passer([1, 5, 7, 0]), Tapered::default()]);
+    }
+
+    #[test]
+    fn test_piece_positions() {
+        let piece_positions = |counts: [i32; 7]| {
+            std::array::from_fn(|kind| {
+                Tapered::new(
+                    counts[kind] * i32::from(PIECE_POSITION_VALUES[0][kind]),
+                    counts[kind] * i32::from(PIECE_POSITION_VALUES[1][kind]),
+                )
+            })
+        };
+
+        // knight outpost on d5, a rook on the seventh and one on the semi-open h file
+        let trace = Board::new("4k3/pR6/7p/3N4/4P3/8/8/4K2R w - - 0 1").trace();
+        assert_eq!(
+            trace.piece_positions,
+            [
+                piece_positions([1, 0, 1, 0, 1, 0, 0]),
+                piece_positions([0; 7])
+            ]
+        );
+
+        // the early queen leaves every minor piece at home, e4 and e5 block a bishop each
+        let trace =
+            Board::new("rnbqkbnr/pppp1ppp/8/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2").trace();
+        assert_eq!(
+            trace.piece_positions,
+            [
+                piece_positions([0, 0, 0, 0, 0, 1, 4]),
+                piece_positions([0, 0, 0, 0, 0, 1, 0])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_king_danger() {
+        let danger = |fen: &str| Board::new(fen).trace().king_danger[0].mg;
+
+        // the pawn shelter keeps the king safe, advanced opponent pawns storm it
+        let sheltered = danger("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let exposed = danger("6k1/8/8/8/8/8/8/6K1 w - - 0 1");
+        let stormed = danger("6k1/8/8/8/8/6p1/8/6K1 w - - 0 1");
+        assert!(sheltered < exposed);
+        assert!(exposed < stormed);
+
+        // the queen has safe checks on a1 and e1, unless the knight on c2 takes it there
+        let safe_check = danger("6k1/8/8/q7/8/8/8/6K1 w - - 0 1");
+        let defended_check = danger("6k1/8/8/q7/8/8/2N5/6K1 w - - 0 1");
+        assert!(defended_check < safe_check);
+    }
+
+    #[test]
+    fn test_material_imbalance() {
+        // with all pawns on the board the knight is worth more than the bishop
+        let trace = Board::new("2b1k3/pppppppp/8/8/8/8/PPPPPPPP/2N1K3 w - - 0 1").trace();
+        assert!(trace.imbalance[0].mg > trace.imbalance[1].mg);
+        assert_eq!(trace.imbalance[0].mg, trace.imbalance[0].eg);
+
+        // a rook against a bishop without pawns is scaled towards a draw, the endgame module knows nothing about it
+        let trace = Board::new("4k3/8/8/3b4/8/8/8/3RK3 w - - 0 1").trace();
+        let Some(Endgame::Scale([white, black])) = trace.endgame else {
+            panic!("expected a scale factor, got {:?}", trace.endgame);
+        };
+        assert!(white < SCALE_NORMAL / 4);
+        assert_eq!(black, 0);
+    }
+}
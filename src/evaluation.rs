@@ -1,12 +1,48 @@
 #[allow(clippy::wildcard_imports)]
 use crate::{
+    endgame::{self, Endgame, SCALE_NORMAL},
+    eval_params::{self, ConstantParams, EvalParams, LoadedParams},
+    eval_trace::{EvalTrace, Tapered},
     evaluation_constants::*,
-    move_generator::masks::{self, king_safety_mask},
+    material_hash_table,
+    move_generator::{
+        masks::{self, king_safety_mask},
+        normal_targets::KNIGHT_TARGETS,
+        sliding_targets::{get_bishop_targets, get_rook_targets},
+    },
+    nnue,
     prelude::*,
     settings,
+    utils::persistence,
 };
 
 pub const MATE_SCORE: i32 = 30_000;
+/// Every score whose absolute value is above this is a mate score,
+/// the remaining distance to `MATE_SCORE` is the number of plies until mate.
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 256;
+
+/// Converts a mate score into the number of moves (not plies) until mate as used by UCI `score mate`.
+/// Positive if the side to move mates, negative if it gets mated. Returns `None` for non mate scores.
+pub const fn mate_in_moves(score: i32) -> Option<i32> {
+    if score > MATE_THRESHOLD {
+        Some((MATE_SCORE - score + 1) / 2)
+    } else if score < -MATE_THRESHOLD {
+        Some(-(MATE_SCORE + score) / 2)
+    } else {
+        None
+    }
+}
+
+/// Identifies the network and weights searches currently evaluate with,
+/// scores persisted under another evaluation can't be compared to ours
+pub fn evaluation_fingerprint() -> u64 {
+    let network = nnue::network().map(|network| network.fingerprint());
+    let params = eval_params::loaded_params().map_or_else(
+        || persistence::fingerprint(&LoadedParams::default()),
+        |params| persistence::fingerprint(&*params),
+    );
+    persistence::fingerprint(&(network, params))
+}
 
 const KNIGHT: i32 = 1;
 const BISHOP: i32 = 1;
@@ -23,6 +59,60 @@ pub const GAMEPHASE_INC: [i32; 12] = [
 const MG_TABLE: [[i32; 64]; 12] = init_table(&MG_PIECE_VALUES, &MG_BASE_POSITION_TABLE);
 const EG_TABLE: [[i32; 64]; 12] = init_table(&EG_PIECE_VALUES, &EG_BASE_POSITION_TABLE);
 
+/// The linear part of the evaluation: material + PSQT of both sides and the game phase material.
+/// Kept up to date by `Board::toggle`, so `Board::evaluate` doesn't need to loop over every piece for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Psqt {
+    /// white - black
+    pub mg: i32,
+    /// white - black
+    pub eg: i32,
+    /// Sum of `GAMEPHASE_INC` over all pieces on the board
+    pub phase: i32,
+}
+
+impl Psqt {
+    pub const EMPTY: Self = Self {
+        mg: 0,
+        eg: 0,
+        phase: 0,
+    };
+
+    /// Adds `figure` on `square` when `placing`, removes it otherwise
+    #[inline]
+    pub const fn toggle(&mut self, figure: Figure, square: Square, placing: bool) {
+        let idx = figure as usize;
+        if idx >= MG_TABLE.len() {
+            return;
+        }
+
+        // white figures have even indices
+        let sign = if placing { 1 } else { -1 } * if idx & 1 == 0 { 1 } else { -1 };
+        self.mg += sign * MG_TABLE[idx][square.i()];
+        self.eg += sign * EG_TABLE[idx][square.i()];
+        self.phase += if placing { 1 } else { -1 } * GAMEPHASE_INC[idx];
+    }
+
+    /// Material + position of `figure` on `square` with the compiled in weights
+    pub const fn table_value(figure: Figure, square: Square) -> Tapered {
+        Tapered::new(
+            MG_TABLE[figure as usize][square.i()],
+            EG_TABLE[figure as usize][square.i()],
+        )
+    }
+
+    /// Full recomputation, used to verify the incremental updates
+    pub fn compute(board: &Board) -> Self {
+        let mut psqt = Self::EMPTY;
+        for i in 0..=11 {
+            for bit in board.figure_bb_by_index(i).iter_mut() {
+                psqt.toggle(Figure::from_idx(i), bit.to_square(), true);
+            }
+        }
+        psqt
+    }
+}
+
 // Flips square index to flip rows but keep columns the same
 // e.g. a1 becomes a8; e4 -> e5
 const fn flip(sq: usize) -> usize {
@@ -68,6 +158,50 @@ const fn init_table(
 /// Unit = Centipawns, 100 Centipawns => 1 Pawn
 impl Board {
     pub fn evaluate(&self) -> i32 {
+        if settings::NNUE
+            && let Some(nnue) = self.nnue()
+        {
+            debug_assert!(
+                nnue.is_consistent(self),
+                "incremental NNUE accumulators diverged in {}",
+                self.fen()
+            );
+            return nnue.evaluate(self.current_color());
+        }
+
+        self.eval_params().map_or_else(
+            || self.evaluate_with(&ConstantParams),
+            |params| self.evaluate_with(params.as_ref()),
+        )
+    }
+
+    /// The handcrafted evaluation with the weights of `params`
+    pub fn evaluate_with<P: EvalParams>(&self, params: &P) -> i32 {
+        self.evaluate_hce::<P, false>(params, &mut EvalTrace::default())
+    }
+
+    /// Every term of the handcrafted evaluation, also when a NNUE network is attached
+    pub fn trace(&self) -> EvalTrace {
+        self.eval_params().map_or_else(
+            || self.trace_with(&ConstantParams),
+            |params| self.trace_with(params.as_ref()),
+        )
+    }
+
+    pub fn trace_with<P: EvalParams>(&self, params: &P) -> EvalTrace {
+        let mut trace = EvalTrace::default();
+        let score = self.evaluate_hce::<P, true>(params, &mut trace);
+        debug_assert_eq!(trace.score(), score, "trace diverged in {}", self.fen());
+        trace
+    }
+
+    /// The handcrafted evaluation, only fills `trace` if `TRACE` so searching doesn't pay for it
+    #[allow(clippy::too_many_lines)]
+    fn evaluate_hce<P: EvalParams, const TRACE: bool>(
+        &self,
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> i32 {
         let white = 0usize;
         let black = 1usize;
         let mut mg = [0i32; 2];
@@ -75,19 +209,40 @@ impl Board {
 
         // cache the movement bitboards so this information can be used for both king safety and mobility
         let mut figure_movements = [Bitboard::EMPTY; 12];
+        // the same before the figures themselves are re-added, threats need to know what is really attacked
+        let mut attacks = [Bitboard::EMPTY; 12];
 
-        let open_files = self.open_files();
+        let pawns = params.pawn_entry(self);
+        let open_files = pawns.open_files;
+        let psqt = params.psqt(self);
+        let mobility_coefficients = params.mobility_coefficients();
+        let rook_open_file_bonus = params.rook_open_file_bonus();
+        let king_open_file_penalty = params.king_open_file_penalty();
 
-        let mut phase = TOTAL;
         for i in 0..=11 {
             let mut bb = self.figure_bb_by_index(i);
 
             // mobility - only needs to be done once per figure type
-            if settings::KING_SAFETY {
+            if settings::KING_SAFETY || settings::THREATS || settings::PIECE_POSITIONS {
                 let figure_mobility = self.calculate_piece_mobility(i, &mut figure_movements);
+                attacks[i] = figure_movements[i];
                 if settings::MOBILITY {
-                    mg[i & 1] += MOBILITY_COEFFICIENTS[0][i >> 1] * figure_mobility;
-                    eg[i & 1] += MOBILITY_COEFFICIENTS[1][i >> 1] * figure_mobility;
+                    let mobility = Tapered::new(
+                        mobility_coefficients[0][i >> 1] * figure_mobility,
+                        mobility_coefficients[1][i >> 1] * figure_mobility,
+                    );
+                    mg[i & 1] += mobility.mg;
+                    eg[i & 1] += mobility.eg;
+                    if TRACE {
+                        trace.mobility[i & 1][i >> 1] = mobility;
+                        trace.record_tapered(
+                            "mobility_coefficients",
+                            6,
+                            i >> 1,
+                            i & 1,
+                            figure_mobility,
+                        );
+                    }
                 }
                 // for correctness, mobility calculation removes all pieces of the same color (we can't take our own piece)
                 // however for safety calculation we would like to also count a piece as "reaching the king zone" if it is physically in the zone
@@ -96,48 +251,168 @@ impl Board {
             }
 
             for bit in bb.iter_mut() {
+                if TRACE {
+                    // the incremental PSQT only knows the sum, so split it up again
+                    let [mg_values, eg_values] = params.piece_values();
+                    let piece_value = Tapered::new(mg_values[i >> 1], eg_values[i >> 1]);
+                    trace.material[i & 1] += piece_value;
+                    trace.psqt[i & 1] +=
+                        params.piece_square(Figure::from_idx(i), bit.to_square()) - piece_value;
+
+                    // position tables are from black's point of view, see `EvalParams::piece_square`
+                    let square = match i & 1 {
+                        0 => bit.to_square().i() ^ 0x38,
+                        _ => bit.to_square().i(),
+                    };
+                    let table_index = (i >> 1) * 64 + square;
+                    trace.record("mg_piece_values", i >> 1, i & 1, 1.0, 0.0);
+                    trace.record("eg_piece_values", i >> 1, i & 1, 0.0, 1.0);
+                    trace.record("mg_base_position_table", table_index, i & 1, 1.0, 0.0);
+                    trace.record("eg_base_position_table", table_index, i & 1, 0.0, 1.0);
+                }
+
                 if open_files.is_position_set(bit) {
                     // rooks on open files
                     if settings::ROOKS_OPEN_FILES && (i == 6 || i == 7) {
-                        mg[i & 1] += ROOK_OPEN_FILE_BONUS[0];
-                        eg[i & 1] += ROOK_OPEN_FILE_BONUS[1];
+                        mg[i & 1] += rook_open_file_bonus[0];
+                        eg[i & 1] += rook_open_file_bonus[1];
+                        if TRACE {
+                            trace.rook_open_file[i & 1] +=
+                                Tapered::new(rook_open_file_bonus[0], rook_open_file_bonus[1]);
+                            trace.record_tapered("rook_open_file_bonus", 1, 0, i & 1, 1);
+                        }
                     }
                     if settings::KINGS_OPEN_FILES && (i == 10 || i == 11) {
-                        mg[i & 1] += KING_OPEN_FILE_PENALTY[0];
-                        eg[i & 1] += KING_OPEN_FILE_PENALTY[1];
+                        mg[i & 1] += king_open_file_penalty[0];
+                        eg[i & 1] += king_open_file_penalty[1];
+                        if TRACE {
+                            trace.king_open_file[i & 1] +=
+                                Tapered::new(king_open_file_penalty[0], king_open_file_penalty[1]);
+                            trace.record_tapered("king_open_file_penalty", 1, 0, i & 1, 1);
+                        }
                     }
                 }
-
-                let square = bit.to_square();
-                mg[i & 1] += MG_TABLE[i][square];
-                eg[i & 1] += EG_TABLE[i][square];
-
-                phase -= GAMEPHASE_INC[i];
             }
         }
         // value is larger the less pieces are on the board
         // value is bound by the interval [0, 256]
         // (0 = starting position, 256 = only pawns and kings)
+        let phase = TOTAL - psqt.phase;
         let gamephase = (phase * 256 + (TOTAL / 2)) / TOTAL;
 
-        let mut mg_score = mg[white] - mg[black];
-        let mut eg_score = eg[white] - eg[black];
+        let mut mg_score = mg[white] - mg[black] + psqt.mg;
+        let mut eg_score = eg[white] - eg[black] + psqt.eg;
+
+        mg_score += pawns.mg;
+        eg_score += pawns.eg;
+        if TRACE {
+            // the pawn hash table only stores white - black
+            let (mg_pawns, eg_pawns) = self.pawn_structure::<P, true>(params, trace);
+            let doubled = if settings::DOUBLED_PAWNS {
+                self.doubled_pawns()
+            } else {
+                [0; 2]
+            };
+            for side in [white, black] {
+                trace.pawn_structure[side] =
+                    Tapered::new(i32::from(mg_pawns[side]), i32::from(eg_pawns[side]));
+                let penalty = doubled[side] * params.doubled_pawn_penalty();
+                trace.doubled_pawns[side] = Tapered::new(penalty, penalty);
+                // not tapered, so it counts in full in the middlegame and in the endgame
+                let count = f64::from(doubled[side]);
+                trace.record("doubled_pawn_penalty", 0, side, count, count);
+            }
+        }
 
-        let (mg_pawn_structure, eg_pawn_structure) = self.pawn_structure();
-        mg_score += i32::from(mg_pawn_structure[white] - mg_pawn_structure[black]);
-        eg_score += i32::from(eg_pawn_structure[white] - eg_pawn_structure[black]);
+        if settings::PASSERS {
+            let (mg_passers, eg_passers) = self.passers::<P, TRACE>(pawns.passed, params, trace);
+            mg_score += i32::from(mg_passers[white] - mg_passers[black]);
+            eg_score += i32::from(eg_passers[white] - eg_passers[black]);
+            if TRACE {
+                for side in [white, black] {
+                    trace.passers[side] =
+                        Tapered::new(i32::from(mg_passers[side]), i32::from(eg_passers[side]));
+                }
+            }
+        }
 
         if settings::BISHOP_PAIR {
-            let (mg_bishop_pair, eg_bishop_pair) = self.bishop_pair_boni();
+            let (mg_bishop_pair, eg_bishop_pair) = self.bishop_pair_boni(params);
             mg_score += i32::from(mg_bishop_pair[white] - mg_bishop_pair[black]);
             eg_score += i32::from(eg_bishop_pair[white] - eg_bishop_pair[black]);
+            if TRACE {
+                for side in [white, black] {
+                    trace.bishop_pair[side] = Tapered::new(
+                        i32::from(mg_bishop_pair[side]),
+                        i32::from(eg_bishop_pair[side]),
+                    );
+                    let pairs = self.figure_bb_by_index(4 + side).get_count() >> 1;
+                    trace.record_tapered("bishop_pair_bonus", 1, 0, side, pairs);
+                }
+            }
+        }
+
+        let material = params.material_entry(self);
+        if settings::IMBALANCE {
+            let imbalance = material.imbalance;
+            mg_score += imbalance[white] - imbalance[black];
+            eg_score += imbalance[white] - imbalance[black];
+            if TRACE {
+                for side in [white, black] {
+                    trace.imbalance[side] = Tapered::new(imbalance[side], imbalance[side]);
+                }
+                material_hash_table::trace_imbalance(self, trace);
+            }
         }
 
         if settings::KING_SAFETY {
-            let (mg_king_safety, eg_king_safety) = self.king_safety(&figure_movements);
-            // println!("mg king safety w/b: {mg_king_safety:?}");
+            let (mg_danger, eg_danger) =
+                self.king_danger::<P, TRACE>(&figure_movements, &attacks, params, trace);
+            let (mg_king_safety, eg_king_safety) = king_safety(params, mg_danger, eg_danger);
             mg_score -= i32::from(mg_king_safety[white] - mg_king_safety[black]);
             eg_score -= i32::from(eg_king_safety[white] - eg_king_safety[black]);
+            if TRACE {
+                for side in [white, black] {
+                    trace.king_safety[side] = Tapered::new(
+                        -i32::from(mg_king_safety[side]),
+                        -i32::from(eg_king_safety[side]),
+                    );
+                    trace.king_danger[side] =
+                        Tapered::new(i32::from(mg_danger[side]), i32::from(eg_danger[side]));
+                }
+            }
+        }
+
+        if settings::THREATS {
+            let (mg_threats, eg_threats) = self.threats::<P, TRACE>(&attacks, params, trace);
+            mg_score += i32::from(mg_threats[white] - mg_threats[black]);
+            eg_score += i32::from(eg_threats[white] - eg_threats[black]);
+            if TRACE {
+                for side in [white, black] {
+                    trace.threats[side] =
+                        Tapered::new(i32::from(mg_threats[side]), i32::from(eg_threats[side]));
+                }
+            }
+        }
+
+        if settings::PIECE_POSITIONS {
+            let counts = self.piece_positions(&attacks, open_files);
+            let values = params.piece_position_values();
+            for side in [white, black] {
+                for (kind, count) in counts[side].into_iter().enumerate() {
+                    let value = Tapered::new(
+                        i32::from(count * values[0][kind]),
+                        i32::from(count * values[1][kind]),
+                    );
+                    let sign = if side == white { 1 } else { -1 };
+                    mg_score += sign * value.mg;
+                    eg_score += sign * value.eg;
+                    if TRACE {
+                        trace.piece_positions[side][kind] = value;
+                        trace.record_tapered("piece_position_values", 7, kind, side, count);
+                    }
+                }
+            }
         }
 
         let current_color_multiplier = match self.current_color() {
@@ -146,16 +421,49 @@ impl Board {
         };
 
         if settings::INITIATIVE {
-            mg_score += current_color_multiplier * INITIATIVE;
-            eg_score += current_color_multiplier * INITIATIVE;
+            mg_score += current_color_multiplier * params.initiative();
+            eg_score += current_color_multiplier * params.initiative();
+            if TRACE {
+                let side = self.current_color() as usize;
+                trace.initiative[side] = Tapered::new(params.initiative(), params.initiative());
+                trace.record("initiative", 0, side, 1.0, 1.0);
+            }
         }
 
         // Final aggregation of scoring aspects
         let mut score = (mg_score * (256 - gamephase) + eg_score * gamephase) >> 8;
 
-        if settings::DOUBLED_PAWNS {
-            let doubled_pawns = self.doubled_pawn_penalties();
-            score += doubled_pawns[white] - doubled_pawns[black];
+        score += pawns.doubled;
+
+        let endgame = if settings::ENDGAME {
+            endgame::probe(self)
+        } else {
+            None
+        };
+        // the more specific endgame knowledge wins, only the more pessimistic of two scale factors is kept
+        let endgame = match endgame {
+            Some(Endgame::Scale(factors)) => Some(Endgame::Scale([
+                factors[white].min(material.scale[white]),
+                factors[black].min(material.scale[black]),
+            ])),
+            None if material.scale != [SCALE_NORMAL; 2] => Some(Endgame::Scale(material.scale)),
+            endgame => endgame,
+        };
+        if let Some(endgame) = endgame {
+            score = endgame.apply(score);
+        }
+
+        if TRACE {
+            // only where the scale factor of the material is the one which is applied
+            let indices = material_hash_table::scale_indices(self, params);
+            for side in [white, black] {
+                trace.material_scale[side] = indices[side].filter(|_| {
+                    matches!(endgame, Some(Endgame::Scale(factors)) if factors[side] == material.scale[side])
+                });
+            }
+            trace.endgame = endgame;
+            trace.gamephase = gamephase;
+            trace.side_to_move = self.current_color();
         }
 
         score * current_color_multiplier
@@ -163,23 +471,60 @@ impl Board {
 
     /// Format:
     /// (mg: [white, black], eg: [white, black])
-    pub fn pawn_structure(&self) -> ([i16; 2], [i16; 2]) {
+    pub fn pawn_structure<P: EvalParams + ?Sized, const TRACE: bool>(
+        &self,
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> ([i16; 2], [i16; 2]) {
         // [white, black]
         let mut mg_pawn_offset = [0i16; 2];
         let mut eg_pawn_offset = [0i16; 2];
+        let [mg_passed_table, eg_passed_table] = params.passed_pawn_tables();
+        let isolated_penalty = params.isolated_pawn_penalty();
+        let formation_values = params.pawn_formation_values();
 
         for i in 0..=1 {
             let color = Color::from_usize(i);
             for pawn in self.figure_bb_by_index(i).iter_mut() {
-                if settings::PASSED_PAWNS {
-                    let bonus = self.passed_pawn_bonus(pawn, color);
-                    mg_pawn_offset[i] += bonus[0];
-                    eg_pawn_offset[i] += bonus[1];
+                if settings::PASSED_PAWNS
+                    && let Some(idx) = self.passed_pawn_index(pawn, color)
+                {
+                    mg_pawn_offset[i] += mg_passed_table[idx];
+                    eg_pawn_offset[i] += eg_passed_table[idx];
+                    if TRACE {
+                        trace.record("mg_passed_pawn_table", idx, i, 1.0, 0.0);
+                        trace.record("eg_passed_pawn_table", idx, i, 0.0, 1.0);
+                    }
+                }
+                if settings::ISOLATED_PAWNS && self.is_isolated_pawn(pawn, color) {
+                    mg_pawn_offset[i] += isolated_penalty[0];
+                    eg_pawn_offset[i] += isolated_penalty[1];
+                    if TRACE {
+                        trace.record_tapered("isolated_pawn_penalty", 1, 0, i, 1);
+                    }
                 }
-                if settings::ISOLATED_PAWNS {
-                    let penalty = self.isolated_pawn_penalty(pawn, color);
-                    mg_pawn_offset[i] += penalty[0];
-                    eg_pawn_offset[i] += penalty[1];
+                if settings::PAWN_FORMATION {
+                    let formation = self.pawn_formation(pawn, color);
+                    for (kind, applies) in formation.into_iter().enumerate() {
+                        if applies {
+                            mg_pawn_offset[i] += formation_values[0][kind];
+                            eg_pawn_offset[i] += formation_values[1][kind];
+                            if TRACE {
+                                trace.record_tapered("pawn_formation_values", 5, kind, i, 1);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if settings::PAWN_FORMATION {
+            let majorities = self.pawn_majorities();
+            for i in 0..=1 {
+                mg_pawn_offset[i] += majorities[i] * formation_values[0][4];
+                eg_pawn_offset[i] += majorities[i] * formation_values[1][4];
+                if TRACE {
+                    trace.record_tapered("pawn_formation_values", 5, 4, i, majorities[i]);
                 }
             }
         }
@@ -187,40 +532,156 @@ impl Board {
         (mg_pawn_offset, eg_pawn_offset)
     }
 
-    /// Format: `[MG, EG]`
-    /// Note: Penalties are negative, i.e. should be added
+    /// Whether `pawn` is backward, supported, part of a phalanx and a candidate passed pawn,
+    /// see `EvalParams::pawn_formation_values`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn pawn_formation(&self, pawn: Bit, friendly: Color) -> [bool; 4] {
+        let friendly_pawns = self.figure_bb(friendly, Piece::Pawn);
+        let opponent_pawns = self.figure_bb(!friendly, Piece::Pawn);
+        let (x, y) = pawn.to_xy();
+        let neighbours = adjacent_files(x) & friendly_pawns;
+        // friendly pawns on the neighbouring files which aren't ahead, i.e. can still advance to support this one
+        let helpers = neighbours & ranks_up_to(y, friendly);
+        let in_front = Bitboard::passed_pawn_mask(pawn, friendly);
+
+        let supported = pawn_attacks(friendly_pawns, friendly).is_position_set(pawn);
+        let phalanx = !(neighbours & rank(y)).is_empty();
+
+        // the square in front is controlled by an opponent pawn and no friendly pawn can come to its support.
+        // Isolated pawns are penalised as such already
+        let stop = match friendly {
+            White => pawn.to_bb() << 8,
+            Black => pawn.to_bb() >> 8,
+        };
+        let backward = !neighbours.is_empty()
+            && helpers.is_empty()
+            && !(pawn_attacks(opponent_pawns, !friendly) & stop).is_empty();
+
+        // not passed yet, but the way is free and there are at least as many helpers as opponent pawns stopping it
+        let sentries = in_front & opponent_pawns & !Bitboard::file(x as i16);
+        let candidate = !(in_front & opponent_pawns).is_empty()
+            && (in_front & opponent_pawns & Bitboard::file(x as i16)).is_empty()
+            && helpers.get_count() >= sentries.get_count();
+
+        [backward, supported, phalanx, candidate]
+    }
+
+    /// Whether each side has more pawns than the opponent on the queen side (files a to d) and
+    /// on the king side (files e to h), a majority is what creates a passed pawn later on.
+    /// Format: `[white, black]` as the number of wings with a majority
+    fn pawn_majorities(&self) -> [i16; 2] {
+        let white_pawns = self.figure_bb_by_index(0);
+        let black_pawns = self.figure_bb_by_index(1);
+        let mut majorities = [0i16; 2];
+        for wing in [0x0f0f_0f0f_0f0f_0f0f, 0xf0f0_f0f0_f0f0_f0f0] {
+            let white = (white_pawns & wing).get_count();
+            let black = (black_pawns & wing).get_count();
+            majorities[0] += i16::from(white > black);
+            majorities[1] += i16::from(black > white);
+        }
+        majorities
+    }
+
+    /// Passed pawn terms which depend on more than the pawns and therefore can't be cached in the pawn hash table:
+    /// blocked passers, king distances to the square in front of the passer and passers the opponent king
+    /// can't catch anymore (rule of the square) if the opponent has no pieces left.
+    /// `passed` are the passed pawns of both sides from the `PawnEntry`.
+    /// return Format:
+    /// (mg: [white, black], eg: [white, black])
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub fn passers<P: EvalParams, const TRACE: bool>(
+        &self,
+        passed: [Bitboard; 2],
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> ([i16; 2], [i16; 2]) {
+        let values = params.passer_values();
+        let mut mg_passers = [0i16; 2];
+        let mut eg_passers = [0i16; 2];
+
+        for side in 0..=1 {
+            let color = Color::from_usize(side);
+            let own_king = self.king(color).to_square().i();
+            let opp_king = self.king(!color).to_square().i();
+            let opp_has_pieces = !(self.color_bbs_without_king(!color)
+                & !self.figure_bb(!color, Piece::Pawn))
+            .is_empty();
+            let opp_to_move = self.current_color() != color;
+
+            let mut bb = passed[side];
+            for pawn in bb.iter_mut() {
+                let (x, y) = pawn.to_xy();
+                // relative to `color`, i.e. as if it were white
+                let (relative_rank, stop, promotion) = match color {
+                    White => (y, pawn.to_square().i() + 8, 56 + x),
+                    Black => (7 - y, pawn.to_square().i() - 8, x),
+                };
+                let path = Bitboard::file(x as i16) & ranks_ahead(y, color);
+
+                let blocked = i16::from(self.occupied().is_position_set(Square(stop).to_bit()));
+                let own_distance = endgame::distance(own_king, stop) as i16;
+                let opp_distance = endgame::distance(opp_king, stop) as i16;
+
+                // the pawn still has its double step from the second rank
+                let pawn_distance = (7 - relative_rank).min(5) as i32;
+                let unstoppable = i16::from(
+                    !opp_has_pieces
+                        && (path & self.occupied()).is_empty()
+                        && endgame::distance(opp_king, promotion) - i32::from(opp_to_move)
+                            > pawn_distance,
+                );
+
+                for (kind, count) in [blocked, own_distance, opp_distance, unstoppable]
+                    .into_iter()
+                    .enumerate()
+                {
+                    mg_passers[side] += count * values[0][kind];
+                    eg_passers[side] += count * values[1][kind];
+                    if TRACE {
+                        trace.record_tapered("passer_values", 4, kind, side, count);
+                    }
+                }
+            }
+        }
+
+        (mg_passers, eg_passers)
+    }
+
+    /// Whether `pawn` has no friendly pawns on the neighbouring files
     #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
-    fn isolated_pawn_penalty(&self, pawn: Bit, friendly: Color) -> [i16; 2] {
+    fn is_isolated_pawn(&self, pawn: Bit, friendly: Color) -> bool {
         let friendly_pawns = self.figure_bb(friendly, Piece::Pawn);
         let x = pawn.to_x() as i16;
         // we only look at the neighbouring files
         // double isolated pawns are still isolated, in fact an even worse liability than a singular isolated pawn
         let scan_mask = Bitboard::file((x - 1).max(0)) | Bitboard::file((x + 1).min(7));
-        let is_isolated = i16::from((scan_mask & friendly_pawns).is_empty());
-
-        [
-            ISOLATED_PAWN_PENALTY[0] * is_isolated,
-            ISOLATED_PAWN_PENALTY[1] * is_isolated,
-        ]
+        (scan_mask & friendly_pawns).is_empty()
     }
 
-    /// Returns the passed pawn bonusses for `pawn`.
-    /// Format: `[MG, EG]`.
-    /// If the pawn is not passed, returns `[0, 0]`
-    fn passed_pawn_bonus(&self, pawn: Bit, friendly: Color) -> [i16; 2] {
+    /// Index of `pawn` into the passed pawn tables, `None` if the pawn is not passed
+    fn passed_pawn_index(&self, pawn: Bit, friendly: Color) -> Option<usize> {
         let opponent_pawns = self.figure_bb(!friendly, Piece::Pawn);
         let scan_mask = Bitboard::passed_pawn_mask(pawn, friendly);
-        let is_passed = i16::from((scan_mask & opponent_pawns).is_empty());
+        if !(scan_mask & opponent_pawns).is_empty() {
+            return None;
+        }
 
-        let idx: usize = match friendly {
+        Some(match friendly {
             White => flip(pawn.to_square().0),
             Black => pawn.to_square().0,
-        };
+        })
+    }
 
-        [
-            is_passed * MG_PASSED_PAWN_TABLE[idx],
-            is_passed * EG_PASSED_PAWN_TABLE[idx],
-        ]
+    /// All passed pawns of `friendly`
+    pub fn passed_pawns(&self, friendly: Color) -> Bitboard {
+        let opponent_pawns = self.figure_bb(!friendly, Piece::Pawn);
+        let mut passed = Bitboard::EMPTY;
+        for pawn in self.figure_bb(friendly, Piece::Pawn).iter_mut() {
+            if (Bitboard::passed_pawn_mask(pawn, friendly) & opponent_pawns).is_empty() {
+                passed |= pawn;
+            }
+        }
+        passed
     }
 
     /// Calculate the penalties for doubled pawns for both white and black
@@ -228,12 +689,19 @@ impl Board {
     ///
     /// Note: penalties are negative for both sides
     /// TODO: this can probably be improved by weighing it against the remaining pawns (the less pawns are on the board, the worse it is if they're doubled)
+    pub fn doubled_pawn_penalties<P: EvalParams + ?Sized>(&self, params: &P) -> [i32; 2] {
+        self.doubled_pawns()
+            .map(|doubled| doubled * params.doubled_pawn_penalty())
+    }
+
+    /// Number of pawns of each side which have another friendly pawn on their file
+    /// returns: `[white, black]`
     #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
-    pub fn doubled_pawn_penalties(&self) -> [i32; 2] {
+    pub fn doubled_pawns(&self) -> [i32; 2] {
         let white_pawns = self.figure_bb_by_index(0);
         let black_pawns = self.figure_bb_by_index(1);
         // [white, black]
-        let mut penalties: [i32; 2] = [0i32; 2];
+        let mut doubled: [i32; 2] = [0i32; 2];
         for i in 0..=7 {
             let file = Bitboard::file(i);
             let file_pawns = [(file & white_pawns).0, (file & black_pawns).0];
@@ -244,11 +712,11 @@ impl Board {
                 (file_pawns[1] & file_pawns[1].wrapping_sub(1)).count_ones() as i32,
             ];
 
-            penalties[0] += file_pawns[0] * DOUBLED_PAWN_PENALTY;
-            penalties[1] += file_pawns[1] * DOUBLED_PAWN_PENALTY;
+            doubled[0] += file_pawns[0];
+            doubled[1] += file_pawns[1];
         }
 
-        penalties
+        doubled
     }
 
     #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
@@ -268,18 +736,19 @@ impl Board {
     /// (mg: [white, black], eg: [white, black])
     #[inline]
     #[allow(clippy::cast_possible_truncation)]
-    const fn bishop_pair_boni(&self) -> ([i16; 2], [i16; 2]) {
+    fn bishop_pair_boni<P: EvalParams>(&self, params: &P) -> ([i16; 2], [i16; 2]) {
+        let bonus = params.bishop_pair_bonus();
         let white_bishops = self.figure_bb(Color::White, Piece::Bishop).get_count();
         let black_bishops = self.figure_bb(Color::Black, Piece::Bishop).get_count();
 
         (
             [
-                BISHOP_PAIR_BONUS[0] * (white_bishops >> 1) as i16,
-                BISHOP_PAIR_BONUS[0] * (black_bishops >> 1) as i16,
+                bonus[0] * (white_bishops >> 1) as i16,
+                bonus[0] * (black_bishops >> 1) as i16,
             ],
             [
-                BISHOP_PAIR_BONUS[1] * (white_bishops >> 1) as i16,
-                BISHOP_PAIR_BONUS[1] * (black_bishops >> 1) as i16,
+                bonus[1] * (white_bishops >> 1) as i16,
+                bonus[1] * (black_bishops >> 1) as i16,
             ],
         )
     }
@@ -298,8 +767,8 @@ impl Board {
         open_files
     }
 
-    /// Calculates the King Safety Score
-    /// WARNING: technically computes danger; so the higher the value, the worse the position is.
+    /// Calculates the danger score of both kings, i.e. the bucket of the king safety tables.
+    /// The higher the value, the worse the position is.
     /// return Format:
     /// (mg: [white, black], eg: [white, black])
     #[allow(
@@ -307,7 +776,16 @@ impl Board {
         clippy::cast_possible_wrap,
         clippy::similar_names
     )]
-    pub fn king_safety(&self, figure_movements: &[Bitboard; 12]) -> ([i16; 2], [i16; 2]) {
+    pub fn king_danger<P: EvalParams, const TRACE: bool>(
+        &self,
+        figure_movements: &[Bitboard; 12],
+        attacks: &[Bitboard; 12],
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> ([i16; 2], [i16; 2]) {
+        let pawn_shield_bonus = params.pawn_shield_bonus();
+        let piece_attack_values = params.piece_attack_values();
+        let piece_defend_values = params.piece_defend_values();
         let mut mg_safety = [0i16; 2];
         let mut eg_safety = [0i16; 2];
 
@@ -321,20 +799,22 @@ impl Board {
         ];
         // pawn shields
         for i in 0..=1 {
-            mg_safety[i] -= (pawn_shield_zones[i] & self.figure_bb_by_index(i)).get_count() as i16
-                * PAWN_SHIELD_BONUS[0];
-            eg_safety[i] -= (pawn_shield_zones[i] & self.figure_bb_by_index(i)).get_count() as i16
-                * PAWN_SHIELD_BONUS[1];
+            let shield = (pawn_shield_zones[i] & self.figure_bb_by_index(i)).get_count() as i16;
+            mg_safety[i] -= shield * pawn_shield_bonus[0];
+            eg_safety[i] -= shield * pawn_shield_bonus[1];
+            if TRACE {
+                trace.record_danger("pawn_shield_bonus", 1, 0, i, -shield);
+            }
         }
 
         // skip pawns and kings in the evaluation
         for i in 2..=9 {
             let friend = i & 1;
             let opp = friend ^ 1;
-            let attacker_mg = PIECE_ATTACK_VALUES[0][i >> 1];
-            let attacker_eg = PIECE_ATTACK_VALUES[1][i >> 1];
-            let defender_mg = PIECE_DEFEND_VALUES[0][i >> 1];
-            let defender_eg = PIECE_DEFEND_VALUES[1][i >> 1];
+            let attacker_mg = piece_attack_values[0][i >> 1];
+            let attacker_eg = piece_attack_values[1][i >> 1];
+            let defender_mg = piece_defend_values[0][i >> 1];
+            let defender_eg = piece_defend_values[1][i >> 1];
 
             // println!("{:?}", Figure::from_idx(i));
             // println!(
@@ -349,28 +829,412 @@ impl Board {
             // );
 
             // decrease friendly danger score for friendly piece in friendly king zone
-            mg_safety[friend] -=
-                (king_zones[friend] & figure_movements[i]).get_count() as i16 * defender_mg;
-            eg_safety[friend] -=
-                (king_zones[friend] & figure_movements[i]).get_count() as i16 * defender_eg;
+            let defended = (king_zones[friend] & figure_movements[i]).get_count() as i16;
+            mg_safety[friend] -= defended * defender_mg;
+            eg_safety[friend] -= defended * defender_eg;
 
-            mg_safety[opp] +=
-                (king_zones[opp] & figure_movements[i]).get_count() as i16 * attacker_mg;
-            eg_safety[opp] +=
-                (king_zones[opp] & figure_movements[i]).get_count() as i16 * attacker_eg;
+            let attacked = (king_zones[opp] & figure_movements[i]).get_count() as i16;
+            mg_safety[opp] += attacked * attacker_mg;
+            eg_safety[opp] += attacked * attacker_eg;
+
+            if TRACE {
+                trace.record_danger("piece_defend_values", 6, i >> 1, friend, -defended);
+                trace.record_danger("piece_attack_values", 6, i >> 1, opp, attacked);
+            }
+        }
+
+        for side in 0..=1 {
+            let color = Color::from_usize(side);
+            let (mg_danger, eg_danger) = self.king_attack_danger::<P, TRACE>(
+                color,
+                attacks,
+                king_zones[side],
+                params,
+                trace,
+            );
+            mg_safety[side] += mg_danger;
+            eg_safety[side] += eg_danger;
+
+            let (mg_shelter, eg_shelter) =
+                self.pawn_shelter_danger::<P, TRACE>(color, params, trace);
+            mg_safety[side] += mg_shelter;
+            eg_safety[side] += eg_shelter;
         }
 
         // println!("mg score: {mg_safety:?}");
-        #[allow(clippy::cast_sign_loss)]
         (
-            [
-                MG_KING_SAFETY_TABLE[mg_safety[0].clamp(0, 99) as usize],
-                MG_KING_SAFETY_TABLE[mg_safety[1].clamp(0, 99) as usize],
-            ],
-            [
-                EG_KING_SAFETY_TABLE[eg_safety[0].clamp(0, 99) as usize],
-                EG_KING_SAFETY_TABLE[eg_safety[1].clamp(0, 99) as usize],
-            ],
+            [mg_safety[0].clamp(0, 99), mg_safety[1].clamp(0, 99)],
+            [eg_safety[0].clamp(0, 99), eg_safety[1].clamp(0, 99)],
+        )
+    }
+
+    /// Danger of the king of `color` from the pieces of the opponent:
+    /// squares a piece can give a check from without being taken, squares of the king zone which only
+    /// the king or the queen defend and the number of pieces attacking the king zone.
+    /// `attacks` are the attack bitboards of every figure without the squares of the figures themselves.
+    /// Format: `(mg, eg)`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn king_attack_danger<P: EvalParams, const TRACE: bool>(
+        &self,
+        color: Color,
+        attacks: &[Bitboard; 12],
+        king_zone: Bitboard,
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> (i16, i16) {
+        let side = color as usize;
+        let opp = side ^ 1;
+        let safe_check_values = params.safe_check_values();
+        let weak_square_value = params.king_zone_weak_square_value();
+        let [mg_attacker_counts, eg_attacker_counts] = params.attacker_count_values();
+        let occupied = self.occupied();
+        let king_square = self.king(color).to_square();
+
+        let defended = (side..12)
+            .step_by(2)
+            .fold(Bitboard::EMPTY, |all, i| all | attacks[i]);
+        let safe = !defended & !self.color_bbs(!color);
+        let bishop_checks = get_bishop_targets(king_square, occupied);
+        let rook_checks = get_rook_targets(king_square, occupied);
+        let checks = [
+            KNIGHT_TARGETS[king_square],
+            bishop_checks,
+            rook_checks,
+            bishop_checks | rook_checks,
+        ];
+
+        let mut mg_danger = 0i16;
+        let mut eg_danger = 0i16;
+        for (piece, checks) in (1..=4).zip(checks) {
+            let count = (checks & attacks[2 * piece + opp] & safe).get_count() as i16;
+            mg_danger += count * safe_check_values[0][piece];
+            eg_danger += count * safe_check_values[1][piece];
+            if TRACE {
+                trace.record_danger("safe_check_values", 6, piece, side, count);
+            }
+        }
+
+        // only the king or the queen defends these, so they can't really hold them
+        let opp_attacks = (opp..12)
+            .step_by(2)
+            .fold(Bitboard::EMPTY, |all, i| all | attacks[i]);
+        let solid_defence = (side..8)
+            .step_by(2)
+            .fold(Bitboard::EMPTY, |all, i| all | attacks[i]);
+        let weak = (king_zone & opp_attacks & !solid_defence).get_count() as i16;
+        mg_danger += weak * weak_square_value[0];
+        eg_danger += weak * weak_square_value[1];
+        if TRACE {
+            trace.record_danger("king_zone_weak_square_value", 1, 0, side, weak);
+        }
+
+        // every additional attacker makes the attack a lot more dangerous than the squares alone say
+        let mut attackers = 0;
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for bit in self.figure_bb(!color, piece).iter_mut() {
+                let square = bit.to_square();
+                let piece_attacks = match piece {
+                    Piece::Knight => KNIGHT_TARGETS[square],
+                    Piece::Bishop => get_bishop_targets(square, occupied),
+                    Piece::Rook => get_rook_targets(square, occupied),
+                    _ => get_bishop_targets(square, occupied) | get_rook_targets(square, occupied),
+                };
+                attackers += usize::from(!(piece_attacks & king_zone).is_empty());
+            }
+        }
+        let attackers = attackers.min(7);
+        if TRACE {
+            trace.record_danger("attacker_count_values", 8, attackers, side, 1);
+        }
+
+        (
+            mg_danger + mg_attacker_counts[attackers],
+            eg_danger + eg_attacker_counts[attackers],
         )
     }
+
+    /// Danger of the king of `color` by the pawns on its file and both neighbouring files:
+    /// the friendly pawn closest in front of the king shelters it, the opponent pawn closest to it storms it.
+    /// Both are looked up by the rank of the pawn relative to `color`, 0 means there is no such pawn.
+    /// Format: `(mg, eg)`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn pawn_shelter_danger<P: EvalParams, const TRACE: bool>(
+        &self,
+        color: Color,
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> (i16, i16) {
+        let shelter_values = params.pawn_shelter_values();
+        let storm_values = params.pawn_storm_values();
+        let king = self.king(color);
+        let (x, y) = king.to_xy();
+        // keep all three files on the board for kings on the edge
+        let center = x.clamp(1, 6) as i16;
+        let in_front = ranks_ahead(y, color) | rank(y);
+
+        // the pawn closest to the king's rank, returned as rank relative to `color`
+        let closest = |pawns: Bitboard| -> usize {
+            if pawns.is_empty() {
+                return 0;
+            }
+            match color {
+                White => pawns.0.trailing_zeros() as usize / 8,
+                Black => 7 - (63 - pawns.0.leading_zeros() as usize) / 8,
+            }
+        };
+
+        let mut mg_danger = 0i16;
+        let mut eg_danger = 0i16;
+        for file in center - 1..=center + 1 {
+            let file = Bitboard::file(file) & in_front;
+            let shelter = closest(file & self.figure_bb(color, Piece::Pawn));
+            let storm = closest(file & self.figure_bb(!color, Piece::Pawn));
+            mg_danger += shelter_values[0][shelter] + storm_values[0][storm];
+            eg_danger += shelter_values[1][shelter] + storm_values[1][storm];
+            if TRACE {
+                trace.record_danger("pawn_shelter_values", 8, shelter, color as usize, 1);
+                trace.record_danger("pawn_storm_values", 8, storm, color as usize, 1);
+            }
+        }
+
+        (mg_danger, eg_danger)
+    }
+
+    /// Bonus for threatening the pieces of the opponent, kings excluded:
+    /// pieces attacked by pawns, rooks and queens attacked by minor pieces,
+    /// attacked pieces without any defender and pieces a safe pawn push would attack.
+    /// `attacks` are the attack bitboards of every figure without the squares of the figures themselves.
+    /// return Format:
+    /// (mg: [white, black], eg: [white, black])
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub fn threats<P: EvalParams, const TRACE: bool>(
+        &self,
+        attacks: &[Bitboard; 12],
+        params: &P,
+        trace: &mut EvalTrace,
+    ) -> ([i16; 2], [i16; 2]) {
+        let threat_values = params.threat_values();
+        let mut mg_threats = [0i16; 2];
+        let mut eg_threats = [0i16; 2];
+
+        let all_attacks = [0, 1].map(|side| {
+            (side..12)
+                .step_by(2)
+                .fold(Bitboard::EMPTY, |all, i| all | attacks[i])
+        });
+        let empty = !self.occupied();
+
+        for side in 0..=1 {
+            let opp = side ^ 1;
+            let color = Color::from_usize(side);
+            let targets = self.color_bbs_without_king(!color);
+            let pieces = targets & !self.figure_bb_by_index(opp);
+            let majors = self.figure_bb_by_index(6 + opp) | self.figure_bb_by_index(8 + opp);
+
+            let by_pawn = attacks[side] & pieces;
+            let by_minor = (attacks[2 + side] | attacks[4 + side]) & majors;
+            let hanging = all_attacks[side] & targets & !all_attacks[opp];
+
+            // a push is safe if no pawn takes the pawn afterwards and we defend the square if it's attacked at all
+            let pawns = self.figure_bb_by_index(side);
+            let pushes = match color {
+                White => {
+                    let single = (pawns << 8) & empty;
+                    single | ((single << 8) & empty & Bitboard(0x0000_0000_ff00_0000))
+                }
+                Black => {
+                    let single = (pawns >> 8) & empty;
+                    single | ((single >> 8) & empty & Bitboard(0x0000_00ff_0000_0000))
+                }
+            };
+            let safe_pushes = pushes & !attacks[opp] & (all_attacks[side] | !all_attacks[opp]);
+            let by_push = pawn_attacks(safe_pushes, color) & pieces & !by_pawn;
+
+            let counts = [by_pawn, by_minor, hanging, by_push].map(|bb| bb.get_count() as i16);
+            for (kind, count) in counts.into_iter().enumerate() {
+                mg_threats[side] += count * threat_values[0][kind];
+                eg_threats[side] += count * threat_values[1][kind];
+                if TRACE {
+                    trace.record_tapered("threat_values", 4, kind, side, count);
+                }
+            }
+        }
+
+        (mg_threats, eg_threats)
+    }
+
+    /// How often each piece specific positional term of `EvalParams::piece_position_values` applies to either side:
+    /// knight and bishop outposts, rooks on the seventh rank, connected rooks, rooks on semi-open files,
+    /// own blocked pawns on the color of each bishop and undeveloped minor pieces after an early queen move.
+    /// `attacks` are the attack bitboards of every figure without the squares of the figures themselves.
+    /// return Format:
+    /// `[white, black]`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    pub fn piece_positions(&self, attacks: &[Bitboard; 12], open_files: Bitboard) -> [[i16; 7]; 2] {
+        let mut counts = [[0i16; 7]; 2];
+        let occupied = self.occupied();
+
+        for (side, counts) in counts.iter_mut().enumerate() {
+            let color = Color::from_usize(side);
+            let own_pawns = self.figure_bb(color, Piece::Pawn);
+            let opp_pawns = self.figure_bb(!color, Piece::Pawn);
+            let relative_rank = |bit: Bit| match color {
+                White => bit.to_y(),
+                Black => 7 - bit.to_y(),
+            };
+
+            // on the 4th to 6th rank, supported by a pawn and no opponent pawn can ever chase it away
+            let supported = pawn_attacks(own_pawns, color);
+            let is_outpost = |bit: Bit| {
+                (3..=5).contains(&relative_rank(bit))
+                    && supported.is_position_set(bit)
+                    && (Bitboard::passed_pawn_mask(bit, color)
+                        & adjacent_files(bit.to_x())
+                        & opp_pawns)
+                        .is_empty()
+            };
+            for (kind, piece) in [(0, Piece::Knight), (1, Piece::Bishop)] {
+                counts[kind] += self
+                    .figure_bb(color, piece)
+                    .iter_mut()
+                    .filter(|&bit| is_outpost(bit))
+                    .count() as i16;
+            }
+
+            // the seventh rank only matters if there are pawns to eat or it cuts off the king
+            let rooks = self.figure_bb(color, Piece::Rook);
+            let seventh = rank(match color {
+                White => 6,
+                Black => 1,
+            });
+            let eighth = rank(match color {
+                White => 7,
+                Black => 0,
+            });
+            if !(opp_pawns & seventh).is_empty() || eighth.is_position_set(self.king(!color)) {
+                counts[2] += (rooks & seventh).get_count() as i16;
+            }
+
+            // one rook protects the other
+            counts[3] += i16::from(!(attacks[6 + side] & rooks).is_empty());
+
+            let mut bb = rooks;
+            for rook in bb.iter_mut() {
+                let file = Bitboard::file(rook.to_x() as i16);
+                if !open_files.is_position_set(rook) && (file & own_pawns).is_empty() {
+                    counts[4] += 1;
+                }
+            }
+
+            // pawns which can't move anymore and are in the way of the bishop
+            let blocked_pawns = own_pawns
+                & match color {
+                    White => occupied >> 8,
+                    Black => occupied << 8,
+                };
+            for bishop in self.figure_bb(color, Piece::Bishop).iter_mut() {
+                counts[5] += (blocked_pawns & same_colored_squares(bishop)).get_count() as i16;
+            }
+
+            let queen_start = match color {
+                White => Bitboard::from_idx([3]),
+                Black => Bitboard::from_idx([59]),
+            };
+            let queens = self.figure_bb(color, Piece::Queen);
+            if !queens.is_empty() && (queens & queen_start).is_empty() {
+                let minors =
+                    self.figure_bb(color, Piece::Knight) | self.figure_bb(color, Piece::Bishop);
+                counts[6] += (minors & minor_starting_squares(color)).get_count() as i16;
+            }
+        }
+
+        counts
+    }
+}
+
+/// Starting squares of the knights and bishops of `color`
+const fn minor_starting_squares(color: Color) -> Bitboard {
+    match color {
+        White => Bitboard(0x66),
+        Black => Bitboard(0x6600_0000_0000_0000),
+    }
+}
+
+/// Squares of the same color as `bit`
+const fn same_colored_squares(bit: Bit) -> Bitboard {
+    const DARK_SQUARES: u64 = 0xaa55_aa55_aa55_aa55;
+    if bit.0 & DARK_SQUARES == 0 {
+        Bitboard(!DARK_SQUARES)
+    } else {
+        Bitboard(DARK_SQUARES)
+    }
+}
+
+/// Both files next to `x`
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn adjacent_files(x: usize) -> Bitboard {
+    let x = x as i16;
+    let west = if x > 0 {
+        Bitboard::file(x - 1)
+    } else {
+        Bitboard::EMPTY
+    };
+    let east = if x < 7 {
+        Bitboard::file(x + 1)
+    } else {
+        Bitboard::EMPTY
+    };
+    west | east
+}
+
+/// Every square on rank `y`
+const fn rank(y: usize) -> Bitboard {
+    Bitboard(0xff << (y * 8))
+}
+
+/// All ranks from the first rank of `color` up to and including `y`
+const fn ranks_up_to(y: usize, color: Color) -> Bitboard {
+    let below = Bitboard((1u64 << (y * 8)) - 1);
+    match color {
+        White => Bitboard(below.0 | rank(y).0),
+        Black => Bitboard(!below.0),
+    }
+}
+
+/// All ranks in front of `y` from the point of view of `color`
+const fn ranks_ahead(y: usize, color: Color) -> Bitboard {
+    Bitboard(!ranks_up_to(y, color).0)
+}
+
+/// Squares attacked by any of `pawns`
+fn pawn_attacks(pawns: Bitboard, color: Color) -> Bitboard {
+    let west = pawns & !Bitboard::file(0);
+    let east = pawns & !Bitboard::file(7);
+    match color {
+        White => (west << 7) | (east << 9),
+        Black => (west >> 9) | (east >> 7),
+    }
+}
+
+/// Calculates the King Safety Score from the danger buckets of `Board::king_danger`
+/// WARNING: technically computes danger; so the higher the value, the worse the position is.
+/// return Format:
+/// (mg: [white, black], eg: [white, black])
+#[allow(clippy::cast_sign_loss)]
+fn king_safety<P: EvalParams>(
+    params: &P,
+    mg_danger: [i16; 2],
+    eg_danger: [i16; 2],
+) -> ([i16; 2], [i16; 2]) {
+    let [mg_table, eg_table] = params.king_safety_tables();
+    (
+        [
+            mg_table[mg_danger[0] as usize],
+            mg_table[mg_danger[1] as usize],
+        ],
+        [
+            eg_table[eg_danger[0] as usize],
+            eg_table[eg_danger[1] as usize],
+        ],
+    )
 }
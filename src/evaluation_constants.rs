@@ -12,7 +12,36 @@ pub const MOBILITY_COEFFICIENTS: [[i32; 6]; 2] = [[1, 3, 5, 4, 0, -7], [-6, 0, 2
 pub const PIECE_ATTACK_VALUES: [[i16; 6]; 2] = [[0, 3, 3, 3, 4, 0], [0, 1, 1, 1, 3, 0]];
 pub const PIECE_DEFEND_VALUES: [[i16; 6]; 2] = [[0, 3, 0, 0, 0, 0], [0, 0, 0, 0, 7, 0]];
 pub const PAWN_SHIELD_BONUS: [i16; 2] = [5, 1];
+pub const SAFE_CHECK_VALUES: [[i16; 6]; 2] = [[0, 6, 4, 7, 8, 0], [0, 2, 1, 2, 3, 0]];
+pub const KING_ZONE_WEAK_SQUARE_VALUE: [i16; 2] = [2, 1];
+pub const ATTACKER_COUNT_VALUES: [[i16; 8]; 2] =
+    [[0, 0, 3, 6, 9, 12, 12, 12], [0, 0, 1, 2, 3, 4, 4, 4]];
+pub const PAWN_SHELTER_VALUES: [[i16; 8]; 2] =
+    [[6, -5, -3, -1, 0, 0, 0, 0], [2, -1, -1, 0, 0, 0, 0, 0]];
+pub const PAWN_STORM_VALUES: [[i16; 8]; 2] = [[0, 0, 6, 3, 1, 0, 0, 0], [0, 0, 2, 1, 0, 0, 0, 0]];
 pub const INITIATIVE: i32 = 26;
+pub const THREAT_VALUES: [[i16; 4]; 2] = [[40, 30, 20, 15], [30, 25, 15, 10]];
+pub const PAWN_FORMATION_VALUES: [[i16; 5]; 2] = [[-8, 10, 6, 8, 5], [-10, 8, 5, 15, 10]];
+pub const PASSER_VALUES: [[i16; 4]; 2] = [[-10, 0, 0, 0], [-15, -3, 6, 150]];
+pub const PIECE_POSITION_VALUES: [[i16; 7]; 2] =
+    [[20, 10, 15, 10, 10, -3, -8], [10, 5, 25, 5, 5, -6, 0]];
+pub const IMBALANCE_OURS: [[i16; 6]; 6] = [
+    [0, 0, 0, 0, 0, 0],
+    [10, 10, 0, 0, 0, 0],
+    [8, 64, -16, 0, 0, 0],
+    [0, 26, 1, 0, 0, 0],
+    [-7, -1, 12, 26, -52, 0],
+    [-47, 6, 29, 33, -34, -2],
+];
+pub const IMBALANCE_THEIRS: [[i16; 6]; 6] = [
+    [0, 0, 0, 0, 0, 0],
+    [9, 0, 0, 0, 0, 0],
+    [2, 16, 0, 0, 0, 0],
+    [15, 16, 11, 0, 0, 0],
+    [12, 10, 6, -6, 0, 0],
+    [24, 25, -11, 34, 67, 0],
+];
+pub const MATERIAL_SCALE_FACTORS: [i16; 4] = [0, 4, 14, 48];
 pub const MG_PASSED_PAWN_TABLE: [i16; 64] = [
     0, 0, 0, 0, 0, 0, 0, 0, 2, -4, 5, 2, 8, -2, 7, -1, 16, 21, 17, 1, 0, 16, 8, 2, 8, 16, 12, 9, 3,
     15, 11, 0, 8, 7, 7, 0, 0, 5, 6, 2, 2, 11, 10, -2, 5, 17, 11, 6, 1, 8, 12, 3, 8, 13, 18, 3, 0,
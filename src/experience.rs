@@ -0,0 +1,236 @@
+use crate::{
+    evaluation::evaluation_fingerprint,
+    iterative_deepening::SearchResult,
+    prelude::*,
+    transposition_table::{Bound, TT},
+    utils::persistence::{self, invalid_data},
+};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::{LazyLock, Mutex},
+};
+
+/// Root results of searches at least this deep get remembered, shallower ones are found again quickly anyway
+/// THIS IS TUNABLE.
+pub const EXPERIENCE_MIN_DEPTH: usize = 12;
+
+/// Identifies files written by `Experience::save`
+const EXPERIENCE_FILE_MAGIC: [u8; 4] = *b"TFEX";
+
+/// Experience store of the engine, disabled until a file is opened via the `ExperienceFile` option
+pub static EXPERIENCE: LazyLock<Mutex<Experience>> =
+    LazyLock::new(|| Mutex::new(Experience::new()));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExperienceEntry {
+    pub best_move: EncodedMove,
+    pub score: i16,
+    pub depth: u8,
+}
+
+/// Root results of deep searches keyed by `Board::hash` and persisted across sessions,
+/// so positions we have seen before (e.g. the openings of our bot games) start out with what an earlier search found.
+pub struct Experience {
+    file: Option<PathBuf>,
+    entries: HashMap<u64, ExperienceEntry>,
+}
+
+impl Experience {
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Starts using `path`, loading the results of earlier sessions if the file exists.
+    /// Returns the number of known positions
+    pub fn open(&mut self, path: PathBuf) -> io::Result<usize> {
+        self.entries = match File::open(&path) {
+            Ok(file) => Self::read(&mut BufReader::new(file))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err),
+        };
+        self.file = Some(path);
+        Ok(self.entries.len())
+    }
+
+    /// Stops using the store, nothing gets consulted or learned until the next `open`
+    pub fn close(&mut self) {
+        self.file = None;
+        self.entries.clear();
+    }
+
+    pub fn get(&self, hash: u64) -> Option<ExperienceEntry> {
+        self.entries.get(&hash).copied()
+    }
+
+    /// Remembers a root result unless the position is already known from a search at least as deep.
+    /// Returns whether anything changed
+    pub fn record(&mut self, hash: u64, entry: ExperienceEntry) -> bool {
+        if self.file.is_none()
+            || self
+                .get(hash)
+                .is_some_and(|known| known.depth >= entry.depth)
+        {
+            return false;
+        }
+        self.entries.insert(hash, entry);
+        true
+    }
+
+    /// Seeds the TT with the remembered best move of `board`, so the search tries it first.
+    /// The old score only goes in as a depth 0 lower bound, which never cuts off a search,
+    /// the current search has to establish its own score
+    pub fn consult(&self, board: &mut Board) -> Option<ExperienceEntry> {
+        let entry = self.get(board.hash())?;
+        // hashes can collide, don't hand the search a move from another position
+        if !board.is_legal(&entry.best_move.decode()) {
+            return None;
+        }
+
+        TT.view().store(
+            board.hash(),
+            Some(entry.best_move),
+            i32::from(entry.score),
+            None,
+            0,
+            0,
+            Bound::Lower,
+            false,
+        );
+        Some(entry)
+    }
+
+    /// Remembers the result of a finished search on `board` and saves the file if it changed
+    pub fn learn(&mut self, board: &Board, result: &SearchResult) -> io::Result<()> {
+        let Some(&best_move) = result.pv.first() else {
+            return Ok(());
+        };
+        if result.depth < EXPERIENCE_MIN_DEPTH {
+            return Ok(());
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let entry = ExperienceEntry {
+            best_move,
+            score: result.score as i16,
+            depth: result.depth as u8,
+        };
+        if self.record(board.hash(), entry) {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Writes all known positions to the opened file
+    pub fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        persistence::write_header(
+            writer,
+            EXPERIENCE_FILE_MAGIC,
+            evaluation_fingerprint(),
+            self.entries.len() as u64,
+        )?;
+        for (hash, entry) in &self.entries {
+            writer.write_all(&hash.to_le_bytes())?;
+            writer.write_all(&entry.best_move.0.to_le_bytes())?;
+            writer.write_all(&entry.score.to_le_bytes())?;
+            writer.write_all(&[entry.depth])?;
+        }
+        Ok(())
+    }
+
+    fn read(reader: &mut impl Read) -> io::Result<HashMap<u64, ExperienceEntry>> {
+        let count =
+            persistence::read_header(reader, EXPERIENCE_FILE_MAGIC, evaluation_fingerprint())?;
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            let hash = persistence::read_u64(reader)?;
+            let entry = ExperienceEntry {
+                best_move: EncodedMove(persistence::read_u16(reader)?),
+                score: persistence::read_i16(reader)?,
+                depth: persistence::read_u8(reader)?,
+            };
+            entries.insert(hash, entry);
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid_data(
+                "trailing data after the last position".to_owned(),
+            ));
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_keeps_deepest() {
+        let mut experience = Experience::new();
+        let entry = |depth| ExperienceEntry {
+            best_move: EncodedMove(1),
+            score: 20,
+            depth,
+        };
+
+        // disabled until a file is opened
+        assert!(!experience.record(1, entry(14)));
+        experience.file = Some(PathBuf::new());
+
+        assert!(experience.record(1, entry(14)));
+        assert!(!experience.record(1, entry(12)));
+        assert!(experience.record(1, entry(16)));
+        assert_eq!(experience.get(1), Some(entry(16)));
+    }
+
+    #[test]
+    fn test_roundtrip_and_consult() {
+        let path = std::env::temp_dir().join(format!("thunfisch-exp-{}.bin", std::process::id()));
+        // no other test uses this position, so the TT entry probed below is ours
+        let mut board =
+            Board::new("r1bqkb1r/pppppppp/2n2n2/8/8/2N2N2/PPPPPPPP/R1BQKB1R w KQkq - 4 3");
+        let e2e4 = DecodedMove::from_coords("e2e4", &board).encode();
+
+        let mut experience = Experience::new();
+        assert_eq!(experience.open(path.clone()).unwrap(), 0);
+        let result = SearchResult {
+            pv: vec![e2e4],
+            score: 35,
+            depth: EXPERIENCE_MIN_DEPTH,
+        };
+        experience.learn(&board, &result).unwrap();
+
+        let mut reopened = Experience::new();
+        assert_eq!(reopened.open(path.clone()).unwrap(), 1);
+        let entry = reopened.consult(&mut board).unwrap();
+        assert_eq!(entry.best_move, e2e4);
+        assert_eq!(entry.score, 35);
+
+        // the search only gets the move, the old score must not cut it off
+        let hint = TT.view().probe(board.hash(), 0).unwrap();
+        assert_eq!(hint.best_move(), Some(e2e4));
+        assert_eq!(hint.depth(), 0);
+        assert_eq!(hint.bound(), Bound::Lower);
+
+        // a move that isn't legal in the position, e.g. after a hash collision, is ignored
+        let e7e5 = DecodedMove::from_coords("e7e5", &board).encode();
+        reopened.entries.get_mut(&board.hash()).unwrap().best_move = e7e5;
+        assert!(reopened.consult(&mut board).is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,9 +3,13 @@
 use crate::{
     alpha_beta::alpha_beta,
     debug::visualize::{format_f64, format_usize},
+    evaluation::mate_in_moves,
     move_scoring::HISTORY_TABLE,
+    pawn_hash_table::{PAWN_HASH_TABLE, PawnHashTable},
     prelude::*,
+    retrograde, syzygy,
     transposition_table::TT,
+    types::pv_table::PvTable,
 };
 
 use crate::{settings, settings::MAX_AB_DEPTH};
@@ -19,15 +23,31 @@ use std::{
     time::{Duration, Instant},
 };
 
+/// Outcome of the last completed iteration
+pub struct SearchResult {
+    /// The first move is the best move and the second one the expected reply
+    pub pv: Vec<EncodedMove>,
+    pub score: i32,
+    /// 0 if not even the first iteration completed
+    pub depth: usize,
+}
+
 /// <https://www.chessprogramming.org/Iterative_Deepening>
-#[allow(clippy::too_many_lines)]
+/// Returns the result of the last completed iteration
+///
+/// With `mate` set to `Some(n)` the search stops as soon as a mate in at most `n` moves is proven
+#[allow(clippy::too_many_lines, clippy::significant_drop_tightening)]
 pub fn iterative_deepening(
     board: &mut Board,
     max_depth: usize,
     time_limit: Duration,
+    mate: Option<usize>,
     debug: bool,
     help: bool,
-) -> Option<EncodedMove> {
+) -> SearchResult {
+    // held for the whole search, so the TT can't be resized or cleared underneath it
+    let tt = TT.view();
+
     if debug {
         if help {
             println!("Depth   : Current iterative deepening depth (plies)");
@@ -40,6 +60,9 @@ pub fn iterative_deepening(
             println!("AB Nodes: Nodes visited in standard Alpha-Beta");
             println!("QS nodes: Nodes visited in Quiescence search");
             println!("TT Hits : Times a TT entry was reused");
+            println!(
+                "PH Hit% : Percentage of evaluations which found their pawn terms in the pawn hash table"
+            );
             println!("LMR Res : Total Late Move Reduction re-searches");
             println!("PVS Res : Total Principal Variation Search re-searches");
             println!("GlobTime: Total elapsed time since search started (ms)");
@@ -54,10 +77,10 @@ pub fn iterative_deepening(
         println!(
             "TT Estimates: Age={}   {} of {} Entries {} % full  Allocated Size: {} MiB (estimated)",
             TT.get_age(),
-            format_usize(TT.info().0),
-            format_usize(TT.info().1),
-            format_f64(TT.info().2,),
-            TT.info().3
+            format_usize(tt.info().0),
+            format_usize(tt.info().1),
+            format_f64(tt.info().2,),
+            tt.info().3
         );
         println!();
 
@@ -66,7 +89,7 @@ pub fn iterative_deepening(
         }
 
         println!(
-            "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} PV",
+            "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} PV",
             "Depth",
             "Seldepth",
             "Score",
@@ -77,6 +100,7 @@ pub fn iterative_deepening(
             "AB Nodes",
             "QS Nodes",
             "TT Hits",
+            "PH Hit%",
             "LMR Res",
             "PVS Res",
             "GlobTime",
@@ -96,19 +120,48 @@ pub fn iterative_deepening(
         });
     }
 
-    let mut best_eval_overall;
+    let mut best_eval_overall = 0;
+    let mut completed_depth = 0;
     let mut best_pv: Vec<EncodedMove> = Vec::new();
     let global_start = Instant::now();
     let mut previouse_iteration_ab_nodes: usize = 0;
     let mut previouse_iteration_qs_nodes: usize = 0;
     let mut killers = [EncodedMove(0); MAX_AB_DEPTH + 1];
+    let mut pv_table = PvTable::new();
+
+    // with tablebases covering the root only the moves keeping the best result get searched,
+    // once DTZ picked them probing inside the search can't tell them apart anymore
+    let tablebases = if settings::SYZYGY {
+        syzygy::tablebases()
+    } else {
+        None
+    };
+    let root = tablebases
+        .as_ref()
+        .and_then(|tablebases| tablebases.root_moves(board));
+    let probe_in_search = root.as_ref().is_none_or(|&(_, probe)| probe);
+    let retrograde = if settings::RETROGRADE {
+        retrograde::tables()
+    } else {
+        None
+    };
 
     HISTORY_TABLE.age();
     for depth in 1..=max_depth {
         let iteration_start = Instant::now();
+        let pawn_hash_stats_before = PAWN_HASH_TABLE.with_borrow(PawnHashTable::stats);
         let mut seldepth = 0;
-        let mut iteration_search_data =
-            SharedSearchData::new(board, &stop, &mut seldepth, &mut killers);
+        let mut iteration_search_data = SharedSearchData::new(
+            board,
+            &stop,
+            &mut seldepth,
+            &mut killers,
+            &mut pv_table,
+            &tt,
+        );
+        iteration_search_data.tablebases = tablebases.as_deref().filter(|_| probe_in_search);
+        iteration_search_data.root_moves = root.as_ref().map(|(moves, _)| moves.as_slice());
+        iteration_search_data.retrograde = retrograde.as_deref();
 
         let best_eval_local = alpha_beta::<true>(
             depth,
@@ -127,29 +180,12 @@ pub fn iterative_deepening(
         }
 
         best_eval_overall = best_eval_local;
-        let mut pv_local: Vec<EncodedMove> = Vec::new();
-
-        // TT-Walk to obtain the PV
-        let mut b = iteration_search_data.board.clone();
-        let mut ply: i32 = 0;
-
-        while pv_local.len() < depth
-            && let Some(tt_entry) = TT.probe(b.hash(), ply)
-            && let Some(tt_mv) = tt_entry.best_move()
-            && b.is_legal(&tt_mv.decode())
-        {
-            // We add a move even when it leads to a repetition because the move that leads to repetition is still part of pv
-            pv_local.push(tt_mv);
-            b.make_move(tt_mv);
-            ply += 1;
-
-            // If we caused a remis or repition, than cancel
-            if b.is_repetition_in_search() || b.is_50_move_rule() || b.is_insufficient_material() {
-                break;
-            }
-        }
+        completed_depth = depth;
 
-        best_pv = pv_local;
+        // The PV got collected during the search, so unlike a TT-walk it can't be cut short by overwritten entries
+        best_pv = iteration_search_data
+            .pv
+            .legal_line(iteration_search_data.board);
 
         let pv_string = best_pv
             .iter()
@@ -166,6 +202,7 @@ pub fn iterative_deepening(
             .load(Ordering::Relaxed);
         let iteration_not_eval_nodes = iteration_ab_nodes + iteration_qs_nodes;
         let iteration_nodes = iteration_not_eval_nodes + iteration_eval_nodes;
+        let iteration_tb_hits = iteration_search_data.total_tb_hits.load(Ordering::Relaxed);
 
         let iteration_duration = iteration_start.elapsed();
 
@@ -197,6 +234,18 @@ pub fn iterative_deepening(
                 0.0
             };
 
+            // the pawn hash table belongs to this thread, so everything it counted since the start of the iteration was this search
+            let (hits, probes) = PAWN_HASH_TABLE.with_borrow(PawnHashTable::stats);
+            let (iteration_hits, iteration_probes) = (
+                hits - pawn_hash_stats_before.0,
+                probes - pawn_hash_stats_before.1,
+            );
+            let pawn_hash_hit_rate = if iteration_probes > 0 {
+                iteration_hits as f64 * 100.0 / iteration_probes as f64
+            } else {
+                0.0
+            };
+
             let ab_ebf = if previouse_iteration_ab_nodes > 0 {
                 iteration_ab_nodes as f64 / previouse_iteration_ab_nodes as f64
             } else {
@@ -204,17 +253,18 @@ pub fn iterative_deepening(
             };
 
             println!(
-                "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {}",
+                "{:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {}",
                 depth,
                 seldepth,
                 best_eval_overall,
                 format_usize(iteration_nodes),
                 format_usize(nodes_per_seconds),
                 format_usize(iteration_duration.as_millis() as usize),
-                format_f64(TT.info().2),
+                format_f64(tt.info().2),
                 format_usize(iteration_ab_nodes),
                 format_usize(iteration_qs_nodes),
                 format_usize(iteration_tt_hits),
+                format_f64(pawn_hash_hit_rate),
                 format_usize(iteration_lmr_researches),
                 format_usize(iteration_pvs_researches),
                 format_usize(global_duration.as_millis() as usize),
@@ -223,24 +273,121 @@ pub fn iterative_deepening(
                 pv_string
             );
         } else {
-            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-            let fill_rate = TT.info().2 as usize;
+            let score = mate_in_moves(best_eval_overall).map_or_else(
+                || format!("cp {best_eval_overall}"),
+                |moves| format!("mate {moves}"),
+            );
             println!(
-                "info  depth {} seldepth {}  score cp {} nodes {} nps {} time {} tt {} pv {}",
+                "info  depth {} seldepth {}  score {} nodes {} nps {} tbhits {} time {} hashfull {} pv {}",
                 depth,
                 seldepth,
-                best_eval_overall,
+                score,
                 iteration_nodes,
                 nodes_per_seconds,
+                iteration_tb_hits,
                 iteration_duration.as_millis(),
-                fill_rate,
+                tt.hashfull(),
                 pv_string,
             );
         }
 
         previouse_iteration_ab_nodes = iteration_ab_nodes;
         previouse_iteration_qs_nodes = iteration_qs_nodes;
+
+        // `go mate n`: a mate in at most n moves is proven, deeper iterations can't find anything we are looking for
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        if let Some(mate) = mate
+            && let Some(moves) = mate_in_moves(best_eval_overall)
+            && moves > 0
+            && moves <= mate as i32
+        {
+            break;
+        }
+    }
+
+    SearchResult {
+        pv: best_pv,
+        score: best_eval_overall,
+        depth: completed_depth,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_mate_in(fen: &str, moves: usize) {
+        let mut board = Board::new(fen);
+        let pv = iterative_deepening(
+            &mut board,
+            MAX_AB_DEPTH,
+            Duration::from_secs(60),
+            Some(moves),
+            false,
+            false,
+        )
+        .pv;
+
+        assert!(pv.len() < 2 * moves, "{fen}: pv too long {}", pv.len());
+        for mv in &pv {
+            board.make_move(*mv);
+        }
+        assert!(board.is_in_check(), "{fen}: pv does not end in check");
+        assert!(
+            board.generate_all_moves().list.is_empty(),
+            "{fen}: pv does not end in mate"
+        );
     }
 
-    best_pv.first().copied()
+    #[test]
+    fn test_mate_in_2() {
+        assert_mate_in(
+            "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 1",
+            2,
+        );
+        assert_mate_in("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1", 2);
+        assert_mate_in("6k1/pp4p1/2p5/2bp4/8/P5Pb/1P3rrP/2BRRN1K b - - 0 1", 2);
+        assert_mate_in(
+            "r1bq2r1/b4pk1/p1pp1p2/1p2pP2/1P2P1PB/3P4/1PPQ2P1/R3K2R w KQ - 0 1",
+            2,
+        );
+        assert_mate_in(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+            2,
+        );
+        assert_mate_in("2k5/8/1K6/8/8/8/8/7R w - - 0 1", 2);
+    }
+
+    #[test]
+    fn test_zugzwang_mate() {
+        // after 1. Ra6 black is in zugzwang, passing would hold but every move gets mated
+        let mut board = Board::new("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1");
+        let result =
+            iterative_deepening(&mut board, 12, Duration::from_secs(60), None, false, false);
+        assert_eq!(mate_in_moves(result.score), Some(2));
+    }
+
+    #[test]
+    fn test_shortest_mate() {
+        // once a mate is found every other move only has to beat a mate bound, pruning them by their static eval
+        // reports a mate in 9 here instead
+        let mut board = Board::new("8/8/8/3k4/8/8/8/R3K2R w KQ - 0 1");
+        let result =
+            iterative_deepening(&mut board, 15, Duration::from_secs(120), None, false, false);
+        assert_eq!(mate_in_moves(result.score), Some(5));
+    }
+
+    #[test]
+    fn test_mate_in_3() {
+        assert_mate_in("r5rk/5p1p/5R2/4B3/8/8/7P/7K w - - 0 1", 3);
+        assert_mate_in(
+            "r3k2r/ppp2Npp/1b5n/4p2b/2B1P2q/BQP2P2/P5PP/RN5K w kq - 1 1",
+            3,
+        );
+    }
+
+    #[test]
+    fn test_mate_in_4() {
+        assert_mate_in("8/8/k7/8/2K5/8/8/6Q1 w - - 0 1", 4);
+    }
 }
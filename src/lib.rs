@@ -4,18 +4,28 @@
 
 mod alpha_beta;
 mod communication;
+mod correction_history;
 mod debug;
+mod experience;
 mod iterative_deepening;
+mod material_hash_table;
+mod pawn_hash_table;
 mod time_management;
 
+pub mod endgame;
+pub mod eval_params;
+pub mod eval_trace;
 pub mod evaluation;
 pub mod evaluation_constants;
 pub mod move_generator;
 pub mod move_picker;
 pub mod move_scoring;
+pub mod nnue;
 pub mod prelude;
 pub mod quiescence_search;
+pub mod retrograde;
 pub mod settings;
+pub mod syzygy;
 pub mod transposition_table;
 pub mod types;
 pub mod utils;
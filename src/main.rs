@@ -11,16 +11,26 @@
 //! Thunfisch is a UCI chess engine
 mod alpha_beta;
 mod communication;
+mod correction_history;
 mod debug;
+mod endgame;
+mod eval_params;
+mod eval_trace;
 mod evaluation;
 mod evaluation_constants;
+mod experience;
 mod iterative_deepening;
+mod material_hash_table;
 mod move_generator;
 mod move_picker;
 mod move_scoring;
+mod nnue;
+mod pawn_hash_table;
 mod prelude;
 mod quiescence_search;
+mod retrograde;
 mod settings;
+mod syzygy;
 mod time_management;
 mod transposition_table;
 mod types;
@@ -0,0 +1,257 @@
+use crate::{
+    endgame::SCALE_NORMAL,
+    eval_params::{ConstantParams, EvalParams},
+    eval_trace::EvalTrace,
+    prelude::*,
+    settings,
+};
+use std::cell::RefCell;
+
+/// Entries per table, indexed by the lower bits of the material key
+const MATERIAL_HASH_TABLE_SIZE: usize = 8192;
+
+thread_local! {
+    /// Material hash table of the current thread, see `PAWN_HASH_TABLE` for why it isn't shared
+    pub static MATERIAL_HASH_TABLE: RefCell<MaterialHashTable> = RefCell::new(MaterialHashTable::new());
+}
+
+/// Evaluation terms which only depend on the number of pieces of each figure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaterialEntry {
+    /// Format: `[white, black]`, added to the middlegame and the endgame score alike
+    pub imbalance: [i32; 2],
+    /// Scale factor of the evaluation depending on who is ahead, `[white, black]`
+    pub scale: [i32; 2],
+}
+
+impl MaterialEntry {
+    pub fn new<P: EvalParams + ?Sized>(board: &Board, params: &P) -> Self {
+        let counts = [White, Black].map(|color| piece_counts(board, color));
+        let imbalance = if settings::IMBALANCE {
+            [
+                imbalance(&counts[0], &counts[1], params),
+                imbalance(&counts[1], &counts[0], params),
+            ]
+        } else {
+            [0; 2]
+        };
+        let scale = if settings::MATERIAL_SCALING {
+            [
+                scale_factor(&counts[0], &counts[1], params),
+                scale_factor(&counts[1], &counts[0], params),
+            ]
+        } else {
+            [SCALE_NORMAL; 2]
+        };
+
+        Self { imbalance, scale }
+    }
+}
+
+/// Number of pieces of `color` in the order of the imbalance tables:
+/// bishop pair (0 or 1), pawns, knights, bishops, rooks and queens
+#[allow(clippy::cast_possible_wrap)]
+fn piece_counts(board: &Board, color: Color) -> [i32; 6] {
+    let count = |piece: Piece| board.figure_bb(color, piece).get_count() as i32;
+    [
+        i32::from(count(Bishop) >= 2),
+        count(Pawn),
+        count(Knight),
+        count(Bishop),
+        count(Rook),
+        count(Queen),
+    ]
+}
+
+/// Second degree polynomial over the piece counts of both sides: the value of a piece depends on
+/// what else is on the board, e.g. knights get better with more pawns and two rooks fare well against a queen.
+/// <https://www.chessprogramming.org/Material#Imbalance>
+fn imbalance<P: EvalParams + ?Sized>(ours: &[i32; 6], theirs: &[i32; 6], params: &P) -> i32 {
+    let [quadratic_ours, quadratic_theirs] = params.imbalance_tables();
+    let mut bonus = 0;
+    for piece in 0..6 {
+        if ours[piece] == 0 {
+            continue;
+        }
+        let mut value = 0;
+        // only the lower triangle is used, the upper one would count the same pair twice
+        for other in 0..=piece {
+            value += i32::from(quadratic_ours[piece][other]) * ours[other]
+                + i32::from(quadratic_theirs[piece][other]) * theirs[other];
+        }
+        bonus += ours[piece] * value;
+    }
+    bonus / 16
+}
+
+/// How much of its advantage the side with the pieces `ours` can convert by material alone,
+/// the specialised endgame evaluators and scale factors know more about the actual position
+fn scale_factor<P: EvalParams + ?Sized>(ours: &[i32; 6], theirs: &[i32; 6], params: &P) -> i32 {
+    scale_index(ours, theirs, params).map_or(SCALE_NORMAL, |index| {
+        i32::from(params.material_scale_factors()[index]).clamp(0, SCALE_NORMAL)
+    })
+}
+
+/// Index into `EvalParams::material_scale_factors` which applies to `ours`, `None` if the material isn't drawish
+fn scale_index<P: EvalParams + ?Sized>(
+    ours: &[i32; 6],
+    theirs: &[i32; 6],
+    params: &P,
+) -> Option<usize> {
+    let [mg_values, _] = params.piece_values();
+    let non_pawn_material = |counts: &[i32; 6]| {
+        counts[2] * mg_values[Knight as usize]
+            + counts[3] * mg_values[Bishop as usize]
+            + counts[4] * mg_values[Rook as usize]
+            + counts[5] * mg_values[Queen as usize]
+    };
+    let (ours_npm, theirs_npm) = (non_pawn_material(ours), non_pawn_material(theirs));
+    let bishop = mg_values[Bishop as usize];
+
+    match ours[1] {
+        // without pawns a minor piece ahead usually isn't enough to win
+        0 if ours_npm - theirs_npm <= bishop => {
+            if ours_npm < mg_values[Rook as usize] {
+                Some(0)
+            } else if theirs_npm <= bishop {
+                Some(1)
+            } else {
+                Some(2)
+            }
+        }
+        1 if ours_npm - theirs_npm <= bishop => Some(3),
+        _ => None,
+    }
+}
+
+/// The `EvalParams::material_scale_factors` which scale the evaluation of `board` for `[white, black]`
+pub fn scale_indices<P: EvalParams + ?Sized>(board: &Board, params: &P) -> [Option<usize>; 2] {
+    if !settings::MATERIAL_SCALING {
+        return [None; 2];
+    }
+    let counts = [White, Black].map(|color| piece_counts(board, color));
+    [
+        scale_index(&counts[0], &counts[1], params),
+        scale_index(&counts[1], &counts[0], params),
+    ]
+}
+
+/// Records the coefficients of the imbalance tables in `trace`, mirroring `imbalance`
+#[allow(clippy::cast_precision_loss)]
+pub fn trace_imbalance(board: &Board, trace: &mut EvalTrace) {
+    if !settings::IMBALANCE {
+        return;
+    }
+    let counts = [White, Black].map(|color| piece_counts(board, color));
+    for side in 0..2 {
+        let (ours, theirs) = (&counts[side], &counts[side ^ 1]);
+        for piece in 0..6 {
+            if ours[piece] == 0 {
+                continue;
+            }
+            for other in 0..=piece {
+                let coefficient = f64::from(ours[piece] * ours[other]) / 16.0;
+                trace.record(
+                    "imbalance_ours",
+                    piece * 6 + other,
+                    side,
+                    coefficient,
+                    coefficient,
+                );
+                let coefficient = f64::from(ours[piece] * theirs[other]) / 16.0;
+                trace.record(
+                    "imbalance_theirs",
+                    piece * 6 + other,
+                    side,
+                    coefficient,
+                    coefficient,
+                );
+            }
+        }
+    }
+}
+
+/// Caches the material terms of the evaluation by `Board::material_key`
+/// <https://www.chessprogramming.org/Material_Hash_Table>
+pub struct MaterialHashTable {
+    entries: Box<[Option<(u64, MaterialEntry)>]>,
+}
+
+impl MaterialHashTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; MATERIAL_HASH_TABLE_SIZE].into_boxed_slice(),
+        }
+    }
+
+    /// The material terms of `board` with the compiled in weights, only computed if the material isn't cached yet
+    pub fn probe(&mut self, board: &Board) -> MaterialEntry {
+        let key = board.material_key();
+        #[allow(clippy::cast_possible_truncation)]
+        let slot = &mut self.entries[key as usize % MATERIAL_HASH_TABLE_SIZE];
+
+        match slot {
+            Some((entry_key, entry)) if *entry_key == key => *entry,
+            _ => {
+                let entry = MaterialEntry::new(board, &ConstantParams);
+                *slot = Some((key, entry));
+                entry
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_terms_match_computation() {
+        let mut table = MaterialHashTable::new();
+        let mut board =
+            Board::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let expected = MaterialEntry::new(&board, &ConstantParams);
+        assert_eq!(table.probe(&board), expected);
+
+        // a quiet move keeps the material
+        board.make_move(DecodedMove::from_coords("e5g4", &board).encode());
+        assert_eq!(table.probe(&board), expected);
+
+        // a capture doesn't
+        board.make_move(DecodedMove::from_coords("e6d5", &board).encode());
+        assert_eq!(
+            table.probe(&board),
+            MaterialEntry::new(&board, &ConstantParams)
+        );
+    }
+
+    #[test]
+    fn test_symmetric_material() {
+        let entry = MaterialEntry::new(
+            &Board::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            &ConstantParams,
+        );
+        assert_eq!(entry.imbalance[0], entry.imbalance[1]);
+        assert_eq!(entry.scale, [SCALE_NORMAL; 2]);
+    }
+
+    #[test]
+    fn test_drawish_material() {
+        let scale = |fen: &str| MaterialEntry::new(&Board::new(fen), &ConstantParams).scale;
+
+        // a lone minor piece can't mate
+        assert_eq!(scale("4k3/8/8/8/8/8/8/3NK3 w - - 0 1")[0], 0);
+        // rook against minor piece is hard to win
+        let [white, black] = scale("4k3/8/8/3b4/8/8/8/3RK3 w - - 0 1");
+        assert!(white < SCALE_NORMAL / 4);
+        assert_eq!(black, 0);
+        // a queen against a rook is fine
+        assert_eq!(scale("4k3/8/8/3r4/8/8/8/3QK3 w - - 0 1")[0], SCALE_NORMAL);
+        // so are pawns
+        assert_eq!(scale("4k3/8/8/8/8/8/PPP5/3NK3 w - - 0 1")[0], SCALE_NORMAL);
+    }
+}
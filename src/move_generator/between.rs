@@ -16,7 +16,6 @@ pub static IN_BETWEEN: [[Bitboard; 64]; 64] = {
 };
 
 /// Returns all pos on a line between from (incluseive) and to (inclusive)
-#[allow(unused)]
 pub static LINE_THROUGH: [[Bitboard; 64]; 64] = {
     let mut arr = [[Bitboard::EMPTY; 64]; 64];
     let mut from = 0;
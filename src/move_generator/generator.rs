@@ -1,4 +1,14 @@
-use crate::{move_generator::moves, move_picker::MoveList, prelude::*};
+use crate::{
+    move_generator::{
+        between::LINE_THROUGH,
+        moves,
+        normal_targets::{KNIGHT_TARGETS, PAWN_ATTACK_TARGETS},
+        pinmask,
+        sliding_targets::{get_bishop_targets, get_rook_targets},
+    },
+    move_picker::MoveList,
+    prelude::*,
+};
 
 // 218 is the limit: https://www.chessprogramming.org/Chess_Position
 pub const MAX_MOVES_COUNT: usize = 218;
@@ -15,7 +25,7 @@ impl Board {
 
         // if it's double check we know we have to move the king so we return immediately
         if check_counter == 2 {
-            moves::generate_king_move::<QUIETS>(moves, friendly, self);
+            moves::generate_king_move::<QUIETS>(moves, friendly, self, Bitboard::FULL);
             return;
         }
 
@@ -56,7 +66,7 @@ impl Board {
         );
 
         // King moves after other pieces (matches legacy ordering for better move ordering)
-        moves::generate_king_move::<QUIETS>(moves, friendly, self);
+        moves::generate_king_move::<QUIETS>(moves, friendly, self, Bitboard::FULL);
 
         // castling is a quiet move
         if QUIETS {
@@ -82,8 +92,8 @@ impl Board {
 
         // if it's double check we know we have to move the king so we return immediately
         if check_counter == 2 {
-            moves::generate_king_move::<false>(&mut moves, friendly, self);
-            moves::generate_king_move::<true>(&mut moves, friendly, self);
+            moves::generate_king_move::<false>(&mut moves, friendly, self, Bitboard::FULL);
+            moves::generate_king_move::<true>(&mut moves, friendly, self, Bitboard::FULL);
             return moves;
         }
 
@@ -159,8 +169,8 @@ impl Board {
             check_mask,
         );
 
-        moves::generate_king_move::<false>(&mut moves, friendly, self);
-        moves::generate_king_move::<true>(&mut moves, friendly, self);
+        moves::generate_king_move::<false>(&mut moves, friendly, self, Bitboard::FULL);
+        moves::generate_king_move::<true>(&mut moves, friendly, self, Bitboard::FULL);
 
         moves::generate_castle_moves(&mut moves, check_counter, friendly, self);
         moves::generate_ep_moves(self, &mut moves, friendly, hv_pinmask, diag_pinmask);
@@ -168,6 +178,310 @@ impl Board {
         moves
     }
 
+    /// Generates the moves out of check, nothing when not in check.
+    /// In a double check only the king moves, otherwise the checkmask already restricts every other piece
+    /// to capturing the checker or blocking its line. Castling is never an evasion
+    pub fn generate_evasions(&mut self, moves: &mut MoveList) {
+        let check_counter = self.get_check_counter();
+        if check_counter == 0 {
+            return;
+        }
+        let friendly = self.current_color();
+
+        moves::generate_king_move::<false>(moves, friendly, self, Bitboard::FULL);
+        moves::generate_king_move::<true>(moves, friendly, self, Bitboard::FULL);
+        if check_counter == 2 {
+            return;
+        }
+
+        let (hv_pinmask, diag_pinmask) = self.get_pinmasks();
+        let check_mask = self.get_checkmask();
+        self.generate_piece_moves::<false>(moves, hv_pinmask, diag_pinmask, check_mask);
+        self.generate_piece_moves::<true>(moves, hv_pinmask, diag_pinmask, check_mask);
+        moves::generate_ep_moves(self, moves, friendly, hv_pinmask, diag_pinmask);
+    }
+
+    /// Generates the captures and queen promotions (as split by `generate_moves::<false>`) landing on `targets`,
+    /// e.g. the recaptures on the square of the last move.
+    /// `targets` narrows the checkmask, so the generators never produce moves onto other squares
+    pub fn generate_captures_on(&mut self, moves: &mut MoveList, targets: Bitboard) {
+        let friendly = self.current_color();
+        let check_counter = self.get_check_counter();
+
+        if check_counter < 2 {
+            let (hv_pinmask, diag_pinmask) = self.get_pinmasks();
+            let check_mask = self.get_checkmask() & targets;
+            self.generate_piece_moves::<false>(moves, hv_pinmask, diag_pinmask, check_mask);
+            if self
+                .ep_target()
+                .is_some_and(|ep_target| targets.is_position_set(ep_target))
+            {
+                moves::generate_ep_moves(self, moves, friendly, hv_pinmask, diag_pinmask);
+            }
+        }
+        moves::generate_king_move::<false>(moves, friendly, self, targets);
+    }
+
+    /// The moves of every piece but the king onto `check_mask`, captures or quiets like `generate_moves`
+    fn generate_piece_moves<const QUIETS: bool>(
+        &self,
+        moves: &mut MoveList,
+        hv_pinmask: Bitboard,
+        diag_pinmask: Bitboard,
+        check_mask: Bitboard,
+    ) {
+        let friendly = self.current_color();
+        let pinmask = hv_pinmask | diag_pinmask;
+
+        moves::generate_pawn_moves::<QUIETS>(
+            moves,
+            self,
+            friendly,
+            hv_pinmask,
+            diag_pinmask,
+            check_mask,
+        );
+        moves::generate_knight_moves::<QUIETS>(moves, pinmask, friendly, self, check_mask);
+        moves::generate_bishop_moves::<QUIETS>(
+            moves,
+            hv_pinmask,
+            diag_pinmask,
+            friendly,
+            self,
+            check_mask,
+        );
+        moves::generate_rook_moves::<QUIETS>(
+            moves,
+            hv_pinmask,
+            diag_pinmask,
+            friendly,
+            self,
+            check_mask,
+        );
+        moves::generate_queen_moves::<QUIETS>(
+            moves,
+            hv_pinmask,
+            diag_pinmask,
+            friendly,
+            self,
+            check_mask,
+        );
+    }
+
+    /// Generates the quiet moves (as split by `generate_moves::<true>`) that give check.
+    /// Direct checks are generated onto the squares the moved piece attacks the enemy king from,
+    /// discovered checks move one of our pieces out of the way of our own slider (found with the same x-ray as the pinmask).
+    /// Promotions and castling change more than these squares know about, so only they get tested one by one.
+    #[allow(clippy::too_many_lines)]
+    pub fn generate_quiet_checks(&mut self, moves: &mut MoveList) {
+        let friendly = self.current_color();
+        let king_sq = self.king(!friendly).to_square();
+        let occupied = self.occupied();
+
+        let (hv_pinmask, diag_pinmask) = self.get_pinmasks();
+        let pinmask = hv_pinmask | diag_pinmask;
+        let check_counter = self.get_check_counter();
+        let check_mask = self.get_checkmask();
+
+        let promotion_rank = match friendly {
+            White => Bitboard(0xFF00_0000_0000_0000),
+            Black => Bitboard(0xFF),
+        };
+        let pawn_checks = PAWN_ATTACK_TARGETS[!friendly as usize][king_sq];
+        let knight_checks = KNIGHT_TARGETS[king_sq];
+        let bishop_checks = get_bishop_targets(king_sq, occupied);
+        let rook_checks = get_rook_targets(king_sq, occupied);
+        let queen_checks = bishop_checks | rook_checks;
+
+        let mut tested = MoveList::new();
+        // in a double check only the king moves, which can only give a discovered check
+        if check_counter < 2 {
+            // a pawn never gives check from the promotion rank, so these are no promotions
+            moves::generate_pawn_moves::<true>(
+                moves,
+                self,
+                friendly,
+                hv_pinmask,
+                diag_pinmask,
+                check_mask & pawn_checks,
+            );
+            moves::generate_knight_moves::<true>(
+                moves,
+                pinmask,
+                friendly,
+                self,
+                check_mask & knight_checks,
+            );
+            moves::generate_bishop_moves::<true>(
+                moves,
+                hv_pinmask,
+                diag_pinmask,
+                friendly,
+                self,
+                check_mask & bishop_checks,
+            );
+            moves::generate_rook_moves::<true>(
+                moves,
+                hv_pinmask,
+                diag_pinmask,
+                friendly,
+                self,
+                check_mask & rook_checks,
+            );
+            moves::generate_queen_moves::<true>(
+                moves,
+                hv_pinmask,
+                diag_pinmask,
+                friendly,
+                self,
+                check_mask & queen_checks,
+            );
+
+            moves::generate_pawn_moves::<true>(
+                &mut tested,
+                self,
+                friendly,
+                hv_pinmask,
+                diag_pinmask,
+                check_mask & promotion_rank,
+            );
+            moves::generate_castle_moves(&mut tested, check_counter, friendly, self);
+        }
+        for entry in tested.list {
+            if self.gives_check(entry.mv.decode()) {
+                moves.push(entry.mv);
+            }
+        }
+
+        let discoverers = pinmask::generate_discoverers(self);
+        if discoverers.is_empty() {
+            return;
+        }
+
+        // only the moves of the discoverers leaving the line to the king are left,
+        // the ones landing on a direct check square are already generated above
+        let mut discovered = MoveList::new();
+        let has_discoverer =
+            |piece: Piece| !(self.figure_bb(friendly, piece) & discoverers).is_empty();
+        if check_counter < 2 {
+            if has_discoverer(Pawn) {
+                moves::generate_pawn_moves::<true>(
+                    &mut discovered,
+                    self,
+                    friendly,
+                    hv_pinmask,
+                    diag_pinmask,
+                    check_mask & !pawn_checks & !promotion_rank,
+                );
+            }
+            if has_discoverer(Knight) {
+                moves::generate_knight_moves::<true>(
+                    &mut discovered,
+                    pinmask,
+                    friendly,
+                    self,
+                    check_mask & !knight_checks,
+                );
+            }
+            if has_discoverer(Bishop) {
+                moves::generate_bishop_moves::<true>(
+                    &mut discovered,
+                    hv_pinmask,
+                    diag_pinmask,
+                    friendly,
+                    self,
+                    check_mask & !bishop_checks,
+                );
+            }
+            if has_discoverer(Rook) {
+                moves::generate_rook_moves::<true>(
+                    &mut discovered,
+                    hv_pinmask,
+                    diag_pinmask,
+                    friendly,
+                    self,
+                    check_mask & !rook_checks,
+                );
+            }
+            if has_discoverer(Queen) {
+                moves::generate_queen_moves::<true>(
+                    &mut discovered,
+                    hv_pinmask,
+                    diag_pinmask,
+                    friendly,
+                    self,
+                    check_mask & !queen_checks,
+                );
+            }
+        }
+        if has_discoverer(King) {
+            moves::generate_king_move::<true>(&mut discovered, friendly, self, Bitboard::FULL);
+        }
+        for entry in discovered.list {
+            let mv = entry.mv.decode();
+            if discoverers.is_position_set(mv.from.to_bit())
+                && !LINE_THROUGH[king_sq][mv.from].is_position_set(mv.to.to_bit())
+            {
+                moves.push(entry.mv);
+            }
+        }
+    }
+
+    /// Whether the legal move `mv` puts the enemy king in check, directly or by discovery
+    /// Looks at the occupancy after the move from the enemy kings perspective, so castling rooks and ep are covered aswell
+    pub fn gives_check(&self, mv: DecodedMove) -> bool {
+        let friendly = self.current_color();
+        let king_sq = self.king(!friendly).to_square();
+        let from = mv.from.to_bit();
+        let to = mv.to.to_bit();
+        let moved_piece = mv
+            .mv_type
+            .to_promotion_piece()
+            .unwrap_or_else(|| self.piece_at_position(mv.from));
+
+        let mut occupied = (self.occupied() & !from) | to;
+        // the castling rook moves aswell, (from, to)
+        let rook_move = match (mv.mv_type, friendly) {
+            (MoveType::KingCastle, White) => Some((Square(7), Square(5))),
+            (MoveType::QueenCastle, White) => Some((Square(0), Square(3))),
+            (MoveType::KingCastle, Black) => Some((Square(63), Square(61))),
+            (MoveType::QueenCastle, Black) => Some((Square(56), Square(59))),
+            _ => None,
+        };
+        if let Some((rook_from, rook_to)) = rook_move {
+            occupied = (occupied & !rook_from.to_bit()) | rook_to.to_bit();
+        }
+        if mv.mv_type == MoveType::EpCapture {
+            let captured_pawn = match friendly {
+                White => to >> 8,
+                Black => to << 8,
+            };
+            occupied &= !captured_pawn;
+        }
+
+        let pieces_after = |piece: Piece| {
+            let mut bb = self.figure_bb(friendly, piece) & !from;
+            if piece == moved_piece {
+                bb |= to;
+            }
+            if piece == Rook
+                && let Some((rook_from, rook_to)) = rook_move
+            {
+                bb = (bb & !rook_from.to_bit()) | rook_to.to_bit();
+            }
+            bb
+        };
+
+        let queens = pieces_after(Queen);
+        // a pawn of the enemy color on the king square "attacks" the squares our pawns would give check from
+        let attackers = (PAWN_ATTACK_TARGETS[!friendly as usize][king_sq] & pieces_after(Pawn))
+            | (KNIGHT_TARGETS[king_sq] & pieces_after(Knight))
+            | (get_bishop_targets(king_sq, occupied) & (pieces_after(Bishop) | queens))
+            | (get_rook_targets(king_sq, occupied) & (pieces_after(Rook) | queens));
+
+        !attackers.is_empty()
+    }
+
     pub fn is_in_check(&mut self) -> bool {
         if self.get_check_counter() == 0 {
             return false;
@@ -178,7 +492,7 @@ impl Board {
 
 #[cfg(test)]
 mod test {
-    use crate::{debug::perft, prelude::*};
+    use crate::{debug::perft, move_picker::MoveList, prelude::*};
 
     #[test]
     /// Tests the move generation by checking if it finds the correct amount of moves
@@ -218,4 +532,113 @@ mod test {
             }
         }
     }
+
+    fn sorted(moves: &MoveList) -> Vec<u16> {
+        let mut moves: Vec<u16> = moves.list.iter().map(|entry| entry.mv.0).collect();
+        moves.sort_unstable();
+        moves
+    }
+
+    /// Checks for every node of a small perft tree that captures, quiet checks and the other quiets add up to
+    /// `generate_all_moves` and compares `gives_check` and the quiet checks against actually playing the move
+    fn generation_modes_perft(board: &mut Board, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        let all_moves = board.generate_all_moves();
+
+        // evasions are everything when in check and nothing otherwise
+        let mut evasions = MoveList::new();
+        board.generate_evasions(&mut evasions);
+        if board.is_in_check() {
+            assert_eq!(sorted(&evasions), sorted(&all_moves), "{}", board.fen());
+        } else {
+            assert!(evasions.list.is_empty(), "{}", board.fen());
+        }
+
+        // captures onto a target set are exactly the captures landing there,
+        // the same split as `generate_moves` so queen promotions count and underpromotions don't
+        let mut all_captures = MoveList::new();
+        board.generate_moves::<false>(&mut all_captures);
+        for targets in [
+            Bitboard(0x0000_0000_FFFF_FFFF),
+            Bitboard(0xFFFF_FFFF_0000_0000),
+            Bitboard(0x00FF_0000_0000_FF00),
+            board.color_bbs_without_king(!board.current_color()),
+        ] {
+            let mut captures = MoveList::new();
+            board.generate_captures_on(&mut captures, targets);
+            let mut expected = MoveList::new();
+            for entry in &all_captures.list {
+                if targets.is_position_set(entry.mv.decode().to.to_bit()) {
+                    expected.push(entry.mv);
+                }
+            }
+            assert_eq!(sorted(&captures), sorted(&expected), "{}", board.fen());
+        }
+
+        // captures, quiet checks and the remaining quiets
+        let mut union = MoveList::new();
+        board.generate_moves::<false>(&mut union);
+        let mut quiet_checks = MoveList::new();
+        board.generate_quiet_checks(&mut quiet_checks);
+        let mut quiets = MoveList::new();
+        board.generate_moves::<true>(&mut quiets);
+        for entry in &quiet_checks.list {
+            union.push(entry.mv);
+        }
+
+        for entry in quiets.list {
+            let mv = entry.mv;
+            board.make_move(mv);
+            let is_check = board.is_in_check();
+            board.unmake_move();
+            let is_quiet_check = quiet_checks.list.iter().any(|check| check.mv == mv);
+            assert_eq!(
+                is_check,
+                is_quiet_check,
+                "quiet check wrong for {} in {}",
+                mv.decode().to_coords(),
+                board.fen()
+            );
+            if !is_check {
+                union.push(mv);
+            }
+        }
+        assert_eq!(sorted(&union), sorted(&all_moves), "{}", board.fen());
+
+        for entry in all_moves.list {
+            let mv = entry.mv;
+            let gives_check = board.gives_check(mv.decode());
+            board.make_move(mv);
+            assert_eq!(
+                gives_check,
+                board.is_in_check(),
+                "gives_check wrong for {} after {}",
+                board.fen(),
+                mv.decode().to_coords()
+            );
+            generation_modes_perft(board, depth - 1);
+            board.unmake_move();
+        }
+    }
+
+    #[test]
+    fn test_generation_modes() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ", // Pos 2
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1 ",                        // Pos 3
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",  // Pos 4
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8 ",        // Pos 5
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",                                    // castling check
+            "8/8/8/1k6/3Pp3/8/8/4KQ2 b - d3 0 1",                                // ep
+            "8/1P6/8/8/8/8/1k6/4K3 w - - 0 1", // promotion checking through its own square
+            "4k3/8/8/4N3/4Q3/8/8/4R1K1 w - - 0 1", // knight and queen discovering checks
+            "7k/8/8/8/3P4/2R5/1B6/K7 w - - 0 1", // pawn and rook discovering checks
+            "7k/8/8/8/8/8/1K6/Q7 w - - 0 1",   // king discovering a check
+        ];
+        for fen in fens {
+            generation_modes_perft(&mut Board::new(fen), 3);
+        }
+    }
 }
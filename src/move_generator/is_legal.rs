@@ -83,6 +83,18 @@ impl Board {
             return false;
         }
 
+        // A pawn promotes exactly when it reaches the last rank, otherwise e.g. a quiet king move
+        // from the killers turns into a pawn push onto the last rank that never promotes
+        let promotion_rank = match current_color {
+            White => Bitboard(0xFF00_0000_0000_0000),
+            Black => Bitboard(0xFF),
+        };
+        if from_piece == Piece::Pawn
+            && MoveType::is_promotion(mv_type) != promotion_rank.is_position_set(mv.to.to_bit())
+        {
+            return false;
+        }
+
         // Do we capture a king?
         if to_piece == Piece::King {
             return false;
@@ -468,6 +480,27 @@ mod tests_perft {
         }
     }
 
+    #[test]
+    fn test_pawn_moves_promote_on_the_last_rank() {
+        let mut board = Board::new("8/3P1k2/8/8/8/8/4P3/4K3 w - - 0 1");
+        let mv = |from: usize, to: usize, mv_type: MoveType| DecodedMove {
+            from: Square(from),
+            to: Square(to),
+            mv_type,
+        };
+        // d7d8 as a quiet move, e.g. a killer from a position with the king on d7
+        assert!(!board.is_legal(&mv(51, 59, MoveType::Quiet)));
+        assert!(board.is_legal(&mv(51, 59, MoveType::QueenPromo)));
+        assert!(board.is_legal(&mv(51, 59, MoveType::KnightPromo)));
+        assert!(board.is_legal(&mv(12, 20, MoveType::Quiet)));
+        assert!(!board.is_legal(&mv(12, 20, MoveType::QueenPromo)));
+
+        let mut board = Board::new("4k3/4p3/8/8/8/8/1p6/R3K3 b - - 0 1");
+        assert!(!board.is_legal(&mv(9, 0, MoveType::Capture)));
+        assert!(board.is_legal(&mv(9, 0, MoveType::RookPromoCapture)));
+        assert!(board.is_legal(&mv(52, 44, MoveType::Quiet)));
+    }
+
     /// Only used for testing
     /// Important to pass board as value (so copying it) because we change the masks
     fn pseudo_legal_generate(mut board: Board) -> MoveList {
@@ -4,6 +4,6 @@ mod is_legal;
 mod magics;
 pub mod masks;
 pub mod moves;
-mod normal_targets;
+pub mod normal_targets;
 pub mod pinmask;
-mod sliding_targets;
+pub mod sliding_targets;
@@ -461,6 +461,7 @@ pub fn generate_king_move<const QUIETS: bool>(
     moves: &mut MoveList,
     friendly: Color,
     board: &Board,
+    target_mask: Bitboard,
 ) {
     let from_bit = board.king(friendly);
     let from = from_bit.to_square();
@@ -468,7 +469,7 @@ pub fn generate_king_move<const QUIETS: bool>(
     let attackmask = masks::calculate_attackmask(board, occupied_without_king, !friendly, None);
 
     let potential_targets = normal_targets::KING_TARGETS[from.i()];
-    let targets = potential_targets & !attackmask;
+    let targets = potential_targets & !attackmask & target_mask;
 
     if QUIETS {
         let mut quiet_targets = targets & board.empty();
@@ -45,6 +45,31 @@ pub fn generate_pin_masks(board: &Board) -> (Bitboard, Bitboard) {
     (pin_hv, pin_diag)
 }
 
+/// Our own pieces standing between one of our sliders and the enemy king.
+/// Same x-ray as for the pinmask just from the enemy kings view and with our pieces as blockers,
+/// moving such a piece off the line gives a discovered check.
+pub fn generate_discoverers(board: &Board) -> Bitboard {
+    let mut discoverers = Bitboard::EMPTY;
+
+    let friendly = board.current_color();
+    let king_sq = board.king(!friendly).to_square();
+    let occ = board.occupied();
+    let friendly_bb = board.color_bbs(friendly);
+
+    let rooks_queens = board.figure_bb(friendly, Rook) | board.figure_bb(friendly, Queen);
+    let bishops_queens = board.figure_bb(friendly, Bishop) | board.figure_bb(friendly, Queen);
+
+    let mut sliders = (get_rook_xray_targets(king_sq, occ, friendly_bb) & rooks_queens)
+        | (get_bishop_xray_targets(king_sq, occ, friendly_bb) & bishops_queens);
+
+    for slider_bb in sliders.iter_mut() {
+        let sq = slider_bb.to_square();
+        discoverers |= IN_BETWEEN[sq.i()][king_sq.i()] & friendly_bb;
+    }
+
+    discoverers
+}
+
 /// calculate squares which will be attacked by the rook when the blockers will be seen as transparent
 /// <https://www.chessprogramming.org/X-ray_Attacks>_(Bitboards)
 fn get_rook_xray_targets(square: Square, occ: Bitboard, blockers: Bitboard) -> Bitboard {
@@ -15,6 +15,8 @@ enum GenerationState {
     Killer,
     Quiets,
     YieldQuiets,
+    QuietChecks,
+    YieldQuietChecks,
     Done,
 }
 
@@ -66,6 +68,7 @@ pub struct MovePicker {
     state: GenerationState,
     move_index: usize,
     skip_quiets: bool,
+    quiet_checks: bool,
 }
 
 impl MovePicker {
@@ -81,6 +84,15 @@ impl MovePicker {
             state: GenerationState::TTMove,
             move_index: 0,
             skip_quiets,
+            quiet_checks: false,
+        }
+    }
+
+    /// Captures followed by the quiet moves that give check, used at the first ply of QS
+    pub fn with_quiet_checks(tt_move: Option<EncodedMove>) -> Self {
+        Self {
+            quiet_checks: true,
+            ..Self::new(tt_move, None, true)
         }
     }
     pub fn next(&mut self, board: &mut Board) -> Option<EncodedMove> {
@@ -107,8 +119,12 @@ impl MovePicker {
                 if let Some(mv) = self.yield_next_best_move() {
                     Some(mv)
                 } else if self.skip_quiets {
-                    self.state = GenerationState::Done;
-                    None
+                    self.state = if self.quiet_checks {
+                        GenerationState::QuietChecks
+                    } else {
+                        GenerationState::Done
+                    };
+                    self.next(board)
                 } else {
                     self.state = GenerationState::Killer;
                     self.next(board)
@@ -133,7 +149,14 @@ impl MovePicker {
                 self.next(board)
             }
 
-            GenerationState::YieldQuiets => {
+            GenerationState::QuietChecks => {
+                board.generate_quiet_checks(&mut self.move_list);
+                move_scoring::score_quiets(&mut self.move_list, board);
+                self.state = GenerationState::YieldQuietChecks;
+                self.next(board)
+            }
+
+            GenerationState::YieldQuiets | GenerationState::YieldQuietChecks => {
                 if let Some(mv) = self.yield_next_best_move() {
                     Some(mv)
                 } else {
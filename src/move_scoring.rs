@@ -12,7 +12,7 @@ const KING_VALUE: i32 = 10000;
 const ORDERING_OFFSET: i32 = 10000;
 const ORDERING_MULTIPLIER: i32 = 100;
 
-const PIECE_VALUES: [i32; 6] = [
+pub const PIECE_VALUES: [i32; 6] = [
     PAWN_VALUE,
     KNIGHT_VALUE,
     BISHOP_VALUE,
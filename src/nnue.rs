@@ -0,0 +1,482 @@
+// NNUE evaluation: a (768 -> HIDDEN_SIZE)x2 -> 1 perspective network
+// <https://www.chessprogramming.org/NNUE>
+//
+// Network file layout (little endian i16, no header, as written by the usual trainers for this architecture):
+// - feature weights `[INPUT_SIZE][HIDDEN_SIZE]`
+// - feature bias `[HIDDEN_SIZE]`
+// - output weights `[2 * HIDDEN_SIZE]`, the half of the side to move first
+// - output bias
+
+use crate::{
+    evaluation::MATE_THRESHOLD,
+    prelude::*,
+    settings,
+    utils::persistence::{self, invalid_data},
+};
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+/// Neurons of the hidden layer per perspective, networks have to be trained with this size
+pub const HIDDEN_SIZE: usize = 128;
+
+/// One input per figure and square: `[own, opponent][piece][square]` relative to the perspective
+const INPUT_SIZE: usize = 768;
+
+/// Quantisation of the feature transformer, activations get clamped to `0..=QA`
+const QA: i32 = 255;
+
+/// Quantisation of the output weights
+const QB: i32 = 64;
+
+/// Maps the network output to centipawns
+const EVAL_SCALE: i32 = 400;
+
+/// Networks whose output weights all stay within this bound keep `clamp(input) * weight` within an i16,
+/// which the SIMD inference relies on. Networks with larger ones are evaluated by the scalar version
+const MAX_SIMD_OUTPUT_WEIGHT: i16 = 128;
+
+// `QA * weight` has to fit into an i16 and the SIMD dot product of one perspective into an i32
+#[allow(clippy::cast_possible_wrap)]
+const _: () = {
+    assert!(QA * MAX_SIMD_OUTPUT_WEIGHT as i32 <= i16::MAX as i32);
+    assert!(
+        HIDDEN_SIZE as i64 * (QA * QA) as i64 * MAX_SIMD_OUTPUT_WEIGHT as i64 <= i32::MAX as i64
+    );
+};
+
+const NETWORK_BYTES: usize = 2 * (INPUT_SIZE * HIDDEN_SIZE + HIDDEN_SIZE + 2 * HIDDEN_SIZE + 1);
+
+/// Network loaded via the `EvalFile` option
+static NETWORK: Mutex<Option<Arc<Network>>> = Mutex::new(None);
+
+/// The network the search should evaluate with, `None` means the handcrafted evaluation is used
+pub fn network() -> Option<Arc<Network>> {
+    if !settings::NNUE {
+        return None;
+    }
+    NETWORK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Replaces the loaded network, `None` goes back to the handcrafted evaluation
+pub fn load_network(path: Option<&Path>) -> io::Result<()> {
+    let network = match path {
+        Some(path) => Some(Arc::new(Network::from_bytes(&fs::read(path)?)?)),
+        None => None,
+    };
+    *NETWORK.lock().unwrap_or_else(PoisonError::into_inner) = network;
+    Ok(())
+}
+
+/// Hidden layer of one perspective
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C, align(64))]
+pub struct Accumulator([i16; HIDDEN_SIZE]);
+
+impl Accumulator {
+    /// Accumulators only ever get rows added and removed again, wrapping keeps both exact inverses
+    #[inline]
+    fn add(&mut self, row: &Self) {
+        for (value, weight) in self.0.iter_mut().zip(&row.0) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    #[inline]
+    fn sub(&mut self, row: &Self) {
+        for (value, weight) in self.0.iter_mut().zip(&row.0) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+}
+
+pub struct Network {
+    /// One row per input feature
+    feature_weights: Box<[Accumulator]>,
+    feature_bias: Accumulator,
+    /// Format: `[side to move, other side]`
+    output_weights: [Accumulator; 2],
+    output_bias: i16,
+    /// Whether all output weights are small enough for the SIMD inference
+    simd_output: bool,
+    /// Hash of the raw network, ties persisted scores to the network they were searched with
+    fingerprint: u64,
+}
+
+impl Network {
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != NETWORK_BYTES {
+            return Err(invalid_data(format!(
+                "expected a network of {NETWORK_BYTES} bytes ({INPUT_SIZE} -> {HIDDEN_SIZE})x2 -> 1, got {} bytes",
+                bytes.len()
+            )));
+        }
+
+        let mut values = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut accumulator = || Accumulator(std::array::from_fn(|_| values.next().unwrap_or(0)));
+
+        let feature_weights = (0..INPUT_SIZE).map(|_| accumulator()).collect();
+        let feature_bias = accumulator();
+        let output_weights = [accumulator(), accumulator()];
+        let output_bias = values.next().unwrap_or(0);
+
+        let simd_output = output_weights
+            .iter()
+            .flat_map(|weights| weights.0)
+            .all(|weight| weight.unsigned_abs() <= MAX_SIMD_OUTPUT_WEIGHT.unsigned_abs());
+
+        Ok(Self {
+            feature_weights,
+            feature_bias,
+            output_weights,
+            output_bias,
+            simd_output,
+            fingerprint: persistence::fingerprint(&bytes),
+        })
+    }
+
+    pub const fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// Both accumulators of `board` computed from scratch
+    fn refresh(&self, board: &Board) -> [Accumulator; 2] {
+        let mut accumulators = [self.feature_bias; 2];
+        for i in 0..=11 {
+            let figure = Figure::from_idx(i);
+            for bit in board.figure_bb_by_index(i).iter_mut() {
+                for perspective in [White, Black] {
+                    accumulators[perspective as usize]
+                        .add(&self.feature_weights[feature(perspective, figure, bit.to_square())]);
+                }
+            }
+        }
+        accumulators
+    }
+
+    /// Score in centipawns from the perspective of the side whose accumulator is `us`
+    #[allow(clippy::cast_possible_truncation)]
+    fn evaluate(&self, us: &Accumulator, them: &Accumulator) -> i32 {
+        let sum = self.screlu_dot(us, &self.output_weights[0])
+            + self.screlu_dot(them, &self.output_weights[1]);
+        let eval = (sum / i64::from(QA) + i64::from(self.output_bias)) * i64::from(EVAL_SCALE)
+            / i64::from(QA * QB);
+        // the clamp keeps it within i32
+        eval.clamp(
+            i64::from(-MATE_THRESHOLD + 1),
+            i64::from(MATE_THRESHOLD - 1),
+        ) as i32
+    }
+
+    /// `sum(clamp(input, 0, QA)² * weight)`, the `SCReLU` activation fused with the output layer
+    fn screlu_dot(&self, input: &Accumulator, weights: &Accumulator) -> i64 {
+        #[cfg(target_arch = "x86_64")]
+        if self.simd_output && is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, checked right above
+            return i64::from(unsafe { avx2::screlu_dot(input, weights) });
+        }
+        scalar::screlu_dot(input, weights)
+    }
+}
+
+/// Input index of `figure` on `square` as seen by `perspective`,
+/// black sees the board flipped so both sides share the same weights
+const fn feature(perspective: Color, figure: Figure, square: Square) -> usize {
+    // white figures have even indices
+    let idx = figure as usize;
+    let (piece, color) = (idx >> 1, idx & 1);
+    match perspective {
+        White => color * 384 + piece * 64 + square.i(),
+        Black => (color ^ 1) * 384 + piece * 64 + (square.i() ^ 0x38),
+    }
+}
+
+/// The accumulators of a board, kept up to date by `Board::toggle`
+#[derive(Clone)]
+pub struct NnueState {
+    network: Arc<Network>,
+    /// Format: `[white, black]` perspective
+    accumulators: [Accumulator; 2],
+}
+
+impl NnueState {
+    pub fn new(network: Arc<Network>, board: &Board) -> Self {
+        let accumulators = network.refresh(board);
+        Self {
+            network,
+            accumulators,
+        }
+    }
+
+    pub const fn network(&self) -> &Arc<Network> {
+        &self.network
+    }
+
+    /// Adds `figure` on `square` when `placing`, removes it otherwise
+    #[inline]
+    pub fn toggle(&mut self, figure: Figure, square: Square, placing: bool) {
+        if figure == Figure::Empty {
+            return;
+        }
+        for perspective in [White, Black] {
+            let row = &self.network.feature_weights[feature(perspective, figure, square)];
+            let accumulator = &mut self.accumulators[perspective as usize];
+            if placing {
+                accumulator.add(row);
+            } else {
+                accumulator.sub(row);
+            }
+        }
+    }
+
+    /// Whether the incremental updates still match a full recomputation
+    pub fn is_consistent(&self, board: &Board) -> bool {
+        self.accumulators == self.network.refresh(board)
+    }
+
+    /// Score in centipawns from the perspective of `color`
+    pub fn evaluate(&self, color: Color) -> i32 {
+        let us = &self.accumulators[color as usize];
+        let them = &self.accumulators[!color as usize];
+        self.network.evaluate(us, them)
+    }
+}
+
+mod scalar {
+    use super::{Accumulator, QA};
+
+    /// Sums in i64, so any output weights work
+    pub fn screlu_dot(input: &Accumulator, weights: &Accumulator) -> i64 {
+        input
+            .0
+            .iter()
+            .zip(&weights.0)
+            .map(|(&value, &weight)| {
+                let value = i64::from(value).clamp(0, i64::from(QA));
+                value * value * i64::from(weight)
+            })
+            .sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use super::{Accumulator, HIDDEN_SIZE, QA};
+    use std::arch::x86_64::{
+        __m256i, _mm_add_epi32, _mm_cvtsi128_si32, _mm_shuffle_epi32, _mm256_add_epi32,
+        _mm256_castsi256_si128, _mm256_extracti128_si256, _mm256_loadu_si256, _mm256_madd_epi16,
+        _mm256_max_epi16, _mm256_min_epi16, _mm256_mullo_epi16, _mm256_set1_epi16,
+        _mm256_setzero_si256,
+    };
+
+    const LANES: usize = 16;
+    const _: () = assert!(HIDDEN_SIZE.is_multiple_of(LANES));
+
+    /// Same result as the scalar version as long as `clamp(input) * weight` fits into an i16,
+    /// which `Network` only uses it for when the output weights are small enough
+    #[target_feature(enable = "avx2")]
+    pub fn screlu_dot(input: &Accumulator, weights: &Accumulator) -> i32 {
+        let zero = _mm256_setzero_si256();
+        #[allow(clippy::cast_possible_truncation)]
+        let qa = _mm256_set1_epi16(QA as i16);
+        let mut sum = _mm256_setzero_si256();
+
+        for (values, weights) in input
+            .0
+            .chunks_exact(LANES)
+            .zip(weights.0.chunks_exact(LANES))
+        {
+            // SAFETY: both chunks are exactly 16 i16 (256 bit) long and loadu has no alignment requirements
+            #[allow(clippy::cast_ptr_alignment)]
+            let (values, weights) = unsafe {
+                (
+                    _mm256_loadu_si256(values.as_ptr().cast::<__m256i>()),
+                    _mm256_loadu_si256(weights.as_ptr().cast::<__m256i>()),
+                )
+            };
+            let clamped = _mm256_min_epi16(_mm256_max_epi16(values, zero), qa);
+            // (v * w) * v instead of v² * w, so the first product stays within i16
+            let product = _mm256_mullo_epi16(clamped, weights);
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(product, clamped));
+        }
+
+        let sum = _mm_add_epi32(
+            _mm256_castsi256_si128(sum),
+            _mm256_extracti128_si256::<1>(sum),
+        );
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32::<0b0100_1110>(sum));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32::<0b1011_0001>(sum));
+        _mm_cvtsi128_si32(sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::board::START_POS;
+
+    /// Deterministic pseudo random network, weights roughly within the ranges a trained network has
+    fn test_network_bytes(seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        // uniform within `-range..=range`
+        let mut next = |range: i16| {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            i16::try_from(state % (2 * u64::from(range.unsigned_abs()) + 1)).unwrap() - range
+        };
+
+        let mut values = Vec::with_capacity(NETWORK_BYTES / 2);
+        values.extend((0..INPUT_SIZE * HIDDEN_SIZE).map(|_| next(40)));
+        values.extend((0..HIDDEN_SIZE).map(|_| next(100)));
+        values.extend((0..2 * HIDDEN_SIZE).map(|_| next(MAX_SIMD_OUTPUT_WEIGHT)));
+        values.push(next(500));
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    fn test_network() -> Arc<Network> {
+        Arc::new(Network::from_bytes(&test_network_bytes(0x9E37_79B9_7F4A_7C15)).unwrap())
+    }
+
+    #[test]
+    fn test_incremental_matches_refresh() {
+        let network = test_network();
+        // castling, en passant, a capturing promotion and a regular capture
+        let mut board =
+            Board::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        board.set_network(Some(Arc::clone(&network)));
+        let initial_eval = board.evaluate();
+
+        let mut moves = Vec::new();
+        for mv in ["e1c1", "b4c3", "d2c3", "h3g2", "d5d6", "g2h1q", "e5f7"] {
+            let encoded = DecodedMove::from_coords(mv, &board).encode();
+            board.make_move(encoded);
+            moves.push(encoded);
+            assert!(board.nnue().unwrap().is_consistent(&board), "after {mv}");
+        }
+        let mut ep = Board::new("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3");
+        ep.set_network(Some(Arc::clone(&network)));
+        ep.make_move(DecodedMove::from_coords("e5f6", &ep).encode());
+        assert!(ep.nnue().unwrap().is_consistent(&ep));
+
+        for _ in &moves {
+            board.unmake_move();
+            assert!(board.nnue().unwrap().is_consistent(&board));
+        }
+        assert_eq!(board.evaluate(), initial_eval);
+    }
+
+    #[test]
+    fn test_simd_matches_scalar() {
+        let network = test_network();
+        let mut board =
+            Board::new("r1b1k2r/1pqp1ppp/p2bpnn1/6Q1/2BNP3/2N1B3/PPP2PPP/2KR3R w kq - 8 12");
+        board.set_network(Some(Arc::clone(&network)));
+        let accumulators = board.nnue().unwrap().accumulators;
+
+        for accumulator in accumulators {
+            for weights in &network.output_weights {
+                assert_eq!(
+                    network.screlu_dot(&accumulator, weights),
+                    scalar::screlu_dot(&accumulator, weights)
+                );
+            }
+        }
+
+        // values outside of the clamped range and the largest allowed weights
+        let extreme = Accumulator(std::array::from_fn(|i| {
+            [i16::MIN, -1, 300, i16::MAX][i % 4]
+        }));
+        let weights = Accumulator(std::array::from_fn(|i| {
+            [MAX_SIMD_OUTPUT_WEIGHT, -MAX_SIMD_OUTPUT_WEIGHT][i % 2]
+        }));
+        assert_eq!(
+            network.screlu_dot(&extreme, &weights),
+            scalar::screlu_dot(&extreme, &weights)
+        );
+    }
+
+    #[test]
+    fn test_evaluation_is_symmetric() {
+        let network = test_network();
+        let mut board =
+            Board::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let mut mirrored =
+            Board::new("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3");
+        board.set_network(Some(Arc::clone(&network)));
+        mirrored.set_network(Some(network));
+
+        let eval = |board: &Board| board.nnue().unwrap().evaluate(board.current_color());
+        assert_eq!(eval(&board), eval(&mirrored));
+    }
+
+    #[test]
+    fn test_invalid_networks_rejected() {
+        let bytes = test_network_bytes(1);
+        assert!(Network::from_bytes(&bytes).is_ok());
+        assert!(Network::from_bytes(&bytes[..bytes.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn test_large_output_weights() {
+        // every hidden neuron fully active, with the largest output weights an i16 can hold
+        let network_bytes = |output_weight: i16| {
+            let mut values = vec![0; INPUT_SIZE * HIDDEN_SIZE];
+            values.extend([i16::try_from(QA).unwrap(); HIDDEN_SIZE]);
+            values.extend([output_weight; 2 * HIDDEN_SIZE]);
+            values.push(0);
+            values
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect::<Vec<_>>()
+        };
+        let board = Board::new("8/8/8/4k3/8/8/8/KQ6 w - - 0 1");
+        for (output_weight, eval) in [
+            (i16::MAX, MATE_THRESHOLD - 1),
+            (i16::MIN, -MATE_THRESHOLD + 1),
+        ] {
+            let network = Network::from_bytes(&network_bytes(output_weight)).unwrap();
+            assert!(!network.simd_output);
+            let [us, them] = network.refresh(&board);
+            assert_eq!(network.evaluate(&us, &them), eval);
+        }
+
+        let network = Network::from_bytes(&network_bytes(MAX_SIMD_OUTPUT_WEIGHT)).unwrap();
+        assert!(network.simd_output);
+    }
+
+    /// `tests/fixtures/nnue/distilled.nnue` is a real trained network, see the README there
+    #[test]
+    fn test_trained_network() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/nnue/distilled.nnue");
+        let network = Arc::new(Network::from_bytes(&fs::read(path).unwrap()).unwrap());
+        let eval = |fen: &str| {
+            let mut board = Board::new(fen);
+            board.set_network(Some(Arc::clone(&network)));
+            board.evaluate()
+        };
+
+        // balanced openings stay balanced
+        assert!(eval(START_POS).abs() < 50);
+        assert!(
+            eval("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").abs() < 50
+        );
+        // a queen up, from both sides
+        assert!(eval("rnb1kbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 3") > 700);
+        assert!(eval("rnb1kbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3") < -700);
+        // a rook up in the endgame
+        assert!(eval("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1") > 300);
+        assert!(eval("6k1/5ppp/8/8/8/8/5PPP/3R2K1 b - - 0 1") < -300);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::{
+    eval_params::{ConstantParams, EvalParams},
+    eval_trace::EvalTrace,
+    prelude::*,
+    settings,
+};
+use std::cell::RefCell;
+
+/// Entries per table, indexed by the lower bits of the pawn hash
+const PAWN_HASH_TABLE_SIZE: usize = 16384;
+
+thread_local! {
+    /// Pawn hash table of the current thread.
+    /// Every thread evaluates its own positions, so unlike the TT there is nothing to gain from sharing it
+    pub static PAWN_HASH_TABLE: RefCell<PawnHashTable> = RefCell::new(PawnHashTable::new());
+}
+
+/// Evaluation terms which only depend on the pawns of both sides
+/// Scores are from white's perspective, i.e. white's terms minus black's terms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PawnEntry {
+    pub mg: i32,
+    pub eg: i32,
+    /// Doubled pawns aren't tapered, this gets added to the final score
+    pub doubled: i32,
+    pub open_files: Bitboard,
+    /// Format: `[white, black]`
+    pub passed: [Bitboard; 2],
+}
+
+impl PawnEntry {
+    pub fn new<P: EvalParams + ?Sized>(board: &Board, params: &P) -> Self {
+        let (mg, eg) = board.pawn_structure::<P, false>(params, &mut EvalTrace::default());
+        let doubled = if settings::DOUBLED_PAWNS {
+            let doubled = board.doubled_pawn_penalties(params);
+            doubled[0] - doubled[1]
+        } else {
+            0
+        };
+
+        Self {
+            mg: i32::from(mg[0] - mg[1]),
+            eg: i32::from(eg[0] - eg[1]),
+            doubled,
+            open_files: board.open_files(),
+            passed: [board.passed_pawns(White), board.passed_pawns(Black)],
+        }
+    }
+}
+
+/// Caches the pawn terms of the evaluation by `Board::pawn_hash`, pawn structures repeat a lot more than positions do.
+/// <https://www.chessprogramming.org/Pawn_Hash_Table>
+pub struct PawnHashTable {
+    entries: Box<[Option<(u64, PawnEntry)>]>,
+    hits: usize,
+    probes: usize,
+}
+
+impl PawnHashTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; PAWN_HASH_TABLE_SIZE].into_boxed_slice(),
+            hits: 0,
+            probes: 0,
+        }
+    }
+
+    /// The pawn terms of `board` with the compiled in weights, only computed if the pawn structure isn't cached yet
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let key = board.pawn_hash();
+        #[allow(clippy::cast_possible_truncation)]
+        let slot = &mut self.entries[key as usize % PAWN_HASH_TABLE_SIZE];
+        self.probes += 1;
+
+        match slot {
+            Some((entry_key, entry)) if *entry_key == key => {
+                self.hits += 1;
+                *entry
+            }
+            _ => {
+                let entry = PawnEntry::new(board, &ConstantParams);
+                *slot = Some((key, entry));
+                entry
+            }
+        }
+    }
+
+    /// Format: `(hits, probes)` since the table got created or cleared
+    pub const fn stats(&self) -> (usize, usize) {
+        (self.hits, self.probes)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.hits = 0;
+        self.probes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_terms_match_computation() {
+        let mut table = PawnHashTable::new();
+        let mut board =
+            Board::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let expected = PawnEntry::new(&board, &ConstantParams);
+
+        assert_eq!(table.probe(&board), expected);
+        assert_eq!(table.stats(), (0, 1));
+
+        // a piece move keeps the pawn structure
+        board.make_move(DecodedMove::from_coords("e5g4", &board).encode());
+        assert_eq!(table.probe(&board), expected);
+        assert_eq!(table.stats(), (1, 2));
+
+        // a pawn move doesn't
+        board.make_move(DecodedMove::from_coords("b4c3", &board).encode());
+        assert_eq!(table.probe(&board), PawnEntry::new(&board, &ConstantParams));
+        assert_ne!(table.probe(&board), expected);
+        assert_eq!(table.stats(), (2, 4));
+
+        table.clear();
+        assert_eq!(table.stats(), (0, 0));
+    }
+
+    #[test]
+    fn test_no_pawns() {
+        let mut table = PawnHashTable::new();
+        let board = Board::new("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        // an empty slot must not be mistaken for the entry of a board without pawns (pawn hash 0)
+        let entry = table.probe(&board);
+        assert_eq!(entry.open_files, Bitboard(u64::MAX));
+        assert_eq!(entry, PawnEntry::new(&board, &ConstantParams));
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{
-    evaluation::MATE_SCORE,
+    eval_params::EvalParams,
+    evaluation::{MATE_SCORE, MATE_THRESHOLD},
+    evaluation_constants::EG_PIECE_VALUES,
     move_picker::MovePicker,
     prelude::*,
     settings,
-    transposition_table::{Bound, TT},
+    transposition_table::Bound,
 };
 
 use std::sync::atomic::Ordering;
@@ -19,6 +21,7 @@ pub fn quiescence_search(
     ply: usize,
 ) -> i32 {
     *sd.local_seldepth = (*sd.local_seldepth).max(ply);
+    sd.pv.clear(ply);
 
     if sd.stop.load(Ordering::Relaxed) {
         sd.timeout_occurred.store(true, Ordering::Relaxed);
@@ -42,14 +45,16 @@ pub fn quiescence_search(
     let mut tt_move: Option<EncodedMove> = None;
 
     // Choose correct evaluation
-    // Use the MATE eval if in check
+    // There is no stand pat when in check, every evasion gets searched so being mated is the baseline
     let is_check = sd.board.is_in_check();
+    let mut static_eval = None;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
     let eval = if is_check {
-        -MATE_SCORE
+        -MATE_SCORE + ply as i32
     } else if settings::TT_QS {
         // probe tt
         #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-        if let Some(tt_hit) = TT.probe(sd.board.hash(), ply as i32) {
+        if let Some(tt_hit) = sd.tt.probe(sd.board.hash(), ply as i32) {
             sd.total_tt_hits.fetch_add(1, Ordering::Relaxed);
 
             let tt_score = tt_hit.score();
@@ -67,19 +72,22 @@ pub fn quiescence_search(
                 return tt_score;
             }
 
-            let static_eval = sd.board.evaluate();
+            let static_eval =
+                *static_eval.insert(tt_hit.static_eval().unwrap_or_else(|| sd.board.evaluate()));
             // Use TT score as eval when it refines the static eval:
             // - Upper (score <= tt): if tt < static, the position is worse than eval suggests
             // - Lower (score >= tt): if tt > static, the position is better than eval suggests
             // Otherwise fall back to static eval, as the bound doesn't contradict it.
+            // Mate bounds are no evaluation, standing pat on them would claim a mate without a line to it.
             match tt_hit.bound() {
+                _ if tt_score.abs() > MATE_THRESHOLD => static_eval,
                 Bound::Upper if tt_score < static_eval => tt_score,
                 Bound::Lower if tt_score > static_eval => tt_score,
                 _ => static_eval,
             }
         } else {
             // need normal eval when no tt hit
-            sd.board.evaluate()
+            *static_eval.insert(sd.board.evaluate())
         }
     } else {
         // ofc need normal eval when tt is completly disabled aswell
@@ -89,10 +97,11 @@ pub fn quiescence_search(
     if eval >= beta {
         if settings::TT_QS {
             #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-            TT.store(
+            sd.tt.store(
                 sd.board.hash(),
                 None,
                 eval,
+                static_eval,
                 0,
                 ply as i32,
                 Bound::Lower,
@@ -111,12 +120,31 @@ pub fn quiescence_search(
 
     let mut i = 0;
 
-    let mut movepicker =
-        if sd.board.is_in_check() && (ply - sd.ab_ply) < settings::QS_CHECK_EVASION_LIMIT {
-            MovePicker::new(tt_move, None, false)
-        } else {
-            MovePicker::new(tt_move, None, true)
-        };
+    let mut movepicker = if is_check {
+        MovePicker::new(tt_move, None, false)
+    } else if settings::QS_CHECKS && ply == sd.ab_ply {
+        MovePicker::with_quiet_checks(tt_move)
+    } else {
+        MovePicker::new(tt_move, None, true)
+    };
+
+    // Delta pruning needs material to be worth more than the position: with only pawns left or a pawn about to
+    // promote a single capture or push can decide the game, whatever the material says
+    let color = sd.board.current_color();
+    let promotion_row = match color {
+        White => Bitboard(0x00FF_0000_0000_0000),
+        Black => Bitboard(0xFF00),
+    };
+    let pawns = sd.board.figure_bb(color, Pawn);
+    let delta_pruning = settings::DELTA_PRUNING
+        && !is_check
+        && sd.board.color_bbs_without_king(color) != pawns
+        && (pawns & promotion_row).is_empty();
+    // the endgame values, captures matter most when there is little material left
+    let piece_values = sd
+        .board
+        .eval_params()
+        .map_or(EG_PIECE_VALUES, |params| *params.piece_values()[1]);
 
     // let initial_hash = board.hash();
     while let Some(mv) = movepicker.next(sd.board) {
@@ -126,6 +154,21 @@ pub fn quiescence_search(
             sd.timeout_occurred.store(true, Ordering::Relaxed);
             return 0;
         }
+
+        // Delta pruning <https://www.chessprogramming.org/Delta_Pruning>
+        // Even winning the captured piece for free would not get us close to alpha
+        let decoded = mv.decode();
+        if delta_pruning && decoded.mv_type.is_capture() {
+            let victim = if decoded.mv_type == MoveType::EpCapture {
+                Pawn
+            } else {
+                sd.board.piece_at_position(decoded.to)
+            };
+            if eval + piece_values[victim as usize] + settings::QS_DELTA_MARGIN <= alpha {
+                continue;
+            }
+        }
+
         sd.board.make_move(mv);
         let score = -quiescence_search(depth - 1, -beta, -alpha, sd, ply + 1);
         sd.board.unmake_move();
@@ -135,6 +178,7 @@ pub fn quiescence_search(
             if score > alpha {
                 best_move = Some(mv);
                 alpha = score;
+                sd.pv.update(ply, mv);
             }
 
             if settings::AB && alpha >= beta {
@@ -143,9 +187,9 @@ pub fn quiescence_search(
         }
     }
 
+    // All evasions got generated, so no moves while in check really is mate
     if is_check && i == 0 {
-        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-        return -MATE_SCORE + ply as i32;
+        return best_score;
     }
 
     let bound = if best_score >= beta {
@@ -157,10 +201,11 @@ pub fn quiescence_search(
     };
 
     #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
-    TT.store(
+    sd.tt.store(
         sd.board.hash(),
         best_move,
         best_score,
+        static_eval,
         0,
         ply as i32,
         bound,
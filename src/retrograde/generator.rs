@@ -0,0 +1,302 @@
+// Retrograde analysis of one material signature
+//
+// Every position first gets its moves generated once: mates and stalemates are known right away,
+// captures and promotions leave the table and are looked up in the smaller tables, all other moves
+// are counted. Then the positions are resolved in order of their distance to mate by walking the
+// moves backwards: a predecessor of a loss is a win one ply further away, a predecessor of a win
+// is lost once all of its counted moves turned out to be wins for the opponent.
+// Whatever is still unresolved at the end is a draw.
+// <https://www.chessprogramming.org/Retrograde_Analysis>
+
+use super::{MAX_PIECES, Outcome, Tables};
+use crate::{
+    move_generator::{
+        masks,
+        normal_targets::{KING_TARGETS, KNIGHT_TARGETS},
+        sliding_targets::{get_bishop_targets, get_rook_targets},
+    },
+    prelude::*,
+    types::board::UNSET_CHECK_COUNTER,
+};
+use arrayvec::ArrayVec;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Counter of positions which can't occur (pieces on top of each other, the side not to move in check, ...)
+const ILLEGAL: u8 = u8::MAX;
+/// Loss floor of positions with a capture or promotion which doesn't lose
+const CANNOT_LOSE: u8 = u8::MAX;
+/// Start level of positions which are not resolved by looking at their own moves
+const NO_START: u8 = u8::MAX;
+
+/// What the first pass found out about a position
+#[derive(Clone, Copy)]
+struct Initial {
+    /// Moves staying in this table
+    counter: u8,
+    /// A loss is at least this many plies away because of captures and promotions into lost positions
+    floor: u8,
+    /// Level the position is known to be resolved at (a mate or a capture / promotion into a won position)
+    start: u8,
+}
+
+impl Initial {
+    const ILLEGAL: Self = Self {
+        counter: ILLEGAL,
+        floor: CANNOT_LOSE,
+        start: NO_START,
+    };
+}
+
+/// Generates the table with `figures` (kings first), every table reachable through captures and
+/// promotions has to be in `tables` already.
+/// The index of the returned table holds the square of every piece (6 bits each, in the order of `figures`)
+/// with the side to move on top, the `dtm` values are the same as in `Table`
+pub fn generate(figures: &[Figure], tables: &Tables) -> Vec<u8> {
+    let layout = Layout { figures };
+    let initial: Vec<Initial> = (0..2 << (6 * figures.len()))
+        .into_par_iter()
+        .map_init(
+            || Board::new("8/8/8/8/8/8/8/8 w - - 0 1"),
+            |board, index| initial(layout, tables, board, index),
+        )
+        .collect();
+    let mut dtm = vec![0u8; initial.len()];
+
+    let mut counters: Vec<u8> = initial.iter().map(|initial| initial.counter).collect();
+    let mut levels: Vec<Vec<u32>> = Vec::new();
+    for (index, initial) in initial.iter().enumerate() {
+        if initial.start != NO_START {
+            schedule(&mut levels, index, usize::from(initial.start));
+        }
+    }
+
+    // dtm of 0 means unresolved until the very end, resolved positions store their level + 1
+    let mut predecessors = Vec::new();
+    let mut level = 0;
+    while level < levels.len() {
+        let positions = std::mem::take(&mut levels[level]);
+        for index in positions {
+            let index = index as usize;
+            if dtm[index] != 0 {
+                continue;
+            }
+            dtm[index] = u8::try_from(level + 1)
+                .ok()
+                .filter(|&dtm| dtm != u8::MAX)
+                .expect("mates are less than 254 plies away");
+
+            layout.predecessors(index, &mut predecessors);
+            for &predecessor in &predecessors {
+                if counters[predecessor] == ILLEGAL || dtm[predecessor] != 0 {
+                    continue;
+                }
+                // even levels are losses for the side to move
+                if level % 2 == 0 {
+                    schedule(&mut levels, predecessor, level + 1);
+                } else {
+                    counters[predecessor] -= 1;
+                    let floor = initial[predecessor].floor;
+                    if counters[predecessor] == 0 && floor != CANNOT_LOSE {
+                        schedule(
+                            &mut levels,
+                            predecessor,
+                            (level + 1).max(usize::from(floor)),
+                        );
+                    }
+                }
+            }
+        }
+        level += 1;
+    }
+
+    dtm
+}
+
+fn schedule(levels: &mut Vec<Vec<u32>>, index: usize, level: usize) {
+    if levels.len() <= level {
+        levels.resize_with(level + 1, Vec::new);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    levels[level].push(index as u32);
+}
+
+/// Sets up the position at `index` on `board` (which has to be empty) and looks at its moves
+fn initial(layout: Layout, tables: &Tables, board: &mut Board, index: usize) -> Initial {
+    let (stm, squares) = layout.decode(index);
+    let pieces: ArrayVec<(Figure, usize), MAX_PIECES> =
+        layout.figures.iter().copied().zip(squares).collect();
+
+    let occupied = pieces.iter().fold(Bitboard::EMPTY, |bb, &(_, square)| {
+        bb | Square(square).to_bitboard()
+    });
+    let pawn_on_back_rank = pieces
+        .iter()
+        .any(|&(figure, square)| figure.piece() == Pawn && !(8..56).contains(&square));
+    if occupied.get_count() as usize != pieces.len() || pawn_on_back_rank {
+        return Initial::ILLEGAL;
+    }
+
+    for &(figure, square) in &pieces {
+        board.toggle(figure.piece_and_color().1, figure, Square(square));
+    }
+    board.set_current_color(stm);
+    board.set_attackmask(Bitboard::UNSET_ATTACK_MASK);
+    board.set_checkmask(Bitboard::UNSET_CHECK_MASK, UNSET_CHECK_COUNTER);
+    board.set_pinmasks(Bitboard::UNSET_PINMASK, Bitboard::UNSET_PINMASK);
+
+    let attacked = masks::calculate_attackmask(board, occupied, stm, None);
+    let result = if (attacked & board.king(!stm)).is_empty() {
+        let moves = board.generate_all_moves();
+        let in_check = board.get_check_counter() > 0;
+        classify(
+            tables,
+            &pieces,
+            stm,
+            in_check,
+            moves.list.iter().map(|mv| mv.mv.decode()),
+        )
+    } else {
+        Initial::ILLEGAL
+    };
+
+    for &(figure, square) in &pieces {
+        board.toggle(figure.piece_and_color().1, figure, Square(square));
+    }
+    result
+}
+
+fn classify(
+    tables: &Tables,
+    pieces: &[(Figure, usize)],
+    stm: Color,
+    in_check: bool,
+    moves: impl Iterator<Item = DecodedMove>,
+) -> Initial {
+    let mut counter = 0u8;
+    let mut any_move = false;
+    let mut floor = 0u8;
+    let mut start = NO_START;
+
+    for mv in moves {
+        any_move = true;
+        if !mv.mv_type.is_capture() && !mv.mv_type.is_promotion() {
+            counter += 1;
+            continue;
+        }
+
+        // the move leaves this table, the smaller one knows where it leads to
+        let after: ArrayVec<(Figure, usize), MAX_PIECES> = pieces
+            .iter()
+            .filter(|&&(_, square)| square != mv.to.i())
+            .map(|&(figure, square)| {
+                if square == mv.from.i() {
+                    let figure = mv.mv_type.to_promotion_color_piece(stm).unwrap_or(figure);
+                    (figure, mv.to.i())
+                } else {
+                    (figure, square)
+                }
+            })
+            .collect();
+        match tables.probe_pieces(&after, !stm) {
+            Outcome::Loss(plies) => start = start.min(plies + 1),
+            Outcome::Win(plies) => floor = floor.max(plies + 1),
+            Outcome::Draw => floor = CANNOT_LOSE,
+        }
+    }
+
+    if start != NO_START {
+        floor = CANNOT_LOSE;
+    }
+    match (any_move, in_check) {
+        // mate or stalemate
+        (false, true) => start = 0,
+        (false, false) => floor = CANNOT_LOSE,
+        // only captures and promotions and all of them lose
+        (true, _) if counter == 0 && floor != CANNOT_LOSE => start = floor,
+        _ => {}
+    }
+    Initial {
+        counter,
+        floor,
+        start,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Layout<'a> {
+    figures: &'a [Figure],
+}
+
+impl Layout<'_> {
+    const fn stm_bit(self) -> usize {
+        1 << (6 * self.figures.len())
+    }
+
+    fn decode(self, index: usize) -> (Color, ArrayVec<usize, MAX_PIECES>) {
+        let squares = (0..self.figures.len())
+            .map(|slot| (index >> (6 * slot)) & 63)
+            .collect();
+        let stm = if index & self.stm_bit() == 0 {
+            White
+        } else {
+            Black
+        };
+        (stm, squares)
+    }
+
+    /// Positions with the other side to move from which a move that stays in this table leads to `index`
+    fn predecessors(self, index: usize, predecessors: &mut Vec<usize>) {
+        predecessors.clear();
+        let (stm, squares) = self.decode(index);
+        let mover = !stm;
+        let occupied = squares.iter().fold(Bitboard::EMPTY, |bb, &square| {
+            bb | Square(square).to_bitboard()
+        });
+        let flipped = index ^ self.stm_bit();
+
+        for (slot, (&figure, &square)) in self.figures.iter().zip(&squares).enumerate() {
+            let (piece, color) = figure.piece_and_color();
+            if color != mover {
+                continue;
+            }
+            let mut origins: Bitboard = match piece {
+                King => KING_TARGETS[square],
+                Knight => KNIGHT_TARGETS[square],
+                Bishop => get_bishop_targets(Square(square), occupied),
+                Rook => get_rook_targets(Square(square), occupied),
+                Queen => {
+                    get_bishop_targets(Square(square), occupied)
+                        | get_rook_targets(Square(square), occupied)
+                }
+                Pawn => pawn_origins(square, color, occupied),
+                Empty => Bitboard::EMPTY,
+            } & !occupied;
+
+            for origin in origins.iter_mut() {
+                let origin = origin.to_square().i();
+                predecessors.push(flipped ^ ((square ^ origin) << (6 * slot)));
+            }
+        }
+    }
+}
+
+/// Where a pawn of `color` on `square` came from with a push
+fn pawn_origins(square: usize, color: Color, occupied: Bitboard) -> Bitboard {
+    let (behind, second_rank, double_rank) = match color {
+        White => (square.wrapping_sub(8), 1, 3),
+        Black => (square + 8, 6, 4),
+    };
+    let rank = square / 8;
+    if rank == second_rank || rank == 0 || rank == 7 {
+        return Bitboard::EMPTY;
+    }
+    let mut origins = Square(behind).to_bitboard();
+    if rank == double_rank && (origins & occupied).is_empty() {
+        let two_behind = match color {
+            White => behind - 8,
+            Black => behind + 8,
+        };
+        origins |= Square(two_behind).to_bitboard();
+    }
+    origins
+}
@@ -0,0 +1,632 @@
+// Endgame tables of up to four pieces generated by the engine itself
+//
+// Unlike Syzygy tables these store the exact distance to mate of every position, they are built on demand
+// by retrograde analysis (see `generator`) and cached on disk so that only the first use is slow.
+// They know nothing about castling, en passant and the 50 move rule.
+// The generator works on every position, indexed by the square of every piece (kings first) and the side to
+// move, which keeps it simple. The stored tables then drop the mirror images (see `Table::index`):
+// - without pawns the board is mirrored along the files, the ranks and the a1-h8 diagonal until the white king
+//   is in the a1-d1-d4 triangle (`TRIANGLE`)
+// - with pawns only the files get mirrored, the white king stays on the a to d file (`FILES_A_TO_D`)
+// The same material with the colors swapped (e.g. KvKQ) is looked up in the table of the other side with the
+// board flipped along the ranks and the colors swapped.
+
+mod generator;
+
+use crate::{
+    prelude::*,
+    utils::{
+        persistence::{self, invalid_data},
+        zobrist,
+    },
+};
+use arrayvec::ArrayVec;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+/// Most pieces (kings included) a table can have
+pub const MAX_PIECES: usize = 4;
+
+const TABLE_FILE_MAGIC: [u8; 4] = *b"RTBL";
+/// Distances to mate only depend on the rules, not on the evaluation, the indexing is covered by the engine version
+const TABLE_FINGERPRINT: u64 = 0;
+
+/// Outcome of a position for the side to move along with the plies until mate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win(u8),
+    Loss(u8),
+    Draw,
+}
+
+impl Outcome {
+    /// Tables store 0 for draws and the plies until mate + 1 otherwise, which is odd for losses
+    const fn from_dtm(dtm: u8) -> Self {
+        match dtm {
+            0 => Self::Draw,
+            dtm if dtm % 2 == 0 => Self::Win(dtm - 1),
+            dtm => Self::Loss(dtm - 1),
+        }
+    }
+}
+
+/// Tables generated via the `RetrogradeTables` option
+static TABLES: Mutex<Option<Arc<Tables>>> = Mutex::new(None);
+
+/// Directory the tables are cached in, set via the `RetrogradePath` option
+static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The tables the search and evaluation should probe, `None` if there are none
+pub fn tables() -> Option<Arc<Tables>> {
+    TABLES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Caches tables in `directory` from now on, `None` turns caching off
+pub fn set_cache_dir(directory: Option<&Path>) -> io::Result<()> {
+    if let Some(directory) = directory
+        && !directory.is_dir()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", directory.display()),
+        ));
+    }
+    *CACHE_DIR.lock().unwrap_or_else(PoisonError::into_inner) = directory.map(Path::to_path_buf);
+    Ok(())
+}
+
+/// Loads or generates the tables of the signatures in `names` (like `KRK KBNK`) and every table
+/// they depend on, `None` unloads them. Returns the number of tables
+pub fn load_tables(names: Option<&str>) -> io::Result<usize> {
+    let cache_dir = CACHE_DIR
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+
+    let tables = match names {
+        Some(names) => {
+            let mut tables = Tables::default();
+            for name in names.split([' ', ',']).filter(|name| !name.is_empty()) {
+                tables.add(name, cache_dir.as_deref())?;
+            }
+            Some(tables)
+        }
+        None => None,
+    };
+    let count = tables.as_ref().map_or(0, Tables::table_count);
+    *TABLES.lock().unwrap_or_else(PoisonError::into_inner) = tables
+        .filter(|tables| tables.table_count() > 0)
+        .map(Arc::new);
+    Ok(count)
+}
+
+/// Squares the white king gets mirrored to in tables without pawns (the a1-d1-d4 triangle)
+const TRIANGLE: [usize; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
+
+/// Squares the white king gets mirrored to in tables with pawns, they can't be mirrored along the ranks
+const FILES_A_TO_D: [usize; 32] = {
+    let mut squares = [0; 32];
+    let mut idx = 0;
+    while idx < 32 {
+        squares[idx] = idx / 4 * 8 + idx % 4;
+        idx += 1;
+    }
+    squares
+};
+
+/// Distance to mate of every position of one material signature.
+/// Only positions with the white king on one of `king_squares` are stored, the others are mirror images.
+/// An index holds the square of every piece but the white king (6 bits each, in the order of `figures`),
+/// then the index of the white king in `king_squares` and the side to move
+pub struct Table {
+    name: String,
+    /// Which piece the squares of an index belong to, white king and black king first
+    figures: Vec<Figure>,
+    has_pawns: bool,
+    /// See `Outcome::from_dtm`, also 0 for positions that can't occur
+    dtm: Vec<u8>,
+}
+
+impl Table {
+    fn new(name: String, figures: Vec<Figure>) -> Self {
+        let has_pawns = figures.iter().any(|figure| figure.piece() == Pawn);
+        let mut table = Self {
+            name,
+            figures,
+            has_pawns,
+            dtm: Vec::new(),
+        };
+        table.dtm = vec![0; (2 * table.king_squares().len()) << table.king_shift()];
+        table
+    }
+
+    /// Keeps the positions the table stores out of the ones `generator` generated
+    fn from_full(name: String, figures: Vec<Figure>, full: &[u8]) -> Self {
+        let mut table = Self::new(name, figures);
+        let king_shift = table.king_shift();
+        let king_squares = table.king_squares();
+        let stm_shift = 6 * table.figures.len();
+        for (index, dtm) in table.dtm.iter_mut().enumerate() {
+            let king = (index >> king_shift) % king_squares.len();
+            let stm = (index >> king_shift) / king_squares.len();
+            let others = index & ((1 << king_shift) - 1);
+            *dtm = full[(stm << stm_shift) | (others << 6) | king_squares[king]];
+        }
+        table
+    }
+
+    const fn king_squares(&self) -> &'static [usize] {
+        if self.has_pawns {
+            &FILES_A_TO_D
+        } else {
+            &TRIANGLE
+        }
+    }
+
+    const fn king_shift(&self) -> usize {
+        6 * (self.figures.len() - 1)
+    }
+
+    /// `pieces` has to contain exactly the figures of this table
+    fn index(&self, pieces: &[(Figure, usize)], stm: Color) -> usize {
+        // mirror the board until the white king is on one of the stored squares
+        let (_, king) = pieces
+            .iter()
+            .find(|&&(figure, _)| figure == Figure::WhiteKing)
+            .expect("there is a white king");
+        let mut mirror = if king % 8 > 3 { 7 } else { 0 };
+        if !self.has_pawns && king / 8 > 3 {
+            mirror |= 0x38;
+        }
+        let transpose = !self.has_pawns && (king ^ mirror) / 8 > (king ^ mirror) % 8;
+        let map = |square: usize| {
+            let square = square ^ mirror;
+            if transpose {
+                ((square & 7) << 3) | (square >> 3)
+            } else {
+                square
+            }
+        };
+
+        let mut used = 0u32;
+        let mut index = 0;
+        for (slot, &figure) in self.figures.iter().enumerate() {
+            // identical pieces can go in any order, the table has all of them
+            let (idx, &(_, square)) = pieces
+                .iter()
+                .enumerate()
+                .find(|&(idx, &(piece, _))| piece == figure && used & (1 << idx) == 0)
+                .expect("pieces match the table");
+            used |= 1 << idx;
+            if slot == 0 {
+                let king_squares = self.king_squares();
+                let king = king_squares
+                    .iter()
+                    .position(|&king| king == map(square))
+                    .expect("the king got mirrored onto a stored square");
+                index |= (stm as usize * king_squares.len() + king) << self.king_shift();
+            } else {
+                index |= map(square) << (6 * (slot - 1));
+            }
+        }
+        index
+    }
+
+    fn outcome(&self, pieces: &[(Figure, usize)], stm: Color, flipped: bool) -> Outcome {
+        let index = if flipped {
+            let pieces: ArrayVec<(Figure, usize), MAX_PIECES> = pieces
+                .iter()
+                .map(|&(figure, square)| {
+                    let (piece, color) = figure.piece_and_color();
+                    (Figure::from_piece_and_color(piece, !color), square ^ 0x38)
+                })
+                .collect();
+            self.index(&pieces, !stm)
+        } else {
+            self.index(pieces, stm)
+        };
+        Outcome::from_dtm(self.dtm[index])
+    }
+
+    /// The dtm values are stored `PackBits` style: a control byte up to 127 is followed by that many + 1 values,
+    /// anything above repeats the next value control - 125 times
+    /// <https://en.wikipedia.org/wiki/PackBits>
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        persistence::write_header(
+            &mut writer,
+            TABLE_FILE_MAGIC,
+            TABLE_FINGERPRINT,
+            self.dtm.len() as u64,
+        )?;
+
+        let run_length = |values: &[u8]| {
+            values
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&value| value == values[0])
+                .count()
+        };
+        let mut pos = 0;
+        while pos < self.dtm.len() {
+            let run = run_length(&self.dtm[pos..]);
+            if run >= MIN_RUN {
+                #[allow(clippy::cast_possible_truncation)]
+                writer.write_all(&[(run + 125) as u8, self.dtm[pos]])?;
+                pos += run;
+            } else {
+                let start = pos;
+                while pos < self.dtm.len()
+                    && pos - start < MAX_LITERALS
+                    && run_length(&self.dtm[pos..]) < MIN_RUN
+                {
+                    pos += 1;
+                }
+                #[allow(clippy::cast_possible_truncation)]
+                writer.write_all(&[(pos - start - 1) as u8])?;
+                writer.write_all(&self.dtm[start..pos])?;
+            }
+        }
+        writer.flush()
+    }
+
+    fn load(path: &Path, name: String, figures: Vec<Figure>) -> io::Result<Self> {
+        let mut table = Self::new(name, figures);
+        let mut reader = BufReader::new(File::open(path)?);
+        let records = persistence::read_header(&mut reader, TABLE_FILE_MAGIC, TABLE_FINGERPRINT)?;
+        if records != table.dtm.len() as u64 {
+            return Err(invalid_data(format!(
+                "expected {} positions, found {records}",
+                table.dtm.len()
+            )));
+        }
+
+        let mut pos = 0;
+        while pos < table.dtm.len() {
+            let control = usize::from(persistence::read_u8(&mut reader)?);
+            let length = if control < MAX_LITERALS {
+                control + 1
+            } else {
+                control - 125
+            };
+            let values = table
+                .dtm
+                .get_mut(pos..pos + length)
+                .ok_or_else(|| invalid_data("positions run past the end".to_string()))?;
+            if control < MAX_LITERALS {
+                reader.read_exact(values)?;
+            } else {
+                values.fill(persistence::read_u8(&mut reader)?);
+            }
+            pos += length;
+        }
+        Ok(table)
+    }
+}
+
+const MIN_RUN: usize = 3;
+const MAX_RUN: usize = 130;
+const MAX_LITERALS: usize = 128;
+
+#[derive(Default)]
+pub struct Tables {
+    /// By material key, every table is there for both colors, `true` if its colors are swapped
+    entries: HashMap<u64, (Arc<Table>, bool)>,
+    table_count: usize,
+}
+
+impl Tables {
+    pub const fn table_count(&self) -> usize {
+        self.table_count
+    }
+
+    /// What the tables say about `board`, `None` if no table covers it or the answer doesn't have to hold:
+    /// the index knows nothing about en passant, and a mate further away than the 50 move rule allows
+    /// may turn into a draw (or stay a mate if the way there resets the counter)
+    pub fn probe(&self, board: &Board) -> Option<Outcome> {
+        if board.occupied().get_count() as usize > MAX_PIECES
+            || board.ep_target().is_some()
+            || board.white_king_castle()
+            || board.white_queen_castle()
+            || board.black_king_castle()
+            || board.black_queen_castle()
+        {
+            return None;
+        }
+        if board.occupied().get_count() == 2 {
+            return Some(Outcome::Draw);
+        }
+        let (table, flipped) = self.entries.get(&board.material_key())?;
+
+        let mut occupied = board.occupied();
+        let pieces: ArrayVec<(Figure, usize), MAX_PIECES> = occupied
+            .iter_mut()
+            .map(|bit| {
+                let square = bit.to_square();
+                (board.figures(square), square.i())
+            })
+            .collect();
+        match table.outcome(&pieces, board.current_color(), *flipped) {
+            Outcome::Win(plies) | Outcome::Loss(plies)
+                if board.halfmove_clock() + usize::from(plies) > 100 =>
+            {
+                None
+            }
+            outcome => Some(outcome),
+        }
+    }
+
+    /// Like `probe` for a position given by its pieces, the table has to be there already
+    fn probe_pieces(&self, pieces: &[(Figure, usize)], stm: Color) -> Outcome {
+        if pieces.len() == 2 {
+            return Outcome::Draw;
+        }
+        let figures: Vec<Figure> = pieces.iter().map(|&(figure, _)| figure).collect();
+        let (table, flipped) = &self.entries[&material_key(&figures)];
+        table.outcome(pieces, stm, *flipped)
+    }
+
+    pub(crate) fn add(&mut self, name: &str, cache_dir: Option<&Path>) -> io::Result<()> {
+        let figures = parse_signature(name).ok_or_else(|| {
+            invalid_data(format!(
+                "{name} is not a signature like KRK of at most {MAX_PIECES} pieces"
+            ))
+        })?;
+        self.add_figures(figures, cache_dir)
+    }
+
+    /// Adds the table of `figures` after the tables its captures and promotions lead to
+    fn add_figures(&mut self, figures: Vec<Figure>, cache_dir: Option<&Path>) -> io::Result<()> {
+        if figures.len() == 2 || self.entries.contains_key(&material_key(&figures)) {
+            return Ok(());
+        }
+        for (slot, &figure) in figures.iter().enumerate().skip(2) {
+            let mut captured = figures.clone();
+            captured.remove(slot);
+            self.add_figures(captured, cache_dir)?;
+
+            let (piece, color) = figure.piece_and_color();
+            if piece == Pawn {
+                for promotion in [Knight, Bishop, Rook, Queen] {
+                    let mut promoted = figures.clone();
+                    promoted[slot] = Figure::from_piece_and_color(promotion, color);
+                    self.add_figures(promoted, cache_dir)?;
+                }
+            }
+        }
+
+        let name = signature(&figures);
+        let path = cache_dir.map(|directory| directory.join(format!("{name}.rtb")));
+        let cached = path
+            .as_deref()
+            .filter(|path| path.exists())
+            .and_then(|path| {
+                Table::load(path, name.clone(), figures.clone())
+                    .inspect_err(|err| println!("info string {}: {err}", path.display()))
+                    .ok()
+            });
+        let table = if let Some(table) = cached {
+            table
+        } else {
+            let start = Instant::now();
+            let full = generator::generate(&figures, self);
+            let table = Table::from_full(name, figures, &full);
+            println!(
+                "info string retrograde: generated {} in {:.1}s",
+                table.name,
+                start.elapsed().as_secs_f64()
+            );
+            if let Some(path) = &path {
+                table.save(path)?;
+            }
+            table
+        };
+        self.insert(table);
+        Ok(())
+    }
+
+    fn insert(&mut self, table: Table) {
+        let key = material_key(&table.figures);
+        let flipped: Vec<Figure> = table
+            .figures
+            .iter()
+            .map(|&figure| {
+                let (piece, color) = figure.piece_and_color();
+                Figure::from_piece_and_color(piece, !color)
+            })
+            .collect();
+        let table = Arc::new(table);
+        self.entries
+            .entry(material_key(&flipped))
+            .or_insert_with(|| (Arc::clone(&table), true));
+        self.entries.insert(key, (table, false));
+        self.table_count += 1;
+    }
+}
+
+/// The figures of a signature like `KRKP` (white has the pieces before the second king), kings first
+fn parse_signature(name: &str) -> Option<Vec<Figure>> {
+    let name = name.to_uppercase();
+    let (white, black) = name.strip_prefix('K')?.split_once('K')?;
+    let mut figures = vec![Figure::WhiteKing, Figure::BlackKing];
+    for (pieces, color) in [(white, White), (black, Black)] {
+        for c in pieces.chars() {
+            let piece = Piece::from_char(c.to_ascii_lowercase()).filter(|&piece| piece != King)?;
+            figures.push(Figure::from_piece_and_color(piece, color));
+        }
+    }
+    (figures.len() <= MAX_PIECES).then_some(figures)
+}
+
+/// Name of the table of `figures`, the strongest pieces come first
+fn signature(figures: &[Figure]) -> String {
+    let mut name = String::new();
+    for color in [White, Black] {
+        let mut pieces: Vec<Piece> = figures
+            .iter()
+            .map(|figure| figure.piece_and_color())
+            .filter(|&(_, piece_color)| piece_color == color)
+            .map(|(piece, _)| piece)
+            .collect();
+        pieces.sort_by_key(|&piece| Reverse(piece as usize));
+        name.extend(pieces.iter().map(|piece| piece.to_fin_char(White)));
+    }
+    name
+}
+
+fn material_key(figures: &[Figure]) -> u64 {
+    let mut counts = [0; Figure::WhiteKing as usize];
+    for &figure in figures {
+        if let Some(count) = counts.get_mut(figure as usize) {
+            *count += 1;
+        }
+    }
+    zobrist::material_key(&counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    fn probe(tables: &Tables, fen: &str) -> Outcome {
+        tables
+            .probe(&Board::new(fen))
+            .expect("covered by the tables")
+    }
+
+    #[test]
+    fn test_signatures() {
+        let figures = parse_signature("kpkr").unwrap();
+        assert_eq!(
+            figures,
+            [
+                Figure::WhiteKing,
+                Figure::BlackKing,
+                Figure::WhitePawn,
+                Figure::BlackRook
+            ]
+        );
+        assert_eq!(signature(&figures), "KPKR");
+        assert_eq!(
+            signature(&parse_signature("KNBK").unwrap()),
+            "KBNK",
+            "strongest pieces come first"
+        );
+        assert!(parse_signature("KRRKR").is_none());
+        assert!(parse_signature("KRR").is_none());
+    }
+
+    #[test]
+    fn test_generate() {
+        let directory =
+            env::temp_dir().join(format!("thunfisch-retrograde-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        // KPK needs the tables of every promotion, that includes KQK and KRK
+        let mut tables = Tables::default();
+        tables.add("KPK", Some(&directory)).unwrap();
+        assert_eq!(tables.table_count(), 5);
+
+        // the longest mates with a queen and a rook are well known
+        let longest = |name: &str| {
+            let (table, _) = &tables.entries[&material_key(&parse_signature(name).unwrap())];
+            table
+                .dtm
+                .iter()
+                .filter_map(|&dtm| match Outcome::from_dtm(dtm) {
+                    Outcome::Win(plies) => Some(plies),
+                    _ => None,
+                })
+                .max()
+        };
+        assert_eq!(longest("KQK"), Some(19));
+        assert_eq!(longest("KRK"), Some(31));
+        assert_eq!(longest("KBK"), None);
+
+        assert_eq!(
+            probe(&tables, "k6Q/8/1K6/8/8/8/8/8 b - - 0 1"),
+            Outcome::Loss(0)
+        );
+        assert_eq!(
+            probe(&tables, "k7/8/1K6/8/8/8/8/6Q1 w - - 0 1"),
+            Outcome::Win(1)
+        );
+        assert_eq!(
+            probe(&tables, "k7/8/2K5/8/8/8/8/8 w - - 0 1"),
+            Outcome::Draw
+        );
+        // the same mates the search has to find
+        assert_eq!(
+            probe(&tables, "2k5/8/1K6/8/8/8/8/7R w - - 0 1"),
+            Outcome::Win(3)
+        );
+        assert_eq!(
+            probe(&tables, "8/8/k7/8/2K5/8/8/6Q1 w - - 0 1"),
+            Outcome::Win(7)
+        );
+        // stalemate
+        assert_eq!(
+            probe(&tables, "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"),
+            Outcome::Draw
+        );
+
+        // king in front of the pawn wins, a rook pawn with the king in the corner doesn't
+        assert!(matches!(
+            probe(&tables, "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"),
+            Outcome::Loss(_)
+        ));
+        assert!(matches!(
+            probe(&tables, "4K3/8/4k3/4p3/8/8/8/8 w - - 0 1"),
+            Outcome::Loss(_)
+        ));
+        assert_eq!(
+            probe(&tables, "k7/8/K7/P7/8/8/8/8 w - - 0 1"),
+            Outcome::Draw
+        );
+
+        // a mate the 50 move rule may prevent isn't sure anymore, the draw still is
+        assert_eq!(
+            probe(&tables, "8/8/k7/8/2K5/8/8/6Q1 w - - 93 80"),
+            Outcome::Win(7)
+        );
+        assert!(
+            tables
+                .probe(&Board::new("8/8/k7/8/2K5/8/8/6Q1 w - - 94 80"))
+                .is_none()
+        );
+        assert_eq!(
+            probe(&tables, "k7/8/2K5/8/8/8/8/8 w - - 99 80"),
+            Outcome::Draw
+        );
+
+        // the cached files give the same tables
+        let mut cached = Tables::default();
+        cached.add("KPK", Some(&directory)).unwrap();
+        for (key, (table, flipped)) in &tables.entries {
+            let (cached_table, cached_flipped) = &cached.entries[key];
+            assert_eq!(flipped, cached_flipped);
+            assert!(table.dtm == cached_table.dtm, "{} differs", table.name);
+        }
+
+        // en passant isn't part of the index, an empty KPKP table is enough to see it's not consulted
+        let figures = parse_signature("KPKP").unwrap();
+        tables.insert(Table::new(signature(&figures), figures));
+        let mut board = Board::new("8/8/8/3pP3/8/8/k7/7K w - d6 0 2");
+        assert!(tables.probe(&board).is_none());
+        board.set_ep_target(None);
+        assert_eq!(tables.probe(&board), Some(Outcome::Draw));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
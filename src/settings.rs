@@ -11,23 +11,43 @@ pub const PVS: bool = cfg!(feature = "pvs");
 pub const KILLERS: bool = cfg!(feature = "killers");
 pub const HISTORIES: bool = cfg!(feature = "histories");
 pub const LMR: bool = cfg!(feature = "lmr");
+pub const QS_CHECKS: bool = cfg!(feature = "qs-checks");
+pub const DELTA_PRUNING: bool = cfg!(feature = "delta-pruning");
+pub const CORRECTION_HISTORY: bool = cfg!(feature = "correction-history");
+pub const PAWN_HASH: bool = cfg!(feature = "pawn-hash");
+pub const MATERIAL_HASH: bool = cfg!(feature = "material-hash");
+pub const NNUE: bool = cfg!(feature = "nnue");
+pub const ENDGAME: bool = cfg!(feature = "endgame");
+pub const SYZYGY: bool = cfg!(feature = "syzygy");
+pub const RETROGRADE: bool = cfg!(feature = "retrograde");
 pub const ROOKS_OPEN_FILES: bool = cfg!(feature = "advanced-eval");
 pub const DOUBLED_PAWNS: bool = cfg!(feature = "advanced-eval");
 pub const PASSED_PAWNS: bool = cfg!(feature = "advanced-eval");
 pub const ISOLATED_PAWNS: bool = cfg!(feature = "advanced-eval");
+pub const PAWN_FORMATION: bool = cfg!(feature = "advanced-eval");
+pub const PASSERS: bool = cfg!(feature = "advanced-eval");
 pub const KINGS_OPEN_FILES: bool = cfg!(feature = "advanced-eval");
 pub const BISHOP_PAIR: bool = cfg!(feature = "advanced-eval");
 pub const MOBILITY: bool = cfg!(feature = "advanced-eval");
 pub const KING_SAFETY: bool = cfg!(feature = "advanced-eval");
 pub const INITIATIVE: bool = cfg!(feature = "advanced-eval");
+pub const THREATS: bool = cfg!(feature = "advanced-eval");
+pub const PIECE_POSITIONS: bool = cfg!(feature = "advanced-eval");
+pub const IMBALANCE: bool = cfg!(feature = "advanced-eval");
+pub const MATERIAL_SCALING: bool = cfg!(feature = "advanced-eval");
 
 // These can be tweaked, have an effect on elo
-pub const QS_CHECK_EVASION_LIMIT: usize = 2;
 pub const MAX_QS_DEPTH: usize = 12;
 // Maximum search depth. In practice likely never reached, but has an effect on memory usage of the program
 pub const MAX_AB_DEPTH: usize = 128;
 // How far a static eval needs to be over beta to initiate an RFP cutoff
 pub const RFP_MARGIN: usize = 50;
+// How much a QS capture may fall short of alpha (after winning the captured piece) before it gets delta pruned
+pub const QS_DELTA_MARGIN: i32 = 200;
+// From this depth on a null move fail high is always verified by a reduced search without the null move
+pub const NMP_VERIFICATION_DEPTH: usize = 8;
+// Below that depth it's only verified if the side to move has at most this many knights, bishops, rooks and queens
+pub const NMP_VERIFICATION_MAX_PIECES: u32 = 1;
 
 // How early we start Late Move Reductions
 // The better our move ordering is, the earlier we can do LMR, the more we hopefully prune
@@ -36,6 +56,6 @@ pub const MOVES_BEFORE_LMR: usize = 4;
 #[inline]
 pub fn repr() -> String {
     format!(
-        "Activated Features: AB={AB:?} QS={QS:?} TT-AB={TT_AB:?} TT-QS={TT_QS:?} MVV-LVA={MVV_LVA:?} QS_CHECK_EVASION_LIMIT={QS_CHECK_EVASION_LIMIT:?} ORDER_TT_MV_FIRST={ORDER_TT_MV_FIRST:?} TT_CUTTOFFS={TT_CUTTOFFS:?} NMP={NMP:?} RFP={RFP:?} PVS={PVS:?} KILLERS={KILLERS:?} HISTORIES={HISTORIES:?} LMR={LMR:?}\nEvaluation Settings: ROF={ROOKS_OPEN_FILES:?} DP={DOUBLED_PAWNS:?} PP={PASSED_PAWNS:?} IP={ISOLATED_PAWNS:?} KOF={KINGS_OPEN_FILES:?} BP={BISHOP_PAIR:?} MOBILITY = {MOBILITY:?}"
+        "Activated Features: AB={AB:?} QS={QS:?} TT-AB={TT_AB:?} TT-QS={TT_QS:?} MVV-LVA={MVV_LVA:?} ORDER_TT_MV_FIRST={ORDER_TT_MV_FIRST:?} TT_CUTTOFFS={TT_CUTTOFFS:?} NMP={NMP:?} RFP={RFP:?} PVS={PVS:?} KILLERS={KILLERS:?} HISTORIES={HISTORIES:?} LMR={LMR:?} QS_CHECKS={QS_CHECKS:?} DELTA_PRUNING={DELTA_PRUNING:?} CORRECTION_HISTORY={CORRECTION_HISTORY:?} PAWN_HASH={PAWN_HASH:?} MATERIAL_HASH={MATERIAL_HASH:?} NNUE={NNUE:?} ENDGAME={ENDGAME:?} SYZYGY={SYZYGY:?} RETROGRADE={RETROGRADE:?}\nEvaluation Settings: ROF={ROOKS_OPEN_FILES:?} DP={DOUBLED_PAWNS:?} PP={PASSED_PAWNS:?} IP={ISOLATED_PAWNS:?} PF={PAWN_FORMATION:?} PASSERS={PASSERS:?} KOF={KINGS_OPEN_FILES:?} BP={BISHOP_PAIR:?} MOBILITY = {MOBILITY:?} THREATS={THREATS:?} PIECE_POSITIONS={PIECE_POSITIONS:?} IMBALANCE={IMBALANCE:?} MATERIAL_SCALING={MATERIAL_SCALING:?}"
     )
 }
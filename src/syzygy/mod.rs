@@ -0,0 +1,576 @@
+// Probing of Syzygy endgame tablebases <https://www.chessprogramming.org/Syzygy_Bases>
+//
+// WDL tables tell whether a position is won, drawn or lost (taking the 50 move rule into account),
+// DTZ tables how many plies it takes until the next capture or pawn move on the way there.
+// Neither stores positions in which the side to move can capture en passant or where a capture is the
+// only good move, so every probe first tries the captures itself, just like Stockfish does.
+
+mod table;
+
+use crate::{evaluation::MATE_THRESHOLD, prelude::*, settings::MAX_AB_DEPTH};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    env, fs, io,
+    ops::Neg,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+};
+use table::{MAX_PIECES, Table, TableInfo, TableKind};
+
+/// Score of a position the tables say is won, minus the ply it was found at.
+/// Above anything the evaluation returns but below the mate scores
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub const TB_WIN: i32 = MATE_THRESHOLD - MAX_AB_DEPTH as i32 - 1;
+
+/// Rank of a root move which wins for sure, see `Tablebases::rank_root_moves`
+const MAX_DTZ: i32 = 1 << 18;
+
+/// Outcome of a position for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss = -2,
+    /// Lost, but drawn by the 50 move rule
+    BlessedLoss = -1,
+    Draw = 0,
+    /// Won, but drawn by the 50 move rule
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    const fn from_value(value: i32) -> Self {
+        match value {
+            ..=-2 => Self::Loss,
+            -1 => Self::BlessedLoss,
+            0 => Self::Draw,
+            1 => Self::CursedWin,
+            2.. => Self::Win,
+        }
+    }
+
+    /// DTZ of a position whose best move zeroes the 50 move counter
+    const fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Self::Win => 1,
+            Self::CursedWin => 101,
+            Self::Draw => 0,
+            Self::BlessedLoss => -101,
+            Self::Loss => -1,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_value(-(self as i32))
+    }
+}
+
+/// Tables found via the `SyzygyPath` option
+static TABLEBASES: Mutex<Option<Arc<Tablebases>>> = Mutex::new(None);
+
+/// The tables the search should probe, `None` if there are none
+pub fn tablebases() -> Option<Arc<Tablebases>> {
+    TABLEBASES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// Looks for tables in `paths` (separated like `PATH`), `None` unloads them.
+/// Returns the number of tables found
+pub fn load_tablebases(paths: Option<&str>) -> io::Result<usize> {
+    let tablebases = match paths {
+        Some(paths) => {
+            let directories: Vec<PathBuf> = env::split_paths(paths).collect();
+            if let Some(missing) = directories.iter().find(|directory| !directory.is_dir()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} is not a directory", missing.display()),
+                ));
+            }
+            Some(Tablebases::new(&directories))
+        }
+        None => None,
+    };
+    let found = tablebases.as_ref().map_or(0, Tablebases::table_count);
+    *TABLEBASES.lock().unwrap_or_else(PoisonError::into_inner) = tablebases
+        .filter(|tablebases| tablebases.table_count() > 0)
+        .map(Arc::new);
+    Ok(found)
+}
+
+/// The files of one material signature, they get read on the first probe
+struct Entry {
+    info: TableInfo,
+    wdl_path: PathBuf,
+    dtz_path: Option<PathBuf>,
+    wdl: OnceLock<Option<Table>>,
+    dtz: OnceLock<Option<Table>>,
+}
+
+impl Entry {
+    fn load(path: Option<&Path>, kind: TableKind, info: &TableInfo) -> Option<Table> {
+        let bytes = fs::read(path?).ok()?;
+        // a broken file only costs us the probes into it
+        Table::from_bytes(bytes, kind, info)
+            .inspect_err(|err| println!("info string {}.{}: {err}", info.name, kind.extension()))
+            .ok()
+    }
+
+    fn wdl(&self) -> Option<&Table> {
+        self.wdl
+            .get_or_init(|| Self::load(Some(&self.wdl_path), TableKind::Wdl, &self.info))
+            .as_ref()
+    }
+
+    fn dtz(&self) -> Option<&Table> {
+        self.dtz
+            .get_or_init(|| Self::load(self.dtz_path.as_deref(), TableKind::Dtz, &self.info))
+            .as_ref()
+    }
+}
+
+pub struct Tablebases {
+    /// By material key, every entry is there for both colors
+    entries: HashMap<u64, Arc<Entry>>,
+    /// Most pieces of any table found
+    max_pieces: usize,
+    tables: usize,
+}
+
+impl Tablebases {
+    /// Registers every table which has a WDL file in one of `directories`
+    pub fn new(directories: &[PathBuf]) -> Self {
+        let mut tablebases = Self {
+            entries: HashMap::new(),
+            max_pieces: 0,
+            tables: 0,
+        };
+        let find = |file: &str| {
+            directories
+                .iter()
+                .map(|directory| directory.join(file))
+                .find(|path| path.is_file())
+        };
+
+        for name in table_names() {
+            let Some(wdl_path) = find(&format!("{name}.rtbw")) else {
+                continue;
+            };
+            let info = TableInfo::new(&name);
+            tablebases.max_pieces = tablebases.max_pieces.max(info.piece_count);
+            tablebases.tables += 1;
+            let entry = Arc::new(Entry {
+                dtz_path: find(&format!("{name}.rtbz")),
+                wdl_path,
+                info,
+                wdl: OnceLock::new(),
+                dtz: OnceLock::new(),
+            });
+            tablebases.entries.insert(entry.info.key, entry.clone());
+            tablebases.entries.insert(entry.info.key2, entry);
+        }
+        tablebases
+    }
+
+    pub const fn table_count(&self) -> usize {
+        self.tables
+    }
+
+    /// Whether `board` is worth probing inside the search: only right after a capture or pawn move,
+    /// the tables don't know how far the 50 move counter already is and don't store castling rights
+    pub const fn can_probe(&self, board: &Board) -> bool {
+        board.halfmove_clock() == 0
+            && board.occupied().get_count() as usize <= self.max_pieces
+            && !can_castle(board)
+    }
+
+    /// Win/draw/loss of the side to move, `None` if a required table is missing
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies to the next capture or pawn move with perfect play, positive if the side to move wins.
+    /// Above 100 (or below -100) the result is a draw by the 50 move rule. Can be one ply too high,
+    /// so only dtz + halfmove clock <= 99 is a sure win. `None` if a required table is missing
+    pub fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, zeroing) = self.search(board, true)?;
+
+        // draws aren't stored and a winning capture or pawn move leaves the DTZ a don't care value
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        let entry = self.entries.get(&board.material_key())?;
+        if let Some(dtz) = entry.dtz()?.probe_dtz(&entry.info, board, wdl as i32) {
+            let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + if cursed { 100 } else { 0 }) * (wdl as i32).signum());
+        }
+
+        // the table only has the other side to move, so look one ply ahead for the best reply
+        let mut min_dtz = i32::MAX;
+        for mv in board.generate_all_moves().list.iter().map(|entry| entry.mv) {
+            let decoded = mv.decode();
+            let zeroing =
+                decoded.mv_type.is_capture() || board.figures(decoded.from).piece() == Pawn;
+
+            board.make_move(mv);
+            // for zeroing moves we want the DTZ before the move, otherwise the one of the next phase
+            let dtz = if zeroing {
+                self.probe_wdl(board).map(|wdl| -wdl.dtz_before_zeroing())
+            } else {
+                self.probe_dtz(board).map(Neg::neg)
+            };
+            let mates =
+                dtz == Some(1) && board.is_in_check() && board.generate_all_moves().list.is_empty();
+            board.unmake_move();
+
+            let mut dtz = dtz?;
+            if mates {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == (wdl as i32).signum() {
+                min_dtz = dtz;
+            }
+        }
+
+        // without legal moves we are mated
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    /// Tries the captures (and with `zeroing_moves` the pawn moves) and combines them with the table value.
+    /// Also returns whether the best move is one of them, in that case a DTZ table doesn't store a valid value
+    fn search(&self, board: &mut Board, zeroing_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = board.generate_all_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+
+        for mv in moves.list.iter().map(|entry| entry.mv) {
+            let decoded = mv.decode();
+            if !decoded.mv_type.is_capture()
+                && (!zeroing_moves || board.figures(decoded.from).piece() != Pawn)
+            {
+                continue;
+            }
+            searched += 1;
+
+            board.make_move(mv);
+            let value = self.search(board, false).map(|(wdl, _)| -wdl);
+            board.unmake_move();
+
+            let value = value?;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        // If every legal move got searched the table isn't needed (and might be wrong, e.g. with en passant)
+        let all_searched = searched > 0 && searched == moves.list.len();
+        let value = if all_searched {
+            best
+        } else {
+            self.probe_wdl_table(board)?
+        };
+
+        if best >= value {
+            return Some((best, best > Wdl::Draw || all_searched));
+        }
+        Some((value, false))
+    }
+
+    fn probe_wdl_table(&self, board: &Board) -> Option<Wdl> {
+        if board.occupied().get_count() == 2 {
+            return Some(Wdl::Draw);
+        }
+        let entry = self.entries.get(&board.material_key())?;
+        let table = entry.wdl()?;
+        Some(Wdl::from_value(table.probe_wdl(&entry.info, board)))
+    }
+
+    /// Ranks the legal moves of `board` by their DTZ (or WDL if DTZ tables are missing), higher is better.
+    /// Sure wins rank equally, as do losses which can't be saved by the 50 move rule.
+    /// Also returns whether the ranking is based on DTZ, `None` if the position isn't covered by the tables
+    pub fn rank_root_moves(&self, board: &mut Board) -> Option<(Vec<(EncodedMove, i32)>, bool)> {
+        if board.occupied().get_count() as usize > self.max_pieces || can_castle(board) {
+            return None;
+        }
+
+        self.rank_root_moves_dtz(board)
+            .map(|moves| (moves, true))
+            .or_else(|| self.rank_root_moves_wdl(board).map(|moves| (moves, false)))
+    }
+
+    fn rank_root_moves_dtz(&self, board: &mut Board) -> Option<Vec<(EncodedMove, i32)>> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        let halfmove_clock = board.halfmove_clock() as i32;
+        let repeated = board.count_repetitions() > 1;
+        let mut ranked = Vec::new();
+
+        for mv in board.generate_all_moves().list.iter().map(|entry| entry.mv) {
+            board.make_move(mv);
+            let dtz = if board.halfmove_clock() == 0 {
+                self.probe_wdl(board).map(|wdl| (-wdl).dtz_before_zeroing())
+            } else if board.is_threefold_repetition() || board.is_50_move_rule() {
+                Some(0)
+            } else {
+                // the DTZ of the position after the move, one ply more from here
+                self.probe_dtz(board).map(|dtz| -dtz - dtz.signum())
+            };
+            let mates = board.is_in_check() && board.generate_all_moves().list.is_empty();
+            board.unmake_move();
+
+            let mut dtz = dtz?;
+            if mates && dtz == 2 {
+                dtz = 1;
+            }
+
+            let rank = match dtz.cmp(&0) {
+                Ordering::Greater if dtz + halfmove_clock <= 99 && !repeated => MAX_DTZ,
+                Ordering::Greater => MAX_DTZ - (dtz + halfmove_clock),
+                Ordering::Less if -dtz * 2 + halfmove_clock < 100 => -MAX_DTZ,
+                Ordering::Less => -MAX_DTZ + (-dtz + halfmove_clock),
+                Ordering::Equal => 0,
+            };
+            ranked.push((mv, rank));
+        }
+        Some(ranked)
+    }
+
+    fn rank_root_moves_wdl(&self, board: &mut Board) -> Option<Vec<(EncodedMove, i32)>> {
+        let mut ranked = Vec::new();
+        for mv in board.generate_all_moves().list.iter().map(|entry| entry.mv) {
+            board.make_move(mv);
+            let wdl = if board.is_threefold_repetition() || board.is_50_move_rule() {
+                Some(Wdl::Draw)
+            } else {
+                self.probe_wdl(board).map(Neg::neg)
+            };
+            board.unmake_move();
+
+            let rank = match wdl? {
+                Wdl::Loss => -MAX_DTZ,
+                Wdl::BlessedLoss => -MAX_DTZ + 101,
+                Wdl::Draw => 0,
+                Wdl::CursedWin => MAX_DTZ - 101,
+                Wdl::Win => MAX_DTZ,
+            };
+            ranked.push((mv, rank));
+        }
+        Some(ranked)
+    }
+
+    /// The moves the search should consider at the root: those with the best rank.
+    /// Also returns whether probing inside the search is still useful, which it isn't once DTZ
+    /// picked the moves which keep the result
+    pub fn root_moves(&self, board: &mut Board) -> Option<(Vec<EncodedMove>, bool)> {
+        let (ranked, dtz) = self.rank_root_moves(board)?;
+        let best = ranked.iter().map(|&(_, rank)| rank).max()?;
+        let moves = ranked
+            .into_iter()
+            .filter(|&(_, rank)| rank == best)
+            .map(|(mv, _)| mv)
+            .collect();
+        Some((moves, !dtz && best > 0))
+    }
+}
+
+const fn can_castle(board: &Board) -> bool {
+    board.white_king_castle()
+        || board.white_queen_castle()
+        || board.black_king_castle()
+        || board.black_queen_castle()
+}
+
+/// Names of every table with up to `MAX_PIECES` pieces, like `KRPvKR`
+fn table_names() -> Vec<String> {
+    const PIECES: [char; 5] = ['P', 'N', 'B', 'R', 'Q'];
+
+    // every side's pieces (besides the king) from strongest to weakest, as indices into `PIECES`
+    let mut sides: Vec<Vec<usize>> = vec![Vec::new()];
+    let mut i = 0;
+    while i < sides.len() {
+        if sides[i].len() < MAX_PIECES - 2 {
+            let weakest = sides[i].last().copied().unwrap_or(PIECES.len() - 1);
+            for piece in (0..=weakest).rev() {
+                let mut side = sides[i].clone();
+                side.push(piece);
+                sides.push(side);
+            }
+        }
+        i += 1;
+    }
+
+    let name = |side: &[usize]| -> String {
+        std::iter::once('K')
+            .chain(side.iter().map(|&piece| PIECES[piece]))
+            .collect()
+    };
+
+    let mut names = Vec::new();
+    for white in &sides {
+        for black in &sides {
+            // white is the stronger side: more pieces, or stronger ones. KvK is a draw without a table
+            if !white.is_empty()
+                && white.len() + black.len() + 2 <= MAX_PIECES
+                && (white.len(), white) >= (black.len(), black)
+            {
+                names.push(format!("{}v{}", name(white), name(black)));
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn test_table_names() {
+        let names = table_names();
+        let with_pieces =
+            |pieces: usize| names.iter().filter(|name| name.len() - 1 == pieces).count();
+        // the number of tables of the complete Syzygy sets
+        assert_eq!(with_pieces(3), 5);
+        assert_eq!(with_pieces(4), 30);
+        assert_eq!(with_pieces(5), 110);
+        assert_eq!(with_pieces(6), 365);
+        assert_eq!(with_pieces(7), 1001);
+        for name in ["KQvK", "KRPvKR", "KBNvK", "KPvKP", "KQRvKQ", "KNNvKP"] {
+            assert!(names.iter().any(|other| other == name), "{name}");
+        }
+        assert!(!names.iter().any(|name| name == "KvKQ" || name == "KRvKQ"));
+    }
+
+    /// A `KQvK` WDL file which claims every position with white to move is won and every one with
+    /// black to move is drawn. Not a real table, but enough to check how positions find their way into it
+    fn write_single_value_table(directory: &Path) {
+        let mut bytes = vec![0; 80];
+        bytes[..4].copy_from_slice(&table::WDL_MAGIC);
+        // split into both sides to move, no pawns
+        bytes[4] = 1;
+        // group order, then the pieces (white king, white queen, black king) for both sides
+        bytes[5] = 0;
+        bytes[6..9].copy_from_slice(&[0x66, 0x55, 0xEE]);
+        // after aligning: a single value for each side to move, stored as WDL + 2
+        bytes[10..14].copy_from_slice(&[0x80, 4, 0x80, 2]);
+        fs::write(directory.join("KQvK.rtbw"), bytes).unwrap();
+    }
+
+    #[test]
+    fn test_probe_wdl() {
+        let directory = env::temp_dir().join(format!("thunfisch-syzygy-{}", process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        write_single_value_table(&directory);
+        let tablebases = Tablebases::new(std::slice::from_ref(&directory));
+
+        assert_eq!(tablebases.table_count(), 1);
+        let probe = |fen: &str| tablebases.probe_wdl(&mut Board::new(fen));
+
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KQ6 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KQ6 b - - 0 1"), Some(Wdl::Draw));
+        // the same with colors swapped
+        assert_eq!(probe("kq6/8/8/8/4K3/8/8/8 b - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("kq6/8/8/8/4K3/8/8/8 w - - 0 1"), Some(Wdl::Draw));
+        // capturing the queen is searched rather than looked up, KvK is drawn without a table
+        assert_eq!(probe("8/8/8/8/8/8/1k6/KQ6 b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("8/8/8/8/8/8/1q6/K6k w - - 0 1"), Some(Wdl::Draw));
+        // no table for KRvK
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KR6 w - - 0 1"), None);
+
+        // only DTZ tables could tell the moves apart, but the WDL ranking keeps the win
+        let mut board = Board::new("8/8/8/4k3/8/8/8/KQ6 b - - 0 1");
+        let (moves, probe_in_search) = tablebases.root_moves(&mut board).unwrap();
+        assert_eq!(moves.len(), board.generate_all_moves().list.len());
+        assert!(!probe_in_search);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    /// The real 3 piece tables (`KQvK`, `KRvK`, `KPvK`, `KBvK` and `KNvK`, WDL and DTZ) downloaded into
+    /// `tests/fixtures/syzygy` by the `fetch.sh` there
+    fn fixture_tablebases() -> Tablebases {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/syzygy");
+        let tablebases = Tablebases::new(&[directory]);
+        assert_eq!(
+            tablebases.table_count(),
+            5,
+            "the 3 piece tables are missing, run tests/fixtures/syzygy/fetch.sh"
+        );
+        tablebases
+    }
+
+    #[test]
+    #[ignore = "needs the 3 piece tables from tests/fixtures/syzygy/fetch.sh"]
+    fn test_real_tables_wdl() {
+        let tablebases = fixture_tablebases();
+        let probe = |fen: &str| tablebases.probe_wdl(&mut Board::new(fen));
+
+        // KQvK and KRvK are won unless the piece hangs or it's stalemate
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KQ6 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KQ6 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(probe("8/8/8/8/8/8/2k5/1Q5K b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("k6Q/8/1K6/8/8/8/8/8 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KR6 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/8/8/8/2k5/1R5K b - - 0 1"), Some(Wdl::Draw));
+
+        // KPvK: the pawn runs, gets taken or stalemates, for both colors
+        assert_eq!(probe("8/8/8/8/8/8/4P3/k3K3 w - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/8/8/8/4P3/k3K3 b - - 0 1"), Some(Wdl::Loss));
+        assert_eq!(probe("K3k3/4p3/8/8/8/8/8/8 b - - 0 1"), Some(Wdl::Win));
+        assert_eq!(probe("8/8/8/8/8/8/3kP3/7K b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("7k/3Kp3/8/8/8/8/8/8 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"), Some(Wdl::Draw));
+
+        // a lone minor piece can't mate, whoever is to move
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KB6 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KB6 b - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("8/8/8/4K3/8/8/8/kn6 w - - 0 1"), Some(Wdl::Draw));
+        assert_eq!(probe("8/8/8/4K3/8/8/8/kn6 b - - 0 1"), Some(Wdl::Draw));
+    }
+
+    #[test]
+    #[ignore = "needs the 3 piece tables from tests/fixtures/syzygy/fetch.sh"]
+    fn test_real_tables_dtz() {
+        let tablebases = fixture_tablebases();
+        let probe = |fen: &str| tablebases.probe_dtz(&mut Board::new(fen));
+
+        assert_eq!(probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), Some(0));
+        assert_eq!(probe("8/8/8/8/8/8/3kP3/7K b - - 0 1"), Some(0));
+        // mate in one and a winning pawn move both end the phase right away
+        assert_eq!(probe("k7/8/1K6/8/8/8/7Q/8 w - - 0 1"), Some(1));
+        assert_eq!(probe("8/8/8/8/8/8/4P3/k3K3 w - - 0 1"), Some(1));
+        // without a mate in one it takes longer
+        assert!(probe("8/8/8/4k3/8/8/8/KQ6 w - - 0 1").unwrap() > 1);
+        assert!(probe("8/8/8/4k3/8/8/8/KQ6 b - - 0 1").unwrap() < -1);
+        assert_eq!(probe("8/8/8/4k3/8/8/8/KB6 w - - 0 1"), Some(0));
+        assert_eq!(probe("8/8/8/4K3/8/8/8/kn6 b - - 0 1"), Some(0));
+
+        // one ply before the 50 move rule only the mate still wins, the DTZ ranking has to find it
+        let mut board = Board::new("k7/8/1K6/8/8/8/7Q/8 w - - 98 80");
+        let h2h8 = DecodedMove::from_coords("h2h8", &board).encode();
+        let (moves, probe_in_search) = tablebases.root_moves(&mut board).unwrap();
+        assert_eq!(moves, vec![h2h8]);
+        assert!(!probe_in_search);
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        assert!(load_tablebases(Some("/this/directory/does/not/exist")).is_err());
+        assert!(tablebases().is_none());
+    }
+}
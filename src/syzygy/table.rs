@@ -0,0 +1,937 @@
+// Decoding of single Syzygy table files (`.rtbw` for WDL, `.rtbz` for DTZ).
+// The format isn't documented apart from the generator and the probing code, this follows the probing code of
+// Stockfish (`syzygy/tbprobe.cpp`) closely, including the names, so the two can be compared side by side.
+//
+// A table stores one value per position index. Positions are mapped to indices by placing groups of pieces
+// (the leading pieces or pawns, then every further group of equal pieces) with as few symmetric duplicates as possible.
+// The values are compressed with recursive pairing (frequent pairs of symbols become new symbols),
+// the symbols are then Huffman coded in blocks of fixed size.
+
+use crate::{prelude::*, utils::persistence::invalid_data, utils::zobrist};
+use std::{io, sync::LazyLock};
+
+/// Tables with more pieces than this don't exist
+pub const MAX_PIECES: usize = 7;
+
+pub const WDL_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+pub const DTZ_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+
+// flags of a `PairsData`
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+// flags in the header of a file
+const SPLIT: u8 = 1;
+const HAS_PAWNS: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+impl TableKind {
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::Wdl => "rtbw",
+            Self::Dtz => "rtbz",
+        }
+    }
+
+    const fn magic(self) -> [u8; 4] {
+        match self {
+            Self::Wdl => WDL_MAGIC,
+            Self::Dtz => DTZ_MAGIC,
+        }
+    }
+
+    /// WDL tables store both sides to move, DTZ tables only one of them
+    const fn sides(self) -> usize {
+        match self {
+            Self::Wdl => 2,
+            Self::Dtz => 1,
+        }
+    }
+}
+
+/// Lookup tables of the index encoding
+struct Indices {
+    /// a2-h7 to 0..47, the pawn with the highest value is the leading one
+    map_pawns: [usize; 64],
+    /// Squares below the a1-h8 diagonal to 0..27
+    map_b1h1h7: [usize; 64],
+    /// The a1-d1-d4 triangle to 0..9, the diagonal squares last
+    map_a1d1d4: [usize; 64],
+    /// The 462 placements of two kings with the first one in the a1-d1-d4 triangle
+    map_kk: [[usize; 64]; 10],
+    /// `binomial[k][n]` ways to choose k of n squares
+    binomial: [[u64; 64]; MAX_PIECES - 1],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES - 1],
+    /// Number of placements of the leading pawns by their count and file
+    lead_pawns_size: [[u64; 4]; MAX_PIECES - 1],
+}
+
+#[allow(clippy::cast_possible_wrap)]
+const fn off_a1h8(square: usize) -> isize {
+    (square / 8) as isize - (square % 8) as isize
+}
+
+static INDICES: LazyLock<Indices> = LazyLock::new(|| {
+    let mut indices = Indices {
+        map_pawns: [0; 64],
+        map_b1h1h7: [0; 64],
+        map_a1d1d4: [0; 64],
+        map_kk: [[0; 64]; 10],
+        binomial: [[0; 64]; MAX_PIECES - 1],
+        lead_pawn_idx: [[0; 64]; MAX_PIECES - 1],
+        lead_pawns_size: [[0; 4]; MAX_PIECES - 1],
+    };
+
+    let mut code = 0;
+    for square in 0..64 {
+        if off_a1h8(square) < 0 {
+            indices.map_b1h1h7[square] = code;
+            code += 1;
+        }
+    }
+
+    let mut diagonal = Vec::new();
+    code = 0;
+    for square in 0..28 {
+        if square % 8 > 3 {
+            continue;
+        }
+        if off_a1h8(square) < 0 {
+            indices.map_a1d1d4[square] = code;
+            code += 1;
+        } else if off_a1h8(square) == 0 {
+            diagonal.push(square);
+        }
+    }
+    for square in diagonal {
+        indices.map_a1d1d4[square] = code;
+        code += 1;
+    }
+
+    // if the first king is on the diagonal the second one can be mirrored below it as well
+    let mut both_on_diagonal = Vec::new();
+    code = 0;
+    for idx in 0..10 {
+        for first in 0..28 {
+            if first % 8 > 3 || indices.map_a1d1d4[first] != idx || (idx == 0 && first != 1) {
+                continue;
+            }
+            for second in 0..64 {
+                // the same or adjacent squares
+                if (first % 8).abs_diff(second % 8) <= 1 && (first / 8).abs_diff(second / 8) <= 1 {
+                    continue;
+                }
+                if off_a1h8(first) == 0 && off_a1h8(second) > 0 {
+                    continue;
+                }
+                if off_a1h8(first) == 0 && off_a1h8(second) == 0 {
+                    both_on_diagonal.push((idx, second));
+                } else {
+                    indices.map_kk[idx][second] = code;
+                    code += 1;
+                }
+            }
+        }
+    }
+    for (idx, second) in both_on_diagonal {
+        indices.map_kk[idx][second] = code;
+        code += 1;
+    }
+
+    indices.binomial[0][0] = 1;
+    for n in 1..64 {
+        for k in 0..(MAX_PIECES - 1).min(n + 1) {
+            indices.binomial[k][n] = if k > 0 {
+                indices.binomial[k - 1][n - 1]
+            } else {
+                0
+            } + if k < n { indices.binomial[k][n - 1] } else { 0 };
+        }
+    }
+
+    // squares the other pawns can be on with the leading pawn on a square, decreasing towards the center and the 7th rank
+    let mut available_squares = 48;
+    for lead_pawns_cnt in 1..MAX_PIECES - 1 {
+        for file in 0..4 {
+            let mut idx = 0;
+            for rank in 1..7 {
+                let square = rank * 8 + file;
+                if lead_pawns_cnt == 1 {
+                    indices.map_pawns[square] = available_squares - 1;
+                    indices.map_pawns[square ^ 7] = available_squares - 2;
+                    available_squares -= 2;
+                }
+                indices.lead_pawn_idx[lead_pawns_cnt][square] = idx;
+                idx += indices.binomial[lead_pawns_cnt - 1][indices.map_pawns[square]];
+            }
+            indices.lead_pawns_size[lead_pawns_cnt][file] = idx;
+        }
+    }
+
+    indices
+});
+
+/// Piece code used in the table files: 1 (pawn) to 6 (king), +8 for black
+const fn tb_piece(figure: Figure) -> u8 {
+    let (piece, color) = figure.piece_and_color();
+    piece as u8 + 1 + 8 * color as u8
+}
+
+/// What is known about a table from its material alone
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    /// e.g. `KRvKP`
+    pub name: String,
+    /// Material key with the pieces left of the `v` being white
+    pub key: u64,
+    /// Material key with the colors swapped
+    pub key2: u64,
+    pub piece_count: usize,
+    has_pawns: bool,
+    /// At least one side has a piece (besides the king) or a pawn only once
+    has_unique_pieces: bool,
+    /// Pawns of the leading color first, that is the side with less pawns (but at least one)
+    pawn_count: [usize; 2],
+}
+
+impl TableInfo {
+    /// `name` is of the form `KQRvKP`, every side's pieces from strongest to weakest
+    pub fn new(name: &str) -> Self {
+        let sides: [&str; 2] = name
+            .split_once('v')
+            .expect("table names contain both sides")
+            .into();
+        let mut counts = [[0; 6]; 2];
+        for (side, pieces) in sides.into_iter().enumerate() {
+            for char in pieces.chars() {
+                let piece = "PNBRQK"
+                    .find(char)
+                    .expect("table names contain only pieces");
+                counts[side][piece] += 1;
+            }
+        }
+
+        let key = |white: usize| {
+            let mut figures = [0; Figure::WhiteKing as usize];
+            for (piece, count) in figures.chunks_exact_mut(2).enumerate() {
+                count[0] = counts[white][piece];
+                count[1] = counts[1 - white][piece];
+            }
+            zobrist::material_key(&figures)
+        };
+
+        let pawns = [counts[0][Pawn as usize], counts[1][Pawn as usize]];
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+
+        Self {
+            name: name.to_string(),
+            key: key(0),
+            key2: key(1),
+            piece_count: counts.iter().flatten().sum(),
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces: counts.iter().any(|side| side[..King as usize].contains(&1)),
+            pawn_count: if white_leads {
+                pawns
+            } else {
+                [pawns[1], pawns[0]]
+            },
+        }
+    }
+
+    const fn is_symmetric(&self) -> bool {
+        self.key == self.key2
+    }
+}
+
+/// Decoding information of one side to move and leading pawn file of a table
+#[derive(Default, Clone)]
+struct PairsData {
+    flags: u8,
+    size_of_block: usize,
+    span: u64,
+    num_blocks: usize,
+    block_length_size: usize,
+    min_sym_len: u8,
+    /// Offset of the lowest symbol of each length
+    lowest_sym: usize,
+    /// Lowest code of each length, left aligned
+    base64: Vec<u64>,
+    /// Number of values each symbol expands to, minus one
+    symlen: Vec<u8>,
+    /// Offset of the pairs each symbol expands to
+    btree: usize,
+    /// Offset of the (block, offset in block) of every `span`th value
+    sparse_index: usize,
+    sparse_index_size: usize,
+    /// Offset of the number of values in each block, minus one
+    block_length: usize,
+    /// Offset of the first block
+    data: usize,
+    /// The order the pieces get encoded in
+    pieces: [u8; MAX_PIECES],
+    /// Number of pieces in each group, zero terminated
+    group_len: [usize; MAX_PIECES + 1],
+    /// Factor of each group in the index, the entry after the last group is the size of the table
+    group_idx: [u64; MAX_PIECES + 1],
+    /// Start of the DTZ value maps by WDL result, offsets into `map` in elements
+    map_idx: [usize; 4],
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"))
+}
+
+fn read_u32_be(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(bytes[at..at + 4].try_into().expect("4 bytes"))
+}
+
+fn read_u64_be(bytes: &[u8], at: usize) -> u64 {
+    u64::from_be_bytes(bytes[at..at + 8].try_into().expect("8 bytes"))
+}
+
+impl PairsData {
+    /// The first and second symbol a symbol expands to, stored as two 12 bit numbers
+    fn pair(&self, bytes: &[u8], sym: usize) -> (usize, usize) {
+        let lr = &bytes[self.btree + 3 * sym..self.btree + 3 * sym + 3];
+        (
+            (usize::from(lr[1] & 0xF) << 8) | usize::from(lr[0]),
+            (usize::from(lr[2]) << 4) | usize::from(lr[1] >> 4),
+        )
+    }
+
+    /// Splits the pieces into groups and calculates the factor of every group in the index.
+    /// `order` is the position of the leading group and the remaining pawns in the index, 0xF if absent
+    fn set_groups(&mut self, info: &TableInfo, order: [u8; 2], file: usize) {
+        let indices = &*INDICES;
+        let mut n = 0;
+        let mut first_len: isize = if info.has_pawns {
+            0
+        } else if info.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        self.group_len[n] = 1;
+
+        for i in 1..info.piece_count {
+            first_len -= 1;
+            if first_len > 0 || self.pieces[i] == self.pieces[i - 1] {
+                self.group_len[n] += 1;
+            } else {
+                n += 1;
+                self.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        self.group_len[n] = 0;
+
+        let both_pawns = info.has_pawns && info.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - self.group_len[0] - if both_pawns { self.group_len[1] } else { 0 };
+        let mut idx = 1;
+
+        let mut k = 0;
+        while next < n || k == usize::from(order[0]) || k == usize::from(order[1]) {
+            if k == usize::from(order[0]) {
+                self.group_idx[0] = idx;
+                idx *= if info.has_pawns {
+                    indices.lead_pawns_size[self.group_len[0]][file]
+                } else if info.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == usize::from(order[1]) {
+                self.group_idx[1] = idx;
+                idx *= indices.binomial[self.group_len[1]][48 - self.group_len[0]];
+            } else {
+                self.group_idx[next] = idx;
+                idx *= indices.binomial[self.group_len[next]][free_squares];
+                free_squares -= self.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        self.group_idx[n] = idx;
+    }
+
+    /// Reads the block sizes and the Huffman code, returns the offset after them
+    fn set_sizes(&mut self, bytes: &[u8], mut at: usize) -> io::Result<usize> {
+        let truncated = || invalid_data("tablebase file is truncated".to_string());
+
+        self.flags = *bytes.get(at).ok_or_else(truncated)?;
+        at += 1;
+        if self.flags & SINGLE_VALUE != 0 {
+            // the single value is stored as the minimal symbol length
+            self.min_sym_len = *bytes.get(at).ok_or_else(truncated)?;
+            return Ok(at + 1);
+        }
+        if bytes.len() < at + 10 {
+            return Err(truncated());
+        }
+
+        let groups = self.group_len.iter().position(|&len| len == 0).unwrap_or(0);
+        let tb_size = self.group_idx[groups];
+
+        self.size_of_block = 1 << bytes[at];
+        self.span = 1 << bytes[at + 1];
+        self.sparse_index_size = usize::try_from(tb_size.div_ceil(self.span))
+            .map_err(|_| invalid_data("tablebase is too large".to_string()))?;
+        let padding = usize::from(bytes[at + 2]);
+        self.num_blocks = read_u32(bytes, at + 3) as usize;
+        // padded so the sparse index can't point out of range
+        self.block_length_size = self.num_blocks + padding;
+        let max_sym_len = bytes[at + 7];
+        self.min_sym_len = bytes[at + 8];
+        at += 9;
+        if self.min_sym_len == 0 || max_sym_len < self.min_sym_len || max_sym_len > 32 {
+            return Err(invalid_data(
+                "tablebase file has invalid symbol lengths".to_string(),
+            ));
+        }
+
+        self.lowest_sym = at;
+        let lengths = usize::from(max_sym_len - self.min_sym_len) + 1;
+        if bytes.len() < at + 2 * lengths + 2 {
+            return Err(truncated());
+        }
+
+        // Canonical Huffman code: longer codes have lower values, all codes of one length are consecutive.
+        // base64[i] is the lowest code of length min_sym_len + i, left aligned to 64 bits
+        self.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(u64::from(read_u16(bytes, at + 2 * i)))
+                .wrapping_sub(u64::from(read_u16(bytes, at + 2 * (i + 1))))
+                / 2;
+        }
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            *base <<= 64 - i - usize::from(self.min_sym_len);
+        }
+        at += 2 * lengths;
+
+        let symbols = usize::from(read_u16(bytes, at));
+        at += 2;
+        self.btree = at;
+        if bytes.len() < at + 3 * symbols {
+            return Err(truncated());
+        }
+
+        self.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                self.set_symlen(bytes, sym, &mut visited)?;
+            }
+        }
+
+        Ok(at + 3 * symbols + (symbols & 1))
+    }
+
+    fn set_symlen(&mut self, bytes: &[u8], sym: usize, visited: &mut [bool]) -> io::Result<()> {
+        visited[sym] = true;
+        let (left, right) = self.pair(bytes, sym);
+        // symbols of length one store their value as the left symbol
+        if right == 0xFFF {
+            return Ok(());
+        }
+        if left >= self.symlen.len() || right >= self.symlen.len() {
+            return Err(invalid_data(
+                "tablebase file has an invalid symbol tree".to_string(),
+            ));
+        }
+        for child in [left, right] {
+            if !visited[child] {
+                self.set_symlen(bytes, child, visited)?;
+            }
+        }
+        self.symlen[sym] = self.symlen[left]
+            .wrapping_add(self.symlen[right])
+            .wrapping_add(1);
+        Ok(())
+    }
+
+    /// Value stored at `idx`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn decompress(&self, bytes: &[u8], idx: u64) -> u16 {
+        if self.flags & SINGLE_VALUE != 0 {
+            return u16::from(self.min_sym_len);
+        }
+
+        // the sparse index points to the block and offset of every `span`th value, relative to the middle of the span
+        let k = (idx / self.span) as usize;
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32(bytes, entry) as usize;
+        let mut offset = i64::from(read_u16(bytes, entry + 4));
+        offset += (idx % self.span) as i64 - (self.span / 2) as i64;
+
+        let block_length = |block: usize| i64::from(read_u16(bytes, self.block_length + 2 * block));
+        while offset < 0 {
+            block -= 1;
+            offset += block_length(block) + 1;
+        }
+        while offset > block_length(block) {
+            offset -= block_length(block) + 1;
+            block += 1;
+        }
+
+        // walk the Huffman coded symbols of the block until the one covering our offset
+        let mut ptr = self.data + block * self.size_of_block;
+        let mut buf64 = read_u64_be(bytes, ptr);
+        ptr += 8;
+        let mut buf64_size = 64;
+        let min_sym_len = usize::from(self.min_sym_len);
+        let mut sym;
+
+        loop {
+            let mut len = 0;
+            while buf64 < self.base64[len] {
+                len += 1;
+            }
+            sym = ((buf64 - self.base64[len]) >> (64 - len - min_sym_len)) as u16;
+            sym = sym.wrapping_add(read_u16(bytes, self.lowest_sym + 2 * len));
+
+            let expands_to = i64::from(self.symlen[usize::from(sym)]) + 1;
+            if offset < expands_to {
+                break;
+            }
+            offset -= expands_to;
+            len += min_sym_len;
+            buf64 <<= len;
+            buf64_size -= len;
+
+            if buf64_size <= 32 {
+                buf64_size += 32;
+                buf64 |= u64::from(read_u32_be(bytes, ptr)) << (64 - buf64_size);
+                ptr += 4;
+            }
+        }
+
+        // the symbol expands into a sequence of values, descend its pairs to the one at our offset
+        let mut sym = usize::from(sym);
+        while self.symlen[sym] != 0 {
+            let (left, right) = self.pair(bytes, sym);
+            let left_len = i64::from(self.symlen[left]) + 1;
+            if offset < left_len {
+                sym = left;
+            } else {
+                offset -= left_len;
+                sym = right;
+            }
+        }
+
+        self.pair(bytes, sym).0 as u16
+    }
+}
+
+/// Every section of a file has an entry for every file and side in this order
+fn in_file_order(
+    items: &mut [[PairsData; 2]; 4],
+    files: usize,
+    sides: usize,
+) -> impl Iterator<Item = &mut PairsData> {
+    items
+        .iter_mut()
+        .take(files)
+        .flat_map(move |file| file.iter_mut().take(sides))
+}
+
+/// A parsed table file
+pub struct Table {
+    kind: TableKind,
+    bytes: Vec<u8>,
+    /// `[leading pawn file][side to move]`, tables without pawns only have the first file,
+    /// DTZ and symmetric tables only the first side
+    items: [[PairsData; 2]; 4],
+    /// Offset of the DTZ value maps
+    map: usize,
+}
+
+impl Table {
+    pub fn from_bytes(bytes: Vec<u8>, kind: TableKind, info: &TableInfo) -> io::Result<Self> {
+        // files consist of 64 byte aligned blocks and a 16 byte checksum
+        if bytes.len() % 64 != 16 || bytes[..4] != kind.magic() {
+            return Err(invalid_data(format!(
+                "not a valid .{} file",
+                kind.extension()
+            )));
+        }
+        if info.has_pawns != (bytes[4] & HAS_PAWNS != 0)
+            || info.is_symmetric() == (bytes[4] & SPLIT != 0)
+        {
+            return Err(invalid_data(format!("file does not contain {}", info.name)));
+        }
+
+        let sides = if kind == TableKind::Wdl && !info.is_symmetric() {
+            2
+        } else {
+            1
+        };
+        let files = if info.has_pawns { 4 } else { 1 };
+        let both_pawns = info.has_pawns && info.pawn_count[1] > 0;
+        let truncated = || invalid_data("tablebase file is truncated".to_string());
+
+        let mut items: [[PairsData; 2]; 4] = Default::default();
+        let mut at = 5;
+        for (file, file_items) in items.iter_mut().take(files).enumerate() {
+            let header = bytes
+                .get(at..at + 1 + usize::from(both_pawns) + info.piece_count)
+                .ok_or_else(truncated)?;
+            let pieces = &header[1 + usize::from(both_pawns)..];
+            for (side, item) in file_items.iter_mut().take(sides).enumerate() {
+                // the low nibbles belong to the first side, the high ones to the second
+                let nibble = |byte: u8| if side == 0 { byte & 0xF } else { byte >> 4 };
+                for (k, &piece) in pieces.iter().enumerate() {
+                    item.pieces[k] = nibble(piece);
+                }
+                let order = [
+                    nibble(header[0]),
+                    if both_pawns { nibble(header[1]) } else { 0xF },
+                ];
+                item.set_groups(info, order, file);
+            }
+            at += header.len();
+        }
+        at += at & 1;
+
+        for item in in_file_order(&mut items, files, sides) {
+            at = item.set_sizes(&bytes, at)?;
+        }
+
+        let map = at;
+        if kind == TableKind::Dtz {
+            for item in in_file_order(&mut items, files, sides) {
+                if item.flags & MAPPED == 0 {
+                    continue;
+                }
+                // four maps (one per WDL result) of a length followed by that many values
+                if item.flags & WIDE != 0 {
+                    at += at & 1;
+                    for map_idx in &mut item.map_idx {
+                        if bytes.len() < at + 2 {
+                            return Err(truncated());
+                        }
+                        *map_idx = (at - map) / 2 + 1;
+                        at += 2 * usize::from(read_u16(&bytes, at)) + 2;
+                    }
+                } else {
+                    for map_idx in &mut item.map_idx {
+                        *map_idx = at - map + 1;
+                        at += usize::from(*bytes.get(at).ok_or_else(truncated)?) + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for item in in_file_order(&mut items, files, sides) {
+            item.sparse_index = at;
+            at += 6 * item.sparse_index_size;
+        }
+        for item in in_file_order(&mut items, files, sides) {
+            item.block_length = at;
+            at += 2 * item.block_length_size;
+        }
+        for item in in_file_order(&mut items, files, sides) {
+            at = at.next_multiple_of(64);
+            item.data = at;
+            at += item.num_blocks * item.size_of_block;
+        }
+
+        if at > bytes.len() {
+            return Err(truncated());
+        }
+        Ok(Self {
+            kind,
+            bytes,
+            items,
+            map,
+        })
+    }
+
+    const fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.items[file][stm % self.kind.sides()]
+    }
+
+    /// Raw value stored for `board` and the file of the leading pawn,
+    /// `None` if this is a DTZ table which only stores the other side to move
+    #[allow(clippy::too_many_lines)]
+    fn lookup(&self, info: &TableInfo, board: &Board) -> Option<(u16, usize)> {
+        let indices = &*INDICES;
+
+        // Tables are stored with white as the stronger side (KRvK, not KvKR), and for symmetric material
+        // with white to move only. Everything else gets looked up with colors swapped and the board flipped
+        let flip = (info.is_symmetric() && board.current_color() == Black)
+            || board.material_key() != info.key;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 0x38 } else { 0 };
+        let stm = usize::from(flip) ^ board.current_color() as usize;
+
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut size = 0;
+
+        // pawn tables are split by the file of the leading pawn,
+        // the one closest to the edge and among those the one on the lowest rank
+        let lead_pawns = if info.has_pawns {
+            let color = if (self.get(0, 0).pieces[0] ^ flip_color) & 8 == 0 {
+                White
+            } else {
+                Black
+            };
+            board.figure_bb(color, Pawn)
+        } else {
+            Bitboard(0)
+        };
+        let mut pawns = lead_pawns;
+        for pawn in pawns.iter_mut() {
+            squares[size] = pawn.to_square().i() ^ flip_squares;
+            size += 1;
+        }
+        let lead_pawns_cnt = size;
+        let lead = (0..lead_pawns_cnt)
+            .max_by_key(|&i| indices.map_pawns[squares[i]])
+            .unwrap_or(0);
+        squares.swap(0, lead);
+        let tb_file = if info.has_pawns {
+            (squares[0] % 8).min(7 - squares[0] % 8)
+        } else {
+            0
+        };
+
+        let item = self.get(stm, tb_file);
+        // symmetric tables without pawns are the same for both sides to move
+        let both_sides = info.is_symmetric() && !info.has_pawns;
+        if self.kind == TableKind::Dtz && usize::from(item.flags & STM) != stm && !both_sides {
+            return None;
+        }
+
+        let mut rest = board.occupied() & !lead_pawns;
+        for bit in rest.iter_mut() {
+            let square = bit.to_square();
+            squares[size] = square.i() ^ flip_squares;
+            pieces[size] = tb_piece(board.figures(square)) ^ flip_color;
+            size += 1;
+        }
+
+        // order the pieces like the table does
+        for i in lead_pawns_cnt..size - 1 {
+            if let Some(j) = (i + 1..size).find(|&j| pieces[j] == item.pieces[i]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // mirror the leading piece to the a-d files
+        if squares[0] % 8 > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx;
+        if info.has_pawns {
+            idx = indices.lead_pawn_idx[lead_pawns_cnt][squares[0]];
+            squares[1..lead_pawns_cnt].sort_by_key(|&square| indices.map_pawns[square]);
+            for (i, &square) in squares[..lead_pawns_cnt].iter().enumerate().skip(1) {
+                idx += indices.binomial[i][indices.map_pawns[square]];
+            }
+        } else {
+            // without pawns the leading piece can be mirrored below rank 5 and below the a1-h8 diagonal as well
+            if squares[0] / 8 > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 0x38;
+                }
+            }
+            for i in 0..item.group_len[0] {
+                let off = off_a1h8(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            idx = if info.has_unique_pieces {
+                let rank = |square: usize| (square / 8) as u64;
+                let adjust1 = u64::from(squares[1] > squares[0]);
+                let adjust2 =
+                    u64::from(squares[2] > squares[0]) + u64::from(squares[2] > squares[1]);
+                let (s1, s2) = (squares[1] as u64, squares[2] as u64);
+
+                if off_a1h8(squares[0]) != 0 {
+                    (indices.map_a1d1d4[squares[0]] as u64 * 63 + (s1 - adjust1)) * 62 + s2
+                        - adjust2
+                } else if off_a1h8(squares[1]) != 0 {
+                    (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]] as u64) * 62
+                        + s2
+                        - adjust2
+                } else if off_a1h8(squares[2]) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(squares[0]) * 7 * 28
+                        + (rank(squares[1]) - adjust1) * 28
+                        + indices.map_b1h1h7[squares[2]] as u64
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(squares[0]) * 7 * 6
+                        + (rank(squares[1]) - adjust1) * 6
+                        + (rank(squares[2]) - adjust2)
+                }
+            } else {
+                indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        // the remaining groups get encoded as combinations of the squares the previous groups left free
+        idx *= item.group_idx[0];
+        let mut group_start = item.group_len[0];
+        let mut remaining_pawns = info.has_pawns && info.pawn_count[1] > 0;
+        let mut next = 1;
+        while item.group_len[next] != 0 {
+            let group_end = group_start + item.group_len[next];
+            squares[group_start..group_end].sort_unstable();
+
+            let mut n = 0;
+            for i in 0..item.group_len[next] {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|&&previous| square > previous)
+                    .count();
+                n += indices.binomial[i + 1][square - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+
+            remaining_pawns = false;
+            idx += n * item.group_idx[next];
+            group_start = group_end;
+            next += 1;
+        }
+
+        Some((item.decompress(&self.bytes, idx), tb_file))
+    }
+
+    /// Win/draw/loss of the side to move, from -2 (loss) to 2 (win) with ±1 being a cursed win / blessed loss
+    pub fn probe_wdl(&self, info: &TableInfo, board: &Board) -> i32 {
+        let (value, _) = self
+            .lookup(info, board)
+            .expect("WDL tables store both sides to move");
+        i32::from(value) - 2
+    }
+
+    /// Plies (or moves, the result can be off by one) to the next capture or pawn move for a position
+    /// with the result `wdl`, `None` if the table only stores the other side to move
+    pub fn probe_dtz(&self, info: &TableInfo, board: &Board, wdl: i32) -> Option<i32> {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+
+        let (mut value, file) = self.lookup(info, board)?;
+        let item = self.get(0, file);
+        let flags = item.flags;
+        if flags & MAPPED != 0 {
+            let wdl_idx = usize::try_from(wdl + 2).expect("WDL results are at least -2");
+            let at = item.map_idx[WDL_MAP[wdl_idx]] + usize::from(value);
+            value = if flags & WIDE != 0 {
+                read_u16(&self.bytes, self.map + 2 * at)
+            } else {
+                u16::from(self.bytes[self.map + at])
+            };
+        }
+
+        let mut value = i32::from(value);
+        if (wdl == 2 && flags & WIN_PLIES == 0)
+            || (wdl == -2 && flags & LOSS_PLIES == 0)
+            || wdl.abs() == 1
+        {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_tables() {
+        let indices = &*INDICES;
+        // the well known sizes of the encodings
+        assert_eq!(
+            (0..10)
+                .flat_map(|idx| indices.map_kk[idx].iter().copied())
+                .max(),
+            Some(461)
+        );
+        assert_eq!(indices.map_b1h1h7.iter().max(), Some(&27));
+        assert_eq!(indices.binomial[2][62], 62 * 61 / 2);
+        assert_eq!(indices.binomial[5][48], 1_712_304);
+        // a single leading pawn can be on any of the 6 squares of its file, the others left of it
+        assert_eq!(indices.lead_pawns_size[1], [6, 6, 6, 6]);
+        assert_eq!(
+            indices.map_pawns[Square::from_coords("a2").unwrap().i()],
+            47
+        );
+        assert_eq!(
+            indices.map_pawns[Square::from_coords("h2").unwrap().i()],
+            46
+        );
+        assert_eq!(indices.map_pawns[Square::from_coords("e7").unwrap().i()], 0);
+    }
+
+    #[test]
+    fn test_table_info() {
+        let krvk = TableInfo::new("KRvK");
+        assert_eq!(
+            krvk.key,
+            Board::new("8/8/8/8/8/8/8/KR5k w - - 0 1").material_key()
+        );
+        assert_eq!(
+            krvk.key2,
+            Board::new("8/8/8/8/8/8/8/Kr5k w - - 0 1").material_key()
+        );
+        assert_eq!(krvk.piece_count, 3);
+        assert!(krvk.has_unique_pieces && !krvk.has_pawns);
+
+        let kbbvk = TableInfo::new("KBBvK");
+        assert!(!kbbvk.has_unique_pieces);
+
+        let kpvkpp = TableInfo::new("KPvKPP");
+        assert!(kpvkpp.has_pawns);
+        assert_eq!(kpvkpp.pawn_count, [1, 2]);
+        assert_eq!(TableInfo::new("KPPvKP").pawn_count, [1, 2]);
+        assert!(TableInfo::new("KPvKP").is_symmetric());
+    }
+
+    #[test]
+    fn test_reject_invalid_files() {
+        let info = TableInfo::new("KQvK");
+        let mut bytes = vec![0; 80];
+        assert!(Table::from_bytes(bytes.clone(), TableKind::Wdl, &info).is_err());
+
+        // right magic but the wrong material
+        bytes[..4].copy_from_slice(&WDL_MAGIC);
+        bytes[4] = 2;
+        assert!(Table::from_bytes(bytes.clone(), TableKind::Wdl, &info).is_err());
+        assert!(Table::from_bytes(bytes, TableKind::Dtz, &info).is_err());
+    }
+}
@@ -102,3 +102,11 @@ pub fn calc_search_time(args: &[&str], board: &Board) -> (usize, Duration) {
     };
     (MAX_AB_DEPTH, time_limit)
 }
+
+/// Parses `go mate <moves>`, the search then runs until a mate in at most `moves` moves is found
+pub fn parse_mate(args: &[&str]) -> Option<usize> {
+    args.iter()
+        .position(|&tok| tok == "mate")
+        .and_then(|idx| args.get(idx + 1))
+        .and_then(|val| val.parse().ok())
+}
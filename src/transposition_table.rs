@@ -1,7 +1,17 @@
-use crate::{evaluation::MATE_SCORE, prelude::*};
+use crate::{
+    evaluation::{MATE_THRESHOLD, evaluation_fingerprint},
+    prelude::*,
+    utils::persistence::{self, invalid_data},
+};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{
-    cell::UnsafeCell,
-    sync::atomic::{AtomicU8, AtomicU64, Ordering},
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    sync::{
+        PoisonError, RwLock, RwLockReadGuard,
+        atomic::{AtomicI16, AtomicU8, AtomicU64, Ordering},
+    },
 };
 
 const MAX_AGE: i32 = 1 << 5; // needs to match TTInfo layout
@@ -9,43 +19,13 @@ const AGE_MASK: i32 = MAX_AGE - 1;
 pub const DEFAULT_TT_SIZE: usize = 512; // MiB
 pub const MAX_TT_SIZE: usize = 8000; // OCI free tier memlimit - 4 GB (safety margin)
 
+/// Identifies dumps written by `TranspositionTable::save`
+const TT_FILE_MAGIC: [u8; 4] = *b"TFTT";
+
 /// Transposition Table shared between all search threads
 pub static TT: std::sync::LazyLock<TranspositionTable> =
     std::sync::LazyLock::new(|| TranspositionTable::new(DEFAULT_TT_SIZE));
 
-/// We use this struct to be able to resize the TT at runtime by basically allowing to mutate tt.entries even though we only got a read only ref to &self
-/// This is because TT is a global static var and only allows access via readonly refs
-pub struct UntrackedCell<T> {
-    value: UnsafeCell<T>,
-}
-
-/// SAFETY: This is not safe. The user must manually guarantee that structural modifications occur only when no other threads are accessing the cell
-unsafe impl<T> Sync for UntrackedCell<T> {}
-
-impl<T> UntrackedCell<T> {
-    pub const fn new(value: T) -> Self {
-        Self {
-            value: UnsafeCell::new(value),
-        }
-    }
-
-    /// # Safety
-    /// Not save. This can only be called when there is no search active
-    #[allow(clippy::mut_from_ref, clippy::inline_always)]
-    #[inline(always)]
-    pub unsafe fn get_mut_unsafe(&self) -> &mut T {
-        // SAFETY: Not save. This can only be called when there is no search active
-        unsafe { &mut *self.value.get() }
-    }
-
-    #[allow(clippy::inline_always)]
-    #[inline(always)]
-    pub fn get_ref(&self) -> &T {
-        // SAFETY: Reading atomic values is save as long as there is no resize at the same time
-        unsafe { &*self.value.get() }
-    }
-}
-
 // Inspired by Viridithas
 /// Holds the age, pv flag, and bound type packed into a single byte.
 ///
@@ -92,31 +72,58 @@ pub enum Bound {
     Exact = 3,
 }
 
+/// Number of entries sharing one index, six entries plus their static evals fill exactly one cache line
+const ENTRIES_PER_BUCKET: usize = 6;
+
+/// Marks an entry which got stored without a static eval (e.g. QS nodes in check)
+const NO_STATIC_EVAL: i16 = i16::MIN;
+
+/// Cache line sized group of entries which all live at the same index, so a collision
+/// doesn't force us to decide between exactly two positions.
+/// The static evals are kept apart from the packed entries to keep every entry a single atomic.
+/// A torn read between both can only hand out the static eval of another position,
+/// which misguides pruning in one node but never the bounds, scores and moves guarded by the key.
 #[derive(Default)]
-pub struct EncodedHashEntry {
-    data: AtomicU64,
+#[repr(C, align(64))]
+pub struct Bucket {
+    entries: [AtomicU64; ENTRIES_PER_BUCKET],
+    static_evals: [AtomicI16; ENTRIES_PER_BUCKET],
 }
 
-impl Clone for EncodedHashEntry {
-    fn clone(&self) -> Self {
-        Self {
-            data: AtomicU64::new(self.data.load(Ordering::Relaxed)),
+const _: () = assert!(
+    size_of::<Bucket>() == 64,
+    "Bucket must fill exactly one cache line"
+);
+
+impl Bucket {
+    fn load(&self, slot: usize) -> DecodedTTEntry {
+        DecodedTTEntry::unpack(
+            self.entries[slot].load(Ordering::Relaxed),
+            self.static_evals[slot].load(Ordering::Relaxed),
+        )
+    }
+
+    fn clear(&self) {
+        for entry in &self.entries {
+            entry.store(0, Ordering::Relaxed);
         }
     }
 }
 
 /// Single Entry in the transposition table
-/// my move has 16 bits but it has currently no way of storing null moves so I use an u32 as a tempory solution
-/// this needs to be fixed in the future
-/// Also the Score being 4 bytes leads to the struct being padded to 24 bytes (from the 17 it actually needs)
-/// should also be fixed
-#[repr(C)]
+/// Everything except the static eval is packed into one u64:
+/// - Bits  0-15: upper 16 bits of the hash (key)
+/// - Bits 16-31: best move
+/// - Bits 32-47: score
+/// - Bits 48-55: depth
+/// - Bits 56-63: `TTInfo`
 pub struct DecodedTTEntry {
     key: u16,
     best_move: EncodedMove,
     score: i16,
     depth: i8,
     info: TTInfo,
+    static_eval: i16,
 }
 impl DecodedTTEntry {
     pub fn depth(&self) -> i32 {
@@ -139,70 +146,98 @@ impl DecodedTTEntry {
         Some(self.best_move)
     }
 
-    // pass by value should be faster but idk
-    #[allow(clippy::needless_pass_by_value)]
-    pub fn from_internal(atom: EncodedHashEntry) -> Self {
-        // SAFETY: DecodedTTEntry is #[repr(C)] and has a total size of 8 bytes (64 bits),
-        // matching the size of the u64 loaded from the atomic storage.
-        unsafe { std::mem::transmute(atom.data.load(Ordering::Relaxed)) }
+    /// Static evaluation of the position, saves calling `Board::evaluate` again on a hit
+    pub fn static_eval(&self) -> Option<i32> {
+        if self.static_eval == NO_STATIC_EVAL {
+            return None;
+        }
+
+        Some(i32::from(self.static_eval))
+    }
+
+    /// Every stored entry has a bound, so `Bound::None` only occurs in slots that were never written
+    const fn is_empty(&self) -> bool {
+        matches!(self.info.bound(), Bound::None)
     }
 
-    // pass by value should be faster but idk
-    #[allow(clippy::needless_pass_by_value)]
-    pub fn convert_to_u64(self) -> u64 {
-        // SAFETY: DecodedTTEntry is #[repr(C)] and exactly 8 bytes wide.
-        // Transmuting it to u64 is safe as all bit patterns are valid for u64.
-        unsafe { std::mem::transmute(self) }
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    const fn unpack(data: u64, static_eval: i16) -> Self {
+        Self {
+            key: data as u16,
+            best_move: EncodedMove((data >> 16) as u16),
+            score: (data >> 32) as u16 as i16,
+            depth: (data >> 48) as u8 as i8,
+            info: TTInfo {
+                data: (data >> 56) as u8,
+            },
+            static_eval,
+        }
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    const fn pack(&self) -> u64 {
+        self.key as u64
+            | (self.best_move.0 as u64) << 16
+            | (self.score as u16 as u64) << 32
+            | (self.depth as u8 as u64) << 48
+            | (self.info.data as u64) << 56
     }
 }
 
 /// <https://www.chessprogramming.org/Transposition_Table>
+///
+/// Entries can only be read and written through a [`TTView`], which holds a read lock on the buckets.
+/// Resizing and clearing take the write lock, so they wait until every search dropped its view
+/// and can never change the table underneath a running search.
 pub struct TranspositionTable {
-    entries: UntrackedCell<Vec<EncodedHashEntry>>,
+    buckets: RwLock<Box<[Bucket]>>,
     age: AtomicU8,
 }
 
 impl TranspositionTable {
     pub fn new(size_in_mib: usize) -> Self {
-        let cap = Self::calc_capacity(size_in_mib);
-        let entries = (0..cap)
-            .map(|_| EncodedHashEntry {
-                data: AtomicU64::new(0),
-            })
-            .collect();
-
         Self {
-            entries: UntrackedCell::new(entries),
+            buckets: RwLock::new(Self::allocate(Self::calc_capacity(size_in_mib))),
             age: AtomicU8::new(0),
         }
     }
 
-    pub fn resize(&self, size_in_mib: usize) {
-        let cap = Self::calc_capacity(size_in_mib);
-
-        let new_entries = (0..cap)
-            .map(|_| EncodedHashEntry {
-                data: AtomicU64::new(0),
-            })
-            .collect();
-
-        // SAFETY: Not save. This can only be called when there is no search active
-        unsafe {
-            let current_entries_ref = self.entries.get_mut_unsafe();
-            *current_entries_ref = new_entries;
+    /// Access to the entries for the duration of a search.
+    /// Must not be held by the thread calling `resize` or `clear`, that would deadlock.
+    pub fn view(&self) -> TTView<'_> {
+        TTView {
+            // Buckets only consist of atomics, a panic while holding the lock can't leave them in an invalid state
+            buckets: self.buckets.read().unwrap_or_else(PoisonError::into_inner),
+            age: &self.age,
         }
+    }
+
+    /// Waits for running searches to drop their views before swapping in the new table
+    pub fn resize(&self, size_in_mib: usize) {
+        let new_buckets = Self::allocate(Self::calc_capacity(size_in_mib));
+        *self.buckets.write().unwrap_or_else(PoisonError::into_inner) = new_buckets;
 
         self.age.store(0, Ordering::Relaxed);
     }
 
+    /// Zeroed buckets are empty, touching them on all threads spreads the page faults of multi-GB tables
+    fn allocate(bucket_count: usize) -> Box<[Bucket]> {
+        (0..bucket_count)
+            .into_par_iter()
+            .map(|_| Bucket::default())
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+    }
+
+    /// Number of buckets fitting into `size_in_mib`, rounded down to a power of 2
     const fn calc_capacity(size_in_mib: usize) -> usize {
         let bytes = size_in_mib * 1024 * 1024;
-        let entry_size = size_of::<EncodedHashEntry>();
+        let bucket_size = size_of::<Bucket>();
 
-        // Calculate max entries to the next lower power of 2
-        let max_entries = bytes / entry_size;
-        if max_entries > 0 {
-            1_usize << max_entries.ilog2() // ilog2 gets rounded down next log2
+        // Calculate max buckets to the next lower power of 2
+        let max_buckets = bytes / bucket_size;
+        if max_buckets > 0 {
+            1_usize << max_buckets.ilog2() // ilog2 gets rounded down next log2
         } else {
             1
         }
@@ -219,6 +254,168 @@ impl TranspositionTable {
         self.age.load(Ordering::Relaxed)
     }
 
+    /// Clears the transposition table by resetting all entries and the age to 0.
+    /// Waits for running searches to drop their views, the buckets are then cleared in parallel.
+    pub fn clear(&self) {
+        self.buckets
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .par_iter()
+            .for_each(Bucket::clear);
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    /// Dumps every bucket to `path`, behind a header with the size, the age, the engine version and `file_fingerprint`
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let view = self.view();
+        let mut writer = BufWriter::new(File::create(path)?);
+
+        persistence::write_header(
+            &mut writer,
+            TT_FILE_MAGIC,
+            Self::file_fingerprint(),
+            view.buckets.len() as u64,
+        )?;
+        writer.write_all(&[self.get_age()])?;
+        for bucket in view.buckets.iter() {
+            for slot in 0..ENTRIES_PER_BUCKET {
+                let entry = bucket.load(slot);
+                writer.write_all(&entry.pack().to_le_bytes())?;
+                writer.write_all(&entry.static_eval.to_le_bytes())?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Replaces the table with a dump of `save`, the table takes the size of the dump.
+    /// Waits for running searches like `resize`, on an error the current table is kept.
+    pub fn load(&self, path: &Path) -> io::Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let bucket_count =
+            persistence::read_header(&mut reader, TT_FILE_MAGIC, Self::file_fingerprint())?;
+        let age = persistence::read_u8(&mut reader)?;
+        let max_buckets = Self::calc_capacity(MAX_TT_SIZE) as u64;
+        if !bucket_count.is_power_of_two() || bucket_count > max_buckets {
+            return Err(invalid_data(format!("invalid bucket count {bucket_count}")));
+        }
+        if i32::from(age) > AGE_MASK {
+            return Err(invalid_data(format!("invalid age {age}")));
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let buckets = Self::allocate(bucket_count as usize);
+        for bucket in &buckets {
+            for slot in 0..ENTRIES_PER_BUCKET {
+                bucket.entries[slot].store(persistence::read_u64(&mut reader)?, Ordering::Relaxed);
+                bucket.static_evals[slot]
+                    .store(persistence::read_i16(&mut reader)?, Ordering::Relaxed);
+            }
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid_data(
+                "trailing data after the last bucket".to_owned(),
+            ));
+        }
+
+        *self.buckets.write().unwrap_or_else(PoisonError::into_inner) = buckets;
+        self.age.store(age, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Dumps are only valid for the evaluation their scores and static evals come from
+    /// and the entry packing they were written with, so both go into the header.
+    /// The packing is captured by packing an entry whose fields all differ
+    fn file_fingerprint() -> u64 {
+        let sample = DecodedTTEntry {
+            key: 0x1234,
+            best_move: EncodedMove(0x5678),
+            score: -0x0bcd,
+            depth: 0x2e,
+            info: TTInfo::encode(0x15, Bound::Lower, true),
+            static_eval: 0x0f0f,
+        };
+        let layout = (
+            sample.pack(),
+            ENTRIES_PER_BUCKET,
+            size_of::<Bucket>(),
+            MAX_AGE,
+        );
+        persistence::fingerprint(&(evaluation_fingerprint(), layout))
+    }
+
+    pub fn handle_debug(&self, args: &[&str], hash: u64) -> Result<String, String> {
+        match args.first() {
+            Some(&"help") => Ok("usage: tt [fill | clear | probe | save <file> | load <file>]".to_owned()),
+            Some(&cmd @ ("save" | "load")) => {
+                let path = Path::new(args.get(1).ok_or("File Required")?);
+                let result = if cmd == "save" {
+                    self.save(path)
+                } else {
+                    self.load(path)
+                };
+                result
+                    .map(|()| format!("{:?}", self.view().info()))
+                    .map_err(|err| format!("tt {cmd} {}: {err}", path.display()))
+            }
+            Some(&"clear") => {
+                self.clear();
+                Ok(format!("{:?}", self.view().info()))
+            }
+            Some(&"fill") => Ok(format!(
+                "{:?} hashfull {}",
+                self.view().info(),
+                self.view().hashfull()
+            )),
+            Some(&"probe") => {
+                self.view().probe(hash, 0).map_or_else(|| Ok("No Entry".to_owned()), |entry| {
+                    let move_info = entry.best_move().map_or_else(|| "None".to_string(), |mv| {
+                            let decoded = mv.decode();
+                            format!("Encoded({}), Coords: {}", mv.0, decoded.to_coords())
+                        });
+
+                    Ok(format!(
+                        "Hash Key (16-bit): {:X}\nScore: {}\nStatic Eval: {:?}\nDepth: {}\nBound: {:?}\nPV Node: {}\nAge: {}\nMove: [{}]",
+                        entry.key,
+                        entry.score(),
+                        entry.static_eval(),
+                        entry.depth(),
+                        entry.bound(),
+                        entry.info.pv(),
+                        entry.info.age(),
+                        move_info
+                    ))
+                })
+            }
+            Some(cmd) => Err(format!("Unknown command: tt {cmd}")),
+            None => Err("Argument Required".to_owned()),
+        }
+    }
+}
+
+/// Read access to the entries of the [`TranspositionTable`], the table can't be resized or cleared while this exists
+pub struct TTView<'a> {
+    buckets: RwLockReadGuard<'a, Box<[Bucket]>>,
+    age: &'a AtomicU8,
+}
+
+impl TTView<'_> {
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn bucket(&self, hash: u64) -> &Bucket {
+        #[allow(clippy::cast_possible_truncation)]
+        &self.buckets[(hash as usize) & (self.buckets.len() - 1)]
+    }
+
+    fn get_age(&self) -> u8 {
+        self.age.load(Ordering::Relaxed)
+    }
+
+    /// How many searches ago `info` was written
+    fn age_differential(&self, info: TTInfo) -> i32 {
+        (MAX_AGE + i32::from(self.get_age()) - i32::from(info.age())) & AGE_MASK
+    }
+
     /// For the most part taken from Viridithas
     #[allow(clippy::too_many_arguments, clippy::cast_possible_truncation)]
     pub fn store(
@@ -226,82 +423,107 @@ impl TranspositionTable {
         hash: u64,
         mut best_move: Option<EncodedMove>,
         score: i32,
+        static_eval: Option<i32>,
         depth: i8,
         ply: i32,
         bound: Bound,
         is_pv: bool,
     ) {
         let key = (hash >> 48) as u16;
-        let entries = self.entries.get_ref();
-        let idx = (hash as usize) & (entries.len() - 1);
-        let previous = DecodedTTEntry::from_internal(entries[idx].clone());
-        let tt_age = i32::from(self.get_age());
-
-        // if we don't have a best move, and the entry is for the same position,
-        // then we should retain the best move from the previous entry.
-        if best_move.is_none() && previous.key == key {
-            best_move = previous.best_move();
-        }
+        let bucket = self.bucket(hash);
+
+        // The slot to write: the entry of the same position, otherwise an empty one,
+        // otherwise the one worth least due to its depth and bound, with quadratic penalty for its age
+        // so really old entries are the first to go even if they are of high depth.
+        let mut slot = 0;
+        let mut slot_worth = i32::MAX;
+        for i in 0..ENTRIES_PER_BUCKET {
+            let entry = bucket.load(i);
+            if entry.key == key && !entry.is_empty() {
+                slot = i;
+                break;
+            }
 
-        // give entries a bonus for type:
-        // exact = 3, lower = 2, upper = 1
-        let insert_flag_bonus = bound as i32;
-        let record_flag_bonus = previous.info.bound() as i32;
-
-        // preferentially overwrite entries that are from searches on previous positions in the game.
-        let age_differential = (MAX_AGE + tt_age - i32::from(previous.info.age())) & AGE_MASK;
-
-        // we use quadratic scaling of the age to allow entries that aren't too old to be kept,
-        // but to ensure that really old entries are overwritten even if they are of high depth.
-        let insert_priority = i32::from(depth)
-            + insert_flag_bonus
-            + (age_differential * age_differential) / 4
-            + i32::from(is_pv);
-        let record_priority = i32::from(previous.depth) + record_flag_bonus;
-
-        // replace the entry if:
-        // 1. it's an exact entry and the old entry is not exact or
-        // 2. the new entry is of higher priority than the old entry
-        // Previously we also copied the condition to check if previous.key != key but this basically caused a always replace strategy because we dont have buckets like other engines
-        if bound == Bound::Exact && previous.info.bound() != Bound::Exact
-            || insert_priority * 3 >= record_priority * 2
-        {
-            // normalise mate  scores:
-            let normalised_score = if score.abs() > (MATE_SCORE - 256) {
-                score + score.signum() * ply
+            let age_differential = self.age_differential(entry.info);
+            let worth = if entry.is_empty() {
+                i32::MIN
             } else {
-                score
+                entry.depth() + entry.bound() as i32 - (age_differential * age_differential) / 4
             };
+            if worth < slot_worth {
+                slot = i;
+                slot_worth = worth;
+            }
+        }
+        let previous = bucket.load(slot);
 
-            debug_assert!(
-                i16::try_from(normalised_score).is_ok(),
-                "Score must fit into i16"
-            );
-
-            let new_entry = DecodedTTEntry {
-                key,
-                best_move: best_move.unwrap_or(EncodedMove(0)),
-                score: normalised_score as i16,
-                depth,
-                info: TTInfo::encode(self.get_age(), bound, is_pv),
+        if previous.key == key && !previous.is_empty() {
+            // if we don't have a best move, and the entry is for the same position,
+            // then we should retain the best move from the previous entry.
+            if best_move.is_none() {
+                best_move = previous.best_move();
             }
-            .convert_to_u64();
 
-            entries[idx].data.store(new_entry, Ordering::Relaxed);
+            // give entries a bonus for type:
+            // exact = 3, lower = 2, upper = 1
+            let insert_flag_bonus = bound as i32;
+            let record_flag_bonus = previous.info.bound() as i32;
+
+            // preferentially overwrite entries that are from searches on previous positions in the game.
+            let age_differential = self.age_differential(previous.info);
+
+            // we use quadratic scaling of the age to allow entries that aren't too old to be kept,
+            // but to ensure that really old entries are overwritten even if they are of high depth.
+            let insert_priority = i32::from(depth)
+                + insert_flag_bonus
+                + (age_differential * age_differential) / 4
+                + i32::from(is_pv);
+            let record_priority = i32::from(previous.depth) + record_flag_bonus;
+
+            // keep the old entry of the same position unless:
+            // 1. it's an exact entry and the old entry is not exact or
+            // 2. the new entry is of higher priority than the old entry
+            if !(bound == Bound::Exact && previous.info.bound() != Bound::Exact
+                || insert_priority * 3 >= record_priority * 2)
+            {
+                return;
+            }
         }
+
+        // normalise mate  scores:
+        let normalised_score = if score.abs() > MATE_THRESHOLD {
+            score + score.signum() * ply
+        } else {
+            score
+        };
+
+        debug_assert!(
+            i16::try_from(normalised_score).is_ok(),
+            "Score must fit into i16"
+        );
+
+        let new_entry = DecodedTTEntry {
+            key,
+            best_move: best_move.unwrap_or(EncodedMove(0)),
+            score: normalised_score as i16,
+            depth,
+            info: TTInfo::encode(self.get_age(), bound, is_pv),
+            static_eval: static_eval.map_or(NO_STATIC_EVAL, |eval| eval as i16),
+        };
+
+        bucket.static_evals[slot].store(new_entry.static_eval, Ordering::Relaxed);
+        bucket.entries[slot].store(new_entry.pack(), Ordering::Relaxed);
     }
 
     #[allow(clippy::cast_possible_truncation)]
     pub fn probe(&self, hash: u64, ply: i32) -> Option<DecodedTTEntry> {
-        let entries = self.entries.get_ref();
-        let idx = (hash as usize) & (entries.len() - 1);
-        let mut entry = DecodedTTEntry::from_internal(entries[idx].clone());
-
-        if entry.key != (hash >> 48) as u16 {
-            return None;
-        }
+        let key = (hash >> 48) as u16;
+        let bucket = self.bucket(hash);
+        let mut entry = (0..ENTRIES_PER_BUCKET)
+            .map(|slot| bucket.load(slot))
+            .find(|entry| entry.key == key && !entry.is_empty())?;
 
-        entry.score -= if entry.score.abs() > (MATE_SCORE - 256) as i16 {
+        entry.score -= if entry.score.abs() > MATE_THRESHOLD as i16 {
             entry.score.signum() * ply as i16
         } else {
             0
@@ -310,32 +532,42 @@ impl TranspositionTable {
         Some(entry)
     }
 
-    pub fn info(&self) -> (usize, usize, f64, usize) {
-        // Sample up to 1000 entries to estimate fill percentage (standard UCI behavior)
-        let entries = self.entries.get_ref();
-        let sample_size = entries.len().min(1000);
+    /// Entries in the first (up to) 1000 buckets matching `filter` and how many got sampled
+    fn sample(&self, filter: impl Fn(&DecodedTTEntry) -> bool) -> (usize, usize) {
+        let sampled_buckets = &self.buckets[..self.buckets.len().min(1000)];
 
-        let filled_sample = entries
+        let matching = sampled_buckets
             .iter()
-            .take(sample_size)
-            .filter(|entry| entry.data.load(Ordering::Relaxed) != 0)
+            .flat_map(|bucket| (0..ENTRIES_PER_BUCKET).map(|slot| bucket.load(slot)))
+            .filter(filter)
             .count();
 
+        (matching, sampled_buckets.len() * ENTRIES_PER_BUCKET)
+    }
+
+    /// Permille of the table written during the current search, as expected by UCI `info hashfull`.
+    /// Entries of previous searches don't count as they are the first to be replaced.
+    pub fn hashfull(&self) -> usize {
+        let (current, sampled) =
+            self.sample(|entry| !entry.is_empty() && entry.info.age() == self.get_age());
+        current * 1000 / sampled
+    }
+
+    pub fn info(&self) -> (usize, usize, f64, usize) {
+        // Sample up to 1000 buckets to estimate fill percentage (standard UCI behavior)
+        let (filled_sample, sample_size) = self.sample(|entry| !entry.is_empty());
+
         #[allow(clippy::cast_precision_loss)]
-        let fill_rate = if sample_size > 0 {
-            filled_sample as f64 / sample_size as f64
-        } else {
-            0.0
-        };
+        let fill_rate = filled_sample as f64 / sample_size as f64;
 
-        let total_entries = entries.len();
+        let total_entries = self.buckets.len() * ENTRIES_PER_BUCKET;
         #[allow(
             clippy::cast_possible_truncation,
             clippy::cast_sign_loss,
             clippy::cast_precision_loss
         )]
         let filled_entries = (total_entries as f64 * fill_rate) as usize;
-        let size_in_mib = (total_entries * std::mem::size_of::<EncodedHashEntry>()) / (1024 * 1024);
+        let size_in_mib = (self.buckets.len() * size_of::<Bucket>()) / (1024 * 1024);
 
         (
             filled_entries,
@@ -344,52 +576,13 @@ impl TranspositionTable {
             size_in_mib,
         )
     }
-
-    /// Clears the transposition table by resetting all entries and the age to 0.
-    pub fn clear(&self) {
-        let entries = self.entries.get_ref();
-        for entry in entries {
-            entry.data.store(0, Ordering::Relaxed);
-        }
-        self.age.store(0, Ordering::Relaxed);
-    }
-
-    pub fn handle_debug(&self, args: &[&str], hash: u64) -> Result<String, String> {
-        match args.first() {
-            Some(&"help") => Ok("usage: tt [fill | clear | probe]".to_owned()),
-            Some(&"clear") => {
-                self.clear();
-                Ok(format!("{:?}", self.info()))
-            }
-            Some(&"fill") => Ok(format!("{:?}", self.info())),
-            Some(&"probe") => {
-                self.probe(hash, 0).map_or_else(|| Ok("No Entry".to_owned()), |entry| {
-                    let move_info = entry.best_move().map_or_else(|| "None".to_string(), |mv| {
-                            let decoded = mv.decode();
-                            format!("Encoded({}), Coords: {}", mv.0, decoded.to_coords())
-                        });
-
-                    Ok(format!(
-                        "Hash Key (16-bit): {:X}\nScore: {}\nDepth: {}\nBound: {:?}\nPV Node: {}\nAge: {}\nMove: [{}]",
-                        entry.key,
-                        entry.score(),
-                        entry.depth(),
-                        entry.bound(),
-                        entry.info.pv(),
-                        entry.info.age(),
-                        move_info
-                    ))
-                })
-            }
-            Some(cmd) => Err(format!("Unknown command: tt {cmd}")),
-            None => Err("Argument Required".to_owned()),
-        }
-    }
 }
 
 #[cfg(test)]
+#[allow(clippy::significant_drop_tightening)]
 mod test_tt_encodings {
     use super::*;
+    use crate::evaluation::MATE_SCORE;
 
     #[test]
     fn test_ttinfo_encoding() {
@@ -411,22 +604,21 @@ mod test_tt_encodings {
             key: 0xABCD,
             best_move: EncodedMove(0x1234),
             score: -150, // Test with negative score to ensure sign bit extension doesn't ruin upper bits
-            depth: 8,
+            depth: -1,
             info,
+            static_eval: -42,
         };
 
-        let packed = entry.convert_to_u64();
-        let unpacked = DecodedTTEntry::from_internal(EncodedHashEntry {
-            data: AtomicU64::new(packed),
-        });
+        let unpacked = DecodedTTEntry::unpack(entry.pack(), entry.static_eval);
 
         assert_eq!(unpacked.key, 0xABCD);
         assert_eq!(unpacked.best_move.0, 0x1234);
         assert_eq!(unpacked.score, -150);
-        assert_eq!(unpacked.depth, 8);
+        assert_eq!(unpacked.depth, -1);
         assert_eq!(unpacked.info.age(), 7);
         assert_eq!(unpacked.info.bound(), Bound::Lower);
         assert!(unpacked.info.pv());
+        assert_eq!(unpacked.static_eval(), Some(-42));
     }
 
     #[test]
@@ -435,14 +627,15 @@ mod test_tt_encodings {
         // (Assuming settings::TRANSPOSITION_TABLE is true during tests or mocked)
 
         let tt = TranspositionTable::new(1); // 1 MB
+        let view = tt.view();
         let hash = 0x1234_5678_90AB_CDEF;
         let mv = EncodedMove(42);
 
         // Store exact score
-        tt.store(hash, Some(mv), 100, 5, 0, Bound::Exact, true);
+        view.store(hash, Some(mv), 100, None, 5, 0, Bound::Exact, true);
 
         // Probe with correct hash
-        let probed = tt.probe(hash, 0).expect("Entry should be present");
+        let probed = view.probe(hash, 0).expect("Entry should be present");
         assert_eq!(probed.score(), 100);
         assert_eq!(probed.depth(), 5);
         assert_eq!(probed.bound(), Bound::Exact);
@@ -450,12 +643,13 @@ mod test_tt_encodings {
         assert_eq!(probed.best_move().unwrap(), mv);
 
         // Probe with incorrect hash (colliding index, different upper bits)
-        assert!(tt.probe(hash ^ (1 << 50), 0).is_none());
+        assert!(view.probe(hash ^ (1 << 50), 0).is_none());
     }
 
     #[test]
     fn test_mate_score_adjustment_comprehensive() {
         let tt = TranspositionTable::new(1);
+        let view = tt.view();
         let hash_win = 0xAAAB_BBCC_CDDD_EEEF;
         let hash_loss = 0x1112_2233_3444_5556;
         let mv = EncodedMove(111);
@@ -463,23 +657,33 @@ mod test_tt_encodings {
         // --- Positive Mate Score (we are winning) ---
         // A score of MATE_SCORE - 5 (Mate in 5 half-moves) found at ply 2.
         let mate_in_5 = MATE_SCORE - 5;
-        tt.store(hash_win, Some(mv), mate_in_5, 10, 2, Bound::Exact, false);
+        view.store(
+            hash_win,
+            Some(mv),
+            mate_in_5,
+            None,
+            10,
+            2,
+            Bound::Exact,
+            false,
+        );
 
         // When probed at the same depth (ply 2), the score must remain exactly the same.
-        let probed_win_same_ply = tt.probe(hash_win, 2).unwrap();
+        let probed_win_same_ply = view.probe(hash_win, 2).unwrap();
         assert_eq!(probed_win_same_ply.score(), mate_in_5);
 
         // If the same TT position is found at ply 4, the mate is closer relative to the new node (Mate in 3).
-        let probed_win_deeper = tt.probe(hash_win, 4).unwrap();
+        let probed_win_deeper = view.probe(hash_win, 4).unwrap();
         assert_eq!(probed_win_deeper.score(), MATE_SCORE - 7);
 
         // --- Negative Mate Score (we are being mated) ---
         // A score of -MATE_SCORE + 5 (we are mated in 5 half-moves) at ply 2.
         let getting_mated_in5 = -MATE_SCORE + 5;
-        tt.store(
+        view.store(
             hash_loss,
             Some(mv),
             getting_mated_in5,
+            None,
             10,
             2,
             Bound::Exact,
@@ -487,31 +691,32 @@ mod test_tt_encodings {
         );
 
         // Probed at the same depth:
-        let probed_loss_same_ply = tt.probe(hash_loss, 2).unwrap();
+        let probed_loss_same_ply = view.probe(hash_loss, 2).unwrap();
         assert_eq!(probed_loss_same_ply.score(), getting_mated_in5);
 
         // Probed at ply 4 (only 3 half-moves left until we lose):
-        let probed_loss_deeper = tt.probe(hash_loss, 4).unwrap();
+        let probed_loss_deeper = view.probe(hash_loss, 4).unwrap();
         assert_eq!(probed_loss_deeper.score(), -MATE_SCORE + 7);
     }
 
     #[test]
     fn test_priority_replacement_same_age() {
         let tt = TranspositionTable::new(1);
+        let view = tt.view();
         let hash = 0x5555_6666_7777_8888;
         let mv_deep = EncodedMove(10);
         let mv_shallow = EncodedMove(20);
 
         // 1. Initial storage with high depth (Exact bound = flag bonus 3)
         // record_priority = 10 + 3 = 13
-        tt.store(hash, Some(mv_deep), 100, 10, 1, Bound::Exact, false);
+        view.store(hash, Some(mv_deep), 100, None, 10, 1, Bound::Exact, false);
 
         // 2. Attempt to overwrite with much lower depth (Exact bound = flag bonus 3)
         // insert_priority = 2 + 3 + 0 + 0 = 5, record_priority = 13
         // 5 * 3 = 15 < 13 * 2 = 26 => NOT replaced
-        tt.store(hash, Some(mv_shallow), 200, 2, 1, Bound::Exact, false);
+        view.store(hash, Some(mv_shallow), 200, None, 2, 1, Bound::Exact, false);
 
-        let probed = tt.probe(hash, 1).unwrap();
+        let probed = view.probe(hash, 1).unwrap();
         assert_eq!(probed.depth(), 10); // The old entry should still be present
         assert_eq!(probed.score(), 100);
     }
@@ -519,12 +724,13 @@ mod test_tt_encodings {
     #[test]
     fn test_priority_replacement_with_aging() {
         let tt = TranspositionTable::new(1);
+        let view = tt.view();
         let hash = 0x5555_6666_7777_8888;
         let mv_deep = EncodedMove(10);
         let mv_shallow = EncodedMove(20);
 
         // 1. Initial storage with high depth
-        tt.store(hash, Some(mv_deep), 100, 10, 1, Bound::Exact, false);
+        view.store(hash, Some(mv_deep), 100, None, 10, 1, Bound::Exact, false);
 
         // 2. Age the TT significantly so old entries become stale
         for _ in 0..10 {
@@ -535,9 +741,9 @@ mod test_tt_encodings {
         // age_diff = 10, quadratic bonus = 100/4 = 25
         // insert_priority = 2 + 3 + 25 + 0 = 30, record_priority = 10 + 3 = 13
         // 30 * 3 = 90 >= 13 * 2 = 26 => REPLACED
-        tt.store(hash, Some(mv_shallow), 200, 2, 1, Bound::Exact, false);
+        view.store(hash, Some(mv_shallow), 200, None, 2, 1, Bound::Exact, false);
 
-        let probed = tt.probe(hash, 1).unwrap();
+        let probed = view.probe(hash, 1).unwrap();
         assert_eq!(probed.depth(), 2);
         assert_eq!(probed.score(), 200);
         assert_eq!(probed.best_move().unwrap(), mv_shallow);
@@ -546,67 +752,233 @@ mod test_tt_encodings {
     #[test]
     fn test_exact_bound_overrides_non_exact() {
         let tt = TranspositionTable::new(1);
+        let view = tt.view();
         let hash = 0x1111_2222_3333_4444;
 
         // 1. Store an Upper bound entry at depth 6
-        tt.store(hash, Some(EncodedMove(1)), 50, 6, 0, Bound::Upper, false);
+        view.store(
+            hash,
+            Some(EncodedMove(1)),
+            50,
+            None,
+            6,
+            0,
+            Bound::Upper,
+            false,
+        );
 
         // 2. Store an Exact entry at lower depth — should always replace a non-Exact entry
-        tt.store(hash, Some(EncodedMove(2)), 100, 2, 0, Bound::Exact, false);
+        view.store(
+            hash,
+            Some(EncodedMove(2)),
+            100,
+            None,
+            2,
+            0,
+            Bound::Exact,
+            false,
+        );
 
-        let probed = tt.probe(hash, 0).unwrap();
+        let probed = view.probe(hash, 0).unwrap();
         assert_eq!(probed.depth(), 2);
         assert_eq!(probed.score(), 100);
         assert_eq!(probed.bound(), Bound::Exact);
         assert_eq!(probed.best_move().unwrap(), EncodedMove(2));
     }
 
+    /// Hashes which all map to the same bucket but have different keys
+    fn colliding_hashes(count: u64) -> impl Iterator<Item = u64> {
+        (1..=count).map(|key| (key << 48) | 0x90AB_CDEF)
+    }
+
     #[test]
-    #[allow(clippy::cast_possible_truncation)]
-    fn test_different_position_not_always_replaced() {
+    fn test_colliding_positions_share_bucket() {
+        let tt = TranspositionTable::new(1);
+        let view = tt.view();
+        let hashes = colliding_hashes(ENTRIES_PER_BUCKET as u64).collect::<Vec<_>>();
+
+        for (i, &hash) in hashes.iter().enumerate() {
+            view.store(
+                hash,
+                Some(EncodedMove(1)),
+                500,
+                None,
+                20,
+                0,
+                Bound::Exact,
+                true,
+            );
+            // other positions of the same bucket don't push out the first one
+            assert!(
+                view.probe(hashes[0], 0).is_some(),
+                "lost entry after {i} stores"
+            );
+        }
+
+        for &hash in &hashes {
+            let probed = view.probe(hash, 0).unwrap();
+            assert_eq!(probed.depth(), 20);
+            assert_eq!(probed.score(), 500);
+        }
+    }
+
+    #[test]
+    fn test_full_bucket_replaces_least_valuable() {
+        let tt = TranspositionTable::new(1);
+        let view = tt.view();
+        let hashes = colliding_hashes(ENTRIES_PER_BUCKET as u64 + 3).collect::<Vec<_>>();
+        let (full, new) = hashes.split_at(ENTRIES_PER_BUCKET);
+
+        // the shallowest entry is the first to go
+        for (depth, &hash) in (10..).zip(full) {
+            view.store(hash, None, 0, None, depth, 0, Bound::Exact, false);
+        }
+        view.store(new[0], None, 0, None, 1, 0, Bound::Upper, false);
+        assert!(view.probe(full[0], 0).is_none());
+        assert!(view.probe(new[0], 0).is_some());
+        assert!(full[1..].iter().all(|&hash| view.probe(hash, 0).is_some()));
+
+        // after a few searches a deep but old entry loses against a shallow current one
+        for _ in 0..10 {
+            tt.increase_age();
+        }
+        view.store(new[1], None, 0, None, 1, 0, Bound::Upper, false);
+        assert!(view.probe(new[0], 0).is_none());
+        view.store(new[2], None, 0, None, 1, 0, Bound::Upper, false);
+        assert!(view.probe(full[1], 0).is_none());
+        assert!(view.probe(new[1], 0).is_some());
+        assert!(view.probe(new[2], 0).is_some());
+        assert!(full[2..].iter().all(|&hash| view.probe(hash, 0).is_some()));
+    }
+
+    #[test]
+    fn test_static_eval_storage() {
         let tt = TranspositionTable::new(1);
-        let entries = tt.entries.get_ref();
-        // Two hashes that map to the same index but have different keys
-        let hash1 = 0x1234_5678_90AB_CDEF_u64;
-        let idx = (hash1 as usize) & (entries.len() - 1);
-        // Construct hash2 with same lower bits (same index) but different upper 16 bits (different key)
-        let hash2 = (hash1 & !(0xFFFF << 48)) | (0xAAAA_u64 << 48);
-        assert_eq!((hash2 as usize) & (entries.len() - 1), idx);
-        assert_ne!((hash1 >> 48) as u16, (hash2 >> 48) as u16);
+        let view = tt.view();
+        let hash = 0x0123_4567_89AB_CDEF;
 
-        // Store deep entry for position 1
-        tt.store(hash1, Some(EncodedMove(1)), 500, 20, 0, Bound::Exact, true);
+        view.store(hash, None, 30, Some(-25), 3, 0, Bound::Lower, false);
+        assert_eq!(view.probe(hash, 0).unwrap().static_eval(), Some(-25));
 
-        // Store shallow entry for different position — shouldn't replace due to priority
-        tt.store(hash2, Some(EncodedMove(2)), 100, 1, 0, Bound::Upper, false);
+        view.store(hash, None, 30, None, 4, 0, Bound::Lower, false);
+        assert_eq!(view.probe(hash, 0).unwrap().static_eval(), None);
+    }
 
-        // Probing hash2 should be None since it wasn't stored
-        assert!(tt.probe(hash2, 0).is_none());
+    #[test]
+    fn test_hashfull() {
+        let tt = TranspositionTable::new(1);
+        let view = tt.view();
+        assert_eq!(view.hashfull(), 0);
+
+        // one entry in every bucket
+        let buckets = view.buckets.len() as u64;
+        for i in 0..buckets {
+            view.store((i << 48) | i, None, 0, None, 1, 0, Bound::Exact, false);
+        }
+        assert_eq!(view.hashfull(), 1000 / ENTRIES_PER_BUCKET);
 
-        // Probing hash1 should still yield the original entry
-        let probed1 = tt.probe(hash1, 0).unwrap();
-        assert_eq!(probed1.depth(), 20);
-        assert_eq!(probed1.score(), 500);
+        // entries of previous searches don't count
+        tt.increase_age();
+        assert_eq!(view.hashfull(), 0);
     }
 
     #[test]
     fn test_best_move_retained_from_previous_entry() {
         let tt = TranspositionTable::new(1);
+        let view = tt.view();
         let hash = 0xAAAA_BBBB_CCCC_DDDD;
         let mv = EncodedMove(42);
 
         // Store with a best move
-        tt.store(hash, Some(mv), 100, 5, 0, Bound::Exact, false);
+        view.store(hash, Some(mv), 100, None, 5, 0, Bound::Exact, false);
 
         // Store again for the same position without a best move but high enough priority to replace.
         // The best move from the previous entry should be retained.
         // insert_priority = 10 + 3 + 0 + 0 = 13, record_priority = 5 + 3 = 8
         // 13 * 3 = 39 >= 8 * 2 = 16 => REPLACED
-        tt.store(hash, None, 200, 10, 0, Bound::Exact, false);
+        view.store(hash, None, 200, None, 10, 0, Bound::Exact, false);
 
-        let probed = tt.probe(hash, 0).unwrap();
+        let probed = view.probe(hash, 0).unwrap();
         assert_eq!(probed.depth(), 10);
         assert_eq!(probed.score(), 200);
         assert_eq!(probed.best_move().unwrap(), mv); // best move retained!
     }
+
+    #[test]
+    fn test_resize_waits_for_search() {
+        let tt = TranspositionTable::new(1);
+        let hash = 0x0123_4567_89AB_CDEF;
+
+        std::thread::scope(|scope| {
+            let view = tt.view();
+            view.store(hash, None, 10, None, 1, 0, Bound::Exact, false);
+            let resize = scope.spawn(|| tt.resize(2));
+
+            // the search still sees the table it started with
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!resize.is_finished());
+            assert!(view.probe(hash, 0).is_some());
+
+            drop(view);
+            resize.join().unwrap();
+        });
+
+        let view = tt.view();
+        assert_eq!(view.info().3, 2);
+        assert!(view.probe(hash, 0).is_none());
+    }
+
+    #[test]
+    fn test_clear() {
+        let tt = TranspositionTable::new(1);
+        let hash = 0x0123_4567_89AB_CDEF;
+        tt.view()
+            .store(hash, None, 10, None, 1, 0, Bound::Exact, false);
+        tt.increase_age();
+
+        tt.clear();
+        assert!(tt.view().probe(hash, 0).is_none());
+        assert_eq!(tt.get_age(), 0);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("thunfisch-tt-{}.bin", std::process::id()));
+        let hash = 0x0123_4567_89AB_CDEF;
+
+        let tt = TranspositionTable::new(1);
+        tt.view().store(
+            hash,
+            Some(EncodedMove(7)),
+            10,
+            Some(5),
+            3,
+            0,
+            Bound::Lower,
+            true,
+        );
+        tt.increase_age();
+        tt.save(&path).unwrap();
+
+        let loaded = TranspositionTable::new(2);
+        loaded.load(&path).unwrap();
+        assert_eq!(loaded.get_age(), 1);
+        let view = loaded.view();
+        assert_eq!(view.info().3, 1);
+        let entry = view.probe(hash, 0).unwrap();
+        assert_eq!(entry.best_move(), Some(EncodedMove(7)));
+        assert_eq!(entry.score(), 10);
+        assert_eq!(entry.static_eval(), Some(5));
+        assert_eq!(entry.depth(), 3);
+        assert_eq!(entry.bound(), Bound::Lower);
+        drop(view);
+
+        // a cut off dump leaves the table as it was
+        let dump = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &dump[..dump.len() - 1]).unwrap();
+        assert!(loaded.load(&path).is_err());
+        assert!(loaded.view().probe(hash, 0).is_some());
+
+        std::fs::remove_file(&path).unwrap();
+    }
 }
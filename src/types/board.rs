@@ -1,9 +1,13 @@
 use crate::{
+    eval_params::LoadedParams,
+    evaluation::Psqt,
     move_generator::{masks, pinmask},
+    nnue::{Network, NnueState},
     prelude::*,
     types::unmake_info::UnmakeInfo,
     utils::{generate_fen::generate_fen, parse_fen::generate_board, zobrist},
 };
+use std::sync::Arc;
 pub const UNSET_CHECK_COUNTER: usize = 100;
 pub const START_POS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
@@ -38,6 +42,14 @@ pub struct Board {
     unmake_info_stack: Vec<UnmakeInfo>,
     repetition_stack: Vec<u64>,
     hash: u64,
+    /// zobrist hash over the pawns only, used to index pawn structure keyed tables
+    pawn_hash: u64,
+    /// material + PSQT and game phase, updated incrementally
+    psqt: Psqt,
+    /// NNUE accumulators, only present while a network is attached
+    nnue: Option<NnueState>,
+    /// Weights of the handcrafted evaluation if they were loaded at runtime
+    eval_params: Option<Arc<LoadedParams>>,
     attackmask: Bitboard,
     checkmask: Bitboard,
     check_counter: usize,
@@ -83,6 +95,10 @@ impl Board {
         unmake_info_stack: Vec::new(),
         repetition_stack: Vec::new(),
         hash: 0,
+        pawn_hash: 0,
+        psqt: Psqt::EMPTY,
+        nnue: None,
+        eval_params: None,
         attackmask: Bitboard::UNSET_ATTACK_MASK, // can never be full so unset value
         checkmask: Bitboard::UNSET_CHECK_MASK,   // can never be empty so unset value
         check_counter: UNSET_CHECK_COUNTER,      // unset value
@@ -229,6 +245,15 @@ impl Board {
             .is_none_or(Bitboard::is_empty) // REsult of reduce should never be None because map should always yield a non-empty iterator
     }
 
+    /// Number of knights, bishops, rooks and queens of `color`
+    pub const fn non_pawn_piece_count(&self, color: Color) -> u32 {
+        (self.figure_bb(color, Knight).0
+            | self.figure_bb(color, Bishop).0
+            | self.figure_bb(color, Rook).0
+            | self.figure_bb(color, Queen).0)
+            .count_ones()
+    }
+
     #[inline]
     pub const fn king(&self, color: Color) -> Bit {
         match color {
@@ -242,7 +267,13 @@ impl Board {
         self.hash ^= zobrist::white_move_key();
     }
 
-    pub const fn toggle(&mut self, color: Color, figure: Figure, square: Square) {
+    pub fn toggle(&mut self, color: Color, figure: Figure, square: Square) {
+        let placing = (self.figure_bbs[figure as usize].0 >> square.i()) & 1 == 0;
+        self.psqt.toggle(figure, square, placing);
+        if let Some(nnue) = &mut self.nnue {
+            nnue.toggle(figure, square, placing);
+        }
+
         self.color_bbs[color as usize].toggle(square);
         self.figure_bbs[Figure::Empty as usize].toggle(square);
         self.figure_bbs[figure as usize].toggle(square);
@@ -253,6 +284,10 @@ impl Board {
         };
 
         self.hash ^= zobrist::figure_key(figure, square);
+        // toggling is its own inverse, so unmaking a move restores the pawn hash aswell
+        if matches!(figure, Figure::WhitePawn | Figure::BlackPawn) {
+            self.pawn_hash ^= zobrist::figure_key(figure, square);
+        }
     }
 
     pub fn push_unmake_info_stack(&mut self, mv: EncodedMove, to_figure: Figure) {
@@ -270,6 +305,7 @@ impl Board {
             ep_target: self.ep_target(),
             halfmove_clock: self.halfmove_clock(),
             hash: self.hash,
+            psqt: self.psqt,
             attackmask,
             checkmask,
             check_counter,
@@ -437,6 +473,63 @@ impl Board {
         self.hash
     }
 
+    pub const fn pawn_hash(&self) -> u64 {
+        self.pawn_hash
+    }
+
+    pub const fn psqt(&self) -> Psqt {
+        self.psqt
+    }
+
+    pub const fn set_psqt(&mut self, psqt: Psqt) {
+        self.psqt = psqt;
+    }
+
+    pub const fn nnue(&self) -> Option<&NnueState> {
+        self.nnue.as_ref()
+    }
+
+    /// Evaluates with `network` from now on (`None` goes back to the handcrafted evaluation).
+    /// The accumulators only get recomputed if the network actually changed
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        let unchanged = match (&self.nnue, &network) {
+            (Some(nnue), Some(network)) => Arc::ptr_eq(nnue.network(), network),
+            (None, None) => true,
+            _ => false,
+        };
+        if !unchanged {
+            self.nnue = network.map(|network| NnueState::new(network, self));
+        }
+    }
+
+    pub const fn eval_params(&self) -> Option<&Arc<LoadedParams>> {
+        self.eval_params.as_ref()
+    }
+
+    /// Evaluates with `params` from now on (`None` goes back to the compiled in weights)
+    pub fn set_eval_params(&mut self, params: Option<Arc<LoadedParams>>) {
+        self.eval_params = params;
+    }
+
+    /// generates the pawn hash from scratch, only needed to verify the incremental one
+    pub fn generate_pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+        for figure in [Figure::WhitePawn, Figure::BlackPawn] {
+            let mut pawns = self.figure_bbs[figure as usize];
+            for pawn in pawns.iter_mut() {
+                hash ^= zobrist::figure_key(figure, pawn.to_square());
+            }
+        }
+        hash
+    }
+
+    /// Key over the number of pieces of each figure, positions with the same material share it
+    pub fn material_key(&self) -> u64 {
+        // kings are always there and don't need to be part of the key
+        let counts = std::array::from_fn(|idx| self.figure_bbs[idx].0.count_ones() as usize);
+        zobrist::material_key(&counts)
+    }
+
     /// generates the hash from scratch is used when parsing a fen
     /// should not be used when doing an board update
     pub fn generate_hash(&self) -> u64 {
@@ -1,8 +1,9 @@
 use crate::prelude::*;
 use std::ops::Not;
 #[repr(usize)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum Color {
+    #[default]
     White = 0usize,
     Black = 1usize,
 }
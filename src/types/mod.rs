@@ -7,6 +7,7 @@ pub mod encoded_move;
 pub mod figure;
 pub mod move_type;
 pub mod piece;
+pub mod pv_table;
 pub mod search_data;
 pub mod square;
 pub mod unmake_info;
@@ -0,0 +1,131 @@
+use crate::{
+    prelude::*,
+    settings::{MAX_AB_DEPTH, MAX_QS_DEPTH},
+};
+
+/// Highest ply a node can be visited at: alpha beta hands over to QS at most at `MAX_AB_DEPTH`
+/// and QS can then go `MAX_QS_DEPTH` plies deeper.
+pub const MAX_PV_PLY: usize = MAX_AB_DEPTH + MAX_QS_DEPTH;
+
+/// Triangular PV table which collects the principal variation while searching.
+/// <https://www.chessprogramming.org/Triangular_PV-Table>
+///
+/// Row `ply` holds the best line found from the node at `ply` onwards. Whenever a move raises alpha
+/// the line of the child (row `ply + 1`) is copied behind that move, so after the search row 0 is the PV of the root.
+/// Because every move in here was actually played during the search the PV never depends on what survived in the TT.
+pub struct PvTable {
+    moves: Box<[[EncodedMove; MAX_PV_PLY + 1]]>,
+    length: [usize; MAX_PV_PLY + 1],
+}
+
+impl PvTable {
+    pub fn new() -> Self {
+        Self {
+            moves: vec![[EncodedMove(0); MAX_PV_PLY + 1]; MAX_PV_PLY + 1].into_boxed_slice(),
+            length: [0; MAX_PV_PLY + 1],
+        }
+    }
+
+    /// Needs to be called when entering a node so a stale line of a sibling is never copied upwards
+    #[inline]
+    pub const fn clear(&mut self, ply: usize) {
+        self.length[ply] = 0;
+    }
+
+    /// Sets `mv` as the best move at `ply` followed by the line of the child node
+    #[inline]
+    pub fn update(&mut self, ply: usize, mv: EncodedMove) {
+        let child_length = if ply < MAX_PV_PLY {
+            self.length[ply + 1]
+        } else {
+            0
+        };
+
+        let (current, child) = self.moves.split_at_mut(ply + 1);
+        let current = &mut current[ply];
+        current[0] = mv;
+        if child_length > 0 {
+            current[1..=child_length].copy_from_slice(&child[0][..child_length]);
+        }
+        self.length[ply] = child_length + 1;
+    }
+
+    /// The PV of the root node
+    pub fn line(&self) -> &[EncodedMove] {
+        &self.moves[0][..self.length[0]]
+    }
+
+    /// The root PV replayed on `board` and cut off at the first move that is not legal there.
+    /// Moves are legal by construction, this only guards against a line that was assembled out of different searches.
+    pub fn legal_line(&self, board: &Board) -> Vec<EncodedMove> {
+        let mut board = board.clone();
+        let mut line = Vec::with_capacity(self.length[0]);
+        for &mv in self.line() {
+            if !board.is_legal(&mv.decode()) {
+                break;
+            }
+            board.make_move(mv);
+            line.push(mv);
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_prepends_child_line() {
+        let board = Board::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let e2e4 = DecodedMove::from_coords("e2e4", &board).encode();
+        let mut after_e4 = board.clone();
+        after_e4.make_move(e2e4);
+        let e7e5 = DecodedMove::from_coords("e7e5", &after_e4).encode();
+        let mut after_e5 = after_e4.clone();
+        after_e5.make_move(e7e5);
+        let g1f3 = DecodedMove::from_coords("g1f3", &after_e5).encode();
+
+        let mut pv = PvTable::new();
+        pv.clear(3);
+        pv.clear(2);
+        pv.update(2, g1f3);
+        pv.clear(1);
+        pv.update(1, e7e5);
+        pv.clear(0);
+        pv.update(0, e2e4);
+
+        assert_eq!(pv.line(), &[e2e4, e7e5, g1f3]);
+        assert_eq!(pv.legal_line(&board), vec![e2e4, e7e5, g1f3]);
+    }
+
+    #[test]
+    fn test_cleared_child_does_not_leak_into_parent() {
+        let board = Board::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let e2e4 = DecodedMove::from_coords("e2e4", &board).encode();
+        let d2d4 = DecodedMove::from_coords("d2d4", &board).encode();
+
+        let mut pv = PvTable::new();
+        pv.update(1, d2d4);
+        // a new child node at ply 1 did not find a move that raised alpha
+        pv.clear(1);
+        pv.update(0, e2e4);
+
+        assert_eq!(pv.line(), &[e2e4]);
+    }
+
+    #[test]
+    fn test_legal_line_stops_at_illegal_move() {
+        let board = Board::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let e2e4 = DecodedMove::from_coords("e2e4", &board).encode();
+        // white cannot move twice in a row
+        let d2d4 = DecodedMove::from_coords("d2d4", &board).encode();
+
+        let mut pv = PvTable::new();
+        pv.update(1, d2d4);
+        pv.update(0, e2e4);
+
+        assert_eq!(pv.line().len(), 2);
+        assert_eq!(pv.legal_line(&board), vec![e2e4]);
+    }
+}
@@ -3,7 +3,10 @@ use std::sync::{
     atomic::{AtomicBool, AtomicUsize},
 };
 
-use crate::{prelude::*, settings::MAX_AB_DEPTH};
+use crate::{
+    prelude::*, retrograde::Tables, settings::MAX_AB_DEPTH, syzygy::Tablebases,
+    transposition_table::TTView, types::pv_table::PvTable,
+};
 
 /// Contains shared search data in one place, as well as debugging metadata.
 /// 'Shared' in this context means that this struct is shared (read and mutated)
@@ -15,13 +18,22 @@ pub struct SharedSearchData<'sd> {
     pub stop: &'sd Arc<AtomicBool>,
     pub local_seldepth: &'sd mut usize,
     pub killers: &'sd mut [EncodedMove; MAX_AB_DEPTH + 1],
+    pub pv: &'sd mut PvTable,
+    pub tt: &'sd TTView<'sd>,
     pub ab_ply: usize,
+    /// Tables to probe inside the search, `None` without tables or once the root moves got filtered by DTZ
+    pub tablebases: Option<&'sd Tablebases>,
+    /// Only these moves get searched at the root if set
+    pub root_moves: Option<&'sd [EncodedMove]>,
+    /// Generated tables with the exact distance to mate, see `retrograde`
+    pub retrograde: Option<&'sd Tables>,
 
     // From here these are only used for additional info collection
     pub total_alpha_beta_nodes: AtomicUsize,
     pub total_qs_nodes: AtomicUsize,
     pub total_eval_nodes: AtomicUsize,
     pub total_tt_hits: AtomicUsize,
+    pub total_tb_hits: AtomicUsize,
     pub total_lmr_researches: AtomicUsize,
     pub total_pvs_researches: AtomicUsize,
     // stores whether the current search got cancelled due to timeout
@@ -35,18 +47,26 @@ impl<'sd> SharedSearchData<'sd> {
         stop: &'sd Arc<AtomicBool>,
         local_seldepth: &'sd mut usize,
         killers: &'sd mut [EncodedMove; MAX_AB_DEPTH + 1],
+        pv: &'sd mut PvTable,
+        tt: &'sd TTView<'sd>,
     ) -> Self {
         Self {
             board,
             stop,
             local_seldepth,
             killers,
+            pv,
+            tt,
             ab_ply: 0,
+            tablebases: None,
+            root_moves: None,
+            retrograde: None,
             timeout_occurred: AtomicBool::new(false),
             total_alpha_beta_nodes: AtomicUsize::new(0),
             total_qs_nodes: AtomicUsize::new(0),
             total_eval_nodes: AtomicUsize::new(0),
             total_tt_hits: AtomicUsize::new(0),
+            total_tb_hits: AtomicUsize::new(0),
             total_lmr_researches: AtomicUsize::new(0),
             total_pvs_researches: AtomicUsize::new(0),
         }
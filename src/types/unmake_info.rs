@@ -1,4 +1,4 @@
-use crate::prelude::*;
+use crate::{evaluation::Psqt, prelude::*};
 
 #[derive(Clone, Copy)]
 #[allow(clippy::struct_excessive_bools)]
@@ -12,6 +12,7 @@ pub struct UnmakeInfo {
     pub ep_target: Option<Bit>,
     pub halfmove_clock: usize,
     pub hash: u64,
+    pub psqt: Psqt,
     pub attackmask: Bitboard,
     pub checkmask: Bitboard,
     pub check_counter: usize,
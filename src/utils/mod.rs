@@ -1,5 +1,7 @@
 pub mod generate_fen;
+pub mod json;
 pub mod make_move;
 pub mod parse_fen;
+pub mod persistence;
 pub mod unmake_move;
 pub mod zobrist;
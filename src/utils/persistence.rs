@@ -0,0 +1,150 @@
+// Shared header of the files the engine persists between sessions (TT dumps, the experience store and retrograde tables)
+//
+// Layout (little endian):
+// - 4 bytes magic identifying the kind of file
+// - 1 byte length of the engine version followed by the version itself
+// - 8 bytes fingerprint of what the records depend on besides the engine version, e.g. the evaluation
+// - 8 bytes number of records following the header
+
+use std::{
+    hash::{Hash, Hasher},
+    io::{self, Read, Write},
+};
+
+const ENGINE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub fn write_header(
+    writer: &mut impl Write,
+    magic: [u8; 4],
+    fingerprint: u64,
+    records: u64,
+) -> io::Result<()> {
+    writer.write_all(&magic)?;
+    #[allow(clippy::cast_possible_truncation)]
+    writer.write_all(&[ENGINE_VERSION.len() as u8])?;
+    writer.write_all(ENGINE_VERSION.as_bytes())?;
+    writer.write_all(&fingerprint.to_le_bytes())?;
+    writer.write_all(&records.to_le_bytes())
+}
+
+/// Returns the number of records, files of other kinds, other engine versions or another `fingerprint` are rejected
+/// because scores and moves of a different evaluation can't be trusted
+pub fn read_header(reader: &mut impl Read, magic: [u8; 4], fingerprint: u64) -> io::Result<u64> {
+    let mut file_magic = [0; 4];
+    reader.read_exact(&mut file_magic)?;
+    if file_magic != magic {
+        return Err(invalid_data(format!(
+            "expected a {} file",
+            String::from_utf8_lossy(&magic)
+        )));
+    }
+
+    let mut version = vec![0; usize::from(read_u8(reader)?)];
+    reader.read_exact(&mut version)?;
+    if version != ENGINE_VERSION.as_bytes() {
+        return Err(invalid_data(format!(
+            "written by engine version {}, this is {ENGINE_VERSION}",
+            String::from_utf8_lossy(&version)
+        )));
+    }
+
+    let file_fingerprint = read_u64(reader)?;
+    if file_fingerprint != fingerprint {
+        return Err(invalid_data(format!(
+            "written with another evaluation or layout (fingerprint {file_fingerprint:016x}, this is {fingerprint:016x})"
+        )));
+    }
+
+    read_u64(reader)
+}
+
+/// Hash of `value` which is the same on every build, unlike the one of `DefaultHasher`
+pub fn fingerprint(value: &impl Hash) -> u64 {
+    let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// <https://en.wikipedia.org/wiki/Fowler%E2%80%93Noll%E2%80%93Vo_hash_function>
+struct Fnv1a(u64);
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+pub fn read_i16(reader: &mut impl Read) -> io::Result<i16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(i16::from_le_bytes(bytes))
+}
+
+pub fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+pub fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let mut file = Vec::new();
+        write_header(&mut file, *b"TEST", 7, 42).unwrap();
+
+        assert_eq!(read_header(&mut file.as_slice(), *b"TEST", 7).unwrap(), 42);
+
+        let err = read_header(&mut file.as_slice(), *b"ELSE", 7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_other_engine_version_rejected() {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"TEST");
+        file.push(5);
+        file.extend_from_slice(b"0.0.0");
+        file.extend_from_slice(&7_u64.to_le_bytes());
+        file.extend_from_slice(&1_u64.to_le_bytes());
+
+        let err = read_header(&mut file.as_slice(), *b"TEST", 7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_other_fingerprint_rejected() {
+        let mut file = Vec::new();
+        write_header(&mut file, *b"TEST", fingerprint(&[1, 2, 3]), 42).unwrap();
+
+        let err = read_header(&mut file.as_slice(), *b"TEST", fingerprint(&[1, 2, 4])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
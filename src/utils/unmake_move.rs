@@ -121,6 +121,7 @@ impl Board {
         }
 
         self.set_hash(prev.hash);
+        self.set_psqt(prev.psqt);
         self.set_attackmask(prev.attackmask);
         self.set_checkmask(prev.checkmask, prev.check_counter);
         self.set_pinmasks(prev.hv_pinmask, prev.diag_pinmask);
@@ -140,12 +141,14 @@ impl Board {
         self.set_checkmask(prev.checkmask, prev.check_counter);
         self.set_pinmasks(prev.hv_pinmask, prev.diag_pinmask);
         self.set_hash(prev.hash);
+        self.set_psqt(prev.psqt);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::evaluation::Psqt;
 
     #[test]
     fn test_idempotency_ummake_move() {
@@ -194,4 +197,45 @@ mod tests {
             "EP target must be restored after unmaking null move"
         );
     }
+
+    #[test]
+    fn test_pawn_hash_make_unmake() {
+        // covers promotions (with captures), ep and pawn captures
+        let fens = [
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+        ];
+        for fen in fens {
+            let mut board = Board::new(fen);
+            let before = board.pawn_hash();
+            assert_eq!(before, board.generate_pawn_hash());
+            for entry in board.generate_all_moves().list {
+                board.make_move(entry.mv);
+                assert_eq!(board.pawn_hash(), board.generate_pawn_hash());
+                board.unmake_move();
+                assert_eq!(board.pawn_hash(), before);
+            }
+        }
+    }
+
+    #[test]
+    fn test_psqt_make_unmake() {
+        // covers castling, promotions (with captures), ep and captures
+        let fens = [
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ];
+        for fen in fens {
+            let mut board = Board::new(fen);
+            let before = board.psqt();
+            assert_eq!(before, Psqt::compute(&board));
+            for entry in board.generate_all_moves().list {
+                board.make_move(entry.mv);
+                assert_eq!(board.psqt(), Psqt::compute(&board));
+                board.unmake_move();
+                assert_eq!(board.psqt(), before);
+            }
+        }
+    }
 }
@@ -9,6 +9,13 @@ pub const fn figure_key(figure: Figure, square: Square) -> u64 {
     POLYGLOT_KEYS[figure_idx * 64 + square.i()]
 }
 
+/// Key over the number of pieces of each figure (indexed like `Figure`, without the kings)
+pub fn material_key(counts: &[usize; Figure::WhiteKing as usize]) -> u64 {
+    counts.iter().enumerate().fold(0, |key, (idx, &count)| {
+        key ^ figure_key(Figure::from_idx(idx), Square(count))
+    })
+}
+
 pub const fn castling_rights_key(idx: usize) -> u64 {
     POLYGLOT_KEYS[768 + idx]
 }
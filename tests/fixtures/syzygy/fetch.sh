@@ -0,0 +1,14 @@
+#!/bin/sh
+# downloads the 3 piece Syzygy tables the ignored syzygy tests run against into this directory
+set -e
+cd "$(dirname "$0")"
+MIRROR=${SYZYGY_MIRROR:-https://tablebase.lichess.ovh/tables/standard/3-4-5}
+
+for table in KQvK KRvK KPvK KBvK KNvK; do
+    for extension in rtbw rtbz; do
+        if [ ! -f "$table.$extension" ]; then
+            echo "fetching $table.$extension"
+            curl --fail --silent --show-error --location -o "$table.$extension" "$MIRROR/$table.$extension"
+        fi
+    done
+done
@@ -1,6 +1,7 @@
 //! Primary Module for tuning
 //! Defines an ADAM routine that is used to find an optimum of evaluation parameters
-//! through ADAM (a gradient descent variant)
+//! through ADAM (a gradient descent variant).
+//! Gradients are calculated analytically from the sparse features of every position, see `features`.
 //!
 //! Credits to [Fatalii](https://github.com/FitzOReilly/fatalii.git) for implementation inspiration
 
@@ -9,14 +10,8 @@ use std::{fs, io, path::Path};
 
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
-use thunfisch::types::board::Board;
 
-use crate::{
-    eval::evaluation::evaluate,
-    output_paths,
-    training_data::TrainingSample,
-    tunable_params::{TunableParams, WeightVector},
-};
+use crate::{features::SparseSample, output_paths, tunable_params::WeightVector};
 
 use rand::{rng, seq::SliceRandom};
 
@@ -32,8 +27,14 @@ pub struct AdamParams {
     pub t: i32,
     pub m: WeightVector,
     pub v: WeightVector,
-    // offset for numerical gradient calculation
-    pub delta: f64,
+    /// Share of the game result in the training targets, the rest is the score of the position, see `TrainingSample::target`.
+    /// Checkpoints from before there were scores only used results
+    #[serde(default = "default_lambda")]
+    pub lambda: f64,
+}
+
+fn default_lambda() -> f64 {
+    1.0
 }
 
 /// Serializable checkpoint for resuming ADAM training.
@@ -110,16 +111,15 @@ impl Default for AdamParams {
             t: 0,
             m: WeightVector::zeros(),
             v: WeightVector::zeros(),
-            delta: 1.0,
+            lambda: default_lambda(),
         }
     }
 }
 
-/// This throws all idiomatic efficiency-focussed rust out of the fucking window because it has a complexity of
-/// O(w * e * p), where w is the number of weights, e is the number of epochs and p is the number of test positions
-/// All because we're doing numeric calculations of gradients this was a horrible design choice
+/// Runs `epochs` epochs of ADAM over the features of the training positions, every epoch writes a checkpoint.
+/// One epoch is a sparse dot product and gradient per position, so even the full zurichess data takes seconds
 pub fn adam(
-    training_data: &mut [TrainingSample],
+    training_data: &mut [SparseSample],
     weights: &mut WeightVector,
     adam_params: &mut AdamParams,
     k: f64,
@@ -132,7 +132,6 @@ pub fn adam(
     let mut t = adam_params.t;
     let beta_1 = adam_params.beta_1;
     let beta_2 = adam_params.beta_2;
-    let delta = adam_params.delta;
     let epoch_start_index = adam_params.epoch;
 
     // core adam loop
@@ -150,8 +149,7 @@ pub fn adam(
         for batch in mini_batches.clone().take(training_batch_count) {
             t += 1;
 
-            let mut gradient = WeightVector::zeros();
-            calculate_weight_gradients(&mut gradient, weights, batch, delta, k);
+            let gradient = calculate_weight_gradients(weights, batch, k, adam_params.lambda);
 
             // update momentum and RMSProp
             m = (beta_1 * m) + (1.0 - beta_1) * gradient;
@@ -167,18 +165,18 @@ pub fn adam(
             });
         }
 
-        let params: TunableParams = weights.into();
-
         // validation
         let mut scored_samples: Vec<(f64, f64)> = vec![];
         for remaining_batch in mini_batches.skip(training_batch_count) {
             scored_samples.extend(
                 remaining_batch
                     .par_iter()
-                    .map(|TrainingSample { fen, result }| {
-                        (result, evaluate(&Board::new(fen), &params))
+                    .map(|sample| {
+                        (
+                            sample.target(k, adam_params.lambda),
+                            sigmoid(sample.features.evaluate(weights), k),
+                        )
                     })
-                    .map(|(&label, pred)| (label.into(), sigmoid(pred as f64, k)))
                     .collect::<Vec<(f64, f64)>>(),
             );
         }
@@ -213,77 +211,54 @@ pub fn adam(
     Ok(())
 }
 
-/// Numerically calculate the gradient of the MSE loss function over the current weights
-/// This has so fucking horrible performance it hurts my soul
-/// but I don't have the time nor the fucks to give to refactor the eval to be linear in respect to its weights
-/// (especially since for king safety this is NOT the case and that is the thing I feel is in the most desperate need of tuning)
-///
-/// So yes throw more hardware at the problem 200 cores werden schon irgendwie regeln hahahaha
-///
-/// this function is the this is fine meme turned code
+/// Calculate the gradient of the MSE loss function over the current weights.
+/// The derivative of the loss by the evaluation is the same for every weight, so it only has to be
+/// multiplied with the derivative of the evaluation by each weight, which `Features::add_gradient` knows.
 fn calculate_weight_gradients(
-    gradient: &mut WeightVector,
-    weights: &mut WeightVector,
-    batch: &[TrainingSample],
-    delta: f64,
+    weights: &WeightVector,
+    batch: &[SparseSample],
     k: f64,
-) {
-    for w in 0..weights.len() {
-        weights[w] -= 1.0;
-        let params_lo: TunableParams = weights.into();
-        weights[w] += 2.0;
-        let params_hi: TunableParams = weights.into();
-        // reset weights to where they were before
-        weights[w] -= 1.0;
-
-        // score every sample in the batch with the params
-        let scored_samples: [Vec<(f64, f64)>; 2] = [
-            batch
-                .par_iter()
-                .map(|TrainingSample { fen, result }| {
-                    (result, evaluate(&Board::new(fen), &params_lo))
-                })
-                .map(|(&label, pred)| (label.into(), sigmoid(pred as f64, k)))
-                .collect(),
-            batch
-                .par_iter()
-                .map(|TrainingSample { fen, result }| {
-                    (result, evaluate(&Board::new(fen), &params_hi))
-                })
-                .map(|(&label, pred)| (label.into(), sigmoid(pred as f64, k)))
-                .collect(),
-        ];
-        let mse = [mse(&scored_samples[0]), mse(&scored_samples[1])];
-        gradient[w] = (mse[1] - mse[0]) / (2.0 * delta);
-    }
+    lambda: f64,
+) -> WeightVector {
+    // derivative of the sigmoid by the evaluation, without the sigmoid terms themselves
+    let sigmoid_slope = k * std::f64::consts::LN_10 / 400.0;
+    let gradient = batch
+        .par_iter()
+        .fold(WeightVector::zeros, |mut gradient, sample| {
+            let prediction = sigmoid(sample.features.evaluate(weights), k);
+            let loss_slope = 2.0
+                * (prediction - sample.target(k, lambda))
+                * prediction
+                * (1.0 - prediction)
+                * sigmoid_slope;
+            sample
+                .features
+                .add_gradient(weights, loss_slope, &mut gradient);
+            gradient
+        })
+        .reduce(WeightVector::zeros, |a, b| a + b);
+    gradient / batch.len() as f64
 }
 
-pub fn optimize_k(training_data: &[TrainingSample], params: &TunableParams) -> f64 {
-    // augment the training data with evaluations from the default weights
+/// Fits `k` to the game results only: it's the scale between centipawns and winning chances, which the scores
+/// of the training data share with the evaluation. Fitting it to targets blended with scores would favour a `k`
+/// close to zero, where every score and evaluation looks the same.
+pub fn optimize_k(samples: &[SparseSample], weights: &WeightVector) -> f64 {
+    // augment the training data with evaluations from the current weights
     let eval_start = Instant::now();
-    let outcomes_with_evals: Vec<(f64, f64)> = training_data
+    let outcomes_with_evals: Vec<(f64, f64)> = samples
         .par_iter()
-        .map(|TrainingSample { fen, result }| {
-            ((*result).into(), evaluate(&Board::new(fen), params) as f64)
-        })
-        .collect();
-    println!("evaluation of one batch took: {:?}", eval_start.elapsed());
-    let fens_with_evals: Vec<(&String, (f64, i32))> = training_data
-        .par_iter()
-        .map(|TrainingSample { fen, result }| {
-            (fen, ((*result).into(), evaluate(&Board::new(fen), params)))
-        })
+        .map(|sample| (sample.result.into(), sample.features.evaluate(weights)))
         .collect();
+    println!(
+        "evaluation of all positions took: {:?}",
+        eval_start.elapsed()
+    );
+    let evals = outcomes_with_evals.iter().map(|(_, eval)| *eval);
     println!(
         "Sanity check: Eval Range [{:?}, {:?}]",
-        fens_with_evals
-            .iter()
-            .min_by_key(|(_, (_, eval))| *eval)
-            .unwrap(),
-        fens_with_evals
-            .iter()
-            .max_by_key(|(_, (_, eval))| *eval)
-            .unwrap()
+        evals.clone().fold(f64::INFINITY, f64::min),
+        evals.fold(f64::NEG_INFINITY, f64::max)
     );
 
     // grid search for the best K value
@@ -349,4 +324,24 @@ mod tests {
 
         assert_eq!(checkpoint, reloaded);
     }
+
+    #[test]
+    fn checkpoint_without_lambda_uses_results_only() {
+        let checkpoint = AdamCheckpoint::new(
+            "training.epd",
+            AdamParams::default(),
+            WeightVector::zeros(),
+            1.23,
+            None,
+        );
+        let mut json = serde_json::to_value(&checkpoint).expect("checkpoint should serialize");
+        json["adam_params"]
+            .as_object_mut()
+            .unwrap()
+            .remove("lambda");
+
+        let reloaded: AdamCheckpoint =
+            serde_json::from_value(json).expect("checkpoint should deserialize");
+        assert_eq!(reloaded.adam_params.lambda, 1.0);
+    }
 }
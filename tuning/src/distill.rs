@@ -0,0 +1,356 @@
+//! Distills the handcrafted evaluation into a NNUE network in the engine's file format.
+//!
+//! The positions come from seeded random playouts, are quieted with the same quiescence search as
+//! `prepare`, and labeled with the handcrafted evaluation. The network is trained in floats and
+//! quantised like the usual trainers for the `(768 -> 128)x2 -> 1` `SCReLU` architecture do, so the
+//! output is a real trained network for testing the NNUE inference, not a strong one.
+
+use std::{fs, io, path::PathBuf, time::Instant};
+
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use thunfisch::{
+    nnue::HIDDEN_SIZE, prelude::*, transposition_table::TranspositionTable, types::board::START_POS,
+};
+
+use crate::{
+    preparation::prepare_quiet_training_position,
+    training_data::{GameResult, TrainingSample},
+};
+
+const INPUT_SIZE: usize = 768;
+/// Quantisation the engine expects, see `thunfisch::nnue`
+const QA: f32 = 255.0;
+const QB: f32 = 64.0;
+/// Centipawns per unit of network output
+const EVAL_SCALE: f32 = 400.0;
+/// Keeps every quantised weight well within an i16 and the output weights within the engine's SIMD bound
+const WEIGHT_CLIP: f32 = 1.98;
+
+const BATCH_SIZE: usize = 256;
+const LEARNING_RATE: f32 = 0.001;
+const MAX_PLAYOUT_PLIES: usize = 120;
+
+/// `distill <output.nnue> [positions] [epochs] [seed]`
+pub fn handle_distill(args: &[String]) -> io::Result<()> {
+    let Some(output_path) = args.first().map(PathBuf::from) else {
+        eprintln!("Usage: tuning distill <output.nnue> [positions] [epochs] [seed]");
+        std::process::exit(1);
+    };
+    let argument = |index: usize, default: u64| {
+        args.get(index)
+            .map_or(Ok(default), |value| value.parse::<u64>())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    };
+    let positions = usize::try_from(argument(1, 300_000)?).unwrap();
+    let epochs = argument(2, 20)?;
+    let seed = argument(3, 0x9E37_79B9_7F4A_7C15)?;
+
+    let start = Instant::now();
+    let samples = generate_samples(positions, seed);
+    println!(
+        "Labeled {} positions in {:.1?}",
+        samples.len(),
+        start.elapsed()
+    );
+
+    let mut network = FloatNetwork::new(seed);
+    let mut adam = Adam::new(network.parameters.len());
+    let mut rng = XorShift(seed ^ 0xD1B5_4A32_D192_ED03);
+    let (validation, training) = samples.split_at(samples.len() / 20);
+    let mut training = training.to_vec();
+    for epoch in 1..=epochs {
+        rng.shuffle(&mut training);
+        for batch in training.chunks(BATCH_SIZE) {
+            let gradient = network.gradient(batch);
+            adam.step(&mut network.parameters, &gradient);
+            network.clip();
+        }
+        println!(
+            "epoch {epoch}: training loss {:.6}, validation loss {:.6}",
+            network.loss(&training),
+            network.loss(validation)
+        );
+    }
+
+    fs::write(&output_path, network.quantise())?;
+    println!("Wrote {} in {:.1?}", output_path.display(), start.elapsed());
+    Ok(())
+}
+
+/// Active inputs of both perspectives, side to move first, and the handcrafted evaluation for the side to move
+#[derive(Clone)]
+struct Sample {
+    features: [Vec<u16>; 2],
+    eval: f32,
+}
+
+/// Quiet positions from random playouts, labeled with the handcrafted evaluation
+fn generate_samples(count: usize, seed: u64) -> Vec<Sample> {
+    let mut rng = XorShift(seed);
+    let mut fens = Vec::with_capacity(count);
+    while fens.len() < count {
+        let mut board = Board::new(START_POS);
+        let plies = rng.below(MAX_PLAYOUT_PLIES) + 1;
+        for ply in 0..plies {
+            let moves = board.generate_all_moves();
+            if moves.list.is_empty() {
+                break;
+            }
+            board.make_move(moves.list[rng.below(moves.list.len())].mv);
+            // a few positions per game, the early ones are similar across games
+            if ply >= 8 && rng.below(8) == 0 {
+                fens.push(board.fen());
+            }
+        }
+    }
+    fens.truncate(count);
+
+    // the tuning QS never probes the TT, so the smallest one is enough
+    let tt = TranspositionTable::new(1);
+    fens.par_iter()
+        .filter_map(|fen| {
+            let position = TrainingSample {
+                fen: fen.clone(),
+                result: GameResult::Draw,
+                score: None,
+            };
+            prepare_quiet_training_position(position, &tt)
+        })
+        .map(|position| {
+            let board = Board::new(&position.fen);
+            #[allow(clippy::cast_precision_loss)]
+            let eval = board.evaluate() as f32;
+            let color = board.current_color();
+            Sample {
+                features: [features(&board, color), features(&board, !color)],
+                eval,
+            }
+        })
+        .collect()
+}
+
+/// Same input layout as the engine: `[own, opponent][piece][square]`, black sees the board flipped
+fn features(board: &Board, perspective: Color) -> Vec<u16> {
+    let mut features = Vec::with_capacity(32);
+    for idx in 0..12 {
+        // white figures have even indices
+        let (piece, color) = (idx >> 1, idx & 1);
+        for bit in board.figure_bb_by_index(idx).iter_mut() {
+            let square = bit.to_square().i();
+            let feature = match perspective {
+                White => color * 384 + piece * 64 + square,
+                Black => (color ^ 1) * 384 + piece * 64 + (square ^ 0x38),
+            };
+            features.push(u16::try_from(feature).unwrap());
+        }
+    }
+    features
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// All parameters in one vector, in the order of the engine's file layout:
+/// feature weights `[INPUT_SIZE][HIDDEN_SIZE]`, feature bias, output weights `[2 * HIDDEN_SIZE]`, output bias
+struct FloatNetwork {
+    parameters: Vec<f32>,
+}
+
+const FEATURE_BIAS: usize = INPUT_SIZE * HIDDEN_SIZE;
+const OUTPUT_WEIGHTS: usize = FEATURE_BIAS + HIDDEN_SIZE;
+const OUTPUT_BIAS: usize = OUTPUT_WEIGHTS + 2 * HIDDEN_SIZE;
+const PARAMETERS: usize = OUTPUT_BIAS + 1;
+
+impl FloatNetwork {
+    fn new(seed: u64) -> Self {
+        let mut rng = XorShift(seed);
+        let mut parameters = vec![0.0; PARAMETERS];
+        for parameter in &mut parameters[..FEATURE_BIAS] {
+            *parameter = rng.uniform(0.1);
+        }
+        for parameter in &mut parameters[OUTPUT_WEIGHTS..OUTPUT_BIAS] {
+            *parameter = rng.uniform(0.1);
+        }
+        Self { parameters }
+    }
+
+    fn accumulators(&self, sample: &Sample) -> [[f32; HIDDEN_SIZE]; 2] {
+        let mut accumulators = [[0.0; HIDDEN_SIZE]; 2];
+        for (accumulator, features) in accumulators.iter_mut().zip(&sample.features) {
+            accumulator.copy_from_slice(&self.parameters[FEATURE_BIAS..OUTPUT_WEIGHTS]);
+            for &feature in features {
+                let row = usize::from(feature) * HIDDEN_SIZE;
+                for (value, weight) in accumulator
+                    .iter_mut()
+                    .zip(&self.parameters[row..row + HIDDEN_SIZE])
+                {
+                    *value += weight;
+                }
+            }
+        }
+        accumulators
+    }
+
+    /// Network output in units of `EVAL_SCALE` centipawns
+    fn output(&self, accumulators: &[[f32; HIDDEN_SIZE]; 2]) -> f32 {
+        let weights = &self.parameters[OUTPUT_WEIGHTS..OUTPUT_BIAS];
+        accumulators
+            .iter()
+            .flatten()
+            .zip(weights)
+            .map(|(&value, weight)| value.clamp(0.0, 1.0).powi(2) * weight)
+            .sum::<f32>()
+            + self.parameters[OUTPUT_BIAS]
+    }
+
+    /// Squared error between the win probabilities of the network and the handcrafted evaluation
+    fn loss(&self, samples: &[Sample]) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        let count = samples.len() as f32;
+        samples
+            .iter()
+            .map(|sample| {
+                let output = self.output(&self.accumulators(sample));
+                (sigmoid(output) - sigmoid(sample.eval / EVAL_SCALE)).powi(2)
+            })
+            .sum::<f32>()
+            / count
+    }
+
+    /// Gradient of the mean loss over `batch`
+    fn gradient(&self, batch: &[Sample]) -> Vec<f32> {
+        let mut gradient = vec![0.0; PARAMETERS];
+        #[allow(clippy::cast_precision_loss)]
+        let scale = 1.0 / batch.len() as f32;
+        for sample in batch {
+            let accumulators = self.accumulators(sample);
+            let prediction = sigmoid(self.output(&accumulators));
+            let target = sigmoid(sample.eval / EVAL_SCALE);
+            let output_gradient =
+                2.0 * (prediction - target) * prediction * (1.0 - prediction) * scale;
+
+            gradient[OUTPUT_BIAS] += output_gradient;
+            for (perspective, (accumulator, features)) in
+                accumulators.iter().zip(&sample.features).enumerate()
+            {
+                let mut hidden_gradient = [0.0; HIDDEN_SIZE];
+                for (i, &value) in accumulator.iter().enumerate() {
+                    let output_weight = OUTPUT_WEIGHTS + perspective * HIDDEN_SIZE + i;
+                    let activation = value.clamp(0.0, 1.0);
+                    gradient[output_weight] += output_gradient * activation * activation;
+                    if value > 0.0 && value < 1.0 {
+                        hidden_gradient[i] =
+                            output_gradient * self.parameters[output_weight] * 2.0 * value;
+                    }
+                }
+                for (bias, hidden) in gradient[FEATURE_BIAS..OUTPUT_WEIGHTS]
+                    .iter_mut()
+                    .zip(&hidden_gradient)
+                {
+                    *bias += hidden;
+                }
+                for &feature in features {
+                    let row = usize::from(feature) * HIDDEN_SIZE;
+                    for (weight, hidden) in gradient[row..row + HIDDEN_SIZE]
+                        .iter_mut()
+                        .zip(&hidden_gradient)
+                    {
+                        *weight += hidden;
+                    }
+                }
+            }
+        }
+        gradient
+    }
+
+    fn clip(&mut self) {
+        for parameter in &mut self.parameters {
+            *parameter = parameter.clamp(-WEIGHT_CLIP, WEIGHT_CLIP);
+        }
+    }
+
+    /// The engine's little endian i16 file, the clipping keeps every value within an i16
+    #[allow(clippy::cast_possible_truncation)]
+    fn quantise(&self) -> Vec<u8> {
+        self.parameters
+            .iter()
+            .enumerate()
+            .map(|(i, parameter)| {
+                let scale = match i {
+                    ..OUTPUT_WEIGHTS => QA,
+                    OUTPUT_WEIGHTS..OUTPUT_BIAS => QB,
+                    _ => QA * QB,
+                };
+                (parameter * scale).round() as i16
+            })
+            .flat_map(i16::to_le_bytes)
+            .collect()
+    }
+}
+
+struct Adam {
+    momentum: Vec<f32>,
+    velocity: Vec<f32>,
+    steps: i32,
+}
+
+impl Adam {
+    const BETA1: f32 = 0.9;
+    const BETA2: f32 = 0.999;
+    const EPSILON: f32 = 1e-8;
+
+    fn new(size: usize) -> Self {
+        Self {
+            momentum: vec![0.0; size],
+            velocity: vec![0.0; size],
+            steps: 0,
+        }
+    }
+
+    fn step(&mut self, parameters: &mut [f32], gradient: &[f32]) {
+        self.steps += 1;
+        let momentum_correction = 1.0 - Self::BETA1.powi(self.steps);
+        let velocity_correction = 1.0 - Self::BETA2.powi(self.steps);
+        for (((parameter, gradient), momentum), velocity) in parameters
+            .iter_mut()
+            .zip(gradient)
+            .zip(&mut self.momentum)
+            .zip(&mut self.velocity)
+        {
+            *momentum = Self::BETA1 * *momentum + (1.0 - Self::BETA1) * gradient;
+            *velocity = Self::BETA2 * *velocity + (1.0 - Self::BETA2) * gradient * gradient;
+            let momentum = *momentum / momentum_correction;
+            let velocity = *velocity / velocity_correction;
+            *parameter -= LEARNING_RATE * momentum / (velocity.sqrt() + Self::EPSILON);
+        }
+    }
+}
+
+/// Seeded, so the same arguments always give the same network
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        usize::try_from(self.next() % bound as u64).unwrap()
+    }
+
+    /// Uniform within `-range..range`
+    #[allow(clippy::cast_precision_loss)]
+    fn uniform(&mut self, range: f32) -> f32 {
+        ((self.next() >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0) * range
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
@@ -74,9 +74,7 @@ pub fn quiescence_search(
     let mut best_line_fen = sd.board.fen();
     let mut moves_searched = 0;
 
-    let mut movepicker = if sd.board.is_in_check()
-        && (ply - sd.ab_ply) < settings::QS_CHECK_EVASION_LIMIT
-    {
+    let mut movepicker = if is_check {
         MovePicker::new(None, None, false)
     } else {
         MovePicker::new(None, None, true)
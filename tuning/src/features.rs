@@ -0,0 +1,353 @@
+//! Sparse feature vectors of training positions for analytical gradients.
+//!
+//! Apart from king safety and the scaling of drawish endgames the evaluation is a sum of weights times how often
+//! they apply. The engine's `EvalTrace` records these coefficients, so every position is traced once and from
+//! then on evaluating it (and differentiating the evaluation by every weight) is a sparse dot product.
+//!
+//! King safety looks up a table by the danger of each king, which itself is a sum of weights times coefficients.
+//! The lookup is linearly interpolated between the buckets, so the danger weights get a gradient as well.
+
+use std::sync::LazyLock;
+
+use thunfisch::{
+    endgame::{Endgame, SCALE_NORMAL},
+    eval_trace::Coefficient,
+    prelude::*,
+};
+
+use crate::{
+    training_data::{GameResult, target},
+    tunable_params::{TunableParams, WeightVector, weight_index},
+};
+
+/// Highest danger bucket of the king safety tables
+const MAX_DANGER: f64 = 99.0;
+
+/// Position of the middlegame and the endgame king safety table in the `WeightVector`
+static KING_SAFETY_TABLES: LazyLock<[usize; 2]> = LazyLock::new(|| {
+    ["mg_king_safety_table", "eg_king_safety_table"]
+        .map(|name| weight_index(name, 0).expect("table is tuned"))
+});
+
+/// `(weight, coefficient)` pairs sorted by the position of the weight in the `WeightVector`
+type SparseVector = Box<[(u16, f32)]>;
+
+/// A training position reduced to the features of its evaluation
+pub struct SparseSample {
+    pub result: GameResult,
+    /// Score of a search from white's perspective, if the training data has one
+    pub score: Option<i32>,
+    pub features: Features,
+}
+
+impl SparseSample {
+    pub fn new(
+        board: &Board,
+        result: GameResult,
+        score: Option<i32>,
+        params: &TunableParams,
+    ) -> Self {
+        Self {
+            result,
+            score,
+            features: Features::new(board, params),
+        }
+    }
+
+    /// Training target between `0.0` (black wins) and `1.0` (white wins), see `training_data::target`
+    pub fn target(&self, k: f64, lambda: f64) -> f64 {
+        target(self.result, self.score, k, lambda)
+    }
+}
+
+/// The evaluation of one position as a function of the `WeightVector`, white - black
+pub struct Features {
+    /// Every term except king safety, already tapered by the game phase
+    linear: SparseVector,
+    /// Danger of each king, `[side][mg, eg]`
+    danger: [[SparseVector; 2]; 2],
+    /// Share of the middlegame score in the tapered score, the endgame gets the rest
+    mg_share: f64,
+    /// Specialised endgame knowledge, which doesn't depend on any weight
+    endgame: Option<Endgame>,
+    /// Weight of each side's scale factor in `endgame` if it comes from the material alone
+    material_scale: [Option<u16>; 2],
+}
+
+impl Features {
+    /// Traces `board`, the coefficients barely depend on `params`: only which material counts as drawish does
+    pub fn new(board: &Board, params: &TunableParams) -> Self {
+        let trace = board.trace_with(params);
+        let mg_share = f64::from(256 - trace.gamephase) / 256.0;
+
+        let linear = sparse(&trace.coefficients, |coefficient| {
+            coefficient.mg * mg_share + coefficient.eg * (1.0 - mg_share)
+        });
+        let danger = trace.danger_coefficients.map(|coefficients| {
+            [
+                sparse(&coefficients, |c| c.mg),
+                sparse(&coefficients, |c| c.eg),
+            ]
+        });
+        let material_scale = trace.material_scale.map(|index| {
+            index.map(|index| {
+                weight_index("material_scale_factors", index).expect("scale factors are tuned")
+                    as u16
+            })
+        });
+
+        Self {
+            linear,
+            danger,
+            mg_share,
+            endgame: trace.endgame,
+            material_scale,
+        }
+    }
+
+    /// The evaluation with `weights`, white - black.
+    /// Unlike the engine this doesn't round, so it is only close to `Board::evaluate_with`.
+    pub fn evaluate(&self, weights: &WeightVector) -> f64 {
+        if let Some(Endgame::Score(score)) = self.endgame {
+            return f64::from(score);
+        }
+        let score = self.unscaled(weights);
+        score * self.scale(weights, score).0
+    }
+
+    /// Adds `factor` times the derivative of `evaluate` by every weight to `gradient`
+    pub fn add_gradient(&self, weights: &WeightVector, factor: f64, gradient: &mut WeightVector) {
+        if let Some(Endgame::Score(_)) = self.endgame {
+            return;
+        }
+        let score = self.unscaled(weights);
+        let (scale, material_scale) = self.scale(weights, score);
+        if let Some(index) = material_scale {
+            gradient[index] += factor * score / f64::from(SCALE_NORMAL);
+        }
+
+        let factor = factor * scale;
+        for &(index, coefficient) in &self.linear {
+            gradient[usize::from(index)] += factor * f64::from(coefficient);
+        }
+
+        for (side, sign) in [(0, 1.0), (1, -1.0)] {
+            for (phase, share) in [(0, self.mg_share), (1, 1.0 - self.mg_share)] {
+                let table = KING_SAFETY_TABLES[phase];
+                let danger = dot(&self.danger[side][phase], weights);
+                let (bucket, fraction) = bucket(danger);
+                // king safety is subtracted from the score of its side
+                let factor = -factor * sign * share;
+                gradient[table + bucket] += factor * (1.0 - fraction);
+                gradient[table + bucket + 1] += factor * fraction;
+
+                // the clamped danger doesn't change with the weights
+                if danger > 0.0 && danger < MAX_DANGER {
+                    let slope = weights[table + bucket + 1] - weights[table + bucket];
+                    for &(index, coefficient) in &self.danger[side][phase] {
+                        gradient[usize::from(index)] += factor * slope * f64::from(coefficient);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The tapered score before the endgame knowledge is applied
+    fn unscaled(&self, weights: &WeightVector) -> f64 {
+        let mut score = dot(&self.linear, weights);
+        for (side, sign) in [(0, 1.0), (1, -1.0)] {
+            for (phase, share) in [(0, self.mg_share), (1, 1.0 - self.mg_share)] {
+                let table = KING_SAFETY_TABLES[phase];
+                let (bucket, fraction) = bucket(dot(&self.danger[side][phase], weights));
+                let safety = weights[table + bucket] * (1.0 - fraction)
+                    + weights[table + bucket + 1] * fraction;
+                score -= sign * share * safety;
+            }
+        }
+        score
+    }
+
+    /// Factor `score` gets scaled with and the weight of that factor if it is tuned
+    fn scale(&self, weights: &WeightVector, score: f64) -> (f64, Option<usize>) {
+        let Some(Endgame::Scale(factors)) = self.endgame else {
+            return (1.0, None);
+        };
+        let side = if score > 0.0 { 0 } else { 1 };
+        let normal = f64::from(SCALE_NORMAL);
+        match self.material_scale[side].map(usize::from) {
+            Some(index) => {
+                let factor = weights[index];
+                // outside of the valid range the engine clamps the factor, so it has no gradient there
+                if (0.0..=normal).contains(&factor) {
+                    (factor / normal, Some(index))
+                } else {
+                    (factor.clamp(0.0, normal) / normal, None)
+                }
+            }
+            None => (f64::from(factors[side]) / normal, None),
+        }
+    }
+}
+
+/// Sums up the coefficients of every weight, `value` selects what of a coefficient counts.
+/// Panics on a coefficient of a weight the tuner doesn't know, dropping it would silently leave the term untuned
+fn sparse(coefficients: &[Coefficient], value: impl Fn(&Coefficient) -> f64) -> SparseVector {
+    let mut entries: Vec<(u16, f64)> = coefficients
+        .iter()
+        .map(|coefficient| {
+            let index = weight_index(coefficient.name, coefficient.index).unwrap_or_else(|| {
+                panic!(
+                    "{}[{}] is traced but not a tuned weight",
+                    coefficient.name, coefficient.index
+                )
+            });
+            (index as u16, value(coefficient))
+        })
+        .collect();
+    entries.sort_unstable_by_key(|&(index, _)| index);
+
+    let mut merged: Vec<(u16, f64)> = Vec::with_capacity(entries.len());
+    for (index, value) in entries {
+        match merged.last_mut() {
+            Some((last, sum)) if *last == index => *sum += value,
+            _ => merged.push((index, value)),
+        }
+    }
+    merged
+        .into_iter()
+        .filter(|&(_, value)| value != 0.0)
+        .map(|(index, value)| (index, value as f32))
+        .collect()
+}
+
+fn dot(entries: &[(u16, f32)], weights: &WeightVector) -> f64 {
+    entries
+        .iter()
+        .map(|&(index, coefficient)| f64::from(coefficient) * weights[usize::from(index)])
+        .sum()
+}
+
+/// Lower bucket of the king safety tables `danger` lies in and how far it is towards the next one
+fn bucket(danger: f64) -> (usize, f64) {
+    let danger = danger.clamp(0.0, MAX_DANGER);
+    let bucket = (danger.floor() as usize).min(MAX_DANGER as usize - 1);
+    (bucket, danger - bucket as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::evaluation::evaluate;
+    use thunfisch::eval_params::LoadedParams;
+
+    const POSITIONS: [&str; 10] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "r1b1k2r/1pqp1ppp/p2bpnn1/6Q1/2BNP3/2N1B3/PPP2PPP/2KR3R b kq - 8 12",
+        "8/k7/3p4/p2P1p2/P2P1P2/8/8/K7 b - - 0 1",
+        "2B5/kpp2rqr/pbbp4/8/8/B7/RP3PPP/QRNn2K1 w - - 0 1",
+        "8/kppp1r1r/pbb3q1/4B3/8/1P1n4/R1R2PPP/3N1QKB b - - 0 1",
+        // rook against bishop, scaled by the material
+        "4k3/8/8/3b4/8/8/8/3RK3 w - - 0 1",
+        // the king attack is worth something
+        "r1bq1rk1/pppp1ppp/2n5/2b1p1NQ/2B1P3/8/PPPP1PPP/RNB1K2R w KQ - 0 1",
+    ];
+
+    #[test]
+    fn every_traced_weight_is_tuned() {
+        let params = TunableParams::default();
+        let mut names = Vec::new();
+        for fen in POSITIONS {
+            let trace = Board::new(fen).trace_with(&params);
+            for coefficient in trace
+                .coefficients
+                .iter()
+                .chain(trace.danger_coefficients.iter().flatten())
+            {
+                assert!(
+                    weight_index(coefficient.name, coefficient.index).is_some(),
+                    "{fen}: {}[{}]",
+                    coefficient.name,
+                    coefficient.index
+                );
+                if !names.contains(&coefficient.name) {
+                    names.push(coefficient.name);
+                }
+            }
+        }
+        // the king safety tables are looked up by the danger and the scale factors by the endgame code,
+        // every other term has to show up in the trace
+        let looked_up = [
+            "mg_king_safety_table",
+            "eg_king_safety_table",
+            "material_scale_factors",
+        ];
+        for term in LoadedParams::TERMS
+            .iter()
+            .filter(|term| !looked_up.contains(term))
+        {
+            assert!(names.contains(term), "{term} is never traced");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown_term[0] is traced but not a tuned weight")]
+    fn unknown_traced_weight_panics() {
+        let coefficient = Coefficient {
+            name: "unknown_term",
+            index: 0,
+            mg: 1.0,
+            eg: 1.0,
+        };
+        sparse(&[coefficient], |coefficient| coefficient.mg);
+    }
+
+    #[test]
+    fn sparse_evaluation_matches_engine() {
+        let params = TunableParams::default();
+        let weights = params.to_weight_vector();
+        for fen in POSITIONS {
+            let board = Board::new(fen);
+            let features = Features::new(&board, &params);
+            let expected = f64::from(evaluate(&board, &params));
+            // the engine rounds the taper, the imbalance and the scaling
+            assert!(
+                (features.evaluate(&weights) - expected).abs() <= 3.0,
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let params = TunableParams::default();
+        let mut weights = params.to_weight_vector();
+        // move the weights off the integers, so the danger lies between two buckets
+        for (i, weight) in weights.iter_mut().enumerate() {
+            *weight += 0.25 + (i % 7) as f64 * 0.05;
+        }
+
+        for fen in POSITIONS {
+            let features = Features::new(&Board::new(fen), &params);
+            let mut gradient = WeightVector::zeros();
+            features.add_gradient(&weights, 1.0, &mut gradient);
+
+            for i in 0..weights.len() {
+                let delta = 1e-4;
+                let mut changed = weights;
+                changed[i] += delta;
+                let high = features.evaluate(&changed);
+                changed[i] -= 2.0 * delta;
+                let low = features.evaluate(&changed);
+                let numeric = (high - low) / (2.0 * delta);
+                assert!(
+                    (gradient[i] - numeric).abs() < 1e-3,
+                    "{fen}: weight {i} {} vs {numeric}",
+                    gradient[i]
+                );
+            }
+        }
+    }
+}
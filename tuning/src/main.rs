@@ -6,16 +6,24 @@
 
 use std::{io, path::{Path, PathBuf}, time::Instant};
 
+use rayon::iter::{IntoParallelRefIterator, ParallelExtend, ParallelIterator};
+use thunfisch::types::board::Board;
+
 use crate::{
     adam::{adam, mse, optimize_k, sigmoid, AdamCheckpoint, AdamParams},
-    eval::evaluation::evaluate,
+    features::SparseSample,
+    packed::{handle_convert, PackedReader, PACKED_EXTENSION},
     preparation::handle_prepare,
+    distill::handle_distill,
     training_data::TrainingSample,
     tunable_params::{TunableParams, WeightVector},
 };
 
 mod adam;
+mod distill;
 mod eval;
+mod features;
+mod packed;
 mod preparation;
 mod output_paths;
 mod training_data;
@@ -27,8 +35,11 @@ mod tunable_params;
 ///
 /// Supported subcommands:
 /// - `prepare <input.epd> [output.epd]`
-/// - `train <input.epd> [epochs] [restore-checkpoint.json]`
+/// - `convert <input.epd> [output.packed]`
+/// - `train <input.epd|input.packed> [epochs] [restore-checkpoint.json] [--lambda <0..1>]`
 /// - `export <checkpoint.json>`
+/// - `trace <input.epd>`
+/// - `distill <output.nnue> [positions] [epochs] [seed]`
 ///
 /// If the output path is omitted, the prepared file is written as
 /// `<input>.prepared.epd`.
@@ -37,10 +48,13 @@ fn main() -> std::io::Result<()> {
     args.remove(0);
     match &args.first().map(|s| s.as_str()) {
         Some("prepare") => handle_prepare(&args[1..])?,
+        Some("convert") => handle_convert(&args[1..])?,
         Some("train") => train(&args[1..])?,
         Some("export") => export(&args[1..])?,
+        Some("trace") => trace(&args[1..])?,
+        Some("distill") => handle_distill(&args[1..])?,
         None | Some(_) => {
-            eprintln!("Usage: tuning [prepare|train|export]");
+            eprintln!("Usage: tuning [prepare|convert|train|export|trace|distill]");
             std::process::exit(1);
         }
     }
@@ -49,11 +63,27 @@ fn main() -> std::io::Result<()> {
 
 /// Run the current training workflow.
 ///
-/// For now this only loads the input positions, constructs the default
-/// evaluator parameters, and optimizes `k` for the sigmoid loss.
+/// Loads the input positions (EPD or packed), extracts the sparse features of
+/// every position, optimizes `k` for the sigmoid loss (unless a checkpoint is
+/// restored) and runs ADAM on them.
+/// `--lambda` sets the share of the game results in the training targets, the rest comes from
+/// the scores of the positions (if the data has any). Defaults to the checkpoint's or `1.0`.
 fn train(args: &[String]) -> std::io::Result<()> {
-    let [input, rest @ ..] = args else {
-        eprintln!("Usage: tuning train <input.epd> [epochs] [checkpoint.json]");
+    let mut args = args.to_vec();
+    let lambda = match args.iter().position(|arg| arg == "--lambda") {
+        Some(position) => {
+            let lambda = args
+                .get(position + 1)
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|lambda| (0.0..=1.0).contains(lambda))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--lambda expects a value between 0 and 1"))?;
+            args.drain(position..=position + 1);
+            Some(lambda)
+        }
+        None => None,
+    };
+    let [input, rest @ ..] = args.as_slice() else {
+        eprintln!("Usage: tuning train <input.epd|input.{PACKED_EXTENSION}> [epochs] [checkpoint.json] [--lambda <0..1>]");
         std::process::exit(1);
     };
 
@@ -66,14 +96,7 @@ fn train(args: &[String]) -> std::io::Result<()> {
 
     let restore_checkpoint_path = rest.get(1).map(std::path::PathBuf::from);
 
-    let mut training_data = TrainingSample::read_epd_file(input)?;
-    println!("Size of Training data: {}", training_data.len());
-    // assert_eq!(training_data.iter().find(|sample| sample.fen.contains("6r1/5q1k/p7/8/P3K3/1n6/7q/3q4")).unwrap().result, GameResult::BlackWin);
-
-    println!("\nRunning ADAM training");
-    println!("---------------------");
-
-    let (mut adam_params, mut weights, k) = if let Some(restore_checkpoint_path) = restore_checkpoint_path {
+    let (mut adam_params, mut weights, restored_k) = if let Some(restore_checkpoint_path) = restore_checkpoint_path {
         let checkpoint = AdamCheckpoint::read_from_file(&restore_checkpoint_path)?;
         if checkpoint.training_data_path != input.as_str() {
             eprintln!(
@@ -81,31 +104,48 @@ fn train(args: &[String]) -> std::io::Result<()> {
                 checkpoint.training_data_path, input
             );
         }
-        checkpoint.into_parts()
+        let (adam_params, weights, k) = checkpoint.into_parts();
+        (adam_params, weights, Some(k))
     } else {
-        let params = TunableParams::default();
-        let weights: WeightVector = (&params).into();
-        println!("\nOptimizing k");
-        println!("-----------------");
-        let optimization_start = Instant::now();
-        let k = optimize_k(&training_data, &params);
-        println!("Best k: {k}\nTook: {:?}", optimization_start.elapsed());
-
-        let initial_mse = training_data
-            .iter()
-            .map(|TrainingSample { fen, result }| {
-                let eval = evaluate(&thunfisch::types::board::Board::new(fen), &params) as f64;
-                ((*result).into(), sigmoid(eval, k))
-            })
-            .collect::<Vec<(f64, f64)>>();
-        println!("Initial MSE at optimized k: {}", mse(&initial_mse));
-
-        (AdamParams::default(), weights, k)
+        (AdamParams::default(), WeightVector::from(&TunableParams::default()), None)
+    };
+    if let Some(lambda) = lambda {
+        adam_params.lambda = lambda;
+    }
+
+    // the features only depend on the weights for what counts as drawish material, so they are extracted once
+    let extraction_start = Instant::now();
+    let mut samples = read_samples(input, &weights.into())?;
+    println!("Size of Training data: {}", samples.len());
+    let scored = samples.iter().filter(|sample| sample.score.is_some()).count();
+    println!("Positions with a score: {scored}");
+    println!("Feature extraction took: {:?}", extraction_start.elapsed());
+
+    println!("\nRunning ADAM training");
+    println!("---------------------");
+
+    let k = match restored_k {
+        Some(k) => k,
+        None => {
+            println!("\nOptimizing k");
+            println!("-----------------");
+            let optimization_start = Instant::now();
+            let k = optimize_k(&samples, &weights);
+            println!("Best k: {k}\nTook: {:?}", optimization_start.elapsed());
+
+            let initial_mse = samples
+                .par_iter()
+                .map(|sample| (sample.target(k, adam_params.lambda), sigmoid(sample.features.evaluate(&weights), k)))
+                .collect::<Vec<(f64, f64)>>();
+            println!("Initial MSE at optimized k: {}", mse(&initial_mse));
+            k
+        }
     };
+    println!("Lambda: {}", adam_params.lambda);
 
     let optimization_start = Instant::now();
     adam(
-        &mut training_data,
+        &mut samples,
         &mut weights,
         &mut adam_params,
         k,
@@ -117,6 +157,36 @@ fn train(args: &[String]) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Positions of a packed file whose features are extracted at once
+const PACKED_CHUNK_SIZE: usize = 1 << 16;
+
+/// Reads the training positions of `input` and extracts their features.
+/// Packed files are streamed in chunks, so only the features of all positions have to fit into memory.
+fn read_samples(input: &str, params: &TunableParams) -> io::Result<Vec<SparseSample>> {
+    if Path::new(input).extension().is_some_and(|extension| extension == PACKED_EXTENSION) {
+        let mut reader = PackedReader::open(input)?;
+        let mut samples = Vec::with_capacity(reader.remaining());
+        loop {
+            let chunk = reader.read_chunk(PACKED_CHUNK_SIZE)?;
+            if chunk.is_empty() {
+                break;
+            }
+            samples.par_extend(
+                chunk
+                    .par_iter()
+                    .map(|position| SparseSample::new(&position.board(), position.result(), position.score(), params)),
+            );
+        }
+        Ok(samples)
+    } else {
+        let training_data = TrainingSample::read_epd_file(input)?;
+        Ok(training_data
+            .par_iter()
+            .map(|sample| SparseSample::new(&Board::new(&sample.fen), sample.result, sample.score, params))
+            .collect())
+    }
+}
+
 /// Export a checkpoint as a Rust constants file.
 fn export(args: &[String]) -> std::io::Result<()> {
     let [checkpoint_path] = args else {
@@ -127,19 +197,67 @@ fn export(args: &[String]) -> std::io::Result<()> {
     let checkpoint_path = PathBuf::from(checkpoint_path);
     let checkpoint = AdamCheckpoint::read_from_file(&checkpoint_path)?;
     let params: TunableParams = checkpoint.weights.into();
-    let export_path = export_path_for_checkpoint(&checkpoint_path)?;
+    let export_path = export_path_for_checkpoint(&checkpoint_path, "rs")?;
+    let json_path = export_path_for_checkpoint(&checkpoint_path, "json")?;
 
     params.write_constants_file(&export_path)?;
     println!("Exported tunable constants to {}", export_path.display());
+    // loadable by the engine without rebuilding via `setoption name EvalParams value <path>`
+    params.write_to_file(&json_path)?;
+    println!("Exported tunable params to {}", json_path.display());
+    Ok(())
+}
+
+/// Print how much every term of the engine's evaluation contributes over a data set.
+///
+/// Terms which barely move the score are candidates for removal, terms whose
+/// mean is far off zero may be missing a counterpart on the other side.
+fn trace(args: &[String]) -> std::io::Result<()> {
+    let [input] = args else {
+        eprintln!("Usage: tuning trace <input.epd>");
+        std::process::exit(1);
+    };
+
+    let training_data = TrainingSample::read_epd_file(input)?;
+    // per term: (sum of the tapered white - black score, sum of its absolute value)
+    let sums = training_data
+        .par_iter()
+        .map(|sample| {
+            let trace = Board::new(&sample.fen).trace();
+            trace
+                .terms()
+                .into_iter()
+                .map(|(name, [white, black])| {
+                    let score = (white - black).taper(trace.gamephase) as i64;
+                    (name, score, score.abs())
+                })
+                .collect::<Vec<_>>()
+        })
+        .reduce(Vec::new, |a, b| {
+            if a.is_empty() {
+                return b;
+            }
+            a.into_iter()
+                .zip(b)
+                .map(|((name, sum_a, abs_a), (_, sum_b, abs_b))| (name, sum_a + sum_b, abs_a + abs_b))
+                .collect()
+        });
+
+    let positions = training_data.len().max(1) as f64;
+    println!("Traced {} positions, scores in centipawns white - black", training_data.len());
+    println!("{:<16} {:>10} {:>10}", "Term", "Mean", "Mean |x|");
+    for (name, sum, abs) in sums {
+        println!("{name:<16} {:>10.2} {:>10.2}", sum as f64 / positions, abs as f64 / positions);
+    }
     Ok(())
 }
 
-fn export_path_for_checkpoint(checkpoint_path: impl AsRef<Path>) -> std::io::Result<PathBuf> {
+fn export_path_for_checkpoint(checkpoint_path: impl AsRef<Path>, extension: &str) -> std::io::Result<PathBuf> {
     let checkpoint_path = checkpoint_path.as_ref();
     let file_name = checkpoint_path
         .file_name()
         .and_then(|value| value.to_str())
         .unwrap_or("checkpoint.json");
 
-    output_paths::in_tuning_data(format!("export-{file_name}.rs"))
+    output_paths::in_tuning_data(format!("export-{file_name}.{extension}"))
 }
@@ -0,0 +1,347 @@
+//! Compact binary format for training positions.
+//!
+//! Every position is a fixed size record of 32 bytes (little endian):
+//! - the occupancy bitboard (8 bytes)
+//! - the figure on every occupied square from a1 to h8, one nibble each (16 bytes, low nibble first)
+//! - flags: side to move (bit 0, set for black) and the castling rights (bits 1 to 4: white king side,
+//!   white queen side, black king side, black queen side)
+//! - the en passant target square, `0xff` if there is none
+//! - the result: `0` black wins, `1` draw, `2` white wins
+//! - the score from white's perspective as `i16`, `i16::MIN` if there is none
+//! - 2 reserved bytes
+//!
+//! Files start with an 8 byte magic, the records follow without any separators. Fixed sizes mean files can be read
+//! in chunks of any size, and the boards are built directly from the records instead of parsing FENs.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use thunfisch::prelude::*;
+
+use crate::training_data::{GameResult, TrainingSample};
+
+/// Extension of packed training data files, `train` reads files with it as packed
+pub const PACKED_EXTENSION: &str = "packed";
+const MAGIC: &[u8; 8] = b"THUNPK01";
+const RECORD_SIZE: usize = 32;
+const NO_EP_TARGET: u8 = 0xff;
+const NO_SCORE: i16 = i16::MIN;
+
+/// One training position packed into `RECORD_SIZE` bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackedPosition {
+    occupied: u64,
+    pieces: [u8; 16],
+    flags: u8,
+    ep_target: u8,
+    result: u8,
+    score: i16,
+}
+
+impl PackedPosition {
+    /// Packs `board`, fails for boards with more than 32 pieces
+    pub fn new(board: &Board, result: GameResult, score: Option<i32>) -> io::Result<Self> {
+        let occupied = board.occupied().0;
+        if occupied.count_ones() > 32 {
+            return Err(invalid_data("more than 32 pieces can't be packed"));
+        }
+
+        let mut pieces = [0u8; 16];
+        for (i, square) in squares(occupied).enumerate() {
+            let figure = board.figures(Square(square)) as u8;
+            pieces[i / 2] |= figure << (4 * (i % 2));
+        }
+
+        let flags = u8::from(board.current_color() == Black)
+            | u8::from(board.white_king_castle()) << 1
+            | u8::from(board.white_queen_castle()) << 2
+            | u8::from(board.black_king_castle()) << 3
+            | u8::from(board.black_queen_castle()) << 4;
+
+        Ok(Self {
+            occupied,
+            pieces,
+            flags,
+            ep_target: board
+                .ep_target()
+                .map_or(NO_EP_TARGET, |bit| bit.to_square().i() as u8),
+            result: match result {
+                GameResult::BlackWin => 0,
+                GameResult::Draw => 1,
+                GameResult::WhiteWin => 2,
+            },
+            score: score.map_or(NO_SCORE, |score| {
+                score.clamp(i32::from(NO_SCORE) + 1, i32::from(i16::MAX)) as i16
+            }),
+        })
+    }
+
+    pub fn from_sample(sample: &TrainingSample) -> io::Result<Self> {
+        Self::new(&Board::new(&sample.fen), sample.result, sample.score)
+    }
+
+    /// The board the way `Board::new` builds it from the FEN of the position
+    pub fn board(&self) -> Board {
+        let mut board = Board::EMPTY;
+        for (i, square) in squares(self.occupied).enumerate() {
+            let figure = Figure::from_idx(usize::from((self.pieces[i / 2] >> (4 * (i % 2))) & 0xf));
+            let (_, color) = figure.piece_and_color();
+            board.toggle(color, figure, Square(square));
+        }
+
+        board.set_current_color(if self.flags & 1 == 0 { White } else { Black });
+        board.set_castling_rights(
+            self.flags & (1 << 2) != 0,
+            self.flags & (1 << 1) != 0,
+            self.flags & (1 << 4) != 0,
+            self.flags & (1 << 3) != 0,
+        );
+        board.set_ep_target(
+            (self.ep_target != NO_EP_TARGET).then(|| Square(usize::from(self.ep_target)).to_bit()),
+        );
+        board.set_halfmove_clock(0);
+        board.set_total_halfmove_counter(1);
+        board.set_hash(board.generate_hash());
+        board
+    }
+
+    pub fn result(&self) -> GameResult {
+        match self.result {
+            0 => GameResult::BlackWin,
+            1 => GameResult::Draw,
+            _ => GameResult::WhiteWin,
+        }
+    }
+
+    /// Score from white's perspective
+    pub fn score(&self) -> Option<i32> {
+        (self.score != NO_SCORE).then_some(i32::from(self.score))
+    }
+
+    fn to_bytes(self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0u8; RECORD_SIZE];
+        bytes[..8].copy_from_slice(&self.occupied.to_le_bytes());
+        bytes[8..24].copy_from_slice(&self.pieces);
+        bytes[24] = self.flags;
+        bytes[25] = self.ep_target;
+        bytes[26] = self.result;
+        bytes[27..29].copy_from_slice(&self.score.to_le_bytes());
+        bytes
+    }
+
+    /// Rejects records which don't describe a board, e.g. because the file is corrupted
+    fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> io::Result<Self> {
+        let position = Self {
+            occupied: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            pieces: bytes[8..24].try_into().unwrap(),
+            flags: bytes[24],
+            ep_target: bytes[25],
+            result: bytes[26],
+            score: i16::from_le_bytes([bytes[27], bytes[28]]),
+        };
+
+        let count = position.occupied.count_ones() as usize;
+        let valid_pieces = count <= 32
+            && (0..count)
+                .all(|i| (position.pieces[i / 2] >> (4 * (i % 2))) & 0xf < Figure::Empty as u8);
+        let valid_ep_target = position.ep_target < 64 || position.ep_target == NO_EP_TARGET;
+        if !valid_pieces || !valid_ep_target || position.flags >> 5 != 0 || position.result > 2 {
+            return Err(invalid_data("invalid packed position"));
+        }
+        Ok(position)
+    }
+}
+
+/// Writes `positions` to a new packed file at `path` and returns how many were written
+pub fn write_packed_file(
+    path: impl AsRef<Path>,
+    positions: impl IntoIterator<Item = PackedPosition>,
+) -> io::Result<usize> {
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    let mut count = 0;
+    for position in positions {
+        writer.write_all(&position.to_bytes())?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Reads a packed file chunk by chunk, so the whole file never has to be in memory
+pub struct PackedReader {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl PackedReader {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0u8; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a packed training data file"));
+        }
+        let records = len - MAGIC.len();
+        if !records.is_multiple_of(RECORD_SIZE) {
+            return Err(invalid_data("packed training data file is truncated"));
+        }
+
+        Ok(Self {
+            reader,
+            remaining: records / RECORD_SIZE,
+        })
+    }
+
+    /// Number of positions which haven't been read yet
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Reads the next (up to) `max` positions, an empty chunk means the file is done
+    pub fn read_chunk(&mut self, max: usize) -> io::Result<Vec<PackedPosition>> {
+        let count = max.min(self.remaining);
+        let mut bytes = vec![0u8; count * RECORD_SIZE];
+        self.reader.read_exact(&mut bytes)?;
+        self.remaining -= count;
+
+        bytes
+            .chunks_exact(RECORD_SIZE)
+            .map(|record| PackedPosition::from_bytes(record.try_into().unwrap()))
+            .collect()
+    }
+}
+
+/// Packs the positions of an EPD file, see `TrainingSample::read_epd_file` for the format
+pub fn handle_convert(args: &[String]) -> io::Result<()> {
+    let (input_path, output_path) = match args {
+        [input, output] => (input.clone(), output.clone()),
+        [input] => (input.clone(), format!("{input}.{PACKED_EXTENSION}")),
+        _ => {
+            eprintln!("Usage: tuning convert <input.epd> [output.{PACKED_EXTENSION}]");
+            std::process::exit(1);
+        }
+    };
+
+    let samples = TrainingSample::read_epd_file(&input_path)?;
+    let positions = samples
+        .iter()
+        .map(PackedPosition::from_sample)
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let output_path = Path::new(&output_path);
+    let output_path = crate::output_paths::in_tuning_data(
+        output_path.file_name().unwrap_or(output_path.as_os_str()),
+    )?;
+    let count = write_packed_file(&output_path, positions)?;
+    println!("Packed {count} positions into {}", output_path.display());
+    Ok(())
+}
+
+/// Indices of the set bits of `bitboard` from a1 to h8
+fn squares(mut bitboard: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [&str; 5] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w Kq f6 0 1",
+        "r1b1k2r/1pqp1ppp/p2bpnn1/6Q1/2BNP3/2N1B3/PPP2PPP/2KR3R b kq - 0 1",
+        "8/k7/3p4/p2P1p2/P2P1P2/8/8/K7 b - - 0 1",
+    ];
+
+    #[test]
+    fn packed_board_matches_fen() {
+        for fen in POSITIONS {
+            let board = Board::new(fen);
+            let packed = PackedPosition::new(&board, GameResult::Draw, Some(-42)).unwrap();
+            let unpacked = packed.board();
+
+            assert_eq!(unpacked.fen(), board.fen());
+            assert_eq!(unpacked.hash(), board.hash());
+            assert_eq!(unpacked.pawn_hash(), board.pawn_hash());
+            assert_eq!(unpacked.evaluate(), board.evaluate(), "{fen}");
+            assert_eq!(packed.result(), GameResult::Draw);
+            assert_eq!(packed.score(), Some(-42));
+            assert_eq!(
+                PackedPosition::from_bytes(&packed.to_bytes()).unwrap(),
+                packed
+            );
+        }
+    }
+
+    #[test]
+    fn read_file_in_chunks() {
+        let positions: Vec<PackedPosition> = POSITIONS
+            .iter()
+            .enumerate()
+            .map(|(i, fen)| {
+                let score = (i % 2 == 0).then_some(i as i32 * 100);
+                PackedPosition::new(&Board::new(fen), GameResult::WhiteWin, score).unwrap()
+            })
+            .collect();
+        let path = std::env::temp_dir().join(format!(
+            "packed_chunks_{}.{PACKED_EXTENSION}",
+            std::process::id()
+        ));
+        assert_eq!(
+            write_packed_file(&path, positions.clone()).unwrap(),
+            POSITIONS.len()
+        );
+
+        let mut reader = PackedReader::open(&path).unwrap();
+        assert_eq!(reader.remaining(), POSITIONS.len());
+        let mut read = Vec::new();
+        loop {
+            let chunk = reader.read_chunk(2).unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            assert!(chunk.len() <= 2);
+            read.extend(chunk);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read, positions);
+        assert_eq!(read[1].score(), None);
+        assert_eq!(read[2].score(), Some(200));
+    }
+
+    #[test]
+    fn reject_invalid_records() {
+        let packed =
+            PackedPosition::new(&Board::new(POSITIONS[0]), GameResult::BlackWin, None).unwrap();
+        let mut bytes = packed.to_bytes();
+        bytes[8] = 0xcc;
+        assert!(PackedPosition::from_bytes(&bytes).is_err());
+
+        let mut bytes = packed.to_bytes();
+        bytes[26] = 3;
+        assert!(PackedPosition::from_bytes(&bytes).is_err());
+    }
+}
@@ -6,8 +6,10 @@ use std::{path::PathBuf, sync::Arc};
 use std::sync::atomic::AtomicBool;
 
 use thunfisch::settings;
+use thunfisch::transposition_table::TranspositionTable;
 use thunfisch::types::board::Board;
 use thunfisch::types::encoded_move::EncodedMove;
+use thunfisch::types::pv_table::PvTable;
 use thunfisch::types::search_data::SharedSearchData;
 
 use crate::eval::quiescence_search::quiescence_search;
@@ -41,10 +43,12 @@ pub fn handle_prepare(args: &[String]) -> std::io::Result<()> {
     };
 
     let positions = TrainingSample::read_epd_file(&input_path)?;
+    // the tuning QS never probes the TT, so the smallest one is enough
+    let tt = TranspositionTable::new(1);
 
     let prepared: Vec<TrainingSample> = positions
         .par_iter()
-        .map(|position| prepare_quiet_training_position(position.clone()))
+        .map(|position| prepare_quiet_training_position(position.clone(), &tt))
         .filter(|result| result.is_some())
         .map(|result| result.unwrap())
         .collect();
@@ -65,14 +69,25 @@ pub fn handle_prepare(args: &[String]) -> std::io::Result<()> {
 /// The returned `TrainingData` keeps the original game result label, but uses the
 /// best-line FEN from the quiescence search so that the training set only
 /// contains quiet positions.
-pub fn prepare_quiet_training_position(position: TrainingSample) -> Option<TrainingSample> {
+pub fn prepare_quiet_training_position(
+    position: TrainingSample,
+    tt: &TranspositionTable,
+) -> Option<TrainingSample> {
     let mut board = Board::new(&position.fen);
     let stop = Arc::new(AtomicBool::new(false));
     let mut local_seldepth = 0usize;
     let mut killers = [EncodedMove(0); settings::MAX_AB_DEPTH + 1];
+    let mut pv = PvTable::new();
+    let tt = tt.view();
 
-    let mut search_data =
-        SharedSearchData::new(&mut board, &stop, &mut local_seldepth, &mut killers);
+    let mut search_data = SharedSearchData::new(
+        &mut board,
+        &stop,
+        &mut local_seldepth,
+        &mut killers,
+        &mut pv,
+        &tt,
+    );
     let search_result = quiescence_search(
         settings::MAX_QS_DEPTH,
         -MATE_SCORE,
@@ -88,5 +103,6 @@ pub fn prepare_quiet_training_position(position: TrainingSample) -> Option<Train
     Some(TrainingSample {
         fen: search_result.best_line_fen,
         result: position.result,
+        score: position.score,
     })
 }
@@ -3,11 +3,16 @@
 //! The training data parser supports lines of the form:
 //! `FEN side castling ep "result";` and converts them into a simplified
 //! `TrainingData` struct that stores the FEN and the game result.
+//! Lines may additionally carry a search score, either as EPD `ce` operation
+//! (relative to the side to move) or as a plain number after the result
+//! (relative to white): `FEN "result"; ce 34;` or `FEN "result"; 34`.
 
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
 use std::path::Path;
 
+use crate::adam::sigmoid;
+
 /// One training position used for Texel tuning.
 ///
 /// The stored FEN is normalized to include the standard halfmove and fullmove
@@ -17,6 +22,8 @@ use std::path::Path;
 pub struct TrainingSample {
     pub fen: String,
     pub result: GameResult,
+    /// Score of a search in centipawns from white's perspective, if the data has one
+    pub score: Option<i32>,
 }
 
 /// The outcome of the game used for supervised tuning labels.
@@ -71,7 +78,7 @@ impl TrainingSample {
 
     /// Writes the given training positions back to an EPD-style file.
     ///
-    /// The output format preserves the FEN and the original result label.
+    /// The output format preserves the FEN, the original result label and the score as `ce` operation.
     pub fn write_epd_file<P: AsRef<Path>>(path: P, positions: &[Self]) -> io::Result<()> {
         if let Some(parent) = path.as_ref().parent() {
             std::fs::create_dir_all(parent)?;
@@ -79,7 +86,11 @@ impl TrainingSample {
         let mut file = File::create(path)?;
 
         for position in positions {
-            writeln!(file, "{} \"{}\";", position.fen, position.result)?;
+            write!(file, "{} \"{}\";", position.fen, position.result)?;
+            if let Some(score) = position.score {
+                write!(file, " ce {};", score * side_to_move_sign(&position.fen))?;
+            }
+            writeln!(file)?;
         }
 
         Ok(())
@@ -89,6 +100,7 @@ impl TrainingSample {
     ///
     /// The function supports an abbreviated FEN prefix and appends `0 1`
     /// for the halfmove/fullmove counters required by the engine.
+    /// Operations other than the result and the score are ignored.
     fn parse_epd_line(line: &str) -> Result<Option<Self>, String> {
         let trimmed = line.trim();
         if trimmed.is_empty() || trimmed.starts_with('#') {
@@ -116,7 +128,42 @@ impl TrainingSample {
             fen_tokens[0], fen_tokens[1], fen_tokens[2], fen_tokens[3]
         );
 
-        Ok(Some(Self { fen, result }))
+        // `ce` may come before or after the result, a plain number only after it
+        // (before it there may be the move counters of a full FEN)
+        let before = fen_tokens[4..].join(" ");
+        let before = before.split(';').map(|operation| (operation.trim(), false));
+        let after = trimmed[quote_end + 1..].split(';').map(|operation| (operation.trim(), true));
+        let mut score = None;
+        for (operation, after_result) in before.chain(after) {
+            let parsed = if let Some(value) = operation.strip_prefix("ce ") {
+                value.trim().parse::<i32>().map(|score| score * side_to_move_sign(&fen))
+            } else if after_result && operation.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                operation.parse::<i32>()
+            } else {
+                continue;
+            };
+            score = Some(parsed.map_err(|_| format!("invalid score '{operation}'"))?);
+        }
+
+        Ok(Some(Self { fen, result, score }))
+    }
+}
+
+/// Training target in `[0, 1]`: the game result blended with the score scaled by the sigmoid.
+/// `lambda = 1.0` only uses the result, `lambda = 0.0` only the score. Without a score it's the result.
+pub fn target(result: GameResult, score: Option<i32>, k: f64, lambda: f64) -> f64 {
+    let result: f64 = result.into();
+    match score {
+        Some(score) => lambda * result + (1.0 - lambda) * sigmoid(f64::from(score), k),
+        None => result,
+    }
+}
+
+/// `1` if white is to move in `fen`, `-1` if black is, converts scores between both perspectives
+fn side_to_move_sign(fen: &str) -> i32 {
+    match fen.split_whitespace().nth(1) {
+        Some("b") => -1,
+        _ => 1,
     }
 }
 
@@ -160,5 +207,55 @@ mod tests {
 
         assert_eq!(result.fen, "r2qkr2/p1pp1ppp/1pn1pn2/2P5/3Pb3/2N1P3/PP3PPP/R1B1KB1R b KQq - 0 1");
         matches!(result.result, GameResult::BlackWin);
+        assert_eq!(result.score, None);
+    }
+
+    #[test]
+    fn parse_scores() {
+        let parse = |line: &str| TrainingSample::parse_epd_line(line).expect("should parse line").unwrap();
+
+        // `ce` is relative to the side to move
+        let sample = parse(r#"4k3/8/8/8/8/8/8/3QK3 b - - ce -850; c9 "1-0";"#);
+        assert_eq!(sample.result, GameResult::WhiteWin);
+        assert_eq!(sample.score, Some(850));
+        assert_eq!(parse(r#"4k3/8/8/8/8/8/8/3QK3 w - - "1-0"; ce 850;"#).score, Some(850));
+
+        // a plain number after the result is relative to white
+        assert_eq!(parse(r#"4k3/8/8/8/8/8/8/3QK3 b - - "1-0"; 850"#).score, Some(850));
+        assert_eq!(parse(r#"4k3/8/8/8/8/8/8/3qK3 w - - "0-1"; -850"#).score, Some(-850));
+
+        assert!(TrainingSample::parse_epd_line(r#"4k3/8/8/8/8/8/8/3QK3 b - - ce x; "1-0";"#).is_err());
+        // move counters aren't a score
+        assert_eq!(parse(r#"4k3/8/8/8/8/8/8/3QK3 b - - 0 1 "1-0";"#).score, None);
+    }
+
+    #[test]
+    fn blend_result_and_score() {
+        assert_eq!(target(GameResult::Draw, None, 1.0, 0.0), 0.5);
+
+        let score = Some(400);
+        assert_eq!(target(GameResult::Draw, score, 1.0, 1.0), 0.5);
+        assert!((target(GameResult::Draw, score, 1.0, 0.0) - 10.0 / 11.0).abs() < 1e-9);
+        assert!((target(GameResult::Draw, score, 1.0, 0.5) - (0.25 + 5.0 / 11.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn write_and_read_scores() {
+        let samples = [
+            r#"4k3/8/8/8/8/8/8/3QK3 b - - "1-0"; ce -850;"#,
+            r#"4k3/8/8/8/8/8/PP6/3QK3 w - - "1/2-1/2";"#,
+        ]
+        .map(|line| TrainingSample::parse_epd_line(line).unwrap().unwrap());
+        let path = std::env::temp_dir().join(format!("training_data_scores_{}.epd", std::process::id()));
+        TrainingSample::write_epd_file(&path, &samples).unwrap();
+
+        let reloaded = TrainingSample::read_epd_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        for (sample, reloaded) in samples.iter().zip(&reloaded) {
+            assert_eq!(sample.fen, reloaded.fen);
+            assert_eq!(sample.result, reloaded.result);
+            assert_eq!(sample.score, reloaded.score);
+        }
     }
 }
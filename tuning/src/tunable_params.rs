@@ -7,6 +7,7 @@ use std::{fmt::Write as _, fs, io, path::Path};
 
 use ngalgebra::SVector;
 use serde::{Deserialize, Serialize};
+use thunfisch::{eval_params::EvalParams, evaluation_constants as engine};
 
 use crate::output_paths;
 
@@ -17,19 +18,96 @@ const N_PIECES: usize = 6;
 const N_COLORS: usize = 2;
 const N_SQUARES: usize = 8 * 8;
 const N_KING_SAFETY_BUCKETS: usize = 10 * 10;
+const N_THREATS: usize = 4;
+const N_PAWN_FORMATIONS: usize = 5;
+const N_PASSER_TERMS: usize = 4;
+const N_PIECE_POSITIONS: usize = 7;
+/// Bishop pair, pawn, knight, bishop, rook and queen
+const N_IMBALANCE_PIECES: usize = 6;
+/// Only the lower triangle of the imbalance tables is used
+const N_IMBALANCE_TERMS: usize = N_IMBALANCE_PIECES * (N_IMBALANCE_PIECES + 1) / 2;
+const N_MATERIAL_SCALE_FACTORS: usize = 4;
+/// Attacker counts and relative ranks of shelter and storm pawns
+const N_KING_RANKS: usize = 8;
 pub const N_TOTAL: usize = (N_PIECES * 2)
     + (N_COLORS * 2)
     + 1
     + (N_COLORS * 2)
     + (N_COLORS * N_PIECES * 3)
     + N_COLORS
+    + (N_COLORS * N_PIECES)
+    + N_COLORS
+    + (N_COLORS * N_KING_RANKS * 3)
     + 1
+    + (N_COLORS * N_THREATS)
+    + (N_COLORS * N_PAWN_FORMATIONS)
+    + (N_COLORS * N_PASSER_TERMS)
+    + (N_COLORS * N_PIECE_POSITIONS)
+    + (N_IMBALANCE_TERMS * 2)
+    + N_MATERIAL_SCALE_FACTORS
     + (N_SQUARES * 2)
     + (N_KING_SAFETY_BUCKETS * 2)
     + (N_PIECES * N_SQUARES * 2);
 /// A wrapper type around ngalgebra::SVector with the dimension of all available params.
 pub type WeightVector = SVector<f64, N_TOTAL>;
 
+/// Every weight set in the order of the `WeightVector` with its key in the weights files and its number of weights
+const LAYOUT: [(&str, usize); 30] = [
+    ("mg_piece_values", N_PIECES),
+    ("eg_piece_values", N_PIECES),
+    ("rook_open_file_bonus", N_COLORS),
+    ("king_open_file_penalty", N_COLORS),
+    ("doubled_pawn_penalty", 1),
+    ("isolated_pawn_penalty", N_COLORS),
+    ("bishop_pair_bonus", N_COLORS),
+    ("mobility_coefficients", N_COLORS * N_PIECES),
+    ("piece_attack_values", N_COLORS * N_PIECES),
+    ("piece_defend_values", N_COLORS * N_PIECES),
+    ("pawn_shield_bonus", N_COLORS),
+    ("safe_check_values", N_COLORS * N_PIECES),
+    ("king_zone_weak_square_value", N_COLORS),
+    ("attacker_count_values", N_COLORS * N_KING_RANKS),
+    ("pawn_shelter_values", N_COLORS * N_KING_RANKS),
+    ("pawn_storm_values", N_COLORS * N_KING_RANKS),
+    ("initiative", 1),
+    ("threat_values", N_COLORS * N_THREATS),
+    ("pawn_formation_values", N_COLORS * N_PAWN_FORMATIONS),
+    ("passer_values", N_COLORS * N_PASSER_TERMS),
+    ("piece_position_values", N_COLORS * N_PIECE_POSITIONS),
+    ("imbalance_ours", N_IMBALANCE_TERMS),
+    ("imbalance_theirs", N_IMBALANCE_TERMS),
+    ("material_scale_factors", N_MATERIAL_SCALE_FACTORS),
+    ("mg_passed_pawn_table", N_SQUARES),
+    ("eg_passed_pawn_table", N_SQUARES),
+    ("mg_king_safety_table", N_KING_SAFETY_BUCKETS),
+    ("eg_king_safety_table", N_KING_SAFETY_BUCKETS),
+    ("mg_base_position_table", N_PIECES * N_SQUARES),
+    ("eg_base_position_table", N_PIECES * N_SQUARES),
+];
+
+/// Position in the `WeightVector` of the weight `name[index]`, where `name` is the key in the weights files
+/// and `index` the index into the flattened array (the way `EvalTrace` addresses weights).
+/// `None` for unknown weights and for the upper triangle of the imbalance tables, which isn't tuned.
+pub fn weight_index(name: &str, index: usize) -> Option<usize> {
+    let mut offset = 0;
+    for (key, len) in LAYOUT {
+        if key == name {
+            let index = if key.starts_with("imbalance_") {
+                let (row, col) = (index / N_IMBALANCE_PIECES, index % N_IMBALANCE_PIECES);
+                if col > row || row >= N_IMBALANCE_PIECES {
+                    return None;
+                }
+                row * (row + 1) / 2 + col
+            } else {
+                index
+            };
+            return (index < len).then_some(offset + index);
+        }
+        offset += len;
+    }
+    None
+}
+
 /// A runtime-configurable parameter set for the evaluator.
 /// Can be (de-)serializes using serde.
 /// I isomorphically mapped to WeightVector.
@@ -49,8 +127,20 @@ pub struct TunableParams {
     pub piece_attack_values: [[i16; N_PIECES]; N_COLORS],
     pub piece_defend_values: [[i16; N_PIECES]; N_COLORS],
     pub pawn_shield_bonus: [i16; N_COLORS],
+    pub safe_check_values: [[i16; N_PIECES]; N_COLORS],
+    pub king_zone_weak_square_value: [i16; N_COLORS],
+    pub attacker_count_values: [[i16; N_KING_RANKS]; N_COLORS],
+    pub pawn_shelter_values: [[i16; N_KING_RANKS]; N_COLORS],
+    pub pawn_storm_values: [[i16; N_KING_RANKS]; N_COLORS],
 
     pub initiative: i32,
+    pub threat_values: [[i16; N_THREATS]; N_COLORS],
+    pub pawn_formation_values: [[i16; N_PAWN_FORMATIONS]; N_COLORS],
+    pub passer_values: [[i16; N_PASSER_TERMS]; N_COLORS],
+    pub piece_position_values: [[i16; N_PIECE_POSITIONS]; N_COLORS],
+    pub imbalance_ours: [[i16; N_IMBALANCE_PIECES]; N_IMBALANCE_PIECES],
+    pub imbalance_theirs: [[i16; N_IMBALANCE_PIECES]; N_IMBALANCE_PIECES],
+    pub material_scale_factors: [i16; N_MATERIAL_SCALE_FACTORS],
 
     pub mg_passed_pawn_table: [i16; N_SQUARES],
     pub eg_passed_pawn_table: [i16; N_SQUARES],
@@ -76,7 +166,19 @@ impl Default for TunableParams {
             piece_attack_values: PIECE_ATTACK_VALUES,
             piece_defend_values: PIECE_DEFEND_VALUES,
             pawn_shield_bonus: PAWN_SHIELD_BONUS,
+            safe_check_values: SAFE_CHECK_VALUES,
+            king_zone_weak_square_value: KING_ZONE_WEAK_SQUARE_VALUE,
+            attacker_count_values: ATTACKER_COUNT_VALUES,
+            pawn_shelter_values: PAWN_SHELTER_VALUES,
+            pawn_storm_values: PAWN_STORM_VALUES,
             initiative: INITIATIVE,
+            threat_values: THREAT_VALUES,
+            pawn_formation_values: PAWN_FORMATION_VALUES,
+            passer_values: PASSER_VALUES,
+            piece_position_values: PIECE_POSITION_VALUES,
+            imbalance_ours: IMBALANCE_OURS,
+            imbalance_theirs: IMBALANCE_THEIRS,
+            material_scale_factors: MATERIAL_SCALE_FACTORS,
             mg_passed_pawn_table: MG_PASSED_PAWN_TABLE,
             eg_passed_pawn_table: EG_PASSED_PAWN_TABLE,
             mg_king_safety_table: MG_KING_SAFETY_TABLE,
@@ -88,6 +190,42 @@ impl Default for TunableParams {
 }
 
 impl TunableParams {
+    /// The weights currently compiled into the engine (`thunfisch::evaluation_constants`).
+    pub fn from_engine_constants() -> Self {
+        Self {
+            mg_piece_values: engine::MG_PIECE_VALUES,
+            eg_piece_values: engine::EG_PIECE_VALUES,
+            rook_open_file_bonus: engine::ROOK_OPEN_FILE_BONUS,
+            king_open_file_penalty: engine::KING_OPEN_FILE_PENALTY,
+            doubled_pawn_penalty: engine::DOUBLED_PAWN_PENALTY,
+            isolated_pawn_penalty: engine::ISOLATED_PAWN_PENALTY,
+            bishop_pair_bonus: engine::BISHOP_PAIR_BONUS,
+            mobility_coefficients: engine::MOBILITY_COEFFICIENTS,
+            piece_attack_values: engine::PIECE_ATTACK_VALUES,
+            piece_defend_values: engine::PIECE_DEFEND_VALUES,
+            pawn_shield_bonus: engine::PAWN_SHIELD_BONUS,
+            safe_check_values: engine::SAFE_CHECK_VALUES,
+            king_zone_weak_square_value: engine::KING_ZONE_WEAK_SQUARE_VALUE,
+            attacker_count_values: engine::ATTACKER_COUNT_VALUES,
+            pawn_shelter_values: engine::PAWN_SHELTER_VALUES,
+            pawn_storm_values: engine::PAWN_STORM_VALUES,
+            initiative: engine::INITIATIVE,
+            threat_values: engine::THREAT_VALUES,
+            pawn_formation_values: engine::PAWN_FORMATION_VALUES,
+            passer_values: engine::PASSER_VALUES,
+            piece_position_values: engine::PIECE_POSITION_VALUES,
+            imbalance_ours: engine::IMBALANCE_OURS,
+            imbalance_theirs: engine::IMBALANCE_THEIRS,
+            material_scale_factors: engine::MATERIAL_SCALE_FACTORS,
+            mg_passed_pawn_table: engine::MG_PASSED_PAWN_TABLE,
+            eg_passed_pawn_table: engine::EG_PASSED_PAWN_TABLE,
+            mg_king_safety_table: engine::MG_KING_SAFETY_TABLE,
+            eg_king_safety_table: engine::EG_KING_SAFETY_TABLE,
+            mg_base_position_table: engine::MG_BASE_POSITION_TABLE,
+            eg_base_position_table: engine::EG_BASE_POSITION_TABLE,
+        }
+    }
+
     /// Read tunable parameters from a JSON file.
     pub fn read_from_file(path: impl AsRef<Path>) -> io::Result<Self> {
         let contents = fs::read_to_string(path)?;
@@ -149,8 +287,32 @@ impl TunableParams {
             .expect("writing to string should never fail");
         writeln!(source, "pub const PAWN_SHIELD_BONUS: [i16; 2] = {:?};", self.pawn_shield_bonus)
             .expect("writing to string should never fail");
+        writeln!(source, "pub const SAFE_CHECK_VALUES: [[i16; 6]; 2] = {:?};", self.safe_check_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const KING_ZONE_WEAK_SQUARE_VALUE: [i16; 2] = {:?};", self.king_zone_weak_square_value)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const ATTACKER_COUNT_VALUES: [[i16; 8]; 2] = {:?};", self.attacker_count_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const PAWN_SHELTER_VALUES: [[i16; 8]; 2] = {:?};", self.pawn_shelter_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const PAWN_STORM_VALUES: [[i16; 8]; 2] = {:?};", self.pawn_storm_values)
+            .expect("writing to string should never fail");
         writeln!(source, "pub const INITIATIVE: i32 = {:?};", self.initiative)
             .expect("writing to string should never fail");
+        writeln!(source, "pub const THREAT_VALUES: [[i16; 4]; 2] = {:?};", self.threat_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const PAWN_FORMATION_VALUES: [[i16; 5]; 2] = {:?};", self.pawn_formation_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const PASSER_VALUES: [[i16; 4]; 2] = {:?};", self.passer_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const PIECE_POSITION_VALUES: [[i16; 7]; 2] = {:?};", self.piece_position_values)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const IMBALANCE_OURS: [[i16; 6]; 6] = {:?};", self.imbalance_ours)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const IMBALANCE_THEIRS: [[i16; 6]; 6] = {:?};", self.imbalance_theirs)
+            .expect("writing to string should never fail");
+        writeln!(source, "pub const MATERIAL_SCALE_FACTORS: [i16; 4] = {:?};", self.material_scale_factors)
+            .expect("writing to string should never fail");
         writeln!(source, "pub const MG_PASSED_PAWN_TABLE: [i16; 64] = {:?};", self.mg_passed_pawn_table)
             .expect("writing to string should never fail");
         writeln!(source, "pub const EG_PASSED_PAWN_TABLE: [i16; 64] = {:?};", self.eg_passed_pawn_table)
@@ -168,6 +330,110 @@ impl TunableParams {
     }
 }
 
+/// Lets the engine's `Board::evaluate_with` run on the tunable parameters,
+/// so the tuner doesn't need an evaluator of its own.
+impl EvalParams for TunableParams {
+    fn piece_values(&self) -> [&[i32; 6]; 2] {
+        [&self.mg_piece_values, &self.eg_piece_values]
+    }
+
+    fn base_position_tables(&self) -> [&[[i32; 64]; 6]; 2] {
+        [&self.mg_base_position_table, &self.eg_base_position_table]
+    }
+
+    fn rook_open_file_bonus(&self) -> [i32; 2] {
+        self.rook_open_file_bonus
+    }
+
+    fn king_open_file_penalty(&self) -> [i32; 2] {
+        self.king_open_file_penalty
+    }
+
+    fn doubled_pawn_penalty(&self) -> i32 {
+        self.doubled_pawn_penalty
+    }
+
+    fn isolated_pawn_penalty(&self) -> [i16; 2] {
+        self.isolated_pawn_penalty
+    }
+
+    fn bishop_pair_bonus(&self) -> [i16; 2] {
+        self.bishop_pair_bonus
+    }
+
+    fn mobility_coefficients(&self) -> &[[i32; 6]; 2] {
+        &self.mobility_coefficients
+    }
+
+    fn piece_attack_values(&self) -> &[[i16; 6]; 2] {
+        &self.piece_attack_values
+    }
+
+    fn piece_defend_values(&self) -> &[[i16; 6]; 2] {
+        &self.piece_defend_values
+    }
+
+    fn pawn_shield_bonus(&self) -> [i16; 2] {
+        self.pawn_shield_bonus
+    }
+
+    fn safe_check_values(&self) -> &[[i16; 6]; 2] {
+        &self.safe_check_values
+    }
+
+    fn king_zone_weak_square_value(&self) -> [i16; 2] {
+        self.king_zone_weak_square_value
+    }
+
+    fn attacker_count_values(&self) -> &[[i16; 8]; 2] {
+        &self.attacker_count_values
+    }
+
+    fn pawn_shelter_values(&self) -> &[[i16; 8]; 2] {
+        &self.pawn_shelter_values
+    }
+
+    fn pawn_storm_values(&self) -> &[[i16; 8]; 2] {
+        &self.pawn_storm_values
+    }
+
+    fn initiative(&self) -> i32 {
+        self.initiative
+    }
+
+    fn threat_values(&self) -> &[[i16; 4]; 2] {
+        &self.threat_values
+    }
+
+    fn pawn_formation_values(&self) -> &[[i16; 5]; 2] {
+        &self.pawn_formation_values
+    }
+
+    fn passer_values(&self) -> &[[i16; 4]; 2] {
+        &self.passer_values
+    }
+
+    fn piece_position_values(&self) -> &[[i16; 7]; 2] {
+        &self.piece_position_values
+    }
+
+    fn imbalance_tables(&self) -> [&[[i16; 6]; 6]; 2] {
+        [&self.imbalance_ours, &self.imbalance_theirs]
+    }
+
+    fn material_scale_factors(&self) -> [i16; 4] {
+        self.material_scale_factors
+    }
+
+    fn passed_pawn_tables(&self) -> [&[i16; 64]; 2] {
+        [&self.mg_passed_pawn_table, &self.eg_passed_pawn_table]
+    }
+
+    fn king_safety_tables(&self) -> [&[i16; 100]; 2] {
+        [&self.mg_king_safety_table, &self.eg_king_safety_table]
+    }
+}
+
 impl From<&TunableParams> for WeightVector {
     fn from(params: &TunableParams) -> Self {
         let mut values = Vec::with_capacity(N_TOTAL);
@@ -193,7 +459,38 @@ impl From<&TunableParams> for WeightVector {
         }
 
         values.extend(params.pawn_shield_bonus.iter().copied().map(f64::from));
+        for row in params.safe_check_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        values.extend(params.king_zone_weak_square_value.iter().copied().map(f64::from));
+        for row in params.attacker_count_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        for row in params.pawn_shelter_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        for row in params.pawn_storm_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
         values.push(f64::from(params.initiative));
+        for row in params.threat_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        for row in params.pawn_formation_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        for row in params.passer_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        for row in params.piece_position_values {
+            values.extend(row.iter().copied().map(f64::from));
+        }
+        for table in [params.imbalance_ours, params.imbalance_theirs] {
+            for (piece, row) in table.iter().enumerate() {
+                values.extend(row[..=piece].iter().copied().map(f64::from));
+            }
+        }
+        values.extend(params.material_scale_factors.iter().copied().map(f64::from));
 
         values.extend(params.mg_passed_pawn_table.iter().copied().map(f64::from));
         values.extend(params.eg_passed_pawn_table.iter().copied().map(f64::from));
@@ -263,6 +560,11 @@ impl From<WeightVector> for TunableParams {
             std::array::from_fn(|_| read_i16_array::<COLS>(values, index))
         }
 
+        /// Lower triangle including the diagonal, the rest stays 0
+        fn read_i16_triangle<const N: usize>(values: &[f64], index: &mut usize) -> [[i16; N]; N] {
+            std::array::from_fn(|row| std::array::from_fn(|col| if col <= row { read_i16(values, index) } else { 0 }))
+        }
+
         Self {
             mg_piece_values: read_i32_array::<N_PIECES>(values, &mut index),
             eg_piece_values: read_i32_array::<N_PIECES>(values, &mut index),
@@ -275,7 +577,19 @@ impl From<WeightVector> for TunableParams {
             piece_attack_values: read_i16_table::<N_COLORS, N_PIECES>(values, &mut index),
             piece_defend_values: read_i16_table::<N_COLORS, N_PIECES>(values, &mut index),
             pawn_shield_bonus: read_i16_array::<N_COLORS>(values, &mut index),
+            safe_check_values: read_i16_table::<N_COLORS, N_PIECES>(values, &mut index),
+            king_zone_weak_square_value: read_i16_array::<N_COLORS>(values, &mut index),
+            attacker_count_values: read_i16_table::<N_COLORS, N_KING_RANKS>(values, &mut index),
+            pawn_shelter_values: read_i16_table::<N_COLORS, N_KING_RANKS>(values, &mut index),
+            pawn_storm_values: read_i16_table::<N_COLORS, N_KING_RANKS>(values, &mut index),
             initiative: read_i32(values, &mut index),
+            threat_values: read_i16_table::<N_COLORS, N_THREATS>(values, &mut index),
+            pawn_formation_values: read_i16_table::<N_COLORS, N_PAWN_FORMATIONS>(values, &mut index),
+            passer_values: read_i16_table::<N_COLORS, N_PASSER_TERMS>(values, &mut index),
+            piece_position_values: read_i16_table::<N_COLORS, N_PIECE_POSITIONS>(values, &mut index),
+            imbalance_ours: read_i16_triangle::<N_IMBALANCE_PIECES>(values, &mut index),
+            imbalance_theirs: read_i16_triangle::<N_IMBALANCE_PIECES>(values, &mut index),
+            material_scale_factors: read_i16_array::<N_MATERIAL_SCALE_FACTORS>(values, &mut index),
             mg_passed_pawn_table: read_i16_array::<N_SQUARES>(values, &mut index),
             eg_passed_pawn_table: read_i16_array::<N_SQUARES>(values, &mut index),
             mg_king_safety_table: read_i16_array::<N_KING_SAFETY_BUCKETS>(values, &mut index),
@@ -305,8 +619,20 @@ struct TunableParamsFile {
     piece_attack_values: [[i16; N_PIECES]; N_COLORS],
     piece_defend_values: [[i16; N_PIECES]; N_COLORS],
     pawn_shield_bonus: [i16; N_COLORS],
+    safe_check_values: [[i16; N_PIECES]; N_COLORS],
+    king_zone_weak_square_value: [i16; N_COLORS],
+    attacker_count_values: [[i16; N_KING_RANKS]; N_COLORS],
+    pawn_shelter_values: [[i16; N_KING_RANKS]; N_COLORS],
+    pawn_storm_values: [[i16; N_KING_RANKS]; N_COLORS],
 
     initiative: i32,
+    threat_values: [[i16; N_THREATS]; N_COLORS],
+    pawn_formation_values: [[i16; N_PAWN_FORMATIONS]; N_COLORS],
+    passer_values: [[i16; N_PASSER_TERMS]; N_COLORS],
+    piece_position_values: [[i16; N_PIECE_POSITIONS]; N_COLORS],
+    imbalance_ours: [[i16; N_IMBALANCE_PIECES]; N_IMBALANCE_PIECES],
+    imbalance_theirs: [[i16; N_IMBALANCE_PIECES]; N_IMBALANCE_PIECES],
+    material_scale_factors: [i16; N_MATERIAL_SCALE_FACTORS],
 
     mg_passed_pawn_table: Vec<i16>,
     eg_passed_pawn_table: Vec<i16>,
@@ -331,7 +657,19 @@ impl From<&TunableParams> for TunableParamsFile {
             piece_attack_values: params.piece_attack_values,
             piece_defend_values: params.piece_defend_values,
             pawn_shield_bonus: params.pawn_shield_bonus,
+            safe_check_values: params.safe_check_values,
+            king_zone_weak_square_value: params.king_zone_weak_square_value,
+            attacker_count_values: params.attacker_count_values,
+            pawn_shelter_values: params.pawn_shelter_values,
+            pawn_storm_values: params.pawn_storm_values,
             initiative: params.initiative,
+            threat_values: params.threat_values,
+            pawn_formation_values: params.pawn_formation_values,
+            passer_values: params.passer_values,
+            piece_position_values: params.piece_position_values,
+            imbalance_ours: params.imbalance_ours,
+            imbalance_theirs: params.imbalance_theirs,
+            material_scale_factors: params.material_scale_factors,
             mg_passed_pawn_table: params.mg_passed_pawn_table.to_vec(),
             eg_passed_pawn_table: params.eg_passed_pawn_table.to_vec(),
             mg_king_safety_table: params.mg_king_safety_table.to_vec(),
@@ -366,7 +704,19 @@ impl TryFrom<TunableParamsFile> for TunableParams {
             piece_attack_values: file.piece_attack_values,
             piece_defend_values: file.piece_defend_values,
             pawn_shield_bonus: file.pawn_shield_bonus,
+            safe_check_values: file.safe_check_values,
+            king_zone_weak_square_value: file.king_zone_weak_square_value,
+            attacker_count_values: file.attacker_count_values,
+            pawn_shelter_values: file.pawn_shelter_values,
+            pawn_storm_values: file.pawn_storm_values,
             initiative: file.initiative,
+            threat_values: file.threat_values,
+            pawn_formation_values: file.pawn_formation_values,
+            passer_values: file.passer_values,
+            piece_position_values: file.piece_position_values,
+            imbalance_ours: file.imbalance_ours,
+            imbalance_theirs: file.imbalance_theirs,
+            material_scale_factors: file.material_scale_factors,
             mg_passed_pawn_table: to_array_64(file.mg_passed_pawn_table, "mg_passed_pawn_table")?,
             eg_passed_pawn_table: to_array_64(file.eg_passed_pawn_table, "eg_passed_pawn_table")?,
             mg_king_safety_table: to_array_100(file.mg_king_safety_table, "mg_king_safety_table")?,
@@ -439,9 +789,54 @@ pub const PIECE_DEFEND_VALUES: [[i16; N_PIECES]; N_COLORS] = [[0, 1, 1, 2, 4, 0]
 // danger points for every pawn in the pawn shield in front of the king
 pub const PAWN_SHIELD_BONUS: [i16; N_COLORS] = [2, 0];
 
+// danger units of the king safety tables
+pub const SAFE_CHECK_VALUES: [[i16; N_PIECES]; N_COLORS] = [[0, 6, 4, 7, 8, 0], [0, 2, 1, 2, 3, 0]];
+pub const KING_ZONE_WEAK_SQUARE_VALUE: [i16; N_COLORS] = [2, 1];
+pub const ATTACKER_COUNT_VALUES: [[i16; N_KING_RANKS]; N_COLORS] = [[0, 0, 3, 6, 9, 12, 12, 12], [0, 0, 1, 2, 3, 4, 4, 4]];
+pub const PAWN_SHELTER_VALUES: [[i16; N_KING_RANKS]; N_COLORS] = [[6, -5, -3, -1, 0, 0, 0, 0], [2, -1, -1, 0, 0, 0, 0, 0]];
+pub const PAWN_STORM_VALUES: [[i16; N_KING_RANKS]; N_COLORS] = [[0, 0, 6, 3, 1, 0, 0, 0], [0, 0, 2, 1, 0, 0, 0, 0]];
+
 // bonus for the side to move
 pub const INITIATIVE: i32 = 15;
 
+// [attacked by a pawn, rook or queen attacked by a minor piece, hanging, attacked by a safe pawn push]
+pub const THREAT_VALUES: [[i16; N_THREATS]; N_COLORS] = [[40, 30, 20, 15], [30, 25, 15, 10]];
+
+// [backward, supported, phalanx, candidate passer, pawn majority]
+pub const PAWN_FORMATION_VALUES: [[i16; N_PAWN_FORMATIONS]; N_COLORS] = [[-8, 10, 6, 8, 5], [-10, 8, 5, 15, 10]];
+
+// [blocked passer, distance of the friendly king, distance of the opponent king, unstoppable passer]
+pub const PASSER_VALUES: [[i16; N_PASSER_TERMS]; N_COLORS] = [[-10, 0, 0, 0], [-15, -3, 6, 150]];
+
+// [knight outpost, bishop outpost, rook on seventh, connected rooks, rook on semi-open file,
+//  blocked pawn on the color of a bishop, undeveloped minor piece after an early queen move]
+pub const PIECE_POSITION_VALUES: [[i16; N_PIECE_POSITIONS]; N_COLORS] =
+    [[20, 10, 15, 10, 10, -3, -8], [10, 5, 25, 5, 5, -6, 0]];
+
+// quadratic in the piece counts, lower triangle of [piece][other piece] in the order
+// [bishop pair, pawn, knight, bishop, rook, queen], summed up per side and divided by 16.
+// The bishop pair on its own is already covered by BISHOP_PAIR_BONUS
+pub const IMBALANCE_OURS: [[i16; N_IMBALANCE_PIECES]; N_IMBALANCE_PIECES] = [
+    [0, 0, 0, 0, 0, 0],
+    [10, 10, 0, 0, 0, 0],
+    [8, 64, -16, 0, 0, 0],
+    [0, 26, 1, 0, 0, 0],
+    [-7, -1, 12, 26, -52, 0],
+    [-47, 6, 29, 33, -34, -2],
+];
+pub const IMBALANCE_THEIRS: [[i16; N_IMBALANCE_PIECES]; N_IMBALANCE_PIECES] = [
+    [0, 0, 0, 0, 0, 0],
+    [9, 0, 0, 0, 0, 0],
+    [2, 16, 0, 0, 0, 0],
+    [15, 16, 11, 0, 0, 0],
+    [12, 10, 6, -6, 0, 0],
+    [24, 25, -11, 34, 67, 0],
+];
+
+// scale factors (out of 64) of the side ahead: [no pawns and less than a rook, rook against a minor piece,
+//  no pawns and at most a minor piece ahead, one pawn and at most a minor piece ahead]
+pub const MATERIAL_SCALE_FACTORS: [i16; N_MATERIAL_SCALE_FACTORS] = [0, 4, 14, 48];
+
 
 
 /// Values here are vaguely inspired in their Shape by Fatalii
@@ -578,7 +973,7 @@ mod tests {
     use super::*;
     use crate::{eval::evaluation::evaluate, training_data::TrainingSample};
     use std::{fs, path::Path, time::{Instant, SystemTime, UNIX_EPOCH}};
-    use thunfisch::types::board::Board;
+    use thunfisch::{eval_params::LoadedParams, types::board::Board};
 
     fn next_u64(state: &mut u64) -> u64 {
         *state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
@@ -601,6 +996,11 @@ mod tests {
         std::array::from_fn(|_| random_i16(state, magnitude))
     }
 
+    /// The upper triangle isn't part of the weight vector, so it has to stay 0
+    fn random_i16_triangle<const N: usize>(state: &mut u64, magnitude: i16) -> [[i16; N]; N] {
+        std::array::from_fn(|row| std::array::from_fn(|col| if col <= row { random_i16(state, magnitude) } else { 0 }))
+    }
+
     fn random_params(state: &mut u64) -> TunableParams {
         TunableParams {
             mg_piece_values: random_i32_array::<N_PIECES>(state, 100_000),
@@ -614,7 +1014,19 @@ mod tests {
             piece_attack_values: std::array::from_fn(|_| random_i16_array::<N_PIECES>(state, 100)),
             piece_defend_values: std::array::from_fn(|_| random_i16_array::<N_PIECES>(state, 100)),
             pawn_shield_bonus: random_i16_array::<N_COLORS>(state, 100),
+            safe_check_values: std::array::from_fn(|_| random_i16_array::<N_PIECES>(state, 100)),
+            king_zone_weak_square_value: random_i16_array::<N_COLORS>(state, 100),
+            attacker_count_values: std::array::from_fn(|_| random_i16_array::<N_KING_RANKS>(state, 100)),
+            pawn_shelter_values: std::array::from_fn(|_| random_i16_array::<N_KING_RANKS>(state, 100)),
+            pawn_storm_values: std::array::from_fn(|_| random_i16_array::<N_KING_RANKS>(state, 100)),
             initiative: random_i32(state, 5_000),
+            threat_values: std::array::from_fn(|_| random_i16_array::<N_THREATS>(state, 500)),
+            pawn_formation_values: std::array::from_fn(|_| random_i16_array::<N_PAWN_FORMATIONS>(state, 500)),
+            passer_values: std::array::from_fn(|_| random_i16_array::<N_PASSER_TERMS>(state, 500)),
+            piece_position_values: std::array::from_fn(|_| random_i16_array::<N_PIECE_POSITIONS>(state, 500)),
+            imbalance_ours: random_i16_triangle::<N_IMBALANCE_PIECES>(state, 500),
+            imbalance_theirs: random_i16_triangle::<N_IMBALANCE_PIECES>(state, 500),
+            material_scale_factors: random_i16_array::<N_MATERIAL_SCALE_FACTORS>(state, 64),
             mg_passed_pawn_table: random_i16_array::<N_SQUARES>(state, 200),
             eg_passed_pawn_table: random_i16_array::<N_SQUARES>(state, 200),
             mg_king_safety_table: random_i16_array::<N_KING_SAFETY_BUCKETS>(state, 500),
@@ -645,6 +1057,50 @@ mod tests {
         fs::remove_file(&path).expect("temporary tunable params file should be removable");
     }
 
+    #[test]
+    fn every_term_round_trips_through_the_engine() {
+        fn flatten(json: &serde_json::Value, weights: &mut Vec<i64>) {
+            match json {
+                serde_json::Value::Array(elements) => elements
+                    .iter()
+                    .for_each(|element| flatten(element, weights)),
+                _ => weights.push(json.as_i64().expect("weights are integers")),
+            }
+        }
+
+        let keys: Vec<&str> = LAYOUT.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, LoadedParams::TERMS);
+
+        let mut state = 0x10ade_u64;
+        let params = random_params(&mut state);
+        let unique_suffix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is before UNIX_EPOCH")
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("tunable_params_engine_{unique_suffix}.json"));
+        params
+            .write_to_file(&path)
+            .expect("random tunable params should be written successfully");
+        let json = fs::read_to_string(&path).expect("written tunable params should be readable");
+        fs::remove_file(&path).expect("temporary tunable params file should be removable");
+
+        let (loaded, missing) =
+            LoadedParams::from_json(&json).expect("the engine should load the tuner's weights");
+        assert!(missing.is_empty(), "{missing:?}");
+
+        let serde_json::Value::Object(terms) =
+            serde_json::from_str(&json).expect("written weights are JSON")
+        else {
+            panic!("weights are no object");
+        };
+        assert_eq!(terms.len(), LoadedParams::TERMS.len());
+        for (key, value) in &terms {
+            let mut written = Vec::new();
+            flatten(value, &mut written);
+            assert_eq!(loaded.term(key), Some(written), "{key}");
+        }
+    }
+
     #[test]
     fn svector_round_trip_is_isomorph() {
         let mut state = 0x5eede67_u64;
@@ -675,6 +1131,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn weight_index_matches_vector_layout() {
+        fn flatten(json: &serde_json::Value, values: &mut Vec<f64>) {
+            match json {
+                serde_json::Value::Array(elements) => {
+                    elements.iter().for_each(|element| flatten(element, values))
+                }
+                value => values.push(value.as_f64().expect("weights are numbers")),
+            }
+        }
+
+        assert_eq!(LAYOUT.iter().map(|(_, len)| len).sum::<usize>(), N_TOTAL);
+        for i in 0..N_TOTAL {
+            let mut vector = WeightVector::zeros();
+            vector[i] = 1.0;
+            let json = serde_json::to_value(TunableParamsFile::from(&TunableParams::from(vector)))
+                .unwrap();
+
+            let mut found = None;
+            for (key, value) in json.as_object().unwrap() {
+                let mut values = Vec::new();
+                flatten(value, &mut values);
+                if let Some(index) = values.iter().position(|&value| value != 0.0) {
+                    assert!(found.is_none(), "weight {i} appears twice");
+                    found = Some((key.clone(), index));
+                }
+            }
+            let (key, index) = found.expect("every weight is serialized");
+            assert_eq!(weight_index(&key, index), Some(i), "{key}[{index}]");
+        }
+
+        assert_eq!(weight_index("imbalance_ours", 1), None);
+        assert_eq!(weight_index("initiative", 1), None);
+        assert_eq!(weight_index("unknown", 0), None);
+    }
+
     #[test]
     fn benchmark_conversion() {
         use std::hint::black_box;